@@ -0,0 +1,832 @@
+//! A web dashboard for the background job queue.
+//!
+//! It shows how many jobs are in each status, per queue and per worker, how
+//! many jobs completed recently and how many of them failed, and lets you
+//! inspect a single job and retry or cancel it.
+//!
+//! The dashboard reads and changes the queue of the application, so mount it
+//! behind authentication:
+//!
+//! ```rust,ignore
+//! AppRoutes::with_default_routes()
+//!     .add_route(bgworker::dashboard::routes().layer(admin_auth_layer))
+//! ```
+//!
+//! Its forms carry a token that has to match a cookie set by the dashboard
+//! pages, so other sites can't retry or cancel jobs through the browser of a
+//! signed in user.
+use std::collections::BTreeMap;
+#[cfg(any(feature = "bg_inmem", feature = "bg_redis"))]
+use std::{cmp::Reverse, collections::HashMap};
+
+use axum::{
+    extract::{MatchedPath, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+#[cfg(any(feature = "bg_inmem", feature = "bg_redis"))]
+use chrono::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tera::{Context, Tera};
+
+use super::{JobStatus, Queue, RetryTarget};
+use crate::{
+    app::AppContext,
+    controller::{format, ErrorDetail, Routes},
+    hash, Error, Result,
+};
+
+/// Number of jobs listed on the overview page.
+const RECENT_JOBS_LIMIT: usize = 50;
+
+/// Cookie holding the token the dashboard forms have to send back.
+const CSRF_COOKIE: &str = "loco_jobs_csrf";
+
+const STATUSES: [JobStatus; 6] = [
+    JobStatus::Queued,
+    JobStatus::Processing,
+    JobStatus::Completed,
+    JobStatus::Failed,
+    JobStatus::Cancelled,
+    JobStatus::Dead,
+];
+
+/// A job as stored by any of the queue providers.
+#[derive(Debug, Serialize, Deserialize)]
+struct Job {
+    id: String,
+    name: String,
+    #[serde(default)]
+    task_data: JsonValue,
+    status: JobStatus,
+    run_at: DateTime<Utc>,
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    attempts: i32,
+    #[serde(default)]
+    last_error: Option<String>,
+    #[serde(default)]
+    backtrace: Option<String>,
+    #[serde(default)]
+    queue: Option<String>,
+    #[serde(default)]
+    priority: i16,
+    #[serde(default)]
+    result: Option<JsonValue>,
+    #[serde(default)]
+    batch_id: Option<String>,
+}
+
+impl Job {
+    fn queue(&self) -> &str {
+        self.queue.as_deref().unwrap_or("default")
+    }
+}
+
+/// Number of jobs of a worker with one status, as counted by the queue
+/// provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct JobCount {
+    /// Queue of the jobs, for providers with named queues.
+    pub queue: Option<String>,
+    pub name: String,
+    pub status: JobStatus,
+    pub total: usize,
+    /// How many of them were last updated within the last hour.
+    pub last_hour: usize,
+    /// How many of them were last updated within the last day.
+    pub last_day: usize,
+}
+
+#[cfg(any(feature = "bg_inmem", feature = "bg_redis"))]
+/// Counts jobs one at a time, for providers that can't count them with a
+/// query.
+pub(crate) struct JobTally {
+    hour_ago: DateTime<Utc>,
+    day_ago: DateTime<Utc>,
+    counts: HashMap<(Option<String>, String, String), JobCount>,
+}
+
+#[cfg(any(feature = "bg_inmem", feature = "bg_redis"))]
+impl JobTally {
+    pub(crate) fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            hour_ago: now - Duration::hours(1),
+            day_ago: now - Duration::days(1),
+            counts: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn add(
+        &mut self,
+        queue: Option<&str>,
+        name: &str,
+        status: &JobStatus,
+        updated_at: Option<DateTime<Utc>>,
+    ) {
+        let key = (
+            queue.map(ToString::to_string),
+            name.to_string(),
+            status.to_string(),
+        );
+        let count = self.counts.entry(key).or_insert_with(|| JobCount {
+            queue: queue.map(ToString::to_string),
+            name: name.to_string(),
+            status: status.clone(),
+            total: 0,
+            last_hour: 0,
+            last_day: 0,
+        });
+        count.total += 1;
+        if updated_at.is_some_and(|at| at >= self.hour_ago) {
+            count.last_hour += 1;
+        }
+        if updated_at.is_some_and(|at| at >= self.day_ago) {
+            count.last_day += 1;
+        }
+    }
+
+    pub(crate) fn into_counts(self) -> Vec<JobCount> {
+        self.counts.into_values().collect()
+    }
+}
+
+#[cfg(any(feature = "bg_inmem", feature = "bg_redis"))]
+/// Keeps the `limit` most recently updated of the jobs it is given, for
+/// providers that can't sort them with a query.
+pub(crate) struct RecentJobs<T> {
+    limit: usize,
+    jobs: Vec<(Option<DateTime<Utc>>, T)>,
+}
+
+#[cfg(any(feature = "bg_inmem", feature = "bg_redis"))]
+impl<T> RecentJobs<T> {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            jobs: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, updated_at: Option<DateTime<Utc>>, job: T) {
+        self.jobs.push((updated_at, job));
+        // sorts once in a while rather than on every job
+        if self.jobs.len() > self.limit.saturating_mul(2) {
+            self.truncate();
+        }
+    }
+
+    fn truncate(&mut self) {
+        self.jobs
+            .sort_by_key(|(updated_at, _)| Reverse(*updated_at));
+        self.jobs.truncate(self.limit);
+    }
+
+    pub(crate) fn into_jobs(mut self) -> Vec<T> {
+        self.truncate();
+        self.jobs.into_iter().map(|(_, job)| job).collect()
+    }
+}
+
+/// Job counts of a queue or a worker, by status.
+#[derive(Debug, Serialize)]
+pub struct GroupStats {
+    pub name: String,
+    pub total: usize,
+    pub statuses: BTreeMap<String, usize>,
+}
+
+/// Jobs that completed recently.
+#[derive(Debug, Serialize)]
+pub struct Throughput {
+    pub last_hour: usize,
+    pub last_day: usize,
+    pub per_minute: f64,
+}
+
+/// Metrics served by the dashboard, also available as JSON at `/stats`.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub total: usize,
+    pub statuses: BTreeMap<String, usize>,
+    pub queues: Vec<GroupStats>,
+    pub workers: Vec<GroupStats>,
+    pub throughput: Throughput,
+    /// Share of the jobs that finished in the last 24 hours which failed,
+    /// ran out of retries or were cancelled, between 0 and 1.
+    pub failure_rate: f64,
+}
+
+fn empty_counts() -> BTreeMap<String, usize> {
+    STATUSES
+        .iter()
+        .map(|status| (status.to_string(), 0))
+        .collect()
+}
+
+fn group_by<'a>(counts: &'a [JobCount], key: impl Fn(&'a JobCount) -> &'a str) -> Vec<GroupStats> {
+    let mut groups: BTreeMap<&str, GroupStats> = BTreeMap::new();
+    for count in counts {
+        let name = key(count);
+        let group = groups.entry(name).or_insert_with(|| GroupStats {
+            name: name.to_string(),
+            total: 0,
+            statuses: empty_counts(),
+        });
+        group.total += count.total;
+        *group.statuses.entry(count.status.to_string()).or_default() += count.total;
+    }
+    groups.into_values().collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn stats(counts: &[JobCount]) -> Stats {
+    let mut statuses = empty_counts();
+    for count in counts {
+        *statuses.entry(count.status.to_string()).or_default() += count.total;
+    }
+
+    let sum = |status: &JobStatus, field: fn(&JobCount) -> usize| {
+        counts
+            .iter()
+            .filter(|count| &count.status == status)
+            .map(field)
+            .sum::<usize>()
+    };
+    let last_hour = sum(&JobStatus::Completed, |count| count.last_hour);
+    let last_day = sum(&JobStatus::Completed, |count| count.last_day);
+    let failed_last_day = [JobStatus::Failed, JobStatus::Dead, JobStatus::Cancelled]
+        .iter()
+        .map(|status| sum(status, |count| count.last_day))
+        .sum::<usize>();
+
+    let finished_last_day = last_day + failed_last_day;
+    Stats {
+        total: counts.iter().map(|count| count.total).sum(),
+        statuses,
+        queues: group_by(counts, |count| count.queue.as_deref().unwrap_or("default")),
+        workers: group_by(counts, |count| count.name.as_str()),
+        throughput: Throughput {
+            last_hour,
+            last_day,
+            per_minute: last_hour as f64 / 60.0,
+        },
+        failure_rate: if finished_last_day == 0 {
+            0.0
+        } else {
+            failed_last_day as f64 / finished_last_day as f64
+        },
+    }
+}
+
+fn queue(ctx: &AppContext) -> Result<&Queue> {
+    ctx.queue_provider
+        .as_deref()
+        .ok_or_else(|| Error::string("queue provider is not configured"))
+}
+
+/// The path the dashboard is mounted at, taken from the route that matched
+/// the request so links keep working under any prefix.
+fn base_path(matched: &MatchedPath, route: &str) -> String {
+    let path = matched.as_str();
+    path.strip_suffix(route)
+        .unwrap_or(path)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Returns the CSRF token of the browser, adding a new one to `jar` when it
+/// has none yet.
+fn csrf_token(jar: CookieJar, base: &str) -> (CookieJar, String) {
+    if let Some(cookie) = jar.get(CSRF_COOKIE) {
+        let token = cookie.value().to_string();
+        return (jar, token);
+    }
+    let token = hash::random_string(32);
+    let cookie = Cookie::build((CSRF_COOKIE, token.clone()))
+        .path(if base.is_empty() { "/" } else { base }.to_string())
+        .http_only(true)
+        .same_site(SameSite::Strict);
+    (jar.add(cookie), token)
+}
+
+#[derive(Debug, Deserialize)]
+struct CsrfForm {
+    #[serde(default)]
+    csrf_token: String,
+}
+
+/// Rejects form posts whose token doesn't match the CSRF cookie.
+fn verify_csrf(jar: &CookieJar, form: &CsrfForm) -> Result<()> {
+    match jar.get(CSRF_COOKIE) {
+        Some(cookie) if !form.csrf_token.is_empty() && cookie.value() == form.csrf_token => Ok(()),
+        _ => Err(Error::CustomError(
+            StatusCode::FORBIDDEN,
+            ErrorDetail::new("forbidden", "invalid CSRF token"),
+        )),
+    }
+}
+
+fn render(template: &str, context: &impl Serialize) -> Result<Response> {
+    let content = Tera::one_off(template, &Context::from_serialize(context)?, true)?;
+    format::html(&content)
+}
+
+#[derive(Debug, Deserialize)]
+struct Filter {
+    status: Option<JobStatus>,
+    name: Option<String>,
+}
+
+async fn index(
+    State(ctx): State<AppContext>,
+    jar: CookieJar,
+    matched: MatchedPath,
+    Query(filter): Query<Filter>,
+) -> Result<Response> {
+    let queue = queue(&ctx)?;
+    let stats = stats(&queue.count_jobs().await?);
+    let jobs: Vec<Job> = serde_json::from_value(
+        queue
+            .recent_jobs(
+                filter.status.as_ref(),
+                filter.name.as_deref(),
+                RECENT_JOBS_LIMIT,
+            )
+            .await?,
+    )?;
+
+    let base = base_path(&matched, "/");
+    let (jar, csrf_token) = csrf_token(jar, &base);
+    let page = render(
+        include_str!("dashboard/index.html"),
+        &serde_json::json!({
+            "base": base,
+            "csrf_token": csrf_token,
+            "per_minute": format!("{:.2}", stats.throughput.per_minute),
+            "failure_rate": format!("{:.1}%", stats.failure_rate * 100.0),
+            "groups": [
+                { "title": "Queues", "rows": stats.queues, "filter": false },
+                { "title": "Workers", "rows": stats.workers, "filter": true },
+            ],
+            "stats": stats,
+            "jobs": jobs,
+            "filter": {
+                "status": filter.status,
+                "name": filter.name,
+            },
+        }),
+    )?;
+    Ok((jar, page).into_response())
+}
+
+async fn stats_json(State(ctx): State<AppContext>) -> Result<Response> {
+    format::json(stats(&queue(&ctx)?.count_jobs().await?))
+}
+
+async fn show(
+    State(ctx): State<AppContext>,
+    jar: CookieJar,
+    matched: MatchedPath,
+    Path(id): Path<String>,
+) -> Result<Response> {
+    let Some(job) = queue(&ctx)?.get_job_data(&id).await? else {
+        return Err(Error::NotFound);
+    };
+    let job: Job = serde_json::from_value(job)?;
+
+    let base = base_path(&matched, "/jobs/{id}");
+    let (jar, csrf_token) = csrf_token(jar, &base);
+    let page = render(
+        include_str!("dashboard/job.html"),
+        &serde_json::json!({
+            "base": base,
+            "csrf_token": csrf_token,
+            "task_data": serde_json::to_string_pretty(&job.task_data)?,
+            "result": job.result.as_ref().map(serde_json::to_string_pretty).transpose()?,
+            "queue": job.queue(),
+            "can_retry": matches!(job.status, JobStatus::Failed | JobStatus::Dead),
+            "can_cancel": matches!(job.status, JobStatus::Queued | JobStatus::Processing),
+            "job": job,
+        }),
+    )?;
+    Ok((jar, page).into_response())
+}
+
+async fn retry(
+    State(ctx): State<AppContext>,
+    jar: CookieJar,
+    matched: MatchedPath,
+    Path(id): Path<String>,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response> {
+    verify_csrf(&jar, &form)?;
+    queue(&ctx)?
+        .retry_jobs(&RetryTarget::Id(id.clone()))
+        .await?;
+    format::redirect(&format!(
+        "{}/jobs/{id}",
+        base_path(&matched, "/jobs/{id}/retry")
+    ))
+}
+
+async fn cancel(
+    State(ctx): State<AppContext>,
+    jar: CookieJar,
+    matched: MatchedPath,
+    Path(id): Path<String>,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response> {
+    verify_csrf(&jar, &form)?;
+    queue(&ctx)?.cancel_job(&id).await?;
+    format::redirect(&format!(
+        "{}/jobs/{id}",
+        base_path(&matched, "/jobs/{id}/cancel")
+    ))
+}
+
+async fn retry_failed(
+    State(ctx): State<AppContext>,
+    jar: CookieJar,
+    matched: MatchedPath,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response> {
+    verify_csrf(&jar, &form)?;
+    queue(&ctx)?.retry_jobs(&RetryTarget::AllFailed).await?;
+    let base = base_path(&matched, "/retry");
+    format::redirect(if base.is_empty() { "/" } else { &base })
+}
+
+/// Defines the dashboard routes, mounted at `/_jobs`. Use
+/// [`Routes::prefix`] to mount them somewhere else.
+#[must_use]
+pub fn routes() -> Routes {
+    Routes::at("_jobs")
+        .add("/", get(index))
+        .add("/stats", get(stats_json))
+        .add("/retry", post(retry_failed))
+        .add("/jobs/{id}", get(show))
+        .add("/jobs/{id}/retry", post(retry))
+        .add("/jobs/{id}/cancel", post(cancel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(
+        queue: Option<&str>,
+        name: &str,
+        status: JobStatus,
+        total: usize,
+        last_hour: usize,
+        last_day: usize,
+    ) -> JobCount {
+        JobCount {
+            queue: queue.map(ToString::to_string),
+            name: name.to_string(),
+            status,
+            total,
+            last_hour,
+            last_day,
+        }
+    }
+
+    #[test]
+    fn can_compute_stats() {
+        let counts = vec![
+            count(None, "Mailer", JobStatus::Completed, 2, 1, 2),
+            count(None, "Mailer", JobStatus::Dead, 1, 0, 1),
+            count(Some("reports"), "Report", JobStatus::Queued, 1, 1, 1),
+            count(None, "Report", JobStatus::Failed, 1, 0, 0),
+        ];
+
+        let stats = stats(&counts);
+        assert_eq!(stats.total, 5);
+        assert_eq!(stats.statuses["completed"], 2);
+        assert_eq!(stats.statuses["processing"], 0);
+        assert_eq!(stats.throughput.last_hour, 1);
+        assert_eq!(stats.throughput.last_day, 2);
+        assert!((stats.failure_rate - 1.0 / 3.0).abs() < f64::EPSILON);
+
+        let queues = stats
+            .queues
+            .iter()
+            .map(|group| (group.name.as_str(), group.total))
+            .collect::<Vec<_>>();
+        assert_eq!(queues, vec![("default", 4), ("reports", 1)]);
+        assert_eq!(stats.workers[0].name, "Mailer");
+        assert_eq!(stats.workers[0].statuses["dead"], 1);
+        assert_eq!(stats.workers[1].statuses["queued"], 1);
+    }
+
+    #[cfg(any(feature = "bg_inmem", feature = "bg_redis"))]
+    #[test]
+    fn can_tally_jobs() {
+        let now = Utc::now();
+        let mut tally = JobTally::new(now);
+        let mut recent = RecentJobs::new(2);
+        for (name, minutes) in [
+            ("Mailer", 10),
+            ("Mailer", 120),
+            ("Mailer", 3000),
+            ("Report", 5),
+        ] {
+            let updated_at = Some(now - Duration::minutes(minutes));
+            tally.add(None, name, &JobStatus::Completed, updated_at);
+            recent.add(updated_at, (name, minutes));
+        }
+
+        let mut counts = tally.into_counts();
+        counts.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            counts,
+            vec![
+                count(None, "Mailer", JobStatus::Completed, 3, 1, 2),
+                count(None, "Report", JobStatus::Completed, 1, 1, 1),
+            ]
+        );
+        assert_eq!(recent.into_jobs(), vec![("Report", 5), ("Mailer", 10)]);
+    }
+
+    #[cfg(any(feature = "bg_inmem", feature = "bg_sqlt"))]
+    mod routes {
+        use std::sync::Arc;
+
+        use axum::{
+            body::Body,
+            http::{header, Request},
+        };
+        use tower::ServiceExt;
+
+        use super::super::*;
+        use crate::{
+            bgworker::BackgroundWorker, config::WorkerMode, controller::AppRoutes, tests_cfg,
+        };
+
+        /// A queue of each provider these tests can run without a server, with
+        /// the folder keeping its database.
+        async fn queues() -> Vec<(Arc<Queue>, tree_fs::Tree)> {
+            let mut queues = Vec::new();
+            #[cfg(feature = "bg_inmem")]
+            queues.push((
+                Arc::new(crate::bgworker::inmem::create_provider(
+                    &crate::config::InMemQueueConfig {
+                        poll_interval_sec: 1,
+                        num_workers: 1,
+                        max_finished_jobs: 100,
+                    },
+                )),
+                tree_fs::TreeBuilder::default()
+                    .drop(true)
+                    .create()
+                    .expect("create temp folder"),
+            ));
+            #[cfg(feature = "bg_sqlt")]
+            {
+                let tree_fs = tree_fs::TreeBuilder::default()
+                    .drop(true)
+                    .create()
+                    .expect("create temp folder");
+                let queue =
+                    crate::bgworker::sqlt::create_provider(&crate::config::SqliteQueueConfig {
+                        uri: format!(
+                            "sqlite://{}?mode=rwc",
+                            tree_fs.root.join("queue.sqlite").display()
+                        ),
+                        dangerously_flush: false,
+                        enable_logging: false,
+                        max_connections: 1,
+                        min_connections: 1,
+                        connect_timeout: 500,
+                        idle_timeout: 500,
+                        poll_interval_sec: 1,
+                        num_workers: 1,
+                    })
+                    .await
+                    .expect("create sqlite queue");
+                queue.setup().await.expect("set up sqlite queue");
+                queues.push((Arc::new(queue), tree_fs));
+            }
+            queues
+        }
+
+        async fn router(queue: &Arc<Queue>) -> axum::Router {
+            let mut ctx = tests_cfg::app::get_app_context().await;
+            ctx.config.workers.mode = WorkerMode::BackgroundQueue;
+            ctx.queue_provider = Some(queue.clone());
+
+            let mut router = axum::Router::new();
+            for route in AppRoutes::empty().add_route(routes()).collect() {
+                router = router.route(&route.uri, route.method);
+            }
+            router.with_state(ctx)
+        }
+
+        async fn get(router: &axum::Router, uri: &str) -> Response {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            router.clone().oneshot(req).await.unwrap()
+        }
+
+        async fn post(router: &axum::Router, uri: &str, cookie: &str, token: &str) -> Response {
+            let req = Request::builder()
+                .uri(uri)
+                .method("POST")
+                .header(header::COOKIE, cookie)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("csrf_token={token}")))
+                .unwrap();
+            router.clone().oneshot(req).await.unwrap()
+        }
+
+        async fn body(response: Response) -> String {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        }
+
+        /// Loads the overview and returns the CSRF cookie it sets, with the
+        /// token its forms post.
+        async fn csrf(router: &axum::Router) -> (String, String) {
+            let response = get(router, "/_jobs").await;
+            let cookie = response.headers()[header::SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .split(';')
+                .next()
+                .unwrap()
+                .to_string();
+            let token = cookie.split_once('=').unwrap().1.to_string();
+            assert!(body(response)
+                .await
+                .contains(&format!("name=\"csrf_token\" value=\"{token}\"")));
+            (cookie, token)
+        }
+
+        async fn wait_for_status(queue: &Queue, id: &str, status: JobStatus) {
+            for _ in 0..50 {
+                let job = queue.get_job(id).await.unwrap().unwrap();
+                if job.status == status {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            panic!("job {id} never reached the {status} status");
+        }
+
+        #[tokio::test]
+        async fn can_render_overview_and_stats() {
+            for (queue, _tree_fs) in queues().await {
+                let router = router(&queue).await;
+                queue
+                    .enqueue("<script>Worker".to_string(), None, (), None)
+                    .await
+                    .unwrap();
+
+                let response = get(&router, "/_jobs").await;
+                assert_eq!(response.status(), 200);
+                let html = body(response).await;
+                assert!(html.contains("&lt;script&gt;Worker"));
+                assert!(!html.contains("<script>Worker"));
+
+                let response = get(&router, "/_jobs?status=completed").await;
+                assert!(!body(response).await.contains("/_jobs/jobs/"));
+
+                let response = get(&router, "/_jobs/stats").await;
+                assert_eq!(response.status(), 200);
+                let stats: JsonValue = serde_json::from_str(&body(response).await).unwrap();
+                assert_eq!(stats["total"], 1);
+                assert_eq!(stats["statuses"]["queued"], 1);
+                assert_eq!(stats["workers"][0]["name"], "<script>Worker");
+            }
+        }
+
+        #[tokio::test]
+        async fn can_show_and_cancel_job() {
+            for (queue, _tree_fs) in queues().await {
+                let router = router(&queue).await;
+                let id = queue
+                    .enqueue("Worker".to_string(), None, "args", None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                let response = get(&router, &format!("/_jobs/jobs/{id}")).await;
+                assert_eq!(response.status(), 200);
+                let html = body(response).await;
+                assert!(html.contains(&id));
+                assert!(html.contains(&format!("action=\"/_jobs/jobs/{id}/cancel\"")));
+                assert!(!html.contains("Retry</button>"));
+
+                let (cookie, token) = csrf(&router).await;
+                let uri = format!("/_jobs/jobs/{id}/cancel");
+                let response = post(&router, &uri, &cookie, &token).await;
+                assert_eq!(response.status(), 303);
+                assert_eq!(
+                    response.headers()["location"],
+                    format!("/_jobs/jobs/{id}").as_str()
+                );
+                let job = queue.get_job(&id).await.unwrap().unwrap();
+                assert_eq!(job.status, JobStatus::Cancelled);
+
+                let response = get(&router, "/_jobs/jobs/missing").await;
+                assert_eq!(response.status(), 404);
+            }
+        }
+
+        #[tokio::test]
+        async fn cannot_change_jobs_without_csrf_token() {
+            for (queue, _tree_fs) in queues().await {
+                let router = router(&queue).await;
+                let id = queue
+                    .enqueue("Worker".to_string(), None, (), None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let uri = format!("/_jobs/jobs/{id}/cancel");
+
+                let (cookie, token) = csrf(&router).await;
+                for (cookie, token) in [
+                    ("", token.as_str()),
+                    (cookie.as_str(), ""),
+                    (cookie.as_str(), "forged"),
+                ] {
+                    let response = post(&router, &uri, cookie, token).await;
+                    assert_eq!(response.status(), 403);
+                }
+                let response = post(&router, "/_jobs/retry", "", "").await;
+                assert_eq!(response.status(), 403);
+
+                let job = queue.get_job(&id).await.unwrap().unwrap();
+                assert_eq!(job.status, JobStatus::Queued);
+            }
+        }
+
+        #[tokio::test]
+        async fn can_retry_jobs() {
+            struct FailingWorker;
+            #[async_trait::async_trait]
+            impl BackgroundWorker<()> for FailingWorker {
+                fn build(_ctx: &AppContext) -> Self {
+                    Self
+                }
+                async fn perform(&self, _args: ()) -> Result<()> {
+                    Err(Error::string("boom"))
+                }
+            }
+
+            for (queue, _tree_fs) in queues().await {
+                let router = router(&queue).await;
+                queue.register(FailingWorker).await.unwrap();
+                let first = queue
+                    .enqueue("FailingWorker".to_string(), None, (), None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let second = queue
+                    .enqueue("FailingWorker".to_string(), None, (), None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                let runner = tokio::spawn({
+                    let queue = queue.clone();
+                    async move { queue.run(vec![]).await }
+                });
+                wait_for_status(&queue, &first, JobStatus::Failed).await;
+                wait_for_status(&queue, &second, JobStatus::Failed).await;
+                queue.shutdown().unwrap();
+                runner.await.unwrap().unwrap();
+
+                let response = get(&router, &format!("/_jobs/jobs/{first}")).await;
+                assert!(body(response).await.contains("boom"));
+
+                let (cookie, token) = csrf(&router).await;
+                let uri = format!("/_jobs/jobs/{first}/retry");
+                let response = post(&router, &uri, &cookie, &token).await;
+                assert_eq!(response.status(), 303);
+                let job = queue.get_job(&first).await.unwrap().unwrap();
+                assert_eq!(job.status, JobStatus::Queued);
+                let job = queue.get_job(&second).await.unwrap().unwrap();
+                assert_eq!(job.status, JobStatus::Failed);
+
+                let response = post(&router, "/_jobs/retry", &cookie, &token).await;
+                assert_eq!(response.status(), 303);
+                assert_eq!(response.headers()["location"], "/_jobs");
+                let job = queue.get_job(&second).await.unwrap().unwrap();
+                assert_eq!(job.status, JobStatus::Queued);
+            }
+        }
+    }
+}
@@ -0,0 +1,2121 @@
+/// In-memory background job queue provider
+///
+/// Jobs are kept in the memory of the running process: they are lost on
+/// restart and only workers of the same process see them. Useful for tests
+/// and for applications deployed as a single binary.
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+#[cfg(feature = "bg_dashboard")]
+use super::dashboard::{JobCount, JobTally, RecentJobs};
+use super::{
+    error_details, keep_alive, perform_job, run_job, BackgroundWorker, Batch, BatchInfo, BatchJob,
+    EnqueueOpts, JobInfo, JobOutcome, JobStatus, Queue, RetryTarget, WorkerInfo, WorkerOpts,
+    HEARTBEAT_TTL,
+};
+use crate::{config::InMemQueueConfig, Error, Result};
+use chrono::{DateTime, Utc};
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tokio::{
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, trace};
+use ulid::Generator;
+type JobId = String;
+type JobData = JsonValue;
+
+/// How many completed and cancelled jobs a [`JobStore`] keeps by default.
+pub const DEFAULT_MAX_FINISHED_JOBS: usize = 1000;
+
+type JobHandler = Box<
+    dyn Fn(
+            JobId,
+            JobData,
+            CancellationToken,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<Option<JsonValue>>> + Send>>
+        + Send
+        + Sync,
+>;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Job {
+    pub id: JobId,
+    pub name: String,
+    #[serde(rename = "task_data")]
+    pub data: JobData,
+    pub status: JobStatus,
+    pub run_at: DateTime<Utc>,
+    pub interval: Option<i64>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub attempts: i32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub backtrace: Option<String>,
+    #[serde(default)]
+    pub priority: i16,
+    #[serde(default)]
+    pub unique_key: Option<String>,
+    #[serde(default)]
+    pub result: Option<JsonValue>,
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    /// Worker process that last picked the job up.
+    #[serde(default)]
+    pub worker_id: Option<String>,
+}
+
+impl From<Job> for JobInfo {
+    fn from(job: Job) -> Self {
+        Self {
+            id: job.id,
+            name: job.name,
+            status: job.status,
+            run_at: job.run_at,
+            attempts: job.attempts,
+            error: job.last_error,
+            result: job.result,
+            batch_id: job.batch_id,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+        }
+    }
+}
+
+struct BatchState {
+    on_complete: Option<BatchJob>,
+    on_failure: Option<BatchJob>,
+    finished_at: Option<DateTime<Utc>>,
+}
+
+/// Indexes of [`State::jobs`] by status, so workers find the next job to run
+/// without going through the whole history.
+#[derive(Default)]
+struct Queues {
+    /// Queued jobs that are due, highest priority first, then oldest first.
+    ready: BTreeSet<(Reverse<i16>, DateTime<Utc>, JobId)>,
+    /// Queued jobs that are not due yet, by `run_at`.
+    scheduled: BTreeSet<(DateTime<Utc>, JobId)>,
+    /// Number of running jobs of each worker.
+    running: HashMap<String, u32>,
+    /// Completed and cancelled jobs, oldest first.
+    finished: BTreeSet<JobId>,
+}
+
+impl Queues {
+    fn add(&mut self, job: &Job, now: DateTime<Utc>) {
+        match job.status {
+            JobStatus::Queued if job.run_at <= now => {
+                self.ready
+                    .insert((Reverse(job.priority), job.run_at, job.id.clone()));
+            }
+            JobStatus::Queued => {
+                self.scheduled.insert((job.run_at, job.id.clone()));
+            }
+            JobStatus::Processing => {
+                *self.running.entry(job.name.clone()).or_default() += 1;
+            }
+            JobStatus::Completed | JobStatus::Cancelled => {
+                self.finished.insert(job.id.clone());
+            }
+            JobStatus::Failed | JobStatus::Dead => {}
+        }
+    }
+
+    fn remove(&mut self, job: &Job) {
+        match job.status {
+            JobStatus::Queued => {
+                if !self
+                    .ready
+                    .remove(&(Reverse(job.priority), job.run_at, job.id.clone()))
+                {
+                    self.scheduled.remove(&(job.run_at, job.id.clone()));
+                }
+            }
+            JobStatus::Processing => {
+                if let Some(running) = self.running.get_mut(&job.name) {
+                    *running = running.saturating_sub(1);
+                    if *running == 0 {
+                        self.running.remove(&job.name);
+                    }
+                }
+            }
+            JobStatus::Completed | JobStatus::Cancelled => {
+                self.finished.remove(&job.id);
+            }
+            JobStatus::Failed | JobStatus::Dead => {}
+        }
+    }
+
+    /// Moves the scheduled jobs whose `run_at` has come to the ready queue.
+    fn promote_due(&mut self, jobs: &BTreeMap<JobId, Job>, now: DateTime<Utc>) {
+        while self
+            .scheduled
+            .first()
+            .is_some_and(|(run_at, _)| *run_at <= now)
+        {
+            let Some((run_at, id)) = self.scheduled.pop_first() else {
+                break;
+            };
+            let priority = jobs.get(&id).map_or(0, |job| job.priority);
+            self.ready.insert((Reverse(priority), run_at, id));
+        }
+    }
+}
+
+struct State {
+    /// Job ids are monotonic ULIDs, so jobs are kept in the order they were
+    /// enqueued.
+    jobs: BTreeMap<JobId, Job>,
+    queues: Queues,
+    /// How many completed and cancelled jobs are kept before the oldest ones
+    /// are dropped.
+    max_finished_jobs: usize,
+    ids: Generator,
+    batches: HashMap<String, BatchState>,
+    /// Start and number of jobs started in the current rate limit window of
+    /// each rate limited worker.
+    rate_windows: HashMap<String, (DateTime<Utc>, u32)>,
+    /// Worker processes by id, as of their last heartbeat.
+    workers: HashMap<String, WorkerInfo>,
+    /// Owner and expiry of each lock taken with [`try_lock`].
+    locks: HashMap<String, (String, DateTime<Utc>)>,
+}
+
+impl State {
+    fn new(max_finished_jobs: usize) -> Self {
+        Self {
+            jobs: BTreeMap::new(),
+            queues: Queues::default(),
+            max_finished_jobs,
+            ids: Generator::new(),
+            batches: HashMap::new(),
+            rate_windows: HashMap::new(),
+            workers: HashMap::new(),
+            locks: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, job: Job) {
+        self.queues.add(&job, Utc::now());
+        self.jobs.insert(job.id.clone(), job);
+    }
+
+    /// Applies `update` to the job `id` when its status is one of `from`,
+    /// keeping the queues in step. Returns whether the job was updated.
+    fn update(&mut self, id: &str, from: &[JobStatus], update: impl FnOnce(&mut Job)) -> bool {
+        let now = Utc::now();
+        let Some(job) = self
+            .jobs
+            .get_mut(id)
+            .filter(|job| from.contains(&job.status))
+        else {
+            return false;
+        };
+        self.queues.remove(job);
+        job.updated_at = Some(now);
+        update(job);
+        self.queues.add(job, now);
+        self.prune_finished();
+        true
+    }
+
+    fn remove(&mut self, id: &str) -> Option<Job> {
+        let job = self.jobs.remove(id)?;
+        self.queues.remove(&job);
+        Some(job)
+    }
+
+    /// Ids of the jobs for which `filter` returns `true`.
+    fn ids_where(&self, mut filter: impl FnMut(&Job) -> bool) -> Vec<JobId> {
+        self.jobs
+            .values()
+            .filter(|job| filter(job))
+            .map(|job| job.id.clone())
+            .collect()
+    }
+
+    /// Removes the jobs for which `filter` returns `true`.
+    fn remove_where(&mut self, filter: impl FnMut(&Job) -> bool) {
+        for id in self.ids_where(filter) {
+            self.remove(&id);
+        }
+    }
+
+    /// Drops the oldest completed and cancelled jobs past `max_finished_jobs`.
+    /// Jobs of batches that are still running are kept, since the progress of
+    /// the batch is worked out from them.
+    fn prune_finished(&mut self) {
+        let excess = self
+            .queues
+            .finished
+            .len()
+            .saturating_sub(self.max_finished_jobs);
+        if excess == 0 {
+            return;
+        }
+        let pruned: Vec<JobId> = self
+            .queues
+            .finished
+            .iter()
+            .filter(|id| {
+                self.jobs
+                    .get(*id)
+                    .and_then(|job| job.batch_id.as_ref())
+                    .and_then(|batch_id| self.batches.get(batch_id))
+                    .map_or(true, |batch| batch.finished_at.is_some())
+            })
+            .take(excess)
+            .cloned()
+            .collect();
+        trace!(count = pruned.len(), "Dropping oldest finished jobs");
+        for id in pruned {
+            self.remove(&id);
+        }
+    }
+}
+
+/// Jobs of an in-memory queue. Clones share the same jobs.
+#[derive(Clone)]
+pub struct JobStore {
+    state: Arc<Mutex<State>>,
+    /// Wakes idle workers up when a job is enqueued.
+    notify: Arc<Notify>,
+}
+
+impl JobStore {
+    /// Creates an empty `JobStore` that keeps up to
+    /// [`DEFAULT_MAX_FINISHED_JOBS`] completed and cancelled jobs.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_max_finished_jobs(DEFAULT_MAX_FINISHED_JOBS)
+    }
+
+    /// Creates an empty `JobStore` that keeps up to `max_finished_jobs`
+    /// completed and cancelled jobs, dropping the oldest ones past that.
+    #[must_use]
+    pub fn with_max_finished_jobs(max_finished_jobs: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::new(max_finished_jobs))),
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // jobs are only changed by short, non-panicking sections, so the state
+        // is still consistent after a panic elsewhere
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for JobStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct JobRegistry {
+    handlers: Arc<HashMap<String, JobHandler>>,
+    worker_opts: Arc<HashMap<String, WorkerOpts>>,
+}
+
+impl JobRegistry {
+    /// Creates a new `JobRegistry`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            handlers: Arc::new(HashMap::new()),
+            worker_opts: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Registers a job handler with the provided name.
+    /// # Errors
+    /// Fails if cannot register worker
+    pub fn register_worker<Args, W>(&mut self, name: String, worker: W) -> Result<()>
+    where
+        Args: Send + Serialize + Sync + 'static,
+        W: BackgroundWorker<Args> + 'static,
+        for<'de> Args: Deserialize<'de>,
+    {
+        let worker = Arc::new(worker);
+        let wrapped_handler = move |_job_id: String,
+                                    job_data: JobData,
+                                    token: CancellationToken| {
+            let w = worker.clone();
+
+            Box::pin(async move {
+                let args = serde_json::from_value::<Args>(job_data);
+                match args {
+                    Ok(args) => {
+                        // Wrap the perform call in catch_unwind to handle panics
+                        match AssertUnwindSafe(perform_job(w.perform_with_token(args, token)))
+                            .catch_unwind()
+                            .await
+                        {
+                            Ok(result) => result,
+                            Err(panic) => {
+                                let panic_msg = panic
+                                    .downcast_ref::<String>()
+                                    .map(String::as_str)
+                                    .or_else(|| panic.downcast_ref::<&str>().copied())
+                                    .unwrap_or("Unknown panic occurred");
+                                error!(err = panic_msg, "worker panicked");
+                                Err(Error::string(panic_msg))
+                            }
+                        }
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }) as Pin<Box<dyn Future<Output = Result<Option<JsonValue>>> + Send>>
+        };
+
+        Arc::get_mut(&mut self.worker_opts)
+            .ok_or_else(|| Error::string("cannot register worker"))?
+            .insert(name.clone(), WorkerOpts::from_worker::<Args, W>());
+        Arc::get_mut(&mut self.handlers)
+            .ok_or_else(|| Error::string("cannot register worker"))?
+            .insert(name, Box::new(wrapped_handler));
+        Ok(())
+    }
+
+    /// Returns a reference to the job handlers.
+    #[must_use]
+    pub fn handlers(&self) -> &Arc<HashMap<String, JobHandler>> {
+        &self.handlers
+    }
+
+    /// Runs the job handlers with the provided number of workers.
+    #[must_use]
+    pub fn run(
+        &self,
+        store: &JobStore,
+        opts: &RunOpts,
+        token: &CancellationToken,
+        tags: &[String],
+    ) -> Vec<JoinHandle<()>> {
+        let mut jobs = Vec::new();
+
+        let interval = opts.poll_interval_sec;
+        let shutdown_timeout = opts.shutdown_timeout;
+        let worker = WorkerInfo::current(tags);
+        let (alive, alive_rx) = mpsc::channel(1);
+        if opts.num_workers > 0 {
+            jobs.push(tokio::spawn(heartbeat(
+                store.clone(),
+                worker.clone(),
+                alive_rx,
+            )));
+        }
+        for idx in 0..opts.num_workers {
+            let handlers = self.handlers.clone();
+            let worker_opts = self.worker_opts.clone();
+            let worker_token = token.clone();
+            let worker_tags = tags.to_vec();
+            let worker_id = worker.id.clone();
+            let alive = alive.clone();
+
+            let store = store.clone();
+            let job = tokio::spawn(async move {
+                // keeps the heartbeat going until this worker is done
+                let _alive = alive;
+                loop {
+                    if worker_token.is_cancelled() {
+                        trace!(worker_id = idx, "Cancellation received, stopping worker");
+                        break;
+                    }
+                    // created before looking for a job, so a job enqueued in
+                    // between still wakes this worker up
+                    let enqueued = store.notify.notified();
+
+                    if let Some(job) = dequeue(&store, &worker_tags, &worker_opts, &worker_id) {
+                        debug!(job_id = %job.id, job_name = %job.name, "Processing job");
+                        if let Some(handler) = handlers.get(&job.name) {
+                            let opts = worker_opts.get(&job.name).copied().unwrap_or_default();
+                            let token = CancellationToken::new();
+                            let outcome = run_job(
+                                handler(job.id.clone(), job.data.clone(), token.clone()),
+                                &token,
+                                opts.timeout,
+                                Duration::from_secs(interval.into()),
+                                || std::future::ready(Ok(is_job_cancelled(&store, &job.id))),
+                                &worker_token,
+                                shutdown_timeout,
+                            )
+                            .await;
+                            let finished = !matches!(outcome, JobOutcome::Interrupted);
+                            match outcome {
+                                JobOutcome::Completed(result) => {
+                                    complete_job(&store, &job.id, job.interval, result);
+                                    debug!(job_id = %job.id, "Job completed successfully");
+                                }
+                                JobOutcome::Failed(err) => {
+                                    if let Some(run_at) = opts.retry_at(job.attempts + 1) {
+                                        retry_job(&store, &job.id, &err, run_at);
+                                        debug!(job_id = %job.id, error = %err, run_at = %run_at, "Job execution failed, retry scheduled");
+                                    } else if opts.max_retries > 0 {
+                                        bury_job(&store, &job.id, &err);
+                                        debug!(job_id = %job.id, error = %err, "Job ran out of retries, moved to the dead-letter queue");
+                                    } else {
+                                        fail_job(&store, &job.id, &err);
+                                        debug!(job_id = %job.id, error = %err, "Job execution failed");
+                                    }
+                                }
+                                JobOutcome::Cancelled => {
+                                    debug!(job_id = %job.id, "Job was cancelled while running");
+                                }
+                                JobOutcome::Interrupted => requeue_job(&store, &job.id),
+                            }
+                            if let Some(batch_id) = job.batch_id.as_ref().filter(|_| finished) {
+                                if let Err(err) = finish_batch_job(&store, batch_id) {
+                                    error!(
+                                        error = %err,
+                                        batch_id = %batch_id,
+                                        "Failed to update batch progress"
+                                    );
+                                }
+                            }
+                        } else {
+                            error!(job_name = %job.name, "No handler registered for job");
+                        }
+                    } else {
+                        tokio::select! {
+                            biased;
+                            () = worker_token.cancelled() => {
+                                trace!(worker_id = idx, "Cancellation received during sleep, stopping worker");
+                                break;
+                            }
+                            () = enqueued => {}
+                            () = sleep(Duration::from_secs(interval.into())) => {
+                                // Interval elapsed, look for scheduled jobs that are due
+                            }
+                        }
+                    }
+                }
+            });
+
+            jobs.push(job);
+        }
+
+        jobs
+    }
+}
+
+impl Default for JobRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records the heartbeat of `worker` until its worker tasks are done, then
+/// removes it from the store.
+async fn heartbeat(store: JobStore, worker: WorkerInfo, alive: mpsc::Receiver<()>) {
+    keep_alive(
+        alive,
+        || {
+            record_heartbeat(&store, &worker);
+            std::future::ready(Ok(()))
+        },
+        || std::future::ready(Ok(requeue_orphaned_jobs(&store, HEARTBEAT_TTL))),
+    )
+    .await;
+    store.state().workers.remove(&worker.id);
+}
+
+fn record_heartbeat(store: &JobStore, worker: &WorkerInfo) {
+    trace!(worker_id = %worker.id, "Recording worker heartbeat");
+    store
+        .state()
+        .workers
+        .entry(worker.id.clone())
+        .or_insert_with(|| worker.clone())
+        .heartbeat_at = Utc::now();
+}
+
+/// Add a job
+///
+/// When `opts.unique_key` is set and a job of the same name with that key is
+/// still queued or processing, nothing is added and the id of the existing
+/// job is returned.
+///
+/// # Errors
+///
+/// Returns an error if `opts.unique_for` is too long to be represented as a
+/// date, or if no more job ids can be generated in the current millisecond.
+pub fn enqueue(
+    store: &JobStore,
+    name: &str,
+    data: JobData,
+    tags: Option<Vec<String>>,
+    opts: &EnqueueOpts,
+) -> Result<JobId> {
+    let id = insert_job(&mut store.state(), name, data, tags, opts, None)?;
+    store.notify.notify_waiters();
+    Ok(id)
+}
+
+fn insert_job(
+    state: &mut State,
+    name: &str,
+    data: JobData,
+    tags: Option<Vec<String>>,
+    opts: &EnqueueOpts,
+    batch_id: Option<&str>,
+) -> Result<JobId> {
+    let now = Utc::now();
+
+    if let Some(unique_key) = &opts.unique_key {
+        let unique_since = opts
+            .unique_for
+            .map(|unique_for| chrono::Duration::from_std(unique_for).map(|window| now - window))
+            .transpose()
+            .map_err(|err| Error::Message(err.to_string()))?;
+        let existing = state.jobs.values().find(|job| {
+            job.name == name
+                && job.unique_key.as_ref() == Some(unique_key)
+                && matches!(job.status, JobStatus::Queued | JobStatus::Processing)
+                && unique_since.map_or(true, |since| {
+                    job.created_at.is_some_and(|created_at| created_at > since)
+                })
+        });
+        if let Some(existing) = existing {
+            debug!(job_id = %existing.id, job_name = %name, "Job with the same unique key is already enqueued");
+            return Ok(existing.id.clone());
+        }
+    }
+
+    let id = state
+        .ids
+        .generate()
+        .map_err(|err| Error::Message(err.to_string()))?
+        .to_string();
+    #[allow(clippy::cast_possible_truncation)]
+    let interval_ms: Option<i64> = opts.interval.map(|i| i.as_millis() as i64);
+    let job = Job {
+        id: id.clone(),
+        name: name.to_string(),
+        data,
+        status: JobStatus::Queued,
+        run_at: opts.run_at.unwrap_or(now),
+        interval: interval_ms,
+        created_at: Some(now),
+        updated_at: Some(now),
+        tags,
+        attempts: 0,
+        last_error: None,
+        backtrace: None,
+        priority: opts.priority.unwrap_or_default(),
+        unique_key: opts.unique_key.clone(),
+        result: None,
+        batch_id: batch_id.map(ToString::to_string),
+        worker_id: None,
+    };
+    debug!(job_id = %id, job_name = %name, run_at = %job.run_at, tags = ?job.tags, priority = job.priority, unique_key = ?job.unique_key, batch_id, "Enqueueing job");
+    state.insert(job);
+    Ok(id)
+}
+
+/// Adds the jobs of `batch`. They are all added at once, so workers can't
+/// finish the batch before its last job is in the queue.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub fn enqueue_batch(store: &JobStore, batch: &Batch) -> Result<()> {
+    debug!(batch_id = %batch.id, jobs = batch.jobs.len(), "Enqueueing batch");
+    {
+        let mut state = store.state();
+        state.batches.insert(
+            batch.id.clone(),
+            BatchState {
+                on_complete: batch.on_complete.clone(),
+                on_failure: batch.on_failure.clone(),
+                finished_at: None,
+            },
+        );
+        for job in &batch.jobs {
+            insert_job(
+                &mut state,
+                &job.name,
+                job.args.clone(),
+                job.tags.clone(),
+                &job.opts(),
+                Some(&batch.id),
+            )?;
+        }
+    }
+    store.notify.notify_waiters();
+
+    if batch.jobs.is_empty() {
+        finish_batch_job(store, &batch.id)?;
+    }
+    Ok(())
+}
+
+/// Fetches the progress of a batch from the status of its jobs.
+#[must_use]
+pub fn get_batch(store: &JobStore, id: &str) -> Option<BatchInfo> {
+    let state = store.state();
+    let batch = state.batches.get(id)?;
+    Some(batch_info(&state, id, batch.finished_at))
+}
+
+fn batch_info(state: &State, id: &str, finished_at: Option<DateTime<Utc>>) -> BatchInfo {
+    let mut info = BatchInfo {
+        id: id.to_string(),
+        total: 0,
+        pending: 0,
+        completed: 0,
+        failed: 0,
+        finished_at,
+    };
+    for job in state
+        .jobs
+        .values()
+        .filter(|job| job.batch_id.as_deref() == Some(id))
+    {
+        info.total += 1;
+        match job.status {
+            JobStatus::Queued | JobStatus::Processing => info.pending += 1,
+            JobStatus::Completed => info.completed += 1,
+            JobStatus::Failed | JobStatus::Cancelled | JobStatus::Dead => info.failed += 1,
+        }
+    }
+    info
+}
+
+/// Called once a job of the batch `batch_id` reached a final status. When
+/// none of its jobs is left to run, marks the batch as finished and enqueues
+/// the matching callback.
+fn finish_batch_job(store: &JobStore, batch_id: &str) -> Result<()> {
+    {
+        let mut state = store.state();
+        let Some(finished_at) = state.batches.get(batch_id).map(|batch| batch.finished_at) else {
+            return Ok(());
+        };
+        let info = batch_info(&state, batch_id, finished_at);
+        if info.pending > 0 || info.finished_at.is_some() {
+            return Ok(());
+        }
+
+        let Some(batch) = state.batches.get_mut(batch_id) else {
+            return Ok(());
+        };
+        batch.finished_at = Some(Utc::now());
+        let callback = if info.failed == 0 {
+            batch.on_complete.clone()
+        } else {
+            batch.on_failure.clone()
+        };
+        debug!(
+            batch_id,
+            completed = info.completed,
+            failed = info.failed,
+            "Batch finished"
+        );
+        if let Some(callback) = callback {
+            insert_job(
+                &mut state,
+                &callback.name,
+                callback.args.clone(),
+                callback.tags.clone(),
+                &callback.opts(),
+                None,
+            )?;
+        }
+    }
+    store.notify.notify_waiters();
+    Ok(())
+}
+
+/// Picks the due job with the highest priority, oldest first, that matches
+/// the worker tags and marks it as [`JobStatus::Processing`]. Jobs of workers
+/// that reached their [`WorkerOpts::concurrency_limit`] or
+/// [`WorkerOpts::rate_limit`] are skipped.
+fn dequeue(
+    store: &JobStore,
+    worker_tags: &[String],
+    worker_opts: &HashMap<String, WorkerOpts>,
+    worker_id: &str,
+) -> Option<Job> {
+    let now = Utc::now();
+    let mut state = store.state();
+    let State {
+        jobs,
+        queues,
+        rate_windows,
+        ..
+    } = &mut *state;
+    queues.promote_due(jobs, now);
+
+    // Workers whose jobs can't start right now
+    let mut limited: Vec<&str> = Vec::new();
+    let mut picked = None;
+    for job in queues.ready.iter().filter_map(|(_, _, id)| jobs.get(id)) {
+        if limited.contains(&job.name.as_str())
+            || !matches_tags(job.tags.as_deref().unwrap_or_default(), worker_tags)
+        {
+            continue;
+        }
+        if let Some(opts) = worker_opts.get(&job.name).filter(|opts| opts.is_limited()) {
+            if !acquire_limits(&queues.running, rate_windows, &job.name, opts, now) {
+                trace!(job_id = %job.id, job_name = %job.name, "Worker limit reached, skipping job");
+                limited.push(&job.name);
+                continue;
+            }
+        }
+        picked = Some(job.id.clone());
+        break;
+    }
+
+    let id = picked?;
+    state.update(&id, &[JobStatus::Queued], |job| {
+        job.status = JobStatus::Processing;
+        job.worker_id = Some(worker_id.to_string());
+    });
+    let job = state.jobs.get(&id)?;
+    trace!(job_id = %job.id, job_name = %job.name, job_tags = ?job.tags, "Dequeueing job for processing");
+    Some(job.clone())
+}
+
+/// Checks whether a job of the worker `name` may start under the limits in
+/// `opts`, counting it against the rate limit when it may.
+fn acquire_limits(
+    running: &HashMap<String, u32>,
+    rate_windows: &mut HashMap<String, (DateTime<Utc>, u32)>,
+    name: &str,
+    opts: &WorkerOpts,
+    now: DateTime<Utc>,
+) -> bool {
+    if let Some(limit) = opts.concurrency_limit {
+        if running.get(name).copied().unwrap_or_default() >= limit {
+            return false;
+        }
+    }
+
+    if let Some(rate_limit) = opts.rate_limit {
+        let (window_start, count) = rate_windows.entry(name.to_string()).or_insert((now, 0));
+        if (now - *window_start)
+            .to_std()
+            .is_ok_and(|elapsed| elapsed >= rate_limit.per)
+        {
+            *window_start = now;
+            *count = 0;
+        }
+        if *count >= rate_limit.max {
+            return false;
+        }
+        *count += 1;
+    }
+
+    true
+}
+
+/// Workers without tags only process jobs without tags, workers with tags
+/// process jobs sharing at least one of them.
+fn matches_tags(job_tags: &[String], worker_tags: &[String]) -> bool {
+    if worker_tags.is_empty() {
+        job_tags.is_empty()
+    } else {
+        worker_tags.iter().any(|tag| job_tags.contains(tag))
+    }
+}
+
+/// Updates the job `id` if it is still running.
+fn update_job(store: &JobStore, id: &str, update: impl FnOnce(&mut Job)) {
+    store.state().update(id, &[JobStatus::Processing], update);
+}
+
+fn complete_job(store: &JobStore, id: &JobId, interval_ms: Option<i64>, result: Option<JsonValue>) {
+    trace!(job_id = %id, "Marking job as completed");
+    update_job(store, id, |job| {
+        job.result = result;
+        if let Some(interval_ms) = interval_ms {
+            job.status = JobStatus::Queued;
+            job.run_at = Utc::now() + chrono::Duration::milliseconds(interval_ms);
+        } else {
+            job.status = JobStatus::Completed;
+        }
+    });
+}
+
+/// Puts a job that was still running when its worker shut down back in the
+/// queue. It doesn't count as an attempt, since the job didn't fail.
+fn requeue_job(store: &JobStore, id: &JobId) {
+    debug!(job_id = %id, "Requeueing interrupted job");
+    update_job(store, id, |job| job.status = JobStatus::Queued);
+    store.notify.notify_waiters();
+}
+
+/// Whether the job was cancelled since a worker picked it up.
+fn is_job_cancelled(store: &JobStore, id: &JobId) -> bool {
+    store
+        .state()
+        .jobs
+        .get(id)
+        .is_some_and(|job| job.status == JobStatus::Cancelled)
+}
+
+fn fail_job(store: &JobStore, id: &JobId, error: &crate::Error) {
+    let (msg, backtrace) = error_details(error);
+    debug!(job_id = %id, error = %msg, "Marking job as failed");
+    update_job(store, id, |job| {
+        job.status = JobStatus::Failed;
+        job.attempts += 1;
+        job.last_error = Some(msg);
+        job.backtrace = backtrace;
+    });
+}
+
+fn retry_job(store: &JobStore, id: &JobId, error: &crate::Error, run_at: DateTime<Utc>) {
+    let (msg, backtrace) = error_details(error);
+    debug!(job_id = %id, error = %msg, run_at = %run_at, "Rescheduling failed job");
+    update_job(store, id, |job| {
+        job.status = JobStatus::Queued;
+        job.run_at = run_at;
+        job.attempts += 1;
+        job.last_error = Some(msg);
+        job.backtrace = backtrace;
+    });
+}
+
+/// Moves a job that ran out of retries to [`JobStatus::Dead`], leaving its
+/// arguments untouched so it can be replayed later.
+fn bury_job(store: &JobStore, id: &JobId, error: &crate::Error) {
+    let (msg, backtrace) = error_details(error);
+    debug!(job_id = %id, error = %msg, "Moving job to the dead-letter queue");
+    update_job(store, id, |job| {
+        job.status = JobStatus::Dead;
+        job.attempts += 1;
+        job.last_error = Some(msg);
+        job.backtrace = backtrace;
+    });
+}
+
+/// Moves [`JobStatus::Failed`] and [`JobStatus::Dead`] jobs matching `target`
+/// back to [`JobStatus::Queued`].
+///
+/// Jobs run right away with their attempt counter reset. A job with a unique
+/// key keeps it, and is left alone while another queued or running job of the
+/// same name holds that key. When several of the jobs share a key, the one
+/// that failed last is retried.
+pub fn retry_jobs(store: &JobStore, target: &RetryTarget) {
+    debug!(target = ?target, "Retrying failed jobs");
+    let now = Utc::now();
+    {
+        let mut state = store.state();
+        let mut ids = state.ids_where(|job| {
+            matches!(job.status, JobStatus::Failed | JobStatus::Dead)
+                && match target {
+                    RetryTarget::Id(id) => &job.id == id,
+                    RetryTarget::Name(name) => &job.name == name,
+                    RetryTarget::AllFailed => true,
+                }
+        });
+        ids.sort_by_key(|id| Reverse(state.jobs.get(id).and_then(|job| job.updated_at)));
+        for id in ids {
+            let held = state.jobs.get(&id).is_some_and(|job| {
+                job.unique_key.as_ref().is_some_and(|unique_key| {
+                    state.jobs.values().any(|other| {
+                        other.name == job.name
+                            && other.unique_key.as_ref() == Some(unique_key)
+                            && matches!(other.status, JobStatus::Queued | JobStatus::Processing)
+                    })
+                })
+            });
+            if held {
+                debug!(job_id = %id, "Unique key is held by another job, not retrying");
+                continue;
+            }
+            state.update(&id, &[JobStatus::Failed, JobStatus::Dead], |job| {
+                job.status = JobStatus::Queued;
+                job.run_at = now;
+                job.attempts = 0;
+            });
+        }
+    }
+    store.notify.notify_waiters();
+}
+
+/// Cancels jobs by their name.
+///
+/// This function updates the status of all jobs with the given `name` and a status of
+/// [`JobStatus::Queued`] or [`JobStatus::Processing`] to [`JobStatus::Cancelled`]. Workers notice that a
+/// job they are running was cancelled on their next poll and cancel the token
+/// it got in [`super::BackgroundWorker::perform_with_token`]. Batches whose last remaining jobs were
+/// cancelled are finished, enqueuing their `on_failure` job.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub fn cancel_jobs_by_name(store: &JobStore, name: &str) -> Result<()> {
+    debug!(job_name = %name, "Cancelling queued and running jobs by name");
+    let mut batch_ids = Vec::new();
+    {
+        let mut state = store.state();
+        let ids = state.ids_where(|job| {
+            job.name == name && matches!(job.status, JobStatus::Queued | JobStatus::Processing)
+        });
+        for id in ids {
+            state.update(&id, &[JobStatus::Queued, JobStatus::Processing], |job| {
+                job.status = JobStatus::Cancelled;
+                batch_ids.extend(job.batch_id.clone());
+            });
+        }
+    }
+
+    batch_ids.sort_unstable();
+    batch_ids.dedup();
+    for batch_id in batch_ids {
+        finish_batch_job(store, &batch_id)?;
+    }
+    Ok(())
+}
+
+/// Cancels a single job by id, the same way [`cancel_jobs_by_name`] does.
+///
+/// Returns `false` when no queued or running job has this id.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub fn cancel_job(store: &JobStore, id: &str) -> Result<bool> {
+    debug!(job_id = %id, "Cancelling job");
+    let mut batch_id = None;
+    let cancelled = store
+        .state()
+        .update(id, &[JobStatus::Queued, JobStatus::Processing], |job| {
+            job.status = JobStatus::Cancelled;
+            batch_id.clone_from(&job.batch_id);
+        });
+    if !cancelled {
+        return Ok(false);
+    }
+
+    if let Some(batch_id) = batch_id {
+        finish_batch_job(store, &batch_id)?;
+    }
+    Ok(true)
+}
+
+/// Clear all jobs
+pub fn clear(store: &JobStore) {
+    let mut state = store.state();
+    state.jobs.clear();
+    state.queues = Queues::default();
+    state.batches.clear();
+    state.rate_windows.clear();
+}
+
+/// Deletes jobs whose status is one of `status`.
+pub fn clear_by_status(store: &JobStore, status: &[JobStatus]) {
+    debug!(status = ?status, "Clearing jobs by status");
+    store
+        .state()
+        .remove_where(|job| status.contains(&job.status));
+}
+
+/// Deletes jobs created more than `age_days` days ago. If `status` is given
+/// and not empty, only jobs with one of these statuses are deleted.
+pub fn clear_jobs_older_than(store: &JobStore, age_days: i64, status: Option<&Vec<JobStatus>>) {
+    debug!(age_days = age_days, status = ?status, "Clearing older jobs");
+    let cutoff = Utc::now() - chrono::Duration::days(age_days);
+    store.state().remove_where(|job| {
+        let expired = job.created_at.is_some_and(|created_at| created_at < cutoff);
+        let matches_status = status.map_or(true, |status| {
+            status.is_empty() || status.contains(&job.status)
+        });
+        expired && matches_status
+    });
+}
+
+/// Puts the jobs that workers whose last heartbeat is older than `ttl` were
+/// running back in the queue, and forgets about those workers. Returns how
+/// many jobs were requeued.
+pub fn requeue_orphaned_jobs(store: &JobStore, ttl: Duration) -> u64 {
+    let cutoff = Utc::now() - ttl;
+    let mut state = store.state();
+    let expired: Vec<String> = state
+        .workers
+        .values()
+        .filter(|worker| worker.heartbeat_at <= cutoff)
+        .map(|worker| worker.id.clone())
+        .collect();
+    if expired.is_empty() {
+        return 0;
+    }
+
+    debug!(workers = ?expired, "Requeueing jobs of expired workers");
+    state.workers.retain(|id, _| !expired.contains(id));
+    let orphaned = state.ids_where(|job| {
+        job.status == JobStatus::Processing
+            && job
+                .worker_id
+                .as_ref()
+                .is_some_and(|id| expired.contains(id))
+    });
+    let mut requeued = 0;
+    for id in orphaned {
+        if state.update(&id, &[JobStatus::Processing], |job| {
+            job.status = JobStatus::Queued;
+        }) {
+            requeued += 1;
+        }
+    }
+    drop(state);
+    if requeued > 0 {
+        store.notify.notify_waiters();
+    }
+    requeued
+}
+
+/// Lists the worker processes whose heartbeat hasn't expired, with the jobs
+/// they are running.
+#[must_use]
+pub fn get_workers(store: &JobStore) -> Vec<WorkerInfo> {
+    let cutoff = Utc::now() - HEARTBEAT_TTL;
+    let state = store.state();
+    let mut workers: Vec<WorkerInfo> = state
+        .workers
+        .values()
+        .filter(|worker| worker.heartbeat_at > cutoff)
+        .cloned()
+        .collect();
+    for worker in &mut workers {
+        worker.jobs = state
+            .jobs
+            .values()
+            .filter(|job| {
+                job.status == JobStatus::Processing && job.worker_id.as_ref() == Some(&worker.id)
+            })
+            .map(|job| job.id.clone())
+            .collect();
+    }
+    workers.sort_by_key(|worker| worker.started_at);
+    workers
+}
+
+/// Takes the lock `name` for `owner` until `ttl` from now, or extends it
+/// when `owner` already holds it. Returns `false` while another owner holds
+/// a lock that hasn't expired.
+#[must_use]
+pub fn try_lock(store: &JobStore, name: &str, owner: &str, ttl: Duration) -> bool {
+    let now = Utc::now();
+    let mut state = store.state();
+    if let Some((holder, expires_at)) = state.locks.get(name) {
+        if holder != owner && *expires_at > now {
+            return false;
+        }
+    }
+    state
+        .locks
+        .insert(name.to_string(), (owner.to_string(), now + ttl));
+    true
+}
+
+/// Requeues jobs from [`JobStatus::Processing`] to [`JobStatus::Queued`].
+///
+/// Only jobs that were last updated more than `age_minutes` ago are affected.
+pub fn requeue(store: &JobStore, age_minutes: &i64) {
+    debug!(age_minutes = age_minutes, "Requeueing stalled jobs");
+    let cutoff = Utc::now() - chrono::Duration::minutes(*age_minutes);
+    {
+        let mut state = store.state();
+        let ids = state.ids_where(|job| {
+            job.status == JobStatus::Processing
+                && job
+                    .updated_at
+                    .is_some_and(|updated_at| updated_at <= cutoff)
+        });
+        for id in ids {
+            state.update(&id, &[JobStatus::Processing], |job| {
+                job.status = JobStatus::Queued;
+            });
+        }
+    }
+    store.notify.notify_waiters();
+}
+
+/// Retrieves jobs, optionally filtered by `status` and by being created at
+/// least `age_days` days ago.
+#[must_use]
+pub fn get_jobs(
+    store: &JobStore,
+    status: Option<&Vec<JobStatus>>,
+    age_days: Option<i64>,
+) -> Vec<Job> {
+    let cutoff = age_days.map(|age_days| Utc::now() - chrono::Duration::days(age_days));
+    let jobs: Vec<Job> = store
+        .state()
+        .jobs
+        .values()
+        .filter(|job| status.map_or(true, |status| status.contains(&job.status)))
+        .filter(|job| {
+            cutoff.map_or(true, |cutoff| {
+                job.created_at
+                    .is_some_and(|created_at| created_at <= cutoff)
+            })
+        })
+        .cloned()
+        .collect();
+    debug!(status = ?status, age_days = ?age_days, job_count = jobs.len(), "Retrieved jobs");
+    jobs
+}
+
+/// Fetches a single job by id.
+#[must_use]
+pub fn get_job(store: &JobStore, id: &str) -> Option<Job> {
+    store.state().jobs.get(id).cloned()
+}
+
+/// Counts the jobs by worker and status, for the dashboard.
+#[cfg(feature = "bg_dashboard")]
+pub(crate) fn count_jobs(store: &JobStore) -> Vec<JobCount> {
+    let mut tally = JobTally::new(Utc::now());
+    for job in store.state().jobs.values() {
+        tally.add(None, &job.name, &job.status, job.updated_at);
+    }
+    tally.into_counts()
+}
+
+/// Lists the `limit` most recently updated jobs, optionally only those with
+/// the given `status` and `name`, for the dashboard.
+#[cfg(feature = "bg_dashboard")]
+pub(crate) fn recent_jobs(
+    store: &JobStore,
+    status: Option<&JobStatus>,
+    name: Option<&str>,
+    limit: usize,
+) -> Vec<Job> {
+    let state = store.state();
+    let mut recent = RecentJobs::new(limit);
+    for job in state.jobs.values().filter(|job| {
+        status.map_or(true, |status| &job.status == status)
+            && name.map_or(true, |name| job.name == name)
+    }) {
+        recent.add(job.updated_at, job);
+    }
+    recent.into_jobs().into_iter().cloned().collect()
+}
+
+#[derive(Debug)]
+pub struct RunOpts {
+    pub num_workers: u32,
+    pub poll_interval_sec: u32,
+    /// How long running jobs may take to finish once the queue shuts down,
+    /// see `workers.shutdown_timeout`.
+    pub shutdown_timeout: Option<Duration>,
+}
+
+/// Create this provider
+#[must_use]
+pub fn create_provider(qcfg: &InMemQueueConfig) -> Queue {
+    debug!(
+        num_workers = qcfg.num_workers,
+        poll_interval = qcfg.poll_interval_sec,
+        max_finished_jobs = qcfg.max_finished_jobs,
+        "Creating job queue provider"
+    );
+    Queue::InMem(
+        JobStore::with_max_finished_jobs(qcfg.max_finished_jobs),
+        Arc::new(tokio::sync::Mutex::new(JobRegistry::new())),
+        RunOpts {
+            num_workers: qcfg.num_workers,
+            poll_interval_sec: qcfg.poll_interval_sec,
+            shutdown_timeout: None,
+        },
+        CancellationToken::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enqueue_job(store: &JobStore, name: &str, opts: &EnqueueOpts) -> JobId {
+        enqueue(store, name, serde_json::json!({}), None, opts).expect("enqueue")
+    }
+
+    fn get_all_jobs(store: &JobStore) -> Vec<Job> {
+        get_jobs(store, None, None)
+    }
+
+    fn set_job(store: &JobStore, id: &str, update: impl FnOnce(&mut Job)) {
+        let any_status = [
+            JobStatus::Queued,
+            JobStatus::Processing,
+            JobStatus::Completed,
+            JobStatus::Failed,
+            JobStatus::Cancelled,
+            JobStatus::Dead,
+        ];
+        assert!(store.state().update(id, &any_status, update));
+    }
+
+    fn start_job(store: &JobStore, id: &str) {
+        set_job(store, id, |job| job.status = JobStatus::Processing);
+    }
+
+    #[test]
+    fn can_enqueue() {
+        let store = JobStore::new();
+
+        let id = enqueue(
+            &store,
+            "PasswordChangeNotification",
+            serde_json::json!({"user_id": 1}),
+            Some(vec!["email".to_string()]),
+            &EnqueueOpts {
+                priority: Some(5),
+                ..Default::default()
+            },
+        )
+        .expect("enqueue");
+
+        let job = get_job(&store, &id).expect("job exists");
+        assert_eq!(job.name, "PasswordChangeNotification");
+        assert_eq!(job.data, serde_json::json!({"user_id": 1}));
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.tags, Some(vec!["email".to_string()]));
+        assert_eq!(job.priority, 5);
+        assert!(job.run_at <= Utc::now());
+        assert_eq!(get_all_jobs(&store).len(), 1);
+    }
+
+    #[test]
+    fn can_dequeue_by_priority() {
+        let store = JobStore::new();
+
+        for (name, priority) in [("Low", -1), ("Normal", 0), ("High", 10)] {
+            enqueue_job(
+                &store,
+                name,
+                &EnqueueOpts {
+                    priority: Some(priority),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut names = Vec::new();
+        while let Some(job) = dequeue(&store, &[], &HashMap::new(), "worker") {
+            assert_eq!(job.status, JobStatus::Processing);
+            names.push(job.name);
+        }
+        assert_eq!(names, vec!["High", "Normal", "Low"]);
+    }
+
+    #[test]
+    fn can_dequeue_with_limits() {
+        let store = JobStore::new();
+        let worker_opts = HashMap::from([
+            (
+                "Limited".to_string(),
+                WorkerOpts {
+                    concurrency_limit: Some(1),
+                    ..Default::default()
+                },
+            ),
+            (
+                "Throttled".to_string(),
+                WorkerOpts {
+                    rate_limit: Some(crate::bgworker::RateLimit::per_minute(2)),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        for (name, count, priority) in [("Limited", 2, 10), ("Throttled", 3, 5), ("Other", 1, 0)] {
+            for _ in 0..count {
+                enqueue_job(
+                    &store,
+                    name,
+                    &EnqueueOpts {
+                        priority: Some(priority),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        let mut jobs = Vec::new();
+        while let Some(job) = dequeue(&store, &[], &worker_opts, "worker") {
+            jobs.push(job);
+        }
+        assert_eq!(
+            jobs.iter().map(|job| job.name.as_str()).collect::<Vec<_>>(),
+            vec!["Limited", "Throttled", "Throttled", "Other"]
+        );
+
+        // a finished job frees a slot and a new window lets more jobs start
+        complete_job(&store, &jobs[0].id, None, None);
+        for (window_start, _) in store.state().rate_windows.values_mut() {
+            *window_start -= chrono::Duration::hours(1);
+        }
+        let mut names = Vec::new();
+        while let Some(job) = dequeue(&store, &[], &worker_opts, "worker") {
+            names.push(job.name);
+        }
+        assert_eq!(names, vec!["Limited", "Throttled"]);
+    }
+
+    #[test]
+    fn can_dequeue_scheduled_jobs_when_due() {
+        let store = JobStore::new();
+
+        let later = enqueue_job(
+            &store,
+            "Later",
+            &EnqueueOpts {
+                run_at: Some(Utc::now() + chrono::Duration::minutes(5)),
+                ..Default::default()
+            },
+        );
+        assert!(dequeue(&store, &[], &HashMap::new(), "worker").is_none());
+
+        set_job(&store, &later, |job| {
+            job.run_at = Utc::now() - chrono::Duration::seconds(1);
+        });
+        assert_eq!(
+            dequeue(&store, &[], &HashMap::new(), "worker").map(|job| job.id),
+            Some(later)
+        );
+    }
+
+    #[test]
+    fn can_dequeue_with_tags() {
+        let store = JobStore::new();
+
+        for (name, tags) in [
+            ("Untagged", None),
+            ("Email", Some(vec!["email".to_string()])),
+            (
+                "Report",
+                Some(vec!["report".to_string(), "slow".to_string()]),
+            ),
+        ] {
+            enqueue(
+                &store,
+                name,
+                serde_json::json!({}),
+                tags,
+                &EnqueueOpts::default(),
+            )
+            .expect("enqueue");
+        }
+
+        let tags = ["slow".to_string(), "sms".to_string()];
+        assert_eq!(
+            dequeue(&store, &tags, &HashMap::new(), "worker")
+                .map(|job| job.name)
+                .as_deref(),
+            Some("Report")
+        );
+        assert!(dequeue(&store, &tags, &HashMap::new(), "worker").is_none());
+        assert_eq!(
+            dequeue(&store, &[], &HashMap::new(), "worker")
+                .map(|job| job.name)
+                .as_deref(),
+            Some("Untagged")
+        );
+        assert!(dequeue(&store, &[], &HashMap::new(), "worker").is_none());
+        assert_eq!(
+            dequeue(&store, &["email".to_string()], &HashMap::new(), "worker")
+                .map(|job| job.name)
+                .as_deref(),
+            Some("Email")
+        );
+    }
+
+    #[test]
+    fn can_enqueue_unique_jobs() {
+        let store = JobStore::new();
+        let opts = EnqueueOpts {
+            unique_key: Some("user:1".to_string()),
+            ..Default::default()
+        };
+
+        let first = enqueue_job(&store, "SyncUser", &opts);
+        assert_eq!(enqueue_job(&store, "SyncUser", &opts), first);
+        assert_ne!(enqueue_job(&store, "DeleteUser", &opts), first);
+
+        set_job(&store, &first, |job| job.status = JobStatus::Completed);
+        assert_ne!(enqueue_job(&store, "SyncUser", &opts), first);
+        assert_eq!(get_all_jobs(&store).len(), 3);
+
+        let window = EnqueueOpts {
+            unique_key: Some("user:2".to_string()),
+            unique_for: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let stale = enqueue_job(&store, "SyncUser", &window);
+        set_job(&store, &stale, |job| {
+            job.created_at = Some(Utc::now() - chrono::Duration::minutes(2));
+        });
+        assert_ne!(enqueue_job(&store, "SyncUser", &window), stale);
+    }
+
+    #[test]
+    fn can_complete_and_fail_jobs() {
+        let store = JobStore::new();
+
+        let once = enqueue_job(&store, "Once", &EnqueueOpts::default());
+        start_job(&store, &once);
+        complete_job(&store, &once, None, Some(serde_json::json!(42)));
+        let job = get_job(&store, &once).expect("job exists");
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.result, Some(serde_json::json!(42)));
+
+        let every_minute = enqueue_job(&store, "EveryMinute", &EnqueueOpts::default());
+        start_job(&store, &every_minute);
+        complete_job(&store, &every_minute, Some(60_000), None);
+        let job = get_job(&store, &every_minute).expect("job exists");
+        assert_eq!(job.status, JobStatus::Queued);
+        assert!(job.run_at > Utc::now());
+
+        let failing = enqueue_job(&store, "Failing", &EnqueueOpts::default());
+        start_job(&store, &failing);
+        fail_job(&store, &failing, &Error::string("boom"));
+        let job = get_job(&store, &failing).expect("job exists");
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.attempts, 1);
+        assert_eq!(job.last_error.as_deref(), Some("boom"));
+        assert_eq!(job.data, serde_json::json!({}));
+    }
+
+    #[test]
+    fn cannot_finish_job_that_is_no_longer_processing() {
+        let store = JobStore::new();
+
+        let cancelled = enqueue_job(&store, "Cancelled", &EnqueueOpts::default());
+        start_job(&store, &cancelled);
+        assert!(cancel_job(&store, &cancelled).expect("cancel job"));
+        complete_job(&store, &cancelled, None, None);
+        assert_eq!(
+            get_job(&store, &cancelled).expect("job exists").status,
+            JobStatus::Cancelled
+        );
+
+        let queued = enqueue_job(&store, "Queued", &EnqueueOpts::default());
+        fail_job(&store, &queued, &Error::string("boom"));
+        retry_job(&store, &queued, &Error::string("boom"), Utc::now());
+        bury_job(&store, &queued, &Error::string("boom"));
+        let job = get_job(&store, &queued).expect("job exists");
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.attempts, 0);
+        assert_eq!(job.last_error, None);
+    }
+
+    #[test]
+    fn can_drop_oldest_finished_jobs() {
+        struct ImportWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<()> for ImportWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, (): ()) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let store = JobStore::with_max_finished_jobs(2);
+
+        let mut batch = Batch::new();
+        batch.add::<ImportWorker, _>(()).expect("add job");
+        batch.add::<ImportWorker, _>(()).expect("add job");
+        assert!(enqueue_batch(&store, &batch).is_ok());
+        let batch_jobs = get_all_jobs(&store);
+
+        let failed = enqueue_job(&store, "Failed", &EnqueueOpts::default());
+        start_job(&store, &failed);
+        fail_job(&store, &failed, &Error::string("boom"));
+        let mut completed = Vec::new();
+        for _ in 0..3 {
+            let id = enqueue_job(&store, "Completed", &EnqueueOpts::default());
+            start_job(&store, &id);
+            complete_job(&store, &id, None, None);
+            completed.push(id);
+        }
+        // the batch job finished first, but its batch still needs it
+        start_job(&store, &batch_jobs[0].id);
+        complete_job(&store, &batch_jobs[0].id, None, None);
+        let cancelled = enqueue_job(&store, "Cancelled", &EnqueueOpts::default());
+        assert!(cancel_job(&store, &cancelled).expect("cancel job"));
+
+        assert!(get_job(&store, &batch_jobs[0].id).is_some());
+        assert!(get_job(&store, &failed).is_some());
+        assert!(completed.iter().all(|id| get_job(&store, id).is_none()));
+        assert!(get_job(&store, &cancelled).is_some());
+        assert_eq!(
+            get_batch(&store, batch.id()).map(|info| (info.total, info.completed)),
+            Some((2, 1))
+        );
+
+        // queued jobs are never dropped, and still run in order
+        assert_eq!(
+            dequeue(&store, &[], &HashMap::new(), "worker").map(|job| job.id),
+            Some(batch_jobs[1].id.clone())
+        );
+        assert!(dequeue(&store, &[], &HashMap::new(), "worker").is_none());
+    }
+
+    #[test]
+    fn can_cancel_job_by_name() {
+        let store = JobStore::new();
+
+        let queued = enqueue_job(&store, "UserAccountActivation", &EnqueueOpts::default());
+        let running = enqueue_job(&store, "UserAccountActivation", &EnqueueOpts::default());
+        set_job(&store, &running, |job| job.status = JobStatus::Processing);
+        let done = enqueue_job(&store, "UserAccountActivation", &EnqueueOpts::default());
+        set_job(&store, &done, |job| job.status = JobStatus::Completed);
+        let other = enqueue_job(
+            &store,
+            "PasswordChangeNotification",
+            &EnqueueOpts::default(),
+        );
+
+        assert!(cancel_jobs_by_name(&store, "UserAccountActivation").is_ok());
+
+        let status = |id: &str| get_job(&store, id).expect("job exists").status;
+        assert_eq!(status(&queued), JobStatus::Cancelled);
+        assert_eq!(status(&running), JobStatus::Cancelled);
+        assert!(is_job_cancelled(&store, &running));
+        assert_eq!(status(&done), JobStatus::Completed);
+        assert_eq!(status(&other), JobStatus::Queued);
+    }
+
+    #[test]
+    fn can_cancel_job() {
+        let store = JobStore::new();
+
+        let queued = enqueue_job(&store, "UserAccountActivation", &EnqueueOpts::default());
+        let running = enqueue_job(&store, "UserAccountActivation", &EnqueueOpts::default());
+        set_job(&store, &running, |job| job.status = JobStatus::Processing);
+        let done = enqueue_job(&store, "UserAccountActivation", &EnqueueOpts::default());
+        set_job(&store, &done, |job| job.status = JobStatus::Completed);
+
+        assert!(cancel_job(&store, &queued).expect("cancel job"));
+        assert!(cancel_job(&store, &running).expect("cancel job"));
+        assert!(!cancel_job(&store, &done).expect("cancel job"));
+        assert!(!cancel_job(&store, "missing").expect("cancel job"));
+
+        let status = |id: &str| get_job(&store, id).expect("job exists").status;
+        assert_eq!(status(&queued), JobStatus::Cancelled);
+        assert_eq!(status(&running), JobStatus::Cancelled);
+        assert!(is_job_cancelled(&store, &running));
+        assert_eq!(status(&done), JobStatus::Completed);
+    }
+
+    #[test]
+    fn can_clear() {
+        let store = JobStore::new();
+
+        let completed = enqueue_job(&store, "Completed", &EnqueueOpts::default());
+        set_job(&store, &completed, |job| job.status = JobStatus::Completed);
+        let failed = enqueue_job(&store, "Failed", &EnqueueOpts::default());
+        set_job(&store, &failed, |job| job.status = JobStatus::Failed);
+        let old = enqueue_job(&store, "Old", &EnqueueOpts::default());
+        set_job(&store, &old, |job| {
+            job.created_at = Some(Utc::now() - chrono::Duration::days(20));
+        });
+        let old_failed = enqueue_job(&store, "OldFailed", &EnqueueOpts::default());
+        set_job(&store, &old_failed, |job| {
+            job.status = JobStatus::Failed;
+            job.created_at = Some(Utc::now() - chrono::Duration::days(20));
+        });
+
+        assert_eq!(
+            get_jobs(&store, None, Some(10))
+                .into_iter()
+                .map(|job| job.name)
+                .collect::<Vec<_>>(),
+            vec!["Old", "OldFailed"]
+        );
+
+        clear_jobs_older_than(&store, 10, Some(&vec![JobStatus::Failed]));
+        assert!(get_job(&store, &old_failed).is_none());
+        assert!(get_job(&store, &old).is_some());
+
+        clear_jobs_older_than(&store, 10, None);
+        assert!(get_job(&store, &old).is_none());
+
+        clear_by_status(&store, &[JobStatus::Completed]);
+        assert_eq!(
+            get_all_jobs(&store)
+                .into_iter()
+                .map(|job| job.name)
+                .collect::<Vec<_>>(),
+            vec!["Failed"]
+        );
+
+        clear(&store);
+        assert!(get_all_jobs(&store).is_empty());
+    }
+
+    #[test]
+    fn can_requeue() {
+        let store = JobStore::new();
+
+        let stalled = enqueue_job(&store, "Stalled", &EnqueueOpts::default());
+        let running = enqueue_job(&store, "Running", &EnqueueOpts::default());
+        for (id, minutes) in [(&stalled, 20), (&running, 5)] {
+            set_job(&store, id, |job| {
+                job.status = JobStatus::Processing;
+                job.updated_at = Some(Utc::now() - chrono::Duration::minutes(minutes));
+            });
+        }
+
+        requeue(&store, &10);
+
+        let status = |id: &str| get_job(&store, id).expect("job exists").status;
+        assert_eq!(status(&stalled), JobStatus::Queued);
+        assert_eq!(status(&running), JobStatus::Processing);
+    }
+
+    #[test]
+    fn can_retry_jobs() {
+        let store = JobStore::new();
+
+        let failed = enqueue(
+            &store,
+            "Import",
+            serde_json::json!({"file": "a.csv", "error": "skip"}),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .expect("enqueue");
+        start_job(&store, &failed);
+        fail_job(&store, &failed, &Error::string("boom"));
+        let dead = enqueue_job(&store, "Export", &EnqueueOpts::default());
+        start_job(&store, &dead);
+        bury_job(&store, &dead, &Error::string("boom"));
+
+        retry_jobs(&store, &RetryTarget::Name("Import".to_string()));
+        let job = get_job(&store, &failed).expect("job exists");
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.attempts, 0);
+        assert_eq!(
+            job.data,
+            serde_json::json!({"file": "a.csv", "error": "skip"})
+        );
+        assert_eq!(
+            get_job(&store, &dead).expect("job exists").status,
+            JobStatus::Dead
+        );
+
+        let unique = |key: &str| EnqueueOpts {
+            unique_key: Some(key.to_string()),
+            ..Default::default()
+        };
+        let held = enqueue_job(&store, "Sync", &unique("order-1"));
+        start_job(&store, &held);
+        fail_job(&store, &held, &Error::string("boom"));
+        let holder = enqueue_job(&store, "Sync", &unique("order-1"));
+        let free = enqueue_job(&store, "Sync", &unique("order-2"));
+        start_job(&store, &free);
+        bury_job(&store, &free, &Error::string("boom"));
+
+        retry_jobs(&store, &RetryTarget::AllFailed);
+        assert_eq!(
+            get_job(&store, &dead).expect("job exists").status,
+            JobStatus::Queued
+        );
+        assert_eq!(
+            get_job(&store, &held).expect("job exists").status,
+            JobStatus::Failed
+        );
+        assert_eq!(
+            get_job(&store, &holder).expect("job exists").status,
+            JobStatus::Queued
+        );
+        let job = get_job(&store, &free).expect("job exists");
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.unique_key.as_deref(), Some("order-2"));
+    }
+
+    #[test]
+    fn can_try_lock() {
+        let store = JobStore::new();
+        let ttl = Duration::from_secs(30);
+        assert!(try_lock(&store, "report", "a", ttl));
+        assert!(!try_lock(&store, "report", "b", ttl));
+        // the owner extends its lock
+        assert!(try_lock(&store, "report", "a", ttl));
+        assert!(try_lock(&store, "other", "b", ttl));
+
+        store
+            .state()
+            .locks
+            .get_mut("report")
+            .expect("lock exists")
+            .1 = Utc::now() - chrono::Duration::seconds(1);
+        assert!(try_lock(&store, "report", "b", ttl));
+        assert!(!try_lock(&store, "report", "a", ttl));
+    }
+
+    #[test]
+    fn can_requeue_jobs_of_expired_workers() {
+        let store = JobStore::new();
+        let worker = WorkerInfo::current(&["email".to_string()]);
+        record_heartbeat(&store, &worker);
+
+        let held = enqueue_job(&store, "EmailWorker", &EnqueueOpts::default());
+        let unowned = enqueue_job(&store, "EmailWorker", &EnqueueOpts::default());
+        assert_eq!(
+            dequeue(&store, &[], &HashMap::new(), &worker.id).map(|job| job.id),
+            Some(held.clone())
+        );
+        assert_eq!(
+            dequeue(&store, &[], &HashMap::new(), "unknown").map(|job| job.id),
+            Some(unowned.clone())
+        );
+
+        let workers = get_workers(&store);
+        assert_eq!(workers.len(), 1);
+        assert_eq!(workers[0].id, worker.id);
+        assert_eq!(workers[0].tags, vec!["email".to_string()]);
+        assert_eq!(workers[0].jobs, vec![held.clone()]);
+
+        assert_eq!(requeue_orphaned_jobs(&store, HEARTBEAT_TTL), 0);
+
+        store
+            .state()
+            .workers
+            .get_mut(&worker.id)
+            .expect("worker exists")
+            .heartbeat_at = Utc::now() - HEARTBEAT_TTL;
+        assert!(get_workers(&store).is_empty());
+        assert_eq!(requeue_orphaned_jobs(&store, HEARTBEAT_TTL), 1);
+
+        assert_eq!(
+            get_job(&store, &held).expect("job exists").status,
+            JobStatus::Queued
+        );
+        // jobs without a known worker are left alone
+        assert_eq!(
+            get_job(&store, &unowned).expect("job exists").status,
+            JobStatus::Processing
+        );
+        assert!(store.state().workers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn can_list_running_workers() {
+        let store = JobStore::new();
+        let registry = JobRegistry::new();
+        let opts = RunOpts {
+            num_workers: 2,
+            poll_interval_sec: 60,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&store, &opts, &token, &["email".to_string()]);
+        sleep(Duration::from_millis(100)).await;
+
+        let workers = get_workers(&store);
+        assert_eq!(workers.len(), 1);
+        assert_eq!(workers[0].pid, std::process::id());
+        assert_eq!(workers[0].tags, vec!["email".to_string()]);
+
+        token.cancel();
+        futures_util::future::join_all(handles).await;
+        assert!(get_workers(&store).is_empty());
+    }
+
+    #[tokio::test]
+    async fn can_requeue_jobs_interrupted_by_shutdown() {
+        struct SlowWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<u64> for SlowWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, millis: u64) -> crate::Result<()> {
+                sleep(Duration::from_millis(millis)).await;
+                Ok(())
+            }
+        }
+
+        let store = JobStore::new();
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("SlowWorker".to_string(), SlowWorker)
+            .is_ok());
+
+        let short = enqueue(
+            &store,
+            "SlowWorker",
+            serde_json::json!(300),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .expect("enqueue");
+        let long = enqueue(
+            &store,
+            "SlowWorker",
+            serde_json::json!(60_000),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .expect("enqueue");
+
+        let opts = RunOpts {
+            num_workers: 2,
+            poll_interval_sec: 60,
+            shutdown_timeout: Some(Duration::from_millis(500)),
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&store, &opts, &token, &[]);
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            get_job(&store, &long).expect("job exists").status,
+            JobStatus::Processing
+        );
+
+        token.cancel();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            futures_util::future::join_all(handles),
+        )
+        .await
+        .expect("workers stop after the shutdown timeout");
+
+        // finished within the shutdown timeout
+        assert_eq!(
+            get_job(&store, &short).expect("job exists").status,
+            JobStatus::Completed
+        );
+        // put back in the queue, without counting as an attempt
+        let job = get_job(&store, &long).expect("job exists");
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn can_run_jobs() {
+        struct ImportWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<bool> for ImportWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, succeed: bool) -> crate::Result<()> {
+                if succeed {
+                    Ok(())
+                } else {
+                    Err(Error::string("import failed"))
+                }
+            }
+        }
+
+        let store = JobStore::new();
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("ImportWorker".to_string(), ImportWorker)
+            .is_ok());
+
+        let opts = RunOpts {
+            num_workers: 2,
+            poll_interval_sec: 60,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&store, &opts, &token, &[]);
+        sleep(Duration::from_millis(100)).await;
+
+        // workers are idle until the poll interval, enqueuing wakes them up
+        let ok = enqueue(
+            &store,
+            "ImportWorker",
+            serde_json::json!(true),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .expect("enqueue");
+        let failing = enqueue(
+            &store,
+            "ImportWorker",
+            serde_json::json!(false),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .expect("enqueue");
+        sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            get_job(&store, &ok).expect("job exists").status,
+            JobStatus::Completed
+        );
+        assert_eq!(
+            get_job(&store, &failing).expect("job exists").status,
+            JobStatus::Failed
+        );
+
+        token.cancel();
+        for handle in handles {
+            assert!(handle.await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn can_finish_batches() {
+        struct ImportWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<bool> for ImportWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, succeed: bool) -> crate::Result<()> {
+                if succeed {
+                    Ok(())
+                } else {
+                    Err(Error::string("import failed"))
+                }
+            }
+        }
+        struct SummaryWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<String> for SummaryWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _batch_id: String) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+        struct AlertWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<String> for AlertWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _batch_id: String) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let store = JobStore::new();
+        let mut batches = Vec::new();
+        for results in [vec![true, true], vec![true, false], vec![]] {
+            let mut batch = Batch::new();
+            for succeed in results {
+                batch.add::<ImportWorker, _>(succeed).expect("add job");
+            }
+            let id = batch.id().to_string();
+            batch
+                .on_complete::<SummaryWorker, _>(id.clone())
+                .expect("set on_complete")
+                .on_failure::<AlertWorker, _>(id.clone())
+                .expect("set on_failure");
+            assert!(enqueue_batch(&store, &batch).is_ok());
+            batches.push(id);
+        }
+
+        let pending = get_batch(&store, &batches[0]).expect("batch exists");
+        assert_eq!((pending.total, pending.pending), (2, 2));
+        assert!(pending.finished_at.is_none());
+
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("ImportWorker".to_string(), ImportWorker)
+            .is_ok());
+
+        let opts = RunOpts {
+            num_workers: 1,
+            poll_interval_sec: 1,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&store, &opts, &token, &[]);
+        sleep(Duration::from_millis(500)).await;
+        token.cancel();
+        for handle in handles {
+            assert!(handle.await.is_ok());
+        }
+
+        let succeeded = get_batch(&store, &batches[0]).expect("batch exists");
+        assert_eq!(
+            (succeeded.pending, succeeded.completed, succeeded.failed),
+            (0, 2, 0)
+        );
+        assert!(succeeded.finished_at.is_some());
+        let failed = get_batch(&store, &batches[1]).expect("batch exists");
+        assert_eq!((failed.pending, failed.completed, failed.failed), (0, 1, 1));
+
+        let callbacks = get_all_jobs(&store)
+            .into_iter()
+            .filter(|job| job.name != "ImportWorker")
+            .map(|job| (job.name, job.data))
+            .collect::<Vec<_>>();
+        assert_eq!(callbacks.len(), 3);
+        assert!(callbacks.contains(&("SummaryWorker".to_string(), serde_json::json!(batches[0]))));
+        assert!(callbacks.contains(&("AlertWorker".to_string(), serde_json::json!(batches[1]))));
+        assert!(callbacks.contains(&("SummaryWorker".to_string(), serde_json::json!(batches[2]))));
+    }
+
+    #[tokio::test]
+    async fn can_dump_and_import_jobs() {
+        let tree_fs = tree_fs::TreeBuilder::default()
+            .drop(true)
+            .create()
+            .expect("create temp folder");
+        let qcfg = InMemQueueConfig {
+            poll_interval_sec: 1,
+            num_workers: 1,
+            max_finished_jobs: 100,
+        };
+
+        let queue = create_provider(&qcfg);
+        for user_id in [1, 2] {
+            assert!(queue
+                .enqueue_with(
+                    "UserAccountActivation".to_string(),
+                    None,
+                    serde_json::json!({"user_id": user_id}),
+                    None,
+                    EnqueueOpts {
+                        priority: Some(3),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .is_ok());
+        }
+        let dump_file = queue
+            .dump(&tree_fs.root, None, None)
+            .await
+            .expect("dump jobs");
+
+        let restored = create_provider(&qcfg);
+        assert!(restored.import(&dump_file).await.is_ok());
+        let Queue::InMem(store, _, _, _) = &restored else {
+            panic!("expected an in-memory queue");
+        };
+        let jobs = get_all_jobs(store);
+        assert_eq!(
+            jobs.iter().map(|job| &job.data).collect::<Vec<_>>(),
+            vec![
+                &serde_json::json!({"user_id": 1}),
+                &serde_json::json!({"user_id": 2})
+            ]
+        );
+        assert!(jobs
+            .iter()
+            .all(|job| job.status == JobStatus::Queued && job.priority == 3));
+    }
+}
@@ -1,15 +1,23 @@
 use std::{
+    cell::RefCell,
     fs::File,
     io::Write,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 #[cfg(feature = "cli")]
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use serde_variant::to_variant_name;
+pub use tokio_util::sync::CancellationToken;
+#[cfg(feature = "bg_dashboard")]
+pub mod dashboard;
+#[cfg(feature = "bg_inmem")]
+pub mod inmem;
 #[cfg(feature = "bg_pg")]
 pub mod pg;
 #[cfg(feature = "bg_redis")]
@@ -39,6 +47,10 @@ pub enum JobStatus {
     Failed,
     #[serde(rename = "cancelled")]
     Cancelled,
+    /// Ran out of retries. The job keeps its arguments and error so it can be
+    /// inspected and replayed with `cargo loco jobs retry`.
+    #[serde(rename = "dead")]
+    Dead,
 }
 
 impl std::str::FromStr for JobStatus {
@@ -51,6 +63,7 @@ impl std::str::FromStr for JobStatus {
             "completed" => Ok(Self::Completed),
             "failed" => Ok(Self::Failed),
             "cancelled" => Ok(Self::Cancelled),
+            "dead" => Ok(Self::Dead),
             _ => Err(format!("Invalid status: {s}")),
         }
     }
@@ -62,6 +75,587 @@ impl std::fmt::Display for JobStatus {
     }
 }
 
+/// Upper bound for the delay between two attempts of the default
+/// [`BackgroundWorker::backoff`].
+const MAX_BACKOFF_SECS: u64 = 60 * 60;
+
+/// Exponential backoff used by [`BackgroundWorker::backoff`]: 2s, 4s, 8s, ...
+/// capped at one hour.
+#[must_use]
+pub fn exponential_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(
+        2u64.checked_pow(attempt)
+            .map_or(MAX_BACKOFF_SECS, |secs| secs.min(MAX_BACKOFF_SECS)),
+    )
+}
+
+/// Settings captured from a [`BackgroundWorker`] when it is registered, so
+/// queue providers can apply them to jobs without knowing the worker type.
+#[derive(Clone, Copy, Debug)]
+pub struct WorkerOpts {
+    pub max_retries: u32,
+    pub backoff: fn(u32) -> Duration,
+    pub timeout: Option<Duration>,
+    pub concurrency_limit: Option<u32>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl Default for WorkerOpts {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: exponential_backoff,
+            timeout: None,
+            concurrency_limit: None,
+            rate_limit: None,
+        }
+    }
+}
+
+impl WorkerOpts {
+    /// Reads the settings declared by the worker `W`.
+    #[must_use]
+    pub fn from_worker<A, W>() -> Self
+    where
+        A: Send + Sync + Serialize + 'static,
+        W: BackgroundWorker<A>,
+    {
+        Self {
+            max_retries: W::max_retries(),
+            backoff: W::backoff,
+            timeout: W::timeout(),
+            concurrency_limit: W::concurrency_limit(),
+            rate_limit: W::rate_limit(),
+        }
+    }
+
+    /// Whether starting a job of this worker has to be checked against
+    /// [`BackgroundWorker::concurrency_limit`] or
+    /// [`BackgroundWorker::rate_limit`].
+    #[must_use]
+    pub fn is_limited(&self) -> bool {
+        self.concurrency_limit.is_some() || self.rate_limit.is_some()
+    }
+
+    /// Returns when a job that has now failed `attempts` times should run
+    /// again, or `None` when it ran out of retries.
+    #[must_use]
+    pub fn retry_at(&self, attempts: i32) -> Option<DateTime<Utc>> {
+        let attempt = u32::try_from(attempts).ok()?;
+        if attempt == 0 || attempt > self.max_retries {
+            return None;
+        }
+        let delay = chrono::Duration::from_std((self.backoff)(attempt)).ok()?;
+        Some(Utc::now() + delay)
+    }
+}
+
+/// At most `max` jobs of a worker start within each window of `per`, counted
+/// across all worker processes. See [`BackgroundWorker::rate_limit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    #[must_use]
+    pub const fn new(max: u32, per: Duration) -> Self {
+        Self { max, per }
+    }
+
+    #[must_use]
+    pub const fn per_second(max: u32) -> Self {
+        Self::new(max, Duration::from_secs(1))
+    }
+
+    #[must_use]
+    pub const fn per_minute(max: u32) -> Self {
+        Self::new(max, Duration::from_secs(60))
+    }
+}
+
+/// When and how often an enqueued job runs. The default runs the job once,
+/// as soon as a worker picks it up.
+#[derive(Clone, Debug, Default)]
+pub struct EnqueueOpts {
+    /// Earliest time the job may run.
+    pub run_at: Option<DateTime<Utc>>,
+    /// Run the job again this long after every successful run.
+    pub interval: Option<Duration>,
+    /// Overrides [`BackgroundWorker::priority`] for this job.
+    pub priority: Option<i16>,
+    /// While a job of the same worker with this key is queued or processing,
+    /// enqueuing another one is a no-op.
+    pub unique_key: Option<String>,
+    /// Stop enforcing `unique_key` once the existing job is this old.
+    pub unique_for: Option<Duration>,
+}
+
+/// Splits a worker error into the message and, when one was captured, the
+/// backtrace stored alongside a failed job.
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+pub(crate) fn error_details(err: &Error) -> (String, Option<String>) {
+    match err {
+        Error::WithBacktrace { inner, backtrace } => {
+            (inner.to_string(), Some(backtrace.to_string()))
+        }
+        _ => (err.to_string(), None),
+    }
+}
+
+/// Status and outcome of a job, as returned by [`Queue::get_job`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub name: String,
+    pub status: JobStatus,
+    pub run_at: DateTime<Utc>,
+    pub attempts: i32,
+    /// Error of the last failed attempt.
+    pub error: Option<String>,
+    /// Payload the worker stored with [`set_job_result`].
+    pub result: Option<serde_json::Value>,
+    /// The [`Batch`] the job was enqueued with.
+    pub batch_id: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A worker process running the queue, as returned by [`Queue::get_workers`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorkerInfo {
+    pub id: String,
+    pub hostname: String,
+    pub pid: u32,
+    /// Tags the worker was started with, see `cargo loco start --worker`.
+    pub tags: Vec<String>,
+    /// Ids of the jobs the worker is running.
+    pub jobs: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub heartbeat_at: DateTime<Utc>,
+}
+
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+impl WorkerInfo {
+    /// Describes the current process, under a new id.
+    pub(crate) fn current(tags: &[String]) -> Self {
+        let now = Utc::now();
+        Self {
+            id: ulid::Ulid::new().to_string(),
+            hostname: hostname::get()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            pid: std::process::id(),
+            tags: tags.to_vec(),
+            jobs: Vec::new(),
+            started_at: now,
+            heartbeat_at: now,
+        }
+    }
+}
+
+/// How often a worker process records its heartbeat.
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long after its last heartbeat a worker process is considered gone.
+/// The jobs it was running are then put back in the queue.
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+pub(crate) const HEARTBEAT_TTL: Duration = Duration::from_secs(60);
+
+/// Drives the heartbeat of a worker process for the `run` loops of the queue
+/// providers.
+///
+/// Every [`HEARTBEAT_INTERVAL`], `beat` records the heartbeat and `reap` puts
+/// the jobs of workers whose heartbeat expired back in the queue. This goes
+/// on until every sender of `alive`, one per worker task, is dropped, so the
+/// jobs of a worker that is still draining are not taken from it.
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+pub(crate) async fn keep_alive<B, BF, R, RF>(
+    mut alive: tokio::sync::mpsc::Receiver<()>,
+    beat: B,
+    reap: R,
+) where
+    B: Fn() -> BF,
+    BF: std::future::Future<Output = Result<()>>,
+    R: Fn() -> RF,
+    RF: std::future::Future<Output = Result<u64>>,
+{
+    loop {
+        if let Err(err) = beat().await {
+            tracing::error!(error = %err, "Failed to record worker heartbeat");
+        }
+        match reap().await {
+            Ok(0) => {}
+            Ok(count) => {
+                tracing::warn!(count, "Requeued jobs of workers whose heartbeat expired");
+            }
+            Err(err) => {
+                tracing::error!(error = %err, "Failed to requeue jobs of expired workers");
+            }
+        }
+        tokio::select! {
+            _ = alive.recv() => break,
+            () = tokio::time::sleep(HEARTBEAT_INTERVAL) => {}
+        }
+    }
+}
+
+/// Jobs enqueued together with [`Queue::enqueue_batch`].
+///
+/// Once every job of the batch has finished, the `on_complete` job is
+/// enqueued if all of them completed, or the `on_failure` job if any of them
+/// failed, ran out of retries or was cancelled. A job waiting for a retry has
+/// not finished yet.
+///
+/// ```rust,ignore
+/// let mut batch = Batch::new();
+/// for file in files {
+///     batch.add::<ImportFileWorker, _>(ImportFileArgs { file })?;
+/// }
+/// batch.on_complete::<SummaryWorker, _>(SummaryArgs { batch_id: batch.id().to_string() })?;
+/// queue.enqueue_batch(batch).await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Batch {
+    id: String,
+    jobs: Vec<BatchJob>,
+    on_complete: Option<BatchJob>,
+    on_failure: Option<BatchJob>,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Batch {
+    /// Creates an empty batch with a new id.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            jobs: Vec::new(),
+            on_complete: None,
+            on_failure: None,
+        }
+    }
+
+    /// Id of the batch, to look it up with [`Queue::get_batch`] or to pass
+    /// it to the callbacks in their arguments.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Adds a job of the worker `W` to the batch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `args` cannot be serialized to JSON.
+    pub fn add<W, A>(&mut self, args: A) -> Result<&mut Self>
+    where
+        W: BackgroundWorker<A>,
+        A: Serialize + Send + Sync + 'static,
+    {
+        self.jobs.push(BatchJob::new::<W, A>(args)?);
+        Ok(self)
+    }
+
+    /// Job of the worker `W` to enqueue once every job of the batch
+    /// completed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `args` cannot be serialized to JSON.
+    pub fn on_complete<W, A>(&mut self, args: A) -> Result<&mut Self>
+    where
+        W: BackgroundWorker<A>,
+        A: Serialize + Send + Sync + 'static,
+    {
+        self.on_complete = Some(BatchJob::new::<W, A>(args)?);
+        Ok(self)
+    }
+
+    /// Job of the worker `W` to enqueue once every job of the batch
+    /// finished, when at least one of them did not complete.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `args` cannot be serialized to JSON.
+    pub fn on_failure<W, A>(&mut self, args: A) -> Result<&mut Self>
+    where
+        W: BackgroundWorker<A>,
+        A: Serialize + Send + Sync + 'static,
+    {
+        self.on_failure = Some(BatchJob::new::<W, A>(args)?);
+        Ok(self)
+    }
+}
+
+/// A job of a [`Batch`], or one of its callbacks, kept by the queue
+/// providers until the batch finishes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct BatchJob {
+    pub name: String,
+    pub queue: Option<String>,
+    pub args: serde_json::Value,
+    pub tags: Option<Vec<String>>,
+    pub priority: i16,
+}
+
+impl BatchJob {
+    fn new<W, A>(args: A) -> Result<Self>
+    where
+        W: BackgroundWorker<A>,
+        A: Serialize + Send + Sync + 'static,
+    {
+        let tags = W::tags();
+        Ok(Self {
+            name: W::class_name(),
+            queue: W::queue(),
+            args: serde_json::to_value(args)?,
+            tags: if tags.is_empty() { None } else { Some(tags) },
+            priority: W::priority(),
+        })
+    }
+
+    #[cfg(any(
+        feature = "bg_redis",
+        feature = "bg_pg",
+        feature = "bg_sqlt",
+        feature = "bg_inmem"
+    ))]
+    pub(crate) fn opts(&self) -> EnqueueOpts {
+        EnqueueOpts {
+            priority: Some(self.priority),
+            ..Default::default()
+        }
+    }
+}
+
+/// Progress of a [`Batch`], as returned by [`Queue::get_batch`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchInfo {
+    pub id: String,
+    pub total: u64,
+    /// Jobs still queued, processing or waiting for a retry.
+    pub pending: u64,
+    pub completed: u64,
+    /// Jobs that failed, ran out of retries or were cancelled.
+    pub failed: u64,
+    /// When the last job finished and the callback was enqueued.
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// State of the job a queue provider is performing, reachable from
+/// `perform` through [`set_job_result`].
+struct JobScope {
+    result: RefCell<Option<serde_json::Value>>,
+}
+
+tokio::task_local! {
+    static CURRENT_JOB: JobScope;
+}
+
+/// Stores `result` on the job being performed, where it can be read back
+/// with [`Queue::get_job`] once the job completes. Calling it again replaces
+/// the previous value.
+///
+/// Only jobs run by a queue provider keep a result; in the other worker
+/// modes this does nothing.
+///
+/// # Errors
+///
+/// Returns an error if `result` cannot be serialized to JSON.
+pub fn set_job_result<T: Serialize>(result: &T) -> Result<()> {
+    let value = serde_json::to_value(result)?;
+    // not running inside a queued job, there is nowhere to keep the result
+    let _ = CURRENT_JOB.try_with(|job| *job.result.borrow_mut() = Some(value));
+    Ok(())
+}
+
+/// Runs `perform` and returns the result it stored with [`set_job_result`].
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+pub(crate) async fn perform_job(
+    perform: impl std::future::Future<Output = Result<()>> + Send,
+) -> Result<Option<serde_json::Value>> {
+    let job = JobScope {
+        result: RefCell::new(None),
+    };
+    CURRENT_JOB
+        .scope(job, async move {
+            perform.await?;
+            Ok(CURRENT_JOB.with(|job| job.result.borrow_mut().take()))
+        })
+        .await
+}
+
+/// How long a job cancelled while running may take to return on its own
+/// before it is dropped.
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How a job run by a queue provider ended.
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+pub(crate) enum JobOutcome {
+    Completed(Option<serde_json::Value>),
+    Failed(Error),
+    /// The job was cancelled in the backend while it was running. Its status
+    /// is already [`JobStatus::Cancelled`].
+    Cancelled,
+    /// The worker shut down and the job was still running after the shutdown
+    /// timeout. It is still processing in the backend and has to be put back
+    /// in the queue.
+    Interrupted,
+}
+
+/// Drives a job handler for the `run` loops of the queue providers.
+///
+/// The job fails once it runs past `timeout`. Every `poll_interval`,
+/// `is_cancelled` checks the backend for a cancellation, in which case
+/// `token` is cancelled and the job gets [`CANCEL_GRACE_PERIOD`] to wind
+/// down.
+///
+/// Once `shutdown` is cancelled, the job may run for `shutdown_timeout` more
+/// before it is dropped and reported as [`JobOutcome::Interrupted`]. Without
+/// a `shutdown_timeout` it runs to the end.
+#[cfg(any(
+    feature = "bg_redis",
+    feature = "bg_pg",
+    feature = "bg_sqlt",
+    feature = "bg_inmem"
+))]
+pub(crate) async fn run_job<F, C, Fut>(
+    perform: F,
+    token: &CancellationToken,
+    timeout: Option<Duration>,
+    poll_interval: Duration,
+    is_cancelled: C,
+    shutdown: &CancellationToken,
+    shutdown_timeout: Option<Duration>,
+) -> JobOutcome
+where
+    F: std::future::Future<Output = Result<Option<serde_json::Value>>>,
+    C: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<bool>>,
+{
+    let perform = async {
+        let Some(timeout) = timeout else {
+            return perform.await;
+        };
+        if let Ok(result) = tokio::time::timeout(timeout, perform).await {
+            result
+        } else {
+            token.cancel();
+            Err(Error::Message(format!("job timed out after {timeout:?}")))
+        }
+    };
+    tokio::pin!(perform);
+
+    let watch = async {
+        loop {
+            tokio::time::sleep(poll_interval).await;
+            match is_cancelled().await {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(err) => {
+                    tracing::error!(error = %err, "Failed to check whether the job was cancelled");
+                }
+            }
+        }
+    };
+
+    let drain = async {
+        let Some(shutdown_timeout) = shutdown_timeout else {
+            return std::future::pending().await;
+        };
+        shutdown.cancelled().await;
+        tokio::time::sleep(shutdown_timeout).await;
+    };
+
+    tokio::select! {
+        result = &mut perform => {
+            return match result {
+                Ok(result) => JobOutcome::Completed(result),
+                Err(err) => JobOutcome::Failed(err),
+            };
+        }
+        () = watch => {}
+        () = drain => {
+            token.cancel();
+            tracing::warn!(
+                ?shutdown_timeout,
+                "Job still running after the shutdown timeout, putting it back in the queue"
+            );
+            return JobOutcome::Interrupted;
+        }
+    }
+
+    token.cancel();
+    if tokio::time::timeout(CANCEL_GRACE_PERIOD, perform)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Cancelled job did not stop within the grace period, dropping it");
+    }
+    JobOutcome::Cancelled
+}
+
+/// Selects the [`JobStatus::Failed`] and [`JobStatus::Dead`] jobs that
+/// [`Queue::retry_jobs`] puts back in the queue.
+#[derive(Clone, Debug)]
+pub enum RetryTarget {
+    /// A single job by id.
+    Id(String),
+    /// Every job of the given worker name.
+    Name(String),
+    /// Every failed or dead job.
+    AllFailed,
+}
+
 // Queue struct now holds both a QueueProvider and QueueRegistrar
 pub enum Queue {
     #[cfg(feature = "bg_redis")]
@@ -85,60 +679,288 @@ pub enum Queue {
         sqlt::RunOpts,
         tokio_util::sync::CancellationToken,
     ),
+    #[cfg(feature = "bg_inmem")]
+    InMem(
+        inmem::JobStore,
+        std::sync::Arc<tokio::sync::Mutex<inmem::JobRegistry>>,
+        inmem::RunOpts,
+        tokio_util::sync::CancellationToken,
+    ),
     None,
 }
 
+impl std::fmt::Debug for Queue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Queue").field(&self.describe()).finish()
+    }
+}
+
 impl Queue {
-    /// Add a job to the queue
+    /// Add a job to the queue, returning its id (`None` when no queue
+    /// provider is configured).
     ///
     /// # Errors
     ///
     /// This function will return an error if fails
-    #[allow(unused_variables)]
     pub async fn enqueue<A: Serialize + Send + Sync>(
         &self,
         class: String,
         queue: Option<String>,
         args: A,
         tags: Option<Vec<String>>,
-    ) -> Result<()> {
-        tracing::debug!(worker = class, queue = ?queue, tags = ?tags, "Enqueuing background job");
-        match self {
+    ) -> Result<Option<String>> {
+        self.enqueue_with(class, queue, args, tags, EnqueueOpts::default())
+            .await
+    }
+
+    /// Add a job to the queue that runs at `opts.run_at` and, when
+    /// `opts.interval` is set, keeps running at that interval.
+    ///
+    /// Returns the id of the job, or of the job already holding
+    /// `opts.unique_key`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if fails
+    #[allow(unused_variables)]
+    pub async fn enqueue_with<A: Serialize + Send + Sync>(
+        &self,
+        class: String,
+        queue: Option<String>,
+        args: A,
+        tags: Option<Vec<String>>,
+        opts: EnqueueOpts,
+    ) -> Result<Option<String>> {
+        tracing::debug!(worker = class, queue = ?queue, tags = ?tags, opts = ?opts, "Enqueuing background job");
+        let id = match self {
             #[cfg(feature = "bg_redis")]
             Self::Redis(pool, _, _, _) => {
-                redis::enqueue(pool, class, queue, args, tags).await?;
+                Some(redis::enqueue(pool, class, queue, args, tags, &opts).await?)
             }
             #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => Some(
+                pg::enqueue(pool, &class, serde_json::to_value(args)?, tags, &opts)
+                    .await
+                    .map_err(Box::from)?,
+            ),
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => Some(
+                sqlt::enqueue(pool, &class, serde_json::to_value(args)?, tags, &opts)
+                    .await
+                    .map_err(Box::from)?,
+            ),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Some(inmem::enqueue(
+                store,
+                &class,
+                serde_json::to_value(args)?,
+                tags,
+                &opts,
+            )?),
+            _ => None,
+        };
+        Ok(id)
+    }
+
+    /// Like [`Queue::enqueue_with`], but adds the job within `tx`, a transaction
+    /// of the application database. Workers only see the job once `tx`
+    /// commits, and it is discarded if `tx` rolls back, so a job never runs
+    /// for model changes that were rolled back.
+    ///
+    /// The queue tables must live in the application database: configure the
+    /// Postgres or SQLite queue with the same database as `database.uri`.
+    ///
+    /// # Errors
+    /// - If the queue provider can't enqueue in a database transaction (Redis, in-memory or none).
+    /// - If `tx` belongs to another kind of database than the queue.
+    /// - Any error in the underlying provider's enqueue logic will propagate from the respective function.
+    #[cfg(feature = "with-db")]
+    #[allow(unused_variables)]
+    pub async fn enqueue_in_tx<A: Serialize + Send + Sync>(
+        &self,
+        tx: &sea_orm::DatabaseTransaction,
+        class: String,
+        queue: Option<String>,
+        args: A,
+        tags: Option<Vec<String>>,
+        opts: EnqueueOpts,
+    ) -> Result<String> {
+        tracing::debug!(worker = class, queue = ?queue, tags = ?tags, opts = ?opts, "Enqueuing background job in transaction");
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(_, _, _, _) => {
+                pg::enqueue_in_tx(tx, &class, serde_json::to_value(args)?, tags, &opts).await
+            }
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(_, _, _, _) => {
+                sqlt::enqueue_in_tx(tx, &class, serde_json::to_value(args)?, tags, &opts).await
+            }
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(_, _, _, _) => Err(Error::string(
+                "the Redis queue can't enqueue jobs in a database transaction",
+            )),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(_, _, _, _) => Err(Error::string(
+                "the in-memory queue can't enqueue jobs in a database transaction",
+            )),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Adds all the jobs of `batch` to the queue at once. See [`Batch`] for
+    /// when its callbacks run.
+    ///
+    /// A batch without jobs is finished right away, so its `on_complete`
+    /// job is enqueued immediately.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if fails
+    #[allow(unused_variables)]
+    pub async fn enqueue_batch(&self, batch: Batch) -> Result<()> {
+        tracing::debug!(
+            batch_id = batch.id,
+            jobs = batch.jobs.len(),
+            "Enqueuing batch of background jobs"
+        );
+        match self {
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => redis::enqueue_batch(pool, &batch).await?,
+            #[cfg(feature = "bg_pg")]
             Self::Postgres(pool, _, _, _) => {
-                pg::enqueue(
-                    pool,
-                    &class,
-                    serde_json::to_value(args)?,
-                    chrono::Utc::now(),
-                    None,
-                    tags,
-                )
-                .await
-                .map_err(Box::from)?;
+                pg::enqueue_batch(pool, &batch).await.map_err(Box::from)?
             }
             #[cfg(feature = "bg_sqlt")]
             Self::Sqlite(pool, _, _, _) => {
-                sqlt::enqueue(
-                    pool,
-                    &class,
-                    serde_json::to_value(args)?,
-                    chrono::Utc::now(),
-                    None,
-                    tags,
-                )
-                .await
-                .map_err(Box::from)?;
+                sqlt::enqueue_batch(pool, &batch).await.map_err(Box::from)?
             }
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => inmem::enqueue_batch(store, &batch)?,
             _ => {}
         }
         Ok(())
     }
 
+    /// Looks up the progress of a batch by its [`Batch::id`].
+    ///
+    /// # Errors
+    /// - If no queue provider is configured, it will return an error indicating the lack of configuration.
+    /// - Any error in the underlying provider's lookup will propagate from the respective function.
+    pub async fn get_batch(&self, id: &str) -> Result<Option<BatchInfo>> {
+        tracing::debug!(batch_id = id, "Looking up batch");
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => Ok(pg::get_batch(pool, id).await?),
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => Ok(sqlt::get_batch(pool, id).await?),
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => Ok(redis::get_batch(pool, id).await?),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(inmem::get_batch(store, id)),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Looks up a job by the id returned when it was enqueued.
+    ///
+    /// Returns `None` when no job has this id, for example because it was
+    /// cleared from the queue.
+    ///
+    /// # Errors
+    /// - If no queue provider is configured, it will return an error indicating the lack of configuration.
+    /// - Any error in the underlying provider's lookup will propagate from the respective function.
+    pub async fn get_job(&self, id: &str) -> Result<Option<JobInfo>> {
+        tracing::debug!(job_id = id, "Looking up job");
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => Ok(pg::get_job(pool, id).await?.map(JobInfo::from)),
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => Ok(sqlt::get_job(pool, id).await?.map(JobInfo::from)),
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => Ok(redis::get_job(pool, id).await?.map(JobInfo::from)),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(inmem::get_job(store, id).map(JobInfo::from)),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Lists the worker processes running this queue, with the jobs each of
+    /// them is running.
+    ///
+    /// Running workers record a heartbeat every few seconds. A worker whose
+    /// heartbeat expired is left out, and the jobs it was running are put
+    /// back in the queue by the other workers.
+    ///
+    /// # Errors
+    /// - If no queue provider is configured, it will return an error indicating the lack of configuration.
+    /// - Any error in the underlying provider's lookup will propagate from the respective function.
+    pub async fn get_workers(&self) -> Result<Vec<WorkerInfo>> {
+        tracing::debug!("Listing workers");
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => pg::get_workers(pool).await,
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => sqlt::get_workers(pool).await,
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => redis::get_workers(pool).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(inmem::get_workers(store)),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Takes the lock `name` for `owner` until `ttl` from now, or extends it
+    /// when `owner` already holds it. Returns `false` while another owner
+    /// holds a lock that hasn't expired.
+    ///
+    /// The lock lives in the queue backend, so processes sharing a queue can
+    /// use it to agree on which of them does some work, as the scheduler does
+    /// for each tick of a job that enqueues a worker.
+    ///
+    /// # Errors
+    /// - If no queue provider is configured, it will return an error indicating the lack of configuration.
+    /// - Any error in the underlying provider's locking logic will propagate from the respective function.
+    #[allow(unused_variables)]
+    pub async fn try_lock(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool> {
+        tracing::trace!(lock = name, owner, ttl = ?ttl, "Taking lock");
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => pg::try_lock(pool, name, owner, ttl).await,
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => sqlt::try_lock(pool, name, owner, ttl).await,
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => redis::try_lock(pool, name, owner, ttl).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(inmem::try_lock(store, name, owner, ttl)),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
     /// Register a worker
     ///
     /// # Errors
@@ -169,6 +991,11 @@ impl Queue {
                 let mut r = registry.lock().await;
                 r.register_worker(W::class_name(), worker)?;
             }
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(_, registry, _, _) => {
+                let mut r = registry.lock().await;
+                r.register_worker(W::class_name(), worker)?;
+            }
             _ => {}
         }
         Ok(())
@@ -207,6 +1034,14 @@ impl Queue {
                     .run(pool, run_opts, &token.clone(), &tags);
                 Self::process_worker_handles(handles).await?;
             }
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, registry, run_opts, token) => {
+                let handles = registry
+                    .lock()
+                    .await
+                    .run(store, run_opts, &token.clone(), &tags);
+                Self::process_worker_handles(handles).await?;
+            }
             _ => {
                 tracing::error!(
                     "No queue provider is configured: compile with at least one queue provider feature"
@@ -290,6 +1125,8 @@ impl Queue {
             Self::Sqlite(pool, _, _, _) => {
                 sqlt::clear(pool).await.map_err(Box::from)?;
             }
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => inmem::clear(store),
             _ => {}
         }
         Ok(())
@@ -329,10 +1166,40 @@ impl Queue {
             Self::Postgres(_, _, _, _) => "postgres queue".to_string(),
             #[cfg(feature = "bg_sqlt")]
             Self::Sqlite(_, _, _, _) => "sqlite queue".to_string(),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(_, _, _, _) => "inmem queue".to_string(),
             _ => "no queue".to_string(),
         }
     }
 
+    /// Sets how long running jobs may take to finish once the queue shuts
+    /// down, see `workers.shutdown_timeout`.
+    #[cfg(any(
+        feature = "bg_redis",
+        feature = "bg_pg",
+        feature = "bg_sqlt",
+        feature = "bg_inmem"
+    ))]
+    #[must_use]
+    #[allow(unused_mut)]
+    fn with_shutdown_timeout(mut self, timeout: Option<Duration>) -> Self {
+        match &mut self {
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(_, _, run_opts, _) => run_opts.shutdown_timeout = timeout,
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(_, _, run_opts, _) => run_opts.shutdown_timeout = timeout,
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(_, _, run_opts, _) => run_opts.shutdown_timeout = timeout,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(_, _, run_opts, _) => run_opts.shutdown_timeout = timeout,
+            _ => {}
+        }
+        self
+    }
+
+    /// Stops taking new jobs. Running jobs are given `workers.shutdown_timeout`
+    /// to finish, after which they are put back in the queue.
+    ///
     /// # Errors
     ///
     /// Does not currently return an error, but the postgres or other future
@@ -340,45 +1207,171 @@ impl Queue {
     pub fn shutdown(&self) -> Result<()> {
         tracing::info!("Shutting down background job processing");
         match self {
-            #[cfg(feature = "bg_redis")]
-            Self::Redis(_, _, _, cancellation_token) => cancellation_token.cancel(),
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(_, _, _, cancellation_token) => cancellation_token.cancel(),
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(_, _, _, cancellation_token) => cancellation_token.cancel(),
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(_, _, _, cancellation_token) => cancellation_token.cancel(),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(_, _, _, cancellation_token) => cancellation_token.cancel(),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_jobs(
+        &self,
+        status: Option<&Vec<JobStatus>>,
+        age_days: Option<i64>,
+    ) -> Result<serde_json::Value> {
+        tracing::info!(status = ?status, age_days = ?age_days, "Retrieving jobs");
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => {
+                let jobs = pg::get_jobs(pool, status, age_days)
+                    .await
+                    .map_err(Box::from)?;
+                Ok(serde_json::to_value(jobs)?)
+            }
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => {
+                let jobs = sqlt::get_jobs(pool, status, age_days)
+                    .await
+                    .map_err(Box::from)?;
+
+                Ok(serde_json::to_value(jobs)?)
+            }
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => {
+                let jobs = redis::get_jobs(pool, status, age_days).await?;
+                Ok(serde_json::to_value(jobs)?)
+            }
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(serde_json::to_value(inmem::get_jobs(
+                store, status, age_days,
+            ))?),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Counts the jobs by queue, worker and status for the dashboard, without
+    /// loading them.
+    #[cfg(feature = "bg_dashboard")]
+    pub(crate) async fn count_jobs(&self) -> Result<Vec<dashboard::JobCount>> {
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => pg::count_jobs(pool).await,
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => sqlt::count_jobs(pool).await,
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => redis::count_jobs(pool).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(inmem::count_jobs(store)),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Lists the `limit` most recently updated jobs, optionally only those
+    /// with the given `status` and `name`, as stored by the provider.
+    #[cfg(feature = "bg_dashboard")]
+    #[allow(unused_variables)]
+    pub(crate) async fn recent_jobs(
+        &self,
+        status: Option<&JobStatus>,
+        name: Option<&str>,
+        limit: usize,
+    ) -> Result<serde_json::Value> {
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => Ok(serde_json::to_value(
+                pg::recent_jobs(pool, status, name, limit).await?,
+            )?),
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => Ok(serde_json::to_value(
+                sqlt::recent_jobs(pool, status, name, limit).await?,
+            )?),
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => Ok(serde_json::to_value(
+                redis::recent_jobs(pool, status, name, limit).await?,
+            )?),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(serde_json::to_value(inmem::recent_jobs(
+                store, status, name, limit,
+            ))?),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Like [`Queue::get_job`], but returns the job as stored by the provider,
+    /// including its arguments, tags and backtrace.
+    #[cfg(feature = "bg_dashboard")]
+    #[allow(unused_variables)]
+    pub(crate) async fn get_job_data(&self, id: &str) -> Result<Option<serde_json::Value>> {
+        match self {
             #[cfg(feature = "bg_pg")]
-            Self::Postgres(_, _, _, cancellation_token) => cancellation_token.cancel(),
+            Self::Postgres(pool, _, _, _) => Ok(pg::get_job(pool, id)
+                .await?
+                .map(serde_json::to_value)
+                .transpose()?),
             #[cfg(feature = "bg_sqlt")]
-            Self::Sqlite(_, _, _, cancellation_token) => cancellation_token.cancel(),
-            _ => {}
+            Self::Sqlite(pool, _, _, _) => Ok(sqlt::get_job(pool, id)
+                .await?
+                .map(serde_json::to_value)
+                .transpose()?),
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => Ok(redis::get_job(pool, id)
+                .await?
+                .map(serde_json::to_value)
+                .transpose()?),
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => Ok(inmem::get_job(store, id)
+                .map(serde_json::to_value)
+                .transpose()?),
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
         }
-
-        Ok(())
     }
 
-    async fn get_jobs(
-        &self,
-        status: Option<&Vec<JobStatus>>,
-        age_days: Option<i64>,
-    ) -> Result<serde_json::Value> {
-        tracing::info!(status = ?status, age_days = ?age_days, "Retrieving jobs");
+    /// Cancels a single queued or running job by id.
+    ///
+    /// Returns `false` when no queued or running job has this id, for example
+    /// because it already finished.
+    ///
+    /// # Errors
+    /// - If no queue provider is configured, it will return an error indicating the lack of configuration.
+    /// - Any error in the underlying provider's cancellation logic will propagate from the respective function.
+    pub async fn cancel_job(&self, id: &str) -> Result<bool> {
+        tracing::info!(job_id = id, "Cancelling job");
         match self {
             #[cfg(feature = "bg_pg")]
-            Self::Postgres(pool, _, _, _) => {
-                let jobs = pg::get_jobs(pool, status, age_days)
-                    .await
-                    .map_err(Box::from)?;
-                Ok(serde_json::to_value(jobs)?)
-            }
+            Self::Postgres(pool, _, _, _) => pg::cancel_job(pool, id).await,
             #[cfg(feature = "bg_sqlt")]
-            Self::Sqlite(pool, _, _, _) => {
-                let jobs = sqlt::get_jobs(pool, status, age_days)
-                    .await
-                    .map_err(Box::from)?;
-
-                Ok(serde_json::to_value(jobs)?)
-            }
+            Self::Sqlite(pool, _, _, _) => sqlt::cancel_job(pool, id).await,
             #[cfg(feature = "bg_redis")]
-            Self::Redis(pool, _, _, _) => {
-                let jobs = redis::get_jobs(pool, status, age_days).await?;
-                Ok(serde_json::to_value(jobs)?)
-            }
+            Self::Redis(pool, _, _, _) => redis::cancel_job(pool, id).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => inmem::cancel_job(store, id),
             Self::None => {
                 tracing::error!(
                     "No queue provider is configured: compile with at least one queue provider feature"
@@ -405,6 +1398,8 @@ impl Queue {
             Self::Sqlite(pool, _, _, _) => sqlt::cancel_jobs_by_name(pool, job_name).await,
             #[cfg(feature = "bg_redis")]
             Self::Redis(pool, _, _, _) => redis::cancel_jobs_by_name(pool, job_name).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => inmem::cancel_jobs_by_name(store, job_name),
             Self::None => {
                 tracing::error!(
                     "No queue provider is configured: compile with at least one queue provider feature"
@@ -441,6 +1436,11 @@ impl Queue {
             Self::Redis(pool, _, _, _) => {
                 redis::clear_jobs_older_than(pool, age_days, Some(status)).await
             }
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => {
+                inmem::clear_jobs_older_than(store, age_days, Some(status));
+                Ok(())
+            }
             Self::None => {
                 tracing::error!(
                     "No queue provider is configured: compile with at least one queue provider feature"
@@ -465,6 +1465,11 @@ impl Queue {
             Self::Sqlite(pool, _, _, _) => sqlt::clear_by_status(pool, status).await,
             #[cfg(feature = "bg_redis")]
             Self::Redis(pool, _, _, _) => redis::clear_by_status(pool, status).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => {
+                inmem::clear_by_status(store, &status);
+                Ok(())
+            }
             Self::None => {
                 tracing::error!(
                     "No queue provider is configured: compile with at least one queue provider feature"
@@ -489,6 +1494,40 @@ impl Queue {
             Self::Sqlite(pool, _, _, _) => sqlt::requeue(pool, age_minutes).await,
             #[cfg(feature = "bg_redis")]
             Self::Redis(pool, _, _, _) => redis::requeue(pool, age_minutes).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => {
+                inmem::requeue(store, age_minutes);
+                Ok(())
+            }
+            Self::None => {
+                tracing::error!(
+                    "No queue provider is configured: compile with at least one queue provider feature"
+                );
+                Err(Error::string("provider not configured"))
+            }
+        }
+    }
+
+    /// Moves failed and dead jobs matching `target` back to
+    /// [`JobStatus::Queued`] so they run again with a fresh retry budget.
+    ///
+    /// # Errors
+    /// - If no queue provider is configured, it will return an error indicating the lack of configuration.
+    /// - Any error in the underlying provider's retry logic will propagate from the respective function.
+    pub async fn retry_jobs(&self, target: &RetryTarget) -> Result<()> {
+        tracing::info!(target = ?target, "Retrying failed jobs");
+        match self {
+            #[cfg(feature = "bg_pg")]
+            Self::Postgres(pool, _, _, _) => pg::retry_jobs(pool, target).await,
+            #[cfg(feature = "bg_sqlt")]
+            Self::Sqlite(pool, _, _, _) => sqlt::retry_jobs(pool, target).await,
+            #[cfg(feature = "bg_redis")]
+            Self::Redis(pool, _, _, _) => redis::retry_jobs(pool, target).await,
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(store, _, _, _) => {
+                inmem::retry_jobs(store, target);
+                Ok(())
+            }
             Self::None => {
                 tracing::error!(
                     "No queue provider is configured: compile with at least one queue provider feature"
@@ -553,7 +1592,13 @@ impl Queue {
             Self::Postgres(_, _, _, _) => {
                 let jobs: Vec<pg::Job> = serde_yaml::from_reader(File::open(path)?)?;
                 for job in jobs {
-                    self.enqueue(job.name.clone(), None, job.data, None).await?;
+                    let opts = EnqueueOpts {
+                        priority: Some(job.priority),
+                        unique_key: job.unique_key,
+                        ..Default::default()
+                    };
+                    self.enqueue_with(job.name.clone(), None, job.data, None, opts)
+                        .await?;
                 }
 
                 Ok(())
@@ -562,7 +1607,13 @@ impl Queue {
             Self::Sqlite(_, _, _, _) => {
                 let jobs: Vec<sqlt::Job> = serde_yaml::from_reader(File::open(path)?)?;
                 for job in jobs {
-                    self.enqueue(job.name.clone(), None, job.data, None).await?;
+                    let opts = EnqueueOpts {
+                        priority: Some(job.priority),
+                        unique_key: job.unique_key,
+                        ..Default::default()
+                    };
+                    self.enqueue_with(job.name.clone(), None, job.data, None, opts)
+                        .await?;
                 }
                 Ok(())
             }
@@ -570,7 +1621,27 @@ impl Queue {
             Self::Redis(_, _, _, _) => {
                 let jobs: Vec<redis::Job> = serde_yaml::from_reader(File::open(path)?)?;
                 for job in jobs {
-                    self.enqueue(job.name.clone(), None, job.data, None).await?;
+                    let opts = EnqueueOpts {
+                        priority: Some(job.priority),
+                        unique_key: job.unique_key,
+                        ..Default::default()
+                    };
+                    self.enqueue_with(job.name.clone(), None, job.data, None, opts)
+                        .await?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "bg_inmem")]
+            Self::InMem(_, _, _, _) => {
+                let jobs: Vec<inmem::Job> = serde_yaml::from_reader(File::open(path)?)?;
+                for job in jobs {
+                    let opts = EnqueueOpts {
+                        priority: Some(job.priority),
+                        unique_key: job.unique_key,
+                        ..Default::default()
+                    };
+                    self.enqueue_with(job.name.clone(), None, job.data, None, opts)
+                        .await?;
                 }
                 Ok(())
             }
@@ -584,11 +1655,38 @@ impl Queue {
     }
 }
 
+/// Fills the options `opts` leaves unset from the defaults of worker `W`.
+fn worker_enqueue_opts<A, W>(args: &A, opts: EnqueueOpts) -> EnqueueOpts
+where
+    A: Send + Sync + serde::Serialize + 'static,
+    W: BackgroundWorker<A>,
+{
+    EnqueueOpts {
+        priority: opts.priority.or_else(|| Some(W::priority())),
+        unique_key: opts.unique_key.or_else(|| W::unique_key(args)),
+        unique_for: opts.unique_for.or_else(W::unique_for),
+        ..opts
+    }
+}
+
+fn worker_tags<A, W>() -> Option<Vec<String>>
+where
+    A: Send + Sync + serde::Serialize + 'static,
+    W: BackgroundWorker<A>,
+{
+    let tags = W::tags();
+    if tags.is_empty() {
+        None
+    } else {
+        Some(tags)
+    }
+}
+
 #[async_trait]
 pub trait BackgroundWorker<A: Send + Sync + serde::Serialize + 'static>: Send + Sync {
-    /// If you have a specific queue
-    /// in mind and the provider supports custom / priority queues, make your
-    /// worker return it. Otherwise, return `None`.
+    /// If you have a specific queue in mind, make your worker return it.
+    /// Otherwise, return `None`. To run some jobs ahead of others in the same
+    /// queue, see [`BackgroundWorker::priority`].
     #[must_use]
     fn queue() -> Option<String> {
         None
@@ -601,6 +1699,68 @@ pub trait BackgroundWorker<A: Send + Sync + serde::Serialize + 'static>: Send +
         Vec::new()
     }
 
+    /// Jobs with a higher priority are picked up before other jobs of the
+    /// same queue that are due. Defaults to `0`.
+    #[must_use]
+    fn priority() -> i16 {
+        0
+    }
+
+    /// Key that deduplicates jobs: while a job of this worker with the same
+    /// key is queued or processing, enqueuing another one does nothing.
+    /// Defaults to no deduplication.
+    #[must_use]
+    fn unique_key(_args: &A) -> Option<String> {
+        None
+    }
+
+    /// How long [`BackgroundWorker::unique_key`] is enforced after a job is
+    /// enqueued. Defaults to as long as the job is queued or processing.
+    #[must_use]
+    fn unique_for() -> Option<Duration> {
+        None
+    }
+
+    /// How many times a failed job is rescheduled before it is moved to
+    /// [`JobStatus::Dead`]. Defaults to no retries, in which case a failing
+    /// job is marked as [`JobStatus::Failed`] right away.
+    #[must_use]
+    fn max_retries() -> u32 {
+        0
+    }
+
+    /// Delay before retry number `attempt` (starting at `1`). Defaults to
+    /// [`exponential_backoff`].
+    #[must_use]
+    fn backoff(attempt: u32) -> Duration {
+        exponential_backoff(attempt)
+    }
+
+    /// Longest a queued job may run. A job still running after that is
+    /// stopped and counts as a failed attempt, so retries apply. Defaults to
+    /// no limit.
+    #[must_use]
+    fn timeout() -> Option<Duration> {
+        None
+    }
+
+    /// Most jobs of this worker allowed to run at the same time, across all
+    /// worker processes sharing the queue. Jobs over the limit stay queued
+    /// until a running one finishes. Defaults to no limit.
+    #[must_use]
+    fn concurrency_limit() -> Option<u32> {
+        None
+    }
+
+    /// Most jobs of this worker allowed to start per time window, across all
+    /// worker processes sharing the queue, e.g. `RateLimit::per_minute(100)`.
+    /// Jobs over the limit stay queued until the next window. Defaults to no
+    /// limit.
+    #[must_use]
+    fn rate_limit() -> Option<RateLimit> {
+        None
+    }
+
     fn build(ctx: &AppContext) -> Self;
     #[must_use]
     fn class_name() -> String
@@ -612,40 +1772,197 @@ pub trait BackgroundWorker<A: Send + Sync + serde::Serialize + 'static>: Send +
         let name = type_name.split("::").last().unwrap_or(type_name);
         name.to_upper_camel_case()
     }
-    async fn perform_later(ctx: &AppContext, args: A) -> crate::Result<()>
+    /// Runs the job according to the configured [`WorkerMode`].
+    ///
+    /// With [`WorkerMode::BackgroundQueue`] the id of the enqueued job is
+    /// returned, which can be passed to [`Queue::get_job`] to follow its
+    /// progress. The other modes don't enqueue anything and return `None`.
+    async fn perform_later(ctx: &AppContext, args: A) -> crate::Result<Option<String>>
+    where
+        Self: Sized,
+    {
+        Self::perform_later_with(ctx, args, EnqueueOpts::default()).await
+    }
+
+    /// Like [`BackgroundWorker::perform_later`], with per-job options such as
+    /// a priority or a delay.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the job can't be added, or when [`EnqueueOpts::interval`] is
+    /// set in [`WorkerMode::BackgroundAsync`] mode, which has no queue to keep
+    /// recurring jobs in.
+    async fn perform_later_with(
+        ctx: &AppContext,
+        args: A,
+        opts: EnqueueOpts,
+    ) -> crate::Result<Option<String>>
     where
         Self: Sized,
     {
         match &ctx.config.workers.mode {
             WorkerMode::BackgroundQueue => {
                 if let Some(p) = &ctx.queue_provider {
-                    let tags = Self::tags();
-                    let tags_option = if tags.is_empty() { None } else { Some(tags) };
-                    p.enqueue(Self::class_name(), Self::queue(), args, tags_option)
-                        .await?;
-                } else {
-                    tracing::error!(
-                        "perform_later: background queue is selected, but queue was not populated \
-                         in context"
-                    );
+                    let opts = worker_enqueue_opts::<A, Self>(&args, opts);
+                    return p
+                        .enqueue_with(
+                            Self::class_name(),
+                            Self::queue(),
+                            args,
+                            worker_tags::<A, Self>(),
+                            opts,
+                        )
+                        .await;
                 }
+                tracing::error!(
+                    "perform_later: background queue is selected, but queue was not populated in \
+                     context"
+                );
             }
             WorkerMode::ForegroundBlocking => {
-                Self::build(ctx).perform(args).await?;
+                Self::build(ctx)
+                    .perform_with_token(args, CancellationToken::new())
+                    .await?;
             }
             WorkerMode::BackgroundAsync => {
+                if opts.interval.is_some() {
+                    return Err(Error::string(
+                        "recurring jobs need a queue, set `workers.mode` to `BackgroundQueue`",
+                    ));
+                }
                 let dx = ctx.clone();
                 tokio::spawn(async move {
-                    if let Err(err) = Self::build(&dx).perform(args).await {
+                    if let Some(run_at) = opts.run_at {
+                        if let Ok(delay) = (run_at - Utc::now()).to_std() {
+                            tokio::time::sleep(delay).await;
+                        }
+                    }
+                    if let Err(err) = Self::build(&dx)
+                        .perform_with_token(args, CancellationToken::new())
+                        .await
+                    {
                         tracing::error!(err = err.to_string(), "worker failed to perform job");
                     }
                 });
             }
         }
-        Ok(())
+        Ok(None)
+    }
+
+    /// Like [`BackgroundWorker::perform_later`], but in
+    /// [`WorkerMode::BackgroundQueue`] mode the job is enqueued within `tx`,
+    /// see [`Queue::enqueue_in_tx`]. It only runs once `tx` commits, and never
+    /// if `tx` rolls back.
+    ///
+    /// The other modes have no queue to enqueue into and behave like
+    /// [`BackgroundWorker::perform_later`], so the job runs even if `tx` rolls
+    /// back.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the queue provider can't enqueue in a database transaction, or
+    /// if the job can't be added.
+    #[cfg(feature = "with-db")]
+    async fn perform_later_in_tx(
+        ctx: &AppContext,
+        tx: &sea_orm::DatabaseTransaction,
+        args: A,
+    ) -> crate::Result<Option<String>>
+    where
+        Self: Sized,
+    {
+        if let (WorkerMode::BackgroundQueue, Some(p)) =
+            (&ctx.config.workers.mode, &ctx.queue_provider)
+        {
+            let opts = worker_enqueue_opts::<A, Self>(&args, EnqueueOpts::default());
+            let id = p
+                .enqueue_in_tx(
+                    tx,
+                    Self::class_name(),
+                    Self::queue(),
+                    args,
+                    worker_tags::<A, Self>(),
+                    opts,
+                )
+                .await?;
+            return Ok(Some(id));
+        }
+        Self::perform_later(ctx, args).await
+    }
+
+    /// Runs the job at `when`.
+    ///
+    /// In [`WorkerMode::ForegroundBlocking`] the job runs right away.
+    async fn perform_at(
+        ctx: &AppContext,
+        when: DateTime<Utc>,
+        args: A,
+    ) -> crate::Result<Option<String>>
+    where
+        Self: Sized,
+    {
+        let opts = EnqueueOpts {
+            run_at: Some(when),
+            ..Default::default()
+        };
+        Self::perform_later_with(ctx, args, opts).await
+    }
+
+    /// Runs the job once `delay` has passed.
+    ///
+    /// In [`WorkerMode::ForegroundBlocking`] the job runs right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `delay` is too large to be represented as a date.
+    async fn perform_in(ctx: &AppContext, delay: Duration, args: A) -> crate::Result<Option<String>>
+    where
+        Self: Sized,
+    {
+        let delay =
+            chrono::Duration::from_std(delay).map_err(|err| Error::Message(err.to_string()))?;
+        Self::perform_at(ctx, Utc::now() + delay, args).await
+    }
+
+    /// Runs the job now and then again every `interval` after each
+    /// successful run.
+    ///
+    /// In [`WorkerMode::ForegroundBlocking`] the job runs only once.
+    ///
+    /// # Errors
+    ///
+    /// Fails in [`WorkerMode::BackgroundAsync`] mode, where there is no queue
+    /// to keep the job in between runs, or if the job can't be added.
+    async fn perform_every(
+        ctx: &AppContext,
+        interval: Duration,
+        args: A,
+    ) -> crate::Result<Option<String>>
+    where
+        Self: Sized,
+    {
+        let opts = EnqueueOpts {
+            interval: Some(interval),
+            ..Default::default()
+        };
+        Self::perform_later_with(ctx, args, opts).await
     }
 
     async fn perform(&self, args: A) -> crate::Result<()>;
+
+    /// Performs the job with its cancellation token, which is cancelled when
+    /// the job is cancelled with `cargo loco jobs cancel` while running, when
+    /// it runs past [`BackgroundWorker::timeout`], or when the worker shuts
+    /// down and the job is still running after `workers.shutdown_timeout`.
+    ///
+    /// Override it instead of [`BackgroundWorker::perform`] to stop long
+    /// running jobs early, by checking the token between steps or handing it
+    /// to work they spawn. Calls [`BackgroundWorker::perform`] by default.
+    /// Outside of a queue the token is never cancelled.
+    async fn perform_with_token(&self, args: A, token: CancellationToken) -> crate::Result<()> {
+        let _ = token;
+        self.perform(args).await
+    }
 }
 
 /// Initialize the system according to configuration
@@ -689,6 +2006,7 @@ pub async fn converge(queue: &Queue, config: &QueueConfig) -> Result<()> {
                 queue.clear().await?;
             }
         }
+        QueueConfig::InMem(_) => {}
     }
     Ok(())
 }
@@ -701,22 +2019,48 @@ pub async fn converge(queue: &Queue, config: &QueueConfig) -> Result<()> {
 #[allow(clippy::missing_panics_doc)]
 pub async fn create_queue_provider(config: &Config) -> Result<Option<Arc<Queue>>> {
     if config.workers.mode == config::WorkerMode::BackgroundQueue {
+        #[cfg(any(
+            feature = "bg_redis",
+            feature = "bg_pg",
+            feature = "bg_sqlt",
+            feature = "bg_inmem"
+        ))]
+        let shutdown_timeout = config.workers.shutdown_timeout.map(Duration::from_millis);
         if let Some(queue) = &config.queue {
             match queue {
                 #[cfg(feature = "bg_redis")]
                 config::QueueConfig::Redis(qcfg) => {
                     tracing::debug!("Creating Redis queue provider");
-                    Ok(Some(Arc::new(redis::create_provider(qcfg).await?)))
+                    Ok(Some(Arc::new(
+                        redis::create_provider(qcfg)
+                            .await?
+                            .with_shutdown_timeout(shutdown_timeout),
+                    )))
                 }
                 #[cfg(feature = "bg_pg")]
                 config::QueueConfig::Postgres(qcfg) => {
                     tracing::debug!("Creating Postgres queue provider");
-                    Ok(Some(Arc::new(pg::create_provider(qcfg).await?)))
+                    Ok(Some(Arc::new(
+                        pg::create_provider(qcfg)
+                            .await?
+                            .with_shutdown_timeout(shutdown_timeout),
+                    )))
                 }
                 #[cfg(feature = "bg_sqlt")]
                 config::QueueConfig::Sqlite(qcfg) => {
                     tracing::debug!("Creating SQLite queue provider");
-                    Ok(Some(Arc::new(sqlt::create_provider(qcfg).await?)))
+                    Ok(Some(Arc::new(
+                        sqlt::create_provider(qcfg)
+                            .await?
+                            .with_shutdown_timeout(shutdown_timeout),
+                    )))
+                }
+                #[cfg(feature = "bg_inmem")]
+                config::QueueConfig::InMem(qcfg) => {
+                    tracing::debug!("Creating in-memory queue provider");
+                    Ok(Some(Arc::new(
+                        inmem::create_provider(qcfg).with_shutdown_timeout(shutdown_timeout),
+                    )))
                 }
 
                 #[allow(unreachable_patterns)]
@@ -762,6 +2106,244 @@ mod tests {
         }
     }
 
+    #[test]
+    fn can_compute_exponential_backoff() {
+        assert_eq!(exponential_backoff(1), Duration::from_secs(2));
+        assert_eq!(exponential_backoff(3), Duration::from_secs(8));
+        assert_eq!(
+            exponential_backoff(20),
+            Duration::from_secs(MAX_BACKOFF_SECS)
+        );
+        assert_eq!(
+            exponential_backoff(u32::MAX),
+            Duration::from_secs(MAX_BACKOFF_SECS)
+        );
+    }
+
+    #[test]
+    fn can_schedule_retries_until_exhausted() {
+        let opts = WorkerOpts {
+            max_retries: 2,
+            backoff: |_| Duration::from_secs(60),
+            ..Default::default()
+        };
+
+        let retry_at = opts.retry_at(1).expect("first retry");
+        assert!(retry_at > Utc::now() + chrono::Duration::seconds(50));
+        assert!(opts.retry_at(2).is_some());
+        assert!(opts.retry_at(3).is_none());
+        assert!(WorkerOpts::default().retry_at(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn can_time_out_jobs() {
+        let token = CancellationToken::new();
+        let outcome = run_job(
+            async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(None)
+            },
+            &token,
+            Some(Duration::from_millis(10)),
+            Duration::from_secs(60),
+            || async { Ok(false) },
+            &CancellationToken::new(),
+            None,
+        )
+        .await;
+
+        assert!(
+            matches!(outcome, JobOutcome::Failed(err) if err.to_string().contains("timed out"))
+        );
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn can_interrupt_jobs_on_shutdown() {
+        let token = CancellationToken::new();
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        let outcome = run_job(
+            async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(None)
+            },
+            &token,
+            None,
+            Duration::from_secs(60),
+            || async { Ok(false) },
+            &shutdown,
+            Some(Duration::from_millis(10)),
+        )
+        .await;
+
+        assert!(matches!(outcome, JobOutcome::Interrupted));
+        assert!(token.is_cancelled());
+
+        // without a shutdown timeout the job runs to the end
+        let outcome = run_job(
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(None)
+            },
+            &token,
+            None,
+            Duration::from_secs(60),
+            || async { Ok(false) },
+            &shutdown,
+            None,
+        )
+        .await;
+
+        assert!(matches!(outcome, JobOutcome::Completed(None)));
+    }
+
+    #[tokio::test]
+    async fn can_cancel_running_jobs() {
+        let token = CancellationToken::new();
+        let job_token = token.clone();
+        let perform = perform_job(async move {
+            job_token.cancelled().await;
+            set_job_result(&"stopped")
+        });
+        let outcome = run_job(
+            perform,
+            &token,
+            None,
+            Duration::from_millis(10),
+            || async { Ok(true) },
+            &CancellationToken::new(),
+            None,
+        )
+        .await;
+
+        assert!(matches!(outcome, JobOutcome::Cancelled));
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn can_get_job_by_enqueued_id() {
+        struct NoopWorker;
+        #[async_trait]
+        impl BackgroundWorker<()> for NoopWorker {
+            fn build(_ctx: &AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let tree_fs = tree_fs::TreeBuilder::default()
+            .drop(true)
+            .create()
+            .expect("create temp folder");
+        let qcfg = sqlite_config(tree_fs.root.as_path());
+        let queue = sqlt::create_provider(&qcfg)
+            .await
+            .expect("create sqlite queue");
+        queue.setup().await.expect("setup sqlite db");
+
+        let mut ctx = tests_cfg::app::get_app_context().await;
+        ctx.config.workers.mode = WorkerMode::BackgroundQueue;
+        ctx.queue_provider = Some(Arc::new(queue));
+
+        let id = NoopWorker::perform_later(&ctx, ())
+            .await
+            .expect("perform later")
+            .expect("job id");
+
+        let queue = ctx.queue_provider.as_ref().expect("queue provider");
+        let job = queue
+            .get_job(&id)
+            .await
+            .expect("get job")
+            .expect("job exists");
+        assert_eq!(job.id, id);
+        assert_eq!(job.name, "NoopWorker");
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.attempts, 0);
+        assert!(job.error.is_none());
+        assert!(job.result.is_none());
+
+        assert!(queue.get_job("missing").await.expect("get job").is_none());
+    }
+
+    #[tokio::test]
+    async fn can_perform_in_and_every() {
+        struct NoopWorker;
+        #[async_trait]
+        impl BackgroundWorker<()> for NoopWorker {
+            fn build(_ctx: &AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let tree_fs = tree_fs::TreeBuilder::default()
+            .drop(true)
+            .create()
+            .expect("create temp folder");
+        let qcfg = sqlite_config(tree_fs.root.as_path());
+        let queue = sqlt::create_provider(&qcfg)
+            .await
+            .expect("create sqlite queue");
+        queue.setup().await.expect("setup sqlite db");
+
+        let mut ctx = tests_cfg::app::get_app_context().await;
+        ctx.config.workers.mode = WorkerMode::BackgroundQueue;
+        ctx.queue_provider = Some(Arc::new(queue));
+
+        NoopWorker::perform_in(&ctx, Duration::from_secs(60 * 60), ())
+            .await
+            .expect("perform in");
+        NoopWorker::perform_every(&ctx, Duration::from_secs(60), ())
+            .await
+            .expect("perform every");
+
+        let pool = sqlx::SqlitePool::connect(&qcfg.uri)
+            .await
+            .expect("connect to sqlite db");
+        let jobs = sqlt::get_jobs(&pool, None, None).await.expect("get jobs");
+        assert_eq!(jobs.len(), 2);
+
+        let delayed = jobs
+            .iter()
+            .find(|job| job.interval.is_none())
+            .expect("delayed job");
+        assert!(delayed.run_at > Utc::now() + chrono::Duration::minutes(59));
+
+        let recurring = jobs
+            .iter()
+            .find(|job| job.interval.is_some())
+            .expect("recurring job");
+        assert_eq!(recurring.interval, Some(60_000));
+        assert!(recurring.run_at <= Utc::now());
+    }
+
+    #[tokio::test]
+    async fn cannot_perform_every_in_background_async_mode() {
+        struct NoopWorker;
+        #[async_trait]
+        impl BackgroundWorker<()> for NoopWorker {
+            fn build(_ctx: &AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut ctx = tests_cfg::app::get_app_context().await;
+        ctx.config.workers.mode = WorkerMode::BackgroundAsync;
+
+        assert!(NoopWorker::perform_every(&ctx, Duration::from_secs(60), ())
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn can_dump_jobs() {
         let tree_fs = tree_fs::TreeBuilder::default()
@@ -4,7 +4,13 @@ use std::{
     time::Duration,
 };
 
-use super::{BackgroundWorker, JobStatus, Queue};
+#[cfg(feature = "bg_dashboard")]
+use super::dashboard::JobCount;
+use super::{
+    error_details, keep_alive, perform_job, run_job, BackgroundWorker, Batch, BatchInfo, BatchJob,
+    EnqueueOpts, JobInfo, JobOutcome, JobStatus, Queue, RetryTarget, WorkerInfo, WorkerOpts,
+    HEARTBEAT_TTL,
+};
 use crate::{config::PostgresQueueConfig, Error, Result};
 use chrono::{DateTime, Utc};
 use futures_util::FutureExt;
@@ -12,11 +18,15 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 pub use sqlx::PgPool;
 use sqlx::{
-    postgres::{PgConnectOptions, PgPoolOptions, PgRow},
+    postgres::{PgConnectOptions, PgConnection, PgListener, PgPoolOptions, PgRow},
     ConnectOptions, Row,
 };
 use std::fmt::Write;
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::{
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time::sleep,
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, trace};
 use ulid::Ulid;
@@ -27,7 +37,8 @@ type JobHandler = Box<
     dyn Fn(
             JobId,
             JobData,
-        ) -> Pin<Box<dyn std::future::Future<Output = Result<(), crate::Error>> + Send>>
+            CancellationToken,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<Option<JsonValue>>> + Send>>
         + Send
         + Sync,
 >;
@@ -44,10 +55,42 @@ pub struct Job {
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
     pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub attempts: i32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub backtrace: Option<String>,
+    #[serde(default)]
+    pub priority: i16,
+    #[serde(default)]
+    pub unique_key: Option<String>,
+    #[serde(default)]
+    pub result: Option<JsonValue>,
+    #[serde(default)]
+    pub batch_id: Option<String>,
+}
+
+impl From<Job> for JobInfo {
+    fn from(job: Job) -> Self {
+        Self {
+            id: job.id,
+            name: job.name,
+            status: job.status,
+            run_at: job.run_at,
+            attempts: job.attempts,
+            error: job.last_error,
+            result: job.result,
+            batch_id: job.batch_id,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+        }
+    }
 }
 
 pub struct JobRegistry {
     handlers: Arc<HashMap<String, JobHandler>>,
+    worker_opts: Arc<HashMap<String, WorkerOpts>>,
 }
 
 impl JobRegistry {
@@ -56,6 +99,7 @@ impl JobRegistry {
     pub fn new() -> Self {
         Self {
             handlers: Arc::new(HashMap::new()),
+            worker_opts: Arc::new(HashMap::new()),
         }
     }
 
@@ -69,7 +113,9 @@ impl JobRegistry {
         for<'de> Args: Deserialize<'de>,
     {
         let worker = Arc::new(worker);
-        let wrapped_handler = move |_job_id: String, job_data: JobData| {
+        let wrapped_handler = move |_job_id: String,
+                                    job_data: JobData,
+                                    token: CancellationToken| {
             let w = worker.clone();
 
             Box::pin(async move {
@@ -77,7 +123,10 @@ impl JobRegistry {
                 match args {
                     Ok(args) => {
                         // Wrap the perform call in catch_unwind to handle panics
-                        match AssertUnwindSafe(w.perform(args)).catch_unwind().await {
+                        match AssertUnwindSafe(perform_job(w.perform_with_token(args, token)))
+                            .catch_unwind()
+                            .await
+                        {
                             Ok(result) => result,
                             Err(panic) => {
                                 let panic_msg = panic
@@ -92,9 +141,12 @@ impl JobRegistry {
                     }
                     Err(err) => Err(err.into()),
                 }
-            }) as Pin<Box<dyn Future<Output = Result<(), crate::Error>> + Send>>
+            }) as Pin<Box<dyn Future<Output = Result<Option<JsonValue>>> + Send>>
         };
 
+        Arc::get_mut(&mut self.worker_opts)
+            .ok_or_else(|| Error::string("cannot register worker"))?
+            .insert(name.clone(), WorkerOpts::from_worker::<Args, W>());
         Arc::get_mut(&mut self.handlers)
             .ok_or_else(|| Error::string("cannot register worker"))?
             .insert(name, Box::new(wrapped_handler));
@@ -119,25 +171,52 @@ impl JobRegistry {
         let mut jobs = Vec::new();
 
         let interval = opts.poll_interval_sec;
+        let shutdown_timeout = opts.shutdown_timeout;
+        let enqueued = Arc::new(Notify::new());
+        let worker = WorkerInfo::current(tags);
+        let (alive, alive_rx) = mpsc::channel(1);
+        if opts.num_workers > 0 {
+            jobs.push(tokio::spawn(listen(
+                pool.clone(),
+                enqueued.clone(),
+                token.clone(),
+                interval,
+            )));
+            jobs.push(tokio::spawn(heartbeat(
+                pool.clone(),
+                worker.clone(),
+                alive_rx,
+            )));
+        }
         for idx in 0..opts.num_workers {
             let handlers = self.handlers.clone();
+            let worker_opts = self.worker_opts.clone();
             let worker_token = token.clone(); // Clone token for this worker
             let worker_tags = tags.to_vec();
+            let worker_enqueued = enqueued.clone();
+            let worker_id = worker.id.clone();
+            let alive = alive.clone();
 
             let pool = pool.clone();
             let job = tokio::spawn(async move {
+                // keeps the heartbeat going until this worker is done
+                let _alive = alive;
                 loop {
                     // Check for cancellation before potentially blocking on dequeue
                     if worker_token.is_cancelled() {
                         trace!(worker_id = idx, "Cancellation received, stopping worker");
                         break;
                     }
+                    // created before looking for a job, so a job enqueued in
+                    // between still wakes this worker up
+                    let enqueued = worker_enqueued.notified();
                     trace!(
                         pool_size = pool.num_idle(),
                         worker_id = idx,
                         "Connection pool stats"
                     );
-                    let job_opt = match dequeue(&pool, &worker_tags).await {
+                    let job_opt = match dequeue(&pool, &worker_tags, &worker_opts, &worker_id).await
+                    {
                         Ok(t) => t,
                         Err(err) => {
                             error!(error = %err, "Failed to fetch job from queue");
@@ -148,10 +227,23 @@ impl JobRegistry {
                     if let Some(job) = job_opt {
                         debug!(job_id = %job.id, job_name = %job.name, "Processing job");
                         if let Some(handler) = handlers.get(&job.name) {
-                            match handler(job.id.clone(), job.data.clone()).await {
-                                Ok(()) => {
+                            let opts = worker_opts.get(&job.name).copied().unwrap_or_default();
+                            let token = CancellationToken::new();
+                            let outcome = run_job(
+                                handler(job.id.clone(), job.data.clone(), token.clone()),
+                                &token,
+                                opts.timeout,
+                                Duration::from_secs(interval.into()),
+                                || is_job_cancelled(&pool, &job.id),
+                                &worker_token,
+                                shutdown_timeout,
+                            )
+                            .await;
+                            let finished = !matches!(outcome, JobOutcome::Interrupted);
+                            match outcome {
+                                JobOutcome::Completed(result) => {
                                     if let Err(err) =
-                                        complete_job(&pool, &job.id, job.interval).await
+                                        complete_job(&pool, &job.id, job.interval, result).await
                                     {
                                         error!(
                                             error = %err,
@@ -163,8 +255,35 @@ impl JobRegistry {
                                         debug!(job_id = %job.id, "Job completed successfully");
                                     }
                                 }
-                                Err(err) => {
-                                    if let Err(fail_err) = fail_job(&pool, &job.id, &err).await {
+                                JobOutcome::Failed(err) => {
+                                    if let Some(run_at) = opts.retry_at(job.attempts + 1) {
+                                        if let Err(retry_err) =
+                                            retry_job(&pool, &job.id, &err, run_at).await
+                                        {
+                                            error!(
+                                                error = %retry_err,
+                                                job_id = %job.id,
+                                                job_name = %job.name,
+                                                "Failed to reschedule job"
+                                            );
+                                        } else {
+                                            debug!(job_id = %job.id, error = %err, run_at = %run_at, "Job execution failed, retry scheduled");
+                                        }
+                                    } else if opts.max_retries > 0 {
+                                        if let Err(bury_err) = bury_job(&pool, &job.id, &err).await
+                                        {
+                                            error!(
+                                                error = %bury_err,
+                                                job_id = %job.id,
+                                                job_name = %job.name,
+                                                "Failed to move job to the dead-letter queue"
+                                            );
+                                        } else {
+                                            debug!(job_id = %job.id, error = %err, "Job ran out of retries, moved to the dead-letter queue");
+                                        }
+                                    } else if let Err(fail_err) =
+                                        fail_job(&pool, &job.id, &err).await
+                                    {
                                         error!(
                                             error = %fail_err,
                                             job_id = %job.id,
@@ -175,20 +294,43 @@ impl JobRegistry {
                                         debug!(job_id = %job.id, error = %err, "Job execution failed");
                                     }
                                 }
+                                JobOutcome::Cancelled => {
+                                    debug!(job_id = %job.id, "Job was cancelled while running");
+                                }
+                                JobOutcome::Interrupted => {
+                                    if let Err(err) = requeue_job(&pool, &job.id).await {
+                                        error!(
+                                            error = %err,
+                                            job_id = %job.id,
+                                            job_name = %job.name,
+                                            "Failed to put interrupted job back in the queue"
+                                        );
+                                    }
+                                }
+                            }
+                            if let Some(batch_id) = job.batch_id.as_ref().filter(|_| finished) {
+                                if let Err(err) = finish_batch_job(&pool, batch_id).await {
+                                    error!(
+                                        error = %err,
+                                        batch_id = %batch_id,
+                                        "Failed to update batch progress"
+                                    );
+                                }
                             }
                         } else {
                             error!(job_name = %job.name, "No handler registered for job");
                         }
                     } else {
-                        // Use tokio::select! to wait for interval or cancellation
+                        // Wait for a job to be enqueued, the interval or cancellation
                         tokio::select! {
                             biased;
                             () = worker_token.cancelled() => {
                                 trace!(worker_id = idx, "Cancellation received during sleep, stopping worker");
                                 break;
                             }
+                            () = enqueued => {}
                             () = sleep(Duration::from_secs(interval.into())) => {
-                                // Interval elapsed, continue loop
+                                // Interval elapsed, look for scheduled jobs that are due
                             }
                         }
                     }
@@ -208,6 +350,84 @@ impl Default for JobRegistry {
     }
 }
 
+/// Records the heartbeat of `worker` until its worker tasks are done, then
+/// removes it from the workers table.
+async fn heartbeat(pool: PgPool, worker: WorkerInfo, alive: mpsc::Receiver<()>) {
+    keep_alive(
+        alive,
+        || record_heartbeat(&pool, &worker),
+        || requeue_orphaned_jobs(&pool, HEARTBEAT_TTL),
+    )
+    .await;
+    if let Err(err) = sqlx::query("DELETE FROM pg_loco_workers WHERE id = $1")
+        .bind(&worker.id)
+        .execute(&pool)
+        .await
+    {
+        error!(error = %err, worker_id = %worker.id, "Failed to remove worker");
+    }
+}
+
+async fn record_heartbeat(pool: &PgPool, worker: &WorkerInfo) -> Result<()> {
+    trace!(worker_id = %worker.id, "Recording worker heartbeat");
+    sqlx::query(
+        "INSERT INTO pg_loco_workers (id, hostname, pid, tags, started_at, heartbeat_at) VALUES \
+         ($1, $2, $3, $4, $5, NOW()) ON CONFLICT (id) DO UPDATE SET heartbeat_at = NOW()",
+    )
+    .bind(&worker.id)
+    .bind(&worker.hostname)
+    .bind(i64::from(worker.pid))
+    .bind(serde_json::to_value(&worker.tags)?)
+    .bind(worker.started_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Wakes idle workers up whenever a job is enqueued, by listening on
+/// [`NOTIFY_CHANNEL`] with a connection of `pool`.
+///
+/// Workers still poll every `interval` seconds, which picks up jobs scheduled
+/// for later, and jobs enqueued while the listener is reconnecting or where
+/// notifications are not delivered, such as behind a connection pooler in
+/// transaction mode.
+async fn listen(pool: PgPool, enqueued: Arc<Notify>, token: CancellationToken, interval: u32) {
+    let mut listener = match PgListener::connect_with(&pool).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(error = %err, "Failed to listen for enqueued jobs, falling back to polling");
+            return;
+        }
+    };
+    if let Err(err) = listener.listen(NOTIFY_CHANNEL).await {
+        error!(error = %err, "Failed to listen for enqueued jobs, falling back to polling");
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            biased;
+            () = token.cancelled() => break,
+            notification = listener.try_recv() => match notification {
+                Ok(Some(notification)) => {
+                    trace!(job_name = notification.payload(), "Job enqueued");
+                    enqueued.notify_waiters();
+                }
+                // the connection was lost and re-established, jobs enqueued in
+                // between were not notified
+                Ok(None) => enqueued.notify_waiters(),
+                Err(err) => {
+                    error!(error = %err, "Failed to receive enqueued jobs, retrying");
+                    tokio::select! {
+                        () = token.cancelled() => break,
+                        () = sleep(Duration::from_secs(interval.into())) => {}
+                    }
+                }
+            },
+        }
+    }
+}
+
 async fn connect(cfg: &PostgresQueueConfig) -> Result<PgPool> {
     let mut conn_opts: PgConnectOptions = cfg.uri.parse()?;
     if !cfg.enable_logging {
@@ -241,7 +461,60 @@ pub async fn initialize_database(pool: &PgPool) -> Result<()> {
                 interval BIGINT,
                 created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
                 updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
-                tags JSONB
+                tags JSONB,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                backtrace TEXT,
+                priority SMALLINT NOT NULL DEFAULT 0,
+                unique_key VARCHAR,
+                result JSONB,
+                batch_id VARCHAR,
+                worker_id VARCHAR
+            );
+
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS last_error TEXT;
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS backtrace TEXT;
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS priority SMALLINT NOT NULL DEFAULT 0;
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS unique_key VARCHAR;
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS result JSONB;
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS batch_id VARCHAR;
+            ALTER TABLE pg_loco_queue ADD COLUMN IF NOT EXISTS worker_id VARCHAR;
+
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_pg_loco_queue_unique_key
+                ON pg_loco_queue (name, unique_key) WHERE {UNIQUE_JOB_PREDICATE};
+            CREATE INDEX IF NOT EXISTS idx_pg_loco_queue_batch_id
+                ON pg_loco_queue (batch_id) WHERE batch_id IS NOT NULL;
+            CREATE INDEX IF NOT EXISTS idx_pg_loco_queue_updated_at
+                ON pg_loco_queue (updated_at);
+
+            CREATE TABLE IF NOT EXISTS pg_loco_batches (
+                id VARCHAR NOT NULL PRIMARY KEY,
+                on_complete JSONB,
+                on_failure JSONB,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                finished_at TIMESTAMPTZ
+            );
+
+            CREATE TABLE IF NOT EXISTS pg_loco_rate_limits (
+                name VARCHAR NOT NULL PRIMARY KEY,
+                window_start TIMESTAMPTZ NOT NULL,
+                window_count INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS pg_loco_workers (
+                id VARCHAR NOT NULL PRIMARY KEY,
+                hostname VARCHAR NOT NULL,
+                pid BIGINT NOT NULL,
+                tags JSONB,
+                started_at TIMESTAMPTZ NOT NULL,
+                heartbeat_at TIMESTAMPTZ NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS pg_loco_locks (
+                name VARCHAR NOT NULL PRIMARY KEY,
+                owner VARCHAR NOT NULL,
+                expires_at TIMESTAMPTZ NOT NULL
             );
             ",
         JobStatus::Queued
@@ -251,8 +524,50 @@ pub async fn initialize_database(pool: &PgPool) -> Result<()> {
     Ok(())
 }
 
+/// Channel notified by [`enqueue`], with the job name as payload, so idle
+/// workers don't have to wait for their next poll.
+const NOTIFY_CHANNEL: &str = "pg_loco_queue";
+
+/// Jobs holding their unique key. Shared by the partial index and the
+/// `ON CONFLICT` clause of [`enqueue`], which must match it.
+const UNIQUE_JOB_PREDICATE: &str = "unique_key IS NOT NULL AND status IN ('queued', 'processing')";
+
+/// Frees the unique key of jobs enqueued before the `unique_for` window.
+const RELEASE_UNIQUE_KEY_SQL: &str =
+    "UPDATE pg_loco_queue SET unique_key = NULL WHERE name = $1 AND unique_key = $2 AND \
+     created_at <= $3";
+
+fn insert_job_sql() -> String {
+    format!(
+        "INSERT INTO pg_loco_queue (id, task_data, name, run_at, interval, tags, priority, \
+         unique_key, batch_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (name, \
+         unique_key) WHERE {UNIQUE_JOB_PREDICATE} DO NOTHING"
+    )
+}
+
+fn existing_job_sql() -> String {
+    format!(
+        "SELECT id FROM pg_loco_queue WHERE name = $1 AND unique_key = $2 AND \
+         {UNIQUE_JOB_PREDICATE}"
+    )
+}
+
+/// Requeues a failed job unless another job of the same name holds its
+/// unique key, binding the name and key as in [`existing_job_sql`].
+fn retry_unique_job_sql() -> String {
+    format!(
+        "UPDATE pg_loco_queue SET status = $3, run_at = NOW(), updated_at = NOW(), attempts = 0 \
+         WHERE id = $4 AND status = ANY($5) AND NOT EXISTS ({})",
+        existing_job_sql()
+    )
+}
+
 /// Add a job
 ///
+/// When `opts.unique_key` is set and a job of the same name with that key is
+/// still queued or processing, nothing is added and the id of the existing
+/// job is returned.
+///
 /// # Errors
 ///
 /// This function will return an error if it fails
@@ -260,41 +575,333 @@ pub async fn enqueue(
     pool: &PgPool,
     name: &str,
     data: JobData,
-    run_at: DateTime<Utc>,
-    interval: Option<Duration>,
     tags: Option<Vec<String>>,
+    opts: &EnqueueOpts,
+) -> Result<JobId> {
+    let mut conn = pool.acquire().await?;
+    insert_job(&mut conn, name, data, tags, opts, None).await
+}
+
+/// Inserts a job on `conn`, which may be running a transaction, as a member
+/// of `batch_id` when given.
+async fn insert_job(
+    conn: &mut PgConnection,
+    name: &str,
+    data: JobData,
+    tags: Option<Vec<String>>,
+    opts: &EnqueueOpts,
+    batch_id: Option<&str>,
 ) -> Result<JobId> {
     let data_json = serde_json::to_value(data)?;
     let tags_json = tags
         .as_ref()
         .map(|t| serde_json::to_value(t).unwrap_or(serde_json::Value::Null));
 
+    let run_at = opts.run_at.unwrap_or_else(Utc::now);
+    let priority = opts.priority.unwrap_or_default();
     #[allow(clippy::cast_possible_truncation)]
-    let interval_ms: Option<i64> = interval.map(|i| i.as_millis() as i64);
+    let interval_ms: Option<i64> = opts.interval.map(|i| i.as_millis() as i64);
+
+    if let (Some(unique_key), Some(unique_for)) = (&opts.unique_key, opts.unique_for) {
+        let window = chrono::Duration::from_std(unique_for)
+            .map_err(|err| Error::Message(err.to_string()))?;
+        sqlx::query(RELEASE_UNIQUE_KEY_SQL)
+            .bind(name)
+            .bind(unique_key)
+            .bind(Utc::now() - window)
+            .execute(&mut *conn)
+            .await?;
+    }
 
     let id = Ulid::new().to_string();
-    debug!(job_id = %id, job_name = %name, run_at = %run_at, tags = ?tags, "Enqueueing job");
-    sqlx::query(
-        "INSERT INTO pg_loco_queue (id, task_data, name, run_at, interval, tags) VALUES ($1, $2, $3, \
-         $4, $5, $6)",
-    )
-    .bind(id.clone())
-    .bind(data_json)
-    .bind(name)
-    .bind(run_at)
-    .bind(interval_ms)
-    .bind(tags_json)
-    .execute(pool)
+    debug!(job_id = %id, job_name = %name, run_at = %run_at, tags = ?tags, priority, unique_key = ?opts.unique_key, batch_id, "Enqueueing job");
+    loop {
+        let inserted = sqlx::query(&insert_job_sql())
+            .bind(&id)
+            .bind(&data_json)
+            .bind(name)
+            .bind(run_at)
+            .bind(interval_ms)
+            .bind(&tags_json)
+            .bind(priority)
+            .bind(&opts.unique_key)
+            .bind(batch_id)
+            .execute(&mut *conn)
+            .await?;
+        if inserted.rows_affected() > 0 {
+            break;
+        }
+
+        // the job holding the key may have finished since the insert, in
+        // which case the key is free again and the insert is retried
+        let existing_id: Option<JobId> = sqlx::query_scalar(&existing_job_sql())
+            .bind(name)
+            .bind(&opts.unique_key)
+            .fetch_optional(&mut *conn)
+            .await?;
+        if let Some(existing_id) = existing_id {
+            debug!(job_id = %existing_id, job_name = %name, "Job with the same unique key is already enqueued");
+            return Ok(existing_id);
+        }
+    }
+
+    // delivered when the surrounding transaction, if any, commits
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(NOTIFY_CHANNEL)
+        .bind(name)
+        .execute(&mut *conn)
+        .await?;
+    Ok(id)
+}
+
+/// Adds a job within `tx`, a transaction of the application database, which
+/// must also hold the `pg_loco_queue` table. Workers only see the job once
+/// `tx` commits, and it is discarded if `tx` rolls back.
+///
+/// When `opts.unique_key` is set and a job of the same name with that key is
+/// still queued or processing, nothing is added and the id of the existing
+/// job is returned.
+///
+/// # Errors
+///
+/// This function will return an error if `tx` is not a Postgres transaction
+/// or if the job can't be inserted
+#[cfg(feature = "with-db")]
+pub async fn enqueue_in_tx(
+    tx: &sea_orm::DatabaseTransaction,
+    name: &str,
+    data: JobData,
+    tags: Option<Vec<String>>,
+    opts: &EnqueueOpts,
+) -> Result<JobId> {
+    use sea_orm::{ConnectionTrait, DbBackend, Statement};
+
+    if tx.get_database_backend() != DbBackend::Postgres {
+        return Err(Error::string(
+            "the Postgres queue can only enqueue jobs in a Postgres transaction",
+        ));
+    }
+    let statement = |sql: &str, values: Vec<sea_orm::Value>| {
+        Statement::from_sql_and_values(DbBackend::Postgres, sql, values)
+    };
+
+    let tags_json = tags.as_ref().map(serde_json::to_value).transpose()?;
+    let run_at = opts.run_at.unwrap_or_else(Utc::now);
+    let priority = opts.priority.unwrap_or_default();
+    #[allow(clippy::cast_possible_truncation)]
+    let interval_ms: Option<i64> = opts.interval.map(|i| i.as_millis() as i64);
+
+    if let (Some(unique_key), Some(unique_for)) = (&opts.unique_key, opts.unique_for) {
+        let window = chrono::Duration::from_std(unique_for)
+            .map_err(|err| Error::Message(err.to_string()))?;
+        tx.execute(statement(
+            RELEASE_UNIQUE_KEY_SQL,
+            vec![
+                name.into(),
+                unique_key.as_str().into(),
+                (Utc::now() - window).into(),
+            ],
+        ))
+        .await?;
+    }
+
+    let id = Ulid::new().to_string();
+    debug!(job_id = %id, job_name = %name, run_at = %run_at, tags = ?tags, priority, unique_key = ?opts.unique_key, "Enqueueing job in transaction");
+    let inserted = tx
+        .execute(statement(
+            &insert_job_sql(),
+            vec![
+                id.as_str().into(),
+                data.into(),
+                name.into(),
+                run_at.into(),
+                interval_ms.into(),
+                tags_json.into(),
+                priority.into(),
+                opts.unique_key.clone().into(),
+                Option::<String>::None.into(),
+            ],
+        ))
+        .await?;
+
+    if inserted.rows_affected() == 0 {
+        let existing_id: JobId = tx
+            .query_one(statement(
+                &existing_job_sql(),
+                vec![name.into(), opts.unique_key.clone().into()],
+            ))
+            .await?
+            .ok_or_else(|| Error::string("job with the same unique key not found"))?
+            .try_get("", "id")?;
+        debug!(job_id = %existing_id, job_name = %name, "Job with the same unique key is already enqueued");
+        return Ok(existing_id);
+    }
+
+    tx.execute(statement(
+        "SELECT pg_notify($1, $2)",
+        vec![NOTIFY_CHANNEL.into(), name.into()],
+    ))
     .await?;
     Ok(id)
 }
 
-async fn dequeue(client: &PgPool, worker_tags: &[String]) -> Result<Option<Job>> {
+/// Wakes idle workers up after jobs were put back in the queue.
+async fn notify_enqueued(pool: &PgPool) -> Result<()> {
+    sqlx::query("SELECT pg_notify($1, '')")
+        .bind(NOTIFY_CHANNEL)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Adds the jobs of `batch` and records its callbacks in `pg_loco_batches`.
+///
+/// Everything is inserted in one transaction, so workers can't finish the
+/// batch before all of its jobs are added.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn enqueue_batch(pool: &PgPool, batch: &Batch) -> Result<()> {
+    let on_complete = batch
+        .on_complete
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+    let on_failure = batch
+        .on_failure
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+
+    debug!(batch_id = %batch.id, jobs = batch.jobs.len(), "Enqueueing batch");
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO pg_loco_batches (id, on_complete, on_failure) VALUES ($1, $2, $3)")
+        .bind(&batch.id)
+        .bind(on_complete)
+        .bind(on_failure)
+        .execute(&mut *tx)
+        .await?;
+    for job in &batch.jobs {
+        insert_job(
+            &mut tx,
+            &job.name,
+            job.args.clone(),
+            job.tags.clone(),
+            &job.opts(),
+            Some(&batch.id),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    if batch.jobs.is_empty() {
+        finish_batch_job(pool, &batch.id).await?;
+    }
+    Ok(())
+}
+
+/// Fetches the progress of a batch from the status of its jobs.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn get_batch(pool: &PgPool, id: &str) -> Result<Option<BatchInfo>> {
+    let row = sqlx::query(
+        "SELECT b.id, b.finished_at, COUNT(q.id) AS total, COUNT(q.id) FILTER (WHERE q.status \
+         IN ('queued', 'processing')) AS pending, COUNT(q.id) FILTER (WHERE q.status = \
+         'completed') AS completed FROM pg_loco_batches b LEFT JOIN pg_loco_queue q ON \
+         q.batch_id = b.id WHERE b.id = $1 GROUP BY b.id",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| {
+        let count = |column: &str| u64::try_from(row.get::<i64, _>(column)).unwrap_or_default();
+        let (total, pending, completed) = (count("total"), count("pending"), count("completed"));
+        BatchInfo {
+            id: row.get("id"),
+            total,
+            pending,
+            completed,
+            failed: total - pending - completed,
+            finished_at: row.get("finished_at"),
+        }
+    }))
+}
+
+/// Called once a job of the batch `batch_id` reached a final status. When
+/// none of its jobs is left to run, marks the batch as finished and enqueues
+/// the matching callback. Only the caller that marks the batch gets to
+/// enqueue it, so the callback runs once even when the last jobs finish
+/// together.
+async fn finish_batch_job(pool: &PgPool, batch_id: &str) -> Result<()> {
+    let Some(batch) = get_batch(pool, batch_id).await? else {
+        return Ok(());
+    };
+    if batch.pending > 0 {
+        return Ok(());
+    }
+
+    // The batch is marked finished and its callback enqueued together, so a
+    // failure can't leave a finished batch without its callback.
+    let mut tx = pool.begin().await?;
+    let Some(row) = sqlx::query(
+        "UPDATE pg_loco_batches SET finished_at = NOW() WHERE id = $1 AND finished_at IS NULL \
+         RETURNING on_complete, on_failure",
+    )
+    .bind(batch_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let column = if batch.failed == 0 {
+        "on_complete"
+    } else {
+        "on_failure"
+    };
+    debug!(
+        batch_id,
+        completed = batch.completed,
+        failed = batch.failed,
+        "Batch finished"
+    );
+    if let Some(callback) = row.try_get::<Option<JsonValue>, _>(column)? {
+        let callback: BatchJob = serde_json::from_value(callback)?;
+        insert_job(
+            &mut tx,
+            &callback.name,
+            callback.args.clone(),
+            callback.tags.clone(),
+            &callback.opts(),
+            None,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Picks the next due job matching `worker_tags` and marks it as
+/// [`JobStatus::Processing`].
+///
+/// Jobs of workers that reached their [`WorkerOpts::concurrency_limit`] or
+/// [`WorkerOpts::rate_limit`] are skipped, so they don't hold up the other
+/// jobs of the queue.
+async fn dequeue(
+    client: &PgPool,
+    worker_tags: &[String],
+    worker_opts: &HashMap<String, WorkerOpts>,
+    worker_id: &str,
+) -> Result<Option<Job>> {
     let mut tx = client.begin().await?;
 
     // Base query
     let mut query = String::from(
-        "SELECT id, name, task_data, status, run_at, interval, tags FROM pg_loco_queue WHERE status = $1 AND run_at <= NOW() "
+        "SELECT id, name, task_data, status, run_at, interval, tags, attempts, priority, batch_id FROM pg_loco_queue WHERE status = $1 AND run_at <= NOW() AND NOT (name = ANY($2)) "
     );
 
     // Apply tag filtering logic
@@ -311,7 +918,7 @@ async fn dequeue(client: &PgPool, worker_tags: &[String]) -> Result<Option<Job>>
         for (i, _) in worker_tags.iter().enumerate() {
             // Check if the tag exists as a JSON string in the tags array
             // Using ? operator checks if string exists as array element
-            conditions.push(format!("(tags)::jsonb ? ${}", i + 2));
+            conditions.push(format!("(tags)::jsonb ? ${}", i + 3));
         }
 
         if !conditions.is_empty() {
@@ -321,39 +928,115 @@ async fn dequeue(client: &PgPool, worker_tags: &[String]) -> Result<Option<Job>>
         }
     }
 
-    query.push_str(" ORDER BY run_at LIMIT 1 FOR UPDATE SKIP LOCKED");
+    query.push_str(" ORDER BY priority DESC, run_at LIMIT 1 FOR UPDATE SKIP LOCKED");
 
-    // Create the query
-    let mut db_query = sqlx::query(&query).bind(JobStatus::Queued.to_string());
+    // Workers whose jobs can't start right now
+    let mut limited: Vec<String> = Vec::new();
+    loop {
+        // Create the query
+        let mut db_query = sqlx::query(&query)
+            .bind(JobStatus::Queued.to_string())
+            .bind(&limited);
 
-    // Bind tag parameters
-    for tag in worker_tags {
-        db_query = db_query.bind(tag);
-    }
+        // Bind tag parameters
+        for tag in worker_tags {
+            db_query = db_query.bind(tag);
+        }
+
+        let Some(job) = db_query
+            .map(|row: PgRow| to_job(&row).ok())
+            .fetch_optional(&mut *tx)
+            .await?
+            .flatten()
+        else {
+            return Ok(None);
+        };
 
-    let row = db_query
-        .map(|row: PgRow| to_job(&row).ok())
-        .fetch_optional(&mut *tx)
-        .await?
-        .flatten();
+        if let Some(opts) = worker_opts.get(&job.name).filter(|opts| opts.is_limited()) {
+            if !acquire_limits(&mut tx, &job.name, opts).await? {
+                trace!(job_id = %job.id, job_name = %job.name, "Worker limit reached, skipping job");
+                limited.push(job.name);
+                continue;
+            }
+        }
 
-    if let Some(job) = row {
         trace!(job_id = %job.id, job_name = %job.name, job_tags = ?job.tags, "Dequeueing job for processing");
-        sqlx::query("UPDATE pg_loco_queue SET status = $1, updated_at = NOW() WHERE id = $2")
-            .bind(JobStatus::Processing.to_string())
-            .bind(&job.id)
-            .execute(&mut *tx)
-            .await?;
+        sqlx::query(
+            "UPDATE pg_loco_queue SET status = $1, updated_at = NOW(), worker_id = $2 WHERE id = $3",
+        )
+        .bind(JobStatus::Processing.to_string())
+        .bind(worker_id)
+        .bind(&job.id)
+        .execute(&mut *tx)
+        .await?;
 
         tx.commit().await?;
 
-        Ok(Some(job))
-    } else {
-        Ok(None)
+        return Ok(Some(job));
     }
 }
 
-async fn complete_job(pool: &PgPool, id: &JobId, interval_ms: Option<i64>) -> Result<()> {
+/// Checks whether a job of the worker `name` may start under the limits in
+/// `opts`, counting it against the rate limit when it may.
+///
+/// Checks for the same worker are serialized across processes by an advisory
+/// lock held until `tx` ends. When another process holds it, the job is
+/// treated as limited and picked up on a later poll.
+async fn acquire_limits(tx: &mut PgConnection, name: &str, opts: &WorkerOpts) -> Result<bool> {
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_xact_lock(hashtext($1))")
+        .bind(format!("pg_loco_queue:{name}"))
+        .fetch_one(&mut *tx)
+        .await?;
+    if !locked {
+        return Ok(false);
+    }
+
+    if let Some(limit) = opts.concurrency_limit {
+        let running: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM pg_loco_queue WHERE name = $1 AND status = $2",
+        )
+        .bind(name)
+        .bind(JobStatus::Processing.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+        if running >= i64::from(limit) {
+            return Ok(false);
+        }
+    }
+
+    if let Some(rate_limit) = opts.rate_limit {
+        // Starts a new window once the current one is over, otherwise counts
+        // the job in the current window while there is room left
+        let counted = sqlx::query(
+            "INSERT INTO pg_loco_rate_limits (name, window_start, window_count)
+            VALUES ($1, NOW(), 1)
+            ON CONFLICT (name) DO UPDATE SET
+                window_start = CASE WHEN pg_loco_rate_limits.window_start <= NOW() - make_interval(secs => $2)
+                    THEN NOW() ELSE pg_loco_rate_limits.window_start END,
+                window_count = CASE WHEN pg_loco_rate_limits.window_start <= NOW() - make_interval(secs => $2)
+                    THEN 1 ELSE pg_loco_rate_limits.window_count + 1 END
+            WHERE pg_loco_rate_limits.window_start <= NOW() - make_interval(secs => $2)
+                OR pg_loco_rate_limits.window_count < $3",
+        )
+        .bind(name)
+        .bind(rate_limit.per.as_secs_f64())
+        .bind(i64::from(rate_limit.max))
+        .execute(&mut *tx)
+        .await?;
+        if counted.rows_affected() == 0 {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+async fn complete_job(
+    pool: &PgPool,
+    id: &JobId,
+    interval_ms: Option<i64>,
+    result: Option<JsonValue>,
+) -> Result<()> {
     let (status, run_at) = interval_ms.map_or_else(
         || (JobStatus::Completed.to_string(), Utc::now()),
         |interval_ms| {
@@ -372,64 +1055,254 @@ async fn complete_job(pool: &PgPool, id: &JobId, interval_ms: Option<i64>) -> Re
     );
 
     sqlx::query(
-        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW(), run_at = $2 WHERE id = $3",
+        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW(), run_at = $2, result = $3 WHERE \
+         id = $4 AND status = $5",
     )
     .bind(status)
     .bind(run_at)
+    .bind(result)
     .bind(id)
+    .bind(JobStatus::Processing.to_string())
     .execute(pool)
     .await?;
 
     Ok(())
 }
 
+/// Whether the job was cancelled since a worker picked it up.
+async fn is_job_cancelled(pool: &PgPool, id: &JobId) -> Result<bool> {
+    let status: Option<String> =
+        sqlx::query_scalar("SELECT status FROM pg_loco_queue WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(status.is_some_and(|status| status == JobStatus::Cancelled.to_string()))
+}
+
 async fn fail_job(pool: &PgPool, id: &JobId, error: &crate::Error) -> Result<()> {
-    let msg = error.to_string();
+    let (msg, backtrace) = error_details(error);
     debug!(job_id = %id, error = %msg, "Marking job as failed");
-    let error_json = serde_json::json!({ "error": msg });
     sqlx::query(
-        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW(), task_data = task_data || \
-         $2::jsonb WHERE id = $3",
+        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW(), attempts = attempts + 1, \
+         last_error = $2, backtrace = $3 WHERE id = $4 AND status = $5",
     )
     .bind(JobStatus::Failed.to_string())
-    .bind(error_json)
+    .bind(msg)
+    .bind(backtrace)
+    .bind(id)
+    .bind(JobStatus::Processing.to_string())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn retry_job(
+    pool: &PgPool,
+    id: &JobId,
+    error: &crate::Error,
+    run_at: DateTime<Utc>,
+) -> Result<()> {
+    let (msg, backtrace) = error_details(error);
+    debug!(job_id = %id, error = %msg, run_at = %run_at, "Rescheduling failed job");
+    sqlx::query(
+        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW(), run_at = $2, attempts = \
+         attempts + 1, last_error = $3, backtrace = $4 WHERE id = $5 AND status = $6",
+    )
+    .bind(JobStatus::Queued.to_string())
+    .bind(run_at)
+    .bind(msg)
+    .bind(backtrace)
     .bind(id)
+    .bind(JobStatus::Processing.to_string())
     .execute(pool)
     .await?;
     Ok(())
 }
 
+/// Puts a job that was still running when its worker shut down back in the
+/// queue. It doesn't count as an attempt, since the job didn't fail.
+async fn requeue_job(pool: &PgPool, id: &JobId) -> Result<()> {
+    debug!(job_id = %id, "Requeueing interrupted job");
+    sqlx::query(
+        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW() WHERE id = $2 AND status = $3",
+    )
+    .bind(JobStatus::Queued.to_string())
+    .bind(id)
+    .bind(JobStatus::Processing.to_string())
+    .execute(pool)
+    .await?;
+    notify_enqueued(pool).await
+}
+
+/// Moves a job that ran out of retries to [`JobStatus::Dead`], leaving its
+/// arguments untouched so it can be replayed later.
+async fn bury_job(pool: &PgPool, id: &JobId, error: &crate::Error) -> Result<()> {
+    let (msg, backtrace) = error_details(error);
+    debug!(job_id = %id, error = %msg, "Moving job to the dead-letter queue");
+    sqlx::query(
+        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW(), attempts = attempts + 1, \
+         last_error = $2, backtrace = $3 WHERE id = $4 AND status = $5",
+    )
+    .bind(JobStatus::Dead.to_string())
+    .bind(msg)
+    .bind(backtrace)
+    .bind(id)
+    .bind(JobStatus::Processing.to_string())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Moves [`JobStatus::Failed`] and [`JobStatus::Dead`] jobs matching `target`
+/// back to [`JobStatus::Queued`].
+///
+/// Jobs run right away with their attempt counter reset. A job with a unique
+/// key keeps it, and is left as is while another job holds that key.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn retry_jobs(pool: &PgPool, target: &RetryTarget) -> Result<()> {
+    debug!(target = ?target, "Retrying failed jobs");
+
+    let mut query_builder = sqlx::query_builder::QueryBuilder::<sqlx::Postgres>::new(
+        "UPDATE pg_loco_queue SET status = ",
+    );
+    query_builder
+        .push_bind(JobStatus::Queued.to_string())
+        .push(", run_at = NOW(), updated_at = NOW(), attempts = 0 WHERE unique_key IS NULL");
+    push_retry_filter(&mut query_builder, target);
+    query_builder.build().execute(pool).await?;
+
+    // jobs with a unique key go one by one, so that a single one of them takes
+    // back a key that is free
+    let mut query_builder = sqlx::query_builder::QueryBuilder::<sqlx::Postgres>::new(
+        "SELECT id, name, unique_key FROM pg_loco_queue WHERE unique_key IS NOT NULL",
+    );
+    push_retry_filter(&mut query_builder, target);
+    query_builder.push(" ORDER BY updated_at DESC");
+    let unique_jobs: Vec<(JobId, String, String)> =
+        query_builder.build_query_as().fetch_all(pool).await?;
+
+    for (id, name, unique_key) in unique_jobs {
+        let retried = sqlx::query(&retry_unique_job_sql())
+            .bind(&name)
+            .bind(&unique_key)
+            .bind(JobStatus::Queued.to_string())
+            .bind(&id)
+            .bind(retryable_statuses())
+            .execute(pool)
+            .await;
+        match retried {
+            Ok(done) if done.rows_affected() > 0 => {}
+            Ok(_) => {
+                debug!(job_id = %id, unique_key, "Unique key is held by another job, not retrying");
+            }
+            Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+                debug!(job_id = %id, unique_key, "Unique key is held by another job, not retrying");
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    notify_enqueued(pool).await
+}
+
+fn retryable_statuses() -> Vec<String> {
+    vec![JobStatus::Failed.to_string(), JobStatus::Dead.to_string()]
+}
+
+/// Restricts a query to the failed and dead jobs matching `target`.
+fn push_retry_filter(
+    query_builder: &mut sqlx::query_builder::QueryBuilder<'_, sqlx::Postgres>,
+    target: &RetryTarget,
+) {
+    query_builder
+        .push(" AND status = ANY(")
+        .push_bind(retryable_statuses())
+        .push(")");
+    match target {
+        RetryTarget::Id(id) => {
+            query_builder.push(" AND id = ").push_bind(id.clone());
+        }
+        RetryTarget::Name(name) => {
+            query_builder.push(" AND name = ").push_bind(name.clone());
+        }
+        RetryTarget::AllFailed => {}
+    }
+}
+
 /// Cancels jobs in the `pg_loco_queue` table by their name.
 ///
 /// This function updates the status of all jobs with the given `name` and a status of
-/// [`JobStatus::Queued`] to [`JobStatus::Cancelled`]. The update also sets the `updated_at` timestamp to the
-/// current time.
+/// [`JobStatus::Queued`] or [`JobStatus::Processing`] to [`JobStatus::Cancelled`]. The update also sets the
+/// `updated_at` timestamp to the current time. Workers notice that a job they are running was cancelled
+/// on their next poll and cancel the token it got in [`super::BackgroundWorker::perform_with_token`].
+/// Batches whose last remaining jobs were cancelled are finished, enqueuing their `on_failure` job.
 ///
 /// # Errors
 ///
 /// This function will return an error if it fails
 pub async fn cancel_jobs_by_name(pool: &PgPool, name: &str) -> Result<()> {
-    debug!(job_name = %name, "Cancelling queued jobs by name");
-    sqlx::query(
-        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW() WHERE name = $2 AND status = $3",
+    debug!(job_name = %name, "Cancelling queued and running jobs by name");
+    let batch_ids: Vec<String> = sqlx::query_scalar(
+        "WITH cancelled AS (UPDATE pg_loco_queue SET status = $1, updated_at = NOW() WHERE name = \
+         $2 AND status IN ($3, $4) RETURNING batch_id) SELECT DISTINCT batch_id FROM cancelled \
+         WHERE batch_id IS NOT NULL",
     )
     .bind(JobStatus::Cancelled.to_string())
     .bind(name)
     .bind(JobStatus::Queued.to_string())
-    .execute(pool)
+    .bind(JobStatus::Processing.to_string())
+    .fetch_all(pool)
     .await?;
+
+    for batch_id in batch_ids {
+        finish_batch_job(pool, &batch_id).await?;
+    }
     Ok(())
 }
 
+/// Cancels a single job by id, the same way [`cancel_jobs_by_name`] does.
+///
+/// Returns `false` when no queued or running job has this id.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn cancel_job(pool: &PgPool, id: &str) -> Result<bool> {
+    debug!(job_id = %id, "Cancelling job");
+    let cancelled: Option<Option<String>> = sqlx::query_scalar(
+        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW() WHERE id = $2 AND status IN \
+         ($3, $4) RETURNING batch_id",
+    )
+    .bind(JobStatus::Cancelled.to_string())
+    .bind(id)
+    .bind(JobStatus::Queued.to_string())
+    .bind(JobStatus::Processing.to_string())
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(batch_id) = cancelled else {
+        return Ok(false);
+    };
+    if let Some(batch_id) = batch_id {
+        finish_batch_job(pool, &batch_id).await?;
+    }
+    Ok(true)
+}
+
 /// Clear all jobs
 ///
 /// # Errors
 ///
 /// This function will return an error if it fails
 pub async fn clear(pool: &PgPool) -> Result<()> {
-    sqlx::query("DELETE FROM pg_loco_queue")
-        .execute(pool)
-        .await?;
+    sqlx::raw_sql(
+        "DELETE FROM pg_loco_queue; DELETE FROM pg_loco_batches; DELETE FROM pg_loco_rate_limits;",
+    )
+    .execute(pool)
+    .await?;
     Ok(())
 }
 
@@ -517,7 +1390,121 @@ pub async fn requeue(pool: &PgPool, age_minutes: &i64) -> Result<()> {
         .execute(pool)
         .await?;
 
-    Ok(())
+    notify_enqueued(pool).await
+}
+
+/// Puts the jobs that workers whose last heartbeat is older than `ttl` were
+/// running back in the queue, and forgets about those workers. Returns how
+/// many jobs were requeued.
+///
+/// Running workers do this on every heartbeat, so jobs of a worker process
+/// that was killed are picked up again without `cargo loco jobs requeue`.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn requeue_orphaned_jobs(pool: &PgPool, ttl: Duration) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let expired: Vec<String> = sqlx::query_scalar(
+        "DELETE FROM pg_loco_workers WHERE heartbeat_at <= NOW() - make_interval(secs => $1) \
+         RETURNING id",
+    )
+    .bind(ttl.as_secs_f64())
+    .fetch_all(&mut *tx)
+    .await?;
+    if expired.is_empty() {
+        return Ok(0);
+    }
+
+    debug!(workers = ?expired, "Requeueing jobs of expired workers");
+    let requeued = sqlx::query(
+        "UPDATE pg_loco_queue SET status = $1, updated_at = NOW() WHERE status = $2 AND worker_id \
+         = ANY($3)",
+    )
+    .bind(JobStatus::Queued.to_string())
+    .bind(JobStatus::Processing.to_string())
+    .bind(&expired)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+
+    if requeued > 0 {
+        notify_enqueued(pool).await?;
+    }
+    Ok(requeued)
+}
+
+/// Lists the worker processes whose heartbeat hasn't expired, with the jobs
+/// they are running.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn get_workers(pool: &PgPool) -> Result<Vec<WorkerInfo>> {
+    let rows = sqlx::query(
+        "SELECT id, hostname, pid, tags, started_at, heartbeat_at FROM pg_loco_workers WHERE \
+         heartbeat_at > NOW() - make_interval(secs => $1) ORDER BY started_at",
+    )
+    .bind(HEARTBEAT_TTL.as_secs_f64())
+    .fetch_all(pool)
+    .await?;
+
+    let mut workers = Vec::with_capacity(rows.len());
+    for row in rows {
+        let tags: Option<JsonValue> = row.try_get("tags")?;
+        workers.push(WorkerInfo {
+            id: row.try_get("id")?,
+            hostname: row.try_get("hostname")?,
+            pid: u32::try_from(row.try_get::<i64, _>("pid")?).unwrap_or_default(),
+            tags: tags
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            jobs: Vec::new(),
+            started_at: row.try_get("started_at")?,
+            heartbeat_at: row.try_get("heartbeat_at")?,
+        });
+    }
+
+    let ids: Vec<&str> = workers.iter().map(|worker| worker.id.as_str()).collect();
+    let jobs: Vec<(String, String)> = sqlx::query_as(
+        "SELECT worker_id, id FROM pg_loco_queue WHERE status = $1 AND worker_id = ANY($2) ORDER \
+         BY updated_at",
+    )
+    .bind(JobStatus::Processing.to_string())
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+    for (worker_id, id) in jobs {
+        if let Some(worker) = workers.iter_mut().find(|worker| worker.id == worker_id) {
+            worker.jobs.push(id);
+        }
+    }
+    Ok(workers)
+}
+
+/// Takes the lock `name` for `owner` until `ttl` from now, or extends it
+/// when `owner` already holds it. Returns `false` while another owner holds
+/// a lock that hasn't expired.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn try_lock(pool: &PgPool, name: &str, owner: &str, ttl: Duration) -> Result<bool> {
+    let locked = sqlx::query(
+        "INSERT INTO pg_loco_locks (name, owner, expires_at) VALUES ($1, $2, NOW() + \
+         make_interval(secs => $3)) ON CONFLICT (name) DO UPDATE SET owner = EXCLUDED.owner, \
+         expires_at = EXCLUDED.expires_at WHERE pg_loco_locks.owner = EXCLUDED.owner OR \
+         pg_loco_locks.expires_at <= NOW()",
+    )
+    .bind(name)
+    .bind(owner)
+    .bind(ttl.as_secs_f64())
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(locked == 1)
 }
 
 /// Ping system
@@ -573,6 +1560,81 @@ pub async fn get_jobs(
     Ok(jobs)
 }
 
+/// Fetches a single job by id.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn get_job(pool: &PgPool, id: &str) -> Result<Option<Job>> {
+    let row = sqlx::query("SELECT * FROM pg_loco_queue WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(to_job).transpose()
+}
+
+/// Counts the jobs by worker and status, and how many of them were updated
+/// in the last hour and day, for the dashboard.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+#[cfg(feature = "bg_dashboard")]
+pub(crate) async fn count_jobs(pool: &PgPool) -> Result<Vec<JobCount>> {
+    let rows = sqlx::query(
+        "SELECT name, status, COUNT(*) AS total, COUNT(*) FILTER (WHERE updated_at >= NOW() - \
+         INTERVAL '1 hour') AS last_hour, COUNT(*) FILTER (WHERE updated_at >= NOW() - INTERVAL \
+         '1 day') AS last_day FROM pg_loco_queue GROUP BY name, status",
+    )
+    .fetch_all(pool)
+    .await?;
+    let count = |row: &PgRow, column: &str| usize::try_from(row.get::<i64, _>(column)).unwrap_or(0);
+    rows.iter()
+        .map(|row| {
+            Ok(JobCount {
+                queue: None,
+                name: row.get("name"),
+                status: row.get::<String, _>("status").parse().map_err(|err| {
+                    let status: String = row.get("status");
+                    tracing::error!(status, err = %err, "Unsupported job status in database");
+                    Error::string("invalid job status")
+                })?,
+                total: count(row, "total"),
+                last_hour: count(row, "last_hour"),
+                last_day: count(row, "last_day"),
+            })
+        })
+        .collect()
+}
+
+/// Lists the `limit` most recently updated jobs, optionally only those with
+/// the given `status` and `name`, for the dashboard.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+#[cfg(feature = "bg_dashboard")]
+pub(crate) async fn recent_jobs(
+    pool: &PgPool,
+    status: Option<&JobStatus>,
+    name: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Job>> {
+    let mut query =
+        sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM pg_loco_queue WHERE true");
+    if let Some(status) = status {
+        query.push(" AND status = ").push_bind(status.to_string());
+    }
+    if let Some(name) = name {
+        query.push(" AND name = ").push_bind(name.to_string());
+    }
+    query
+        .push(" ORDER BY updated_at DESC LIMIT ")
+        .push_bind(i64::try_from(limit).unwrap_or(i64::MAX));
+    let rows = query.build().fetch_all(pool).await?;
+    rows.iter().map(to_job).collect()
+}
+
 /// Converts a row from the database into a [`Job`] object.
 ///
 /// This function takes a row from the `Postgres` database and manually extracts the necessary
@@ -612,6 +1674,13 @@ fn to_job(row: &PgRow) -> Result<Job> {
         created_at: row.try_get("created_at").unwrap_or_default(),
         updated_at: row.try_get("updated_at").unwrap_or_default(),
         tags,
+        attempts: row.try_get("attempts").unwrap_or_default(),
+        last_error: row.try_get("last_error").unwrap_or_default(),
+        backtrace: row.try_get("backtrace").unwrap_or_default(),
+        priority: row.try_get("priority").unwrap_or_default(),
+        unique_key: row.try_get("unique_key").unwrap_or_default(),
+        result: row.try_get("result").unwrap_or_default(),
+        batch_id: row.try_get("batch_id").unwrap_or_default(),
     })
 }
 
@@ -619,6 +1688,9 @@ fn to_job(row: &PgRow) -> Result<Job> {
 pub struct RunOpts {
     pub num_workers: u32,
     pub poll_interval_sec: u32,
+    /// How long running jobs may take to finish once the queue shuts down,
+    /// see `workers.shutdown_timeout`.
+    pub shutdown_timeout: Option<Duration>,
 }
 
 /// Create this provider
@@ -641,6 +1713,7 @@ pub async fn create_provider(qcfg: &PostgresQueueConfig) -> Result<Queue> {
         RunOpts {
             num_workers: qcfg.num_workers,
             poll_interval_sec: qcfg.poll_interval_sec,
+            shutdown_timeout: None,
         },
         token, // Pass the token
     ))
@@ -696,6 +1769,16 @@ mod tests {
             .expect("job not found")
     }
 
+    /// Marks the job as picked up by a worker, as [`dequeue`] does.
+    async fn start_job(pool: &PgPool, id: &str) {
+        sqlx::query("UPDATE pg_loco_queue SET status = $1 WHERE id = $2")
+            .bind(JobStatus::Processing.to_string())
+            .bind(id)
+            .execute(pool)
+            .await
+            .expect("start job");
+    }
+
     // New setup function that uses our testcontainer
     async fn setup_pg_test() -> (
         PgPool,
@@ -748,9 +1831,11 @@ mod tests {
             &pool,
             "PasswordChangeNotification",
             job_data,
-            run_at,
             None,
-            None
+            &EnqueueOpts {
+                run_at: Some(run_at),
+                ..Default::default()
+            }
         )
         .await
         .is_ok());
@@ -780,9 +1865,11 @@ mod tests {
             &pool,
             "PasswordChangeNotification",
             job_data,
-            run_at,
             None,
-            None
+            &EnqueueOpts {
+                run_at: Some(run_at),
+                ..Default::default()
+            }
         )
         .await
         .is_ok());
@@ -797,7 +1884,7 @@ mod tests {
 
         std::thread::sleep(std::time::Duration::from_secs(1));
 
-        assert!(dequeue(&pool, &[]).await.is_ok());
+        assert!(dequeue(&pool, &[], &HashMap::new(), "worker").await.is_ok());
 
         let job_after_dequeue = get_all_jobs(&pool)
             .await
@@ -813,6 +1900,155 @@ mod tests {
             });
     }
 
+    #[tokio::test]
+    async fn can_dequeue_by_priority() {
+        let (pool, _container) = setup_pg_test().await;
+
+        let run_at = Utc::now() - chrono::Duration::minutes(1);
+        for (name, priority) in [("Low", -1), ("Normal", 0), ("High", 10)] {
+            assert!(enqueue(
+                &pool,
+                name,
+                serde_json::json!({}),
+                None,
+                &EnqueueOpts {
+                    run_at: Some(run_at),
+                    priority: Some(priority),
+                    ..Default::default()
+                }
+            )
+            .await
+            .is_ok());
+        }
+
+        let mut names = Vec::new();
+        while let Some(job) = dequeue(&pool, &[], &HashMap::new(), "worker")
+            .await
+            .expect("dequeue")
+        {
+            names.push(job.name);
+        }
+        assert_eq!(names, vec!["High", "Normal", "Low"]);
+    }
+
+    #[tokio::test]
+    async fn can_dequeue_with_limits() {
+        let (pool, _container) = setup_pg_test().await;
+
+        let run_at = Utc::now() - chrono::Duration::minutes(1);
+        let worker_opts = HashMap::from([
+            (
+                "Limited".to_string(),
+                WorkerOpts {
+                    concurrency_limit: Some(1),
+                    ..Default::default()
+                },
+            ),
+            (
+                "Throttled".to_string(),
+                WorkerOpts {
+                    rate_limit: Some(crate::bgworker::RateLimit::per_minute(2)),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        for (name, count, priority) in [("Limited", 2, 10), ("Throttled", 3, 5), ("Other", 1, 0)] {
+            for _ in 0..count {
+                assert!(enqueue(
+                    &pool,
+                    name,
+                    serde_json::json!({}),
+                    None,
+                    &EnqueueOpts {
+                        run_at: Some(run_at),
+                        priority: Some(priority),
+                        ..Default::default()
+                    }
+                )
+                .await
+                .is_ok());
+            }
+        }
+
+        let mut jobs = Vec::new();
+        while let Some(job) = dequeue(&pool, &[], &worker_opts, "worker")
+            .await
+            .expect("dequeue")
+        {
+            jobs.push(job);
+        }
+        assert_eq!(
+            jobs.iter().map(|job| job.name.as_str()).collect::<Vec<_>>(),
+            vec!["Limited", "Throttled", "Throttled", "Other"]
+        );
+
+        // a finished job frees a slot and a new window lets more jobs start
+        assert!(complete_job(&pool, &jobs[0].id, None, None).await.is_ok());
+        sqlx::query("UPDATE pg_loco_rate_limits SET window_start = NOW() - INTERVAL '1 hour'")
+            .execute(&pool)
+            .await
+            .expect("reset rate limit window");
+        let mut names = Vec::new();
+        while let Some(job) = dequeue(&pool, &[], &worker_opts, "worker")
+            .await
+            .expect("dequeue")
+        {
+            names.push(job.name);
+        }
+        assert_eq!(names, vec!["Limited", "Throttled"]);
+    }
+
+    #[tokio::test]
+    async fn can_enqueue_unique_jobs() {
+        let (pool, _container) = setup_pg_test().await;
+
+        let unique = |key: &str| EnqueueOpts {
+            unique_key: Some(key.to_string()),
+            ..Default::default()
+        };
+
+        let first = enqueue(&pool, "Report", serde_json::json!({}), None, &unique("a"))
+            .await
+            .expect("enqueue");
+        let duplicate = enqueue(&pool, "Report", serde_json::json!({}), None, &unique("a"))
+            .await
+            .expect("enqueue");
+        let other_key = enqueue(&pool, "Report", serde_json::json!({}), None, &unique("b"))
+            .await
+            .expect("enqueue");
+        let other_name = enqueue(&pool, "Export", serde_json::json!({}), None, &unique("a"))
+            .await
+            .expect("enqueue");
+
+        assert_eq!(duplicate, first);
+        assert_ne!(other_key, first);
+        assert_ne!(other_name, first);
+        assert_eq!(get_all_jobs(&pool).await.len(), 3);
+
+        // the key is free again once the job is done
+        start_job(&pool, &first).await;
+        assert!(complete_job(&pool, &first, None, None).await.is_ok());
+        let after_complete = enqueue(&pool, "Report", serde_json::json!({}), None, &unique("a"))
+            .await
+            .expect("enqueue");
+        assert_ne!(after_complete, first);
+
+        // or once the uniqueness window has passed
+        let expired = enqueue(
+            &pool,
+            "Report",
+            serde_json::json!({}),
+            None,
+            &EnqueueOpts {
+                unique_for: Some(Duration::ZERO),
+                ..unique("a")
+            },
+        )
+        .await
+        .expect("enqueue");
+        assert_ne!(expired, after_complete);
+    }
+
     #[tokio::test]
     async fn can_complete_job_without_interval() {
         let (pool, _container) = setup_pg_test().await;
@@ -821,7 +2057,8 @@ mod tests {
         let job = get_job(&pool, "01JDM0X8EVAM823JZBGKYNBA99").await;
 
         assert_eq!(job.status, JobStatus::Queued);
-        assert!(complete_job(&pool, &job.id, None).await.is_ok());
+        start_job(&pool, &job.id).await;
+        assert!(complete_job(&pool, &job.id, None, None).await.is_ok());
 
         let job = get_job(&pool, "01JDM0X8EVAM823JZBGKYNBA99").await;
 
@@ -839,7 +2076,8 @@ mod tests {
 
         std::thread::sleep(std::time::Duration::from_secs(1));
 
-        assert!(complete_job(&pool, &before_complete_job.id, Some(10))
+        start_job(&pool, &before_complete_job.id).await;
+        assert!(complete_job(&pool, &before_complete_job.id, Some(10), None)
             .await
             .is_ok());
 
@@ -863,50 +2101,234 @@ mod tests {
 
         let before_fail_job = get_job(&pool, "01JDM0X8EVAM823JZBGKYNBA97").await;
 
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        std::thread::sleep(std::time::Duration::from_secs(1));
+
+        assert!(fail_job(
+            &pool,
+            &before_fail_job.id,
+            &crate::Error::string("some error")
+        )
+        .await
+        .is_ok());
+
+        let after_fail_job = get_job(&pool, "01JDM0X8EVAM823JZBGKYNBA97").await;
+
+        assert_ne!(after_fail_job.updated_at, before_fail_job.updated_at);
+        with_settings!({
+                filters => reduction().iter().map(|&(pattern, replacement)| (pattern,
+        replacement)),     }, {
+                assert_debug_snapshot!(after_fail_job);
+            });
+    }
+
+    #[tokio::test]
+    async fn cannot_finish_job_that_is_no_longer_processing() {
+        let (pool, _container) = setup_pg_test().await;
+        tests_cfg::queue::postgres_seed_data(&pool).await;
+
+        // cancelled while running
+        assert!(cancel_job(&pool, "01JDM0X8EVAM823JZBGKYNBA97")
+            .await
+            .expect("cancel"));
+        assert!(
+            complete_job(&pool, &"01JDM0X8EVAM823JZBGKYNBA97".to_string(), None, None)
+                .await
+                .is_ok()
+        );
+        let job = get_job(&pool, "01JDM0X8EVAM823JZBGKYNBA97").await;
+        assert_eq!(job.status, JobStatus::Cancelled);
+
+        // put back in the queue after its worker was lost
+        let id = "01JDM0X8EVAM823JZBGKYNBA99".to_string();
+        assert_eq!(get_job(&pool, &id).await.status, JobStatus::Queued);
+        let error = crate::Error::string("boom");
+        assert!(fail_job(&pool, &id, &error).await.is_ok());
+        assert!(retry_job(&pool, &id, &error, Utc::now()).await.is_ok());
+        assert!(bury_job(&pool, &id, &error).await.is_ok());
+        let job = get_job(&pool, &id).await;
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.attempts, 0);
+        assert_eq!(job.last_error, None);
+    }
+
+    #[tokio::test]
+    async fn can_cancel_job_by_name() {
+        let (pool, _container) = setup_pg_test().await;
+        tests_cfg::queue::postgres_seed_data(&pool).await;
+
+        let count_cancelled_jobs = get_all_jobs(&pool)
+            .await
+            .iter()
+            .filter(|j| j.status == JobStatus::Cancelled)
+            .count();
+
+        assert_eq!(count_cancelled_jobs, 1);
+
+        assert!(cancel_jobs_by_name(&pool, "UserAccountActivation")
+            .await
+            .is_ok());
+
+        let count_cancelled_jobs = get_all_jobs(&pool)
+            .await
+            .iter()
+            .filter(|j| j.status == JobStatus::Cancelled)
+            .count();
+
+        assert_eq!(count_cancelled_jobs, 2);
+    }
+
+    #[tokio::test]
+    async fn can_try_lock() {
+        let (pool, _container) = setup_pg_test().await;
+
+        let ttl = Duration::from_secs(30);
+        assert!(try_lock(&pool, "report", "a", ttl).await.expect("lock"));
+        assert!(!try_lock(&pool, "report", "b", ttl).await.expect("lock"));
+        // the owner extends its lock
+        assert!(try_lock(&pool, "report", "a", ttl).await.expect("lock"));
+        assert!(try_lock(&pool, "other", "b", ttl).await.expect("lock"));
+
+        sqlx::query(
+            "UPDATE pg_loco_locks SET expires_at = NOW() - INTERVAL '1 second' WHERE name = \
+             'report'",
+        )
+        .execute(&pool)
+        .await
+        .expect("expire lock");
+        assert!(try_lock(&pool, "report", "b", ttl).await.expect("lock"));
+        assert!(!try_lock(&pool, "report", "a", ttl).await.expect("lock"));
+    }
+
+    #[tokio::test]
+    async fn can_requeue_jobs_of_expired_workers() {
+        let (pool, _container) = setup_pg_test().await;
+
+        let worker = WorkerInfo::current(&["email".to_string()]);
+        record_heartbeat(&pool, &worker)
+            .await
+            .expect("record heartbeat");
+
+        let held = enqueue(
+            &pool,
+            "EmailWorker",
+            serde_json::json!({}),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await
+        .expect("enqueue");
+        let unowned = enqueue(
+            &pool,
+            "EmailWorker",
+            serde_json::json!({}),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await
+        .expect("enqueue");
+        let job = dequeue(&pool, &[], &HashMap::new(), &worker.id)
+            .await
+            .expect("dequeue");
+        assert_eq!(job.map(|job| job.id), Some(held.clone()));
+        let job = dequeue(&pool, &[], &HashMap::new(), "unknown")
+            .await
+            .expect("dequeue");
+        assert_eq!(job.map(|job| job.id), Some(unowned.clone()));
+
+        let workers = get_workers(&pool).await.expect("get workers");
+        assert_eq!(workers.len(), 1);
+        assert_eq!(workers[0].id, worker.id);
+        assert_eq!(workers[0].pid, worker.pid);
+        assert_eq!(workers[0].tags, vec!["email".to_string()]);
+        assert_eq!(workers[0].jobs, vec![held.clone()]);
+
+        assert_eq!(
+            requeue_orphaned_jobs(&pool, HEARTBEAT_TTL)
+                .await
+                .expect("requeue"),
+            0
+        );
+
+        sqlx::query("UPDATE pg_loco_workers SET heartbeat_at = NOW() - INTERVAL '2 minutes'")
+            .execute(&pool)
+            .await
+            .expect("expire heartbeat");
+        assert!(get_workers(&pool).await.expect("get workers").is_empty());
+        assert_eq!(
+            requeue_orphaned_jobs(&pool, HEARTBEAT_TTL)
+                .await
+                .expect("requeue"),
+            1
+        );
+
+        assert_eq!(get_job(&pool, &held).await.status, JobStatus::Queued);
+        // jobs without a known worker are left alone
+        assert_eq!(get_job(&pool, &unowned).await.status, JobStatus::Processing);
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pg_loco_workers")
+            .fetch_one(&pool)
+            .await
+            .expect("count workers");
+        assert_eq!(remaining, 0);
+    }
+
+    #[cfg(feature = "with-db")]
+    #[tokio::test]
+    async fn can_enqueue_in_tx() {
+        use sea_orm::TransactionTrait;
+
+        let (pool, _container) = setup_pg_test().await;
+        let db = sea_orm::SqlxPostgresConnector::from_sqlx_postgres_pool(pool.clone());
+        let opts = EnqueueOpts {
+            unique_key: Some("order-1".to_string()),
+            ..Default::default()
+        };
 
-        assert!(fail_job(
-            &pool,
-            &before_fail_job.id,
-            &crate::Error::string("some error")
-        )
-        .await
-        .is_ok());
+        let tx = db.begin().await.expect("begin");
+        let rolled_back = enqueue_in_tx(&tx, "SendEmail", serde_json::json!({}), None, &opts)
+            .await
+            .expect("enqueue in tx");
+        tx.rollback().await.expect("rollback");
+        assert!(super::get_job(&pool, &rolled_back)
+            .await
+            .expect("get job")
+            .is_none());
 
-        let after_fail_job = get_job(&pool, "01JDM0X8EVAM823JZBGKYNBA97").await;
+        let tx = db.begin().await.expect("begin");
+        let committed = enqueue_in_tx(&tx, "SendEmail", serde_json::json!({}), None, &opts)
+            .await
+            .expect("enqueue in tx");
+        let duplicate = enqueue_in_tx(&tx, "SendEmail", serde_json::json!({}), None, &opts)
+            .await
+            .expect("enqueue in tx");
+        assert_eq!(duplicate, committed);
+        assert!(super::get_job(&pool, &committed)
+            .await
+            .expect("get job")
+            .is_none());
+        tx.commit().await.expect("commit");
 
-        assert_ne!(after_fail_job.updated_at, before_fail_job.updated_at);
-        with_settings!({
-                filters => reduction().iter().map(|&(pattern, replacement)| (pattern,
-        replacement)),     }, {
-                assert_debug_snapshot!(after_fail_job);
-            });
+        let job = get_job(&pool, &committed).await;
+        assert_eq!(job.name, "SendEmail");
+        assert_eq!(job.status, JobStatus::Queued);
     }
 
     #[tokio::test]
-    async fn can_cancel_job_by_name() {
+    async fn can_cancel_job() {
         let (pool, _container) = setup_pg_test().await;
         tests_cfg::queue::postgres_seed_data(&pool).await;
 
-        let count_cancelled_jobs = get_all_jobs(&pool)
-            .await
-            .iter()
-            .filter(|j| j.status == JobStatus::Cancelled)
-            .count();
-
-        assert_eq!(count_cancelled_jobs, 1);
-
-        assert!(cancel_jobs_by_name(&pool, "UserAccountActivation")
-            .await
-            .is_ok());
+        let queued = "01JDM0X8EVAM823JZBGKYNBA99";
+        let running = "01JDM0X8EVAM823JZBGKYNBA97";
+        let completed = "01JDM0X8EVAM823JZBGKYNBA98";
 
-        let count_cancelled_jobs = get_all_jobs(&pool)
-            .await
-            .iter()
-            .filter(|j| j.status == JobStatus::Cancelled)
-            .count();
+        assert!(cancel_job(&pool, queued).await.expect("cancel job"));
+        assert!(cancel_job(&pool, running).await.expect("cancel job"));
+        assert!(!cancel_job(&pool, completed).await.expect("cancel job"));
+        assert!(!cancel_job(&pool, "missing").await.expect("cancel job"));
 
-        assert_eq!(count_cancelled_jobs, 2);
+        assert_eq!(get_job(&pool, queued).await.status, JobStatus::Cancelled);
+        assert_eq!(get_job(&pool, running).await.status, JobStatus::Cancelled);
+        assert_eq!(get_job(&pool, completed).await.status, JobStatus::Completed);
     }
 
     #[tokio::test]
@@ -1129,7 +2551,7 @@ mod tests {
         let (pool, _container) = setup_pg_test().await;
 
         let job_data: JobData = serde_json::json!(null);
-        let job_id = enqueue(&pool, "PanicJob", job_data, Utc::now(), None, None)
+        let job_id = enqueue(&pool, "PanicJob", job_data, None, &EnqueueOpts::default())
             .await
             .expect("Failed to enqueue job");
 
@@ -1157,6 +2579,7 @@ mod tests {
         let opts = RunOpts {
             num_workers: 1,
             poll_interval_sec: 1,
+            shutdown_timeout: None,
         };
         let token = CancellationToken::new();
         let handles = registry.run(&pool, &opts, &token, &[]);
@@ -1173,21 +2596,392 @@ mod tests {
         let failed_job = get_job(&pool, &job_id).await;
         assert_eq!(failed_job.status, JobStatus::Failed);
 
-        // Verify the error message stored in job data
+        // Verify the error message recorded on the job
         let error_msg = failed_job
-            .data
-            .as_array()
-            .and_then(|arr| arr.get(1))
-            .and_then(|obj| obj.as_object())
-            .and_then(|obj| obj.get("error"))
-            .and_then(|v| v.as_str())
-            .expect("Expected error message in job data");
+            .last_error
+            .as_deref()
+            .expect("Expected the error message of the job");
         assert!(
             error_msg.contains("intentional panic for testing"),
             "Error message '{error_msg}' did not contain expected text"
         );
     }
 
+    #[tokio::test]
+    async fn can_wake_workers_on_enqueue() {
+        let (pool, _container) = setup_pg_test().await;
+        struct NoopWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<()> for NoopWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("NoopWorker".to_string(), NoopWorker)
+            .is_ok());
+
+        let opts = RunOpts {
+            num_workers: 2,
+            poll_interval_sec: 60,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&pool, &opts, &token, &[]);
+        sleep(Duration::from_millis(500)).await;
+
+        // workers are idle until the poll interval, enqueuing wakes them up
+        let job_id = enqueue(
+            &pool,
+            "NoopWorker",
+            serde_json::json!(null),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await
+        .expect("enqueue");
+        sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(get_job(&pool, &job_id).await.status, JobStatus::Completed);
+
+        token.cancel();
+        for handle in handles {
+            assert!(handle.await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn can_store_job_result() {
+        let (pool, _container) = setup_pg_test().await;
+        struct ReportWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<()> for ReportWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                crate::bgworker::set_job_result(&serde_json::json!({ "rows": 42 }))
+            }
+        }
+
+        let job_id = enqueue(
+            &pool,
+            "ReportWorker",
+            serde_json::json!(null),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await
+        .expect("Failed to enqueue job");
+
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("ReportWorker".to_string(), ReportWorker)
+            .is_ok());
+
+        let opts = RunOpts {
+            num_workers: 1,
+            poll_interval_sec: 1,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&pool, &opts, &token, &[]);
+        sleep(Duration::from_secs(1)).await;
+        for handle in handles {
+            handle.abort();
+        }
+
+        let job: JobInfo = super::get_job(&pool, &job_id)
+            .await
+            .expect("get job")
+            .expect("job exists")
+            .into();
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.result, Some(serde_json::json!({ "rows": 42 })));
+    }
+
+    #[tokio::test]
+    async fn can_cancel_running_job() {
+        let (pool, _container) = setup_pg_test().await;
+        struct WaitWorker(Arc<std::sync::atomic::AtomicBool>);
+        #[async_trait::async_trait]
+        impl BackgroundWorker<()> for WaitWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self(Arc::default())
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                Ok(())
+            }
+            async fn perform_with_token(
+                &self,
+                _args: (),
+                token: CancellationToken,
+            ) -> crate::Result<()> {
+                token.cancelled().await;
+                self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        let job_id = enqueue(
+            &pool,
+            "WaitWorker",
+            serde_json::json!(null),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await
+        .expect("Failed to enqueue job");
+
+        let stopped = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("WaitWorker".to_string(), WaitWorker(stopped.clone()))
+            .is_ok());
+
+        let opts = RunOpts {
+            num_workers: 1,
+            poll_interval_sec: 1,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&pool, &opts, &token, &[]);
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(get_job(&pool, &job_id).await.status, JobStatus::Processing);
+
+        assert!(cancel_jobs_by_name(&pool, "WaitWorker").await.is_ok());
+        sleep(Duration::from_secs(2)).await;
+        for handle in handles {
+            handle.abort();
+        }
+
+        assert!(stopped.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(get_job(&pool, &job_id).await.status, JobStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn can_finish_batches() {
+        let (pool, _container) = setup_pg_test().await;
+        struct ImportWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<bool> for ImportWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, succeed: bool) -> crate::Result<()> {
+                if succeed {
+                    Ok(())
+                } else {
+                    Err(Error::string("import failed"))
+                }
+            }
+        }
+        struct SummaryWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<String> for SummaryWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _batch_id: String) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+        struct AlertWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<String> for AlertWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _batch_id: String) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut batches = Vec::new();
+        for results in [vec![true, true], vec![true, false], vec![]] {
+            let mut batch = Batch::new();
+            for succeed in results {
+                batch.add::<ImportWorker, _>(succeed).expect("add job");
+            }
+            let id = batch.id().to_string();
+            batch
+                .on_complete::<SummaryWorker, _>(id.clone())
+                .expect("set on_complete")
+                .on_failure::<AlertWorker, _>(id.clone())
+                .expect("set on_failure");
+            assert!(enqueue_batch(&pool, &batch).await.is_ok());
+            batches.push(id);
+        }
+
+        let pending = get_batch(&pool, &batches[0])
+            .await
+            .expect("get batch")
+            .expect("batch exists");
+        assert_eq!((pending.total, pending.pending), (2, 2));
+        assert!(pending.finished_at.is_none());
+
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("ImportWorker".to_string(), ImportWorker)
+            .is_ok());
+
+        let opts = RunOpts {
+            num_workers: 1,
+            poll_interval_sec: 1,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&pool, &opts, &token, &[]);
+        sleep(Duration::from_secs(2)).await;
+        for handle in handles {
+            handle.abort();
+        }
+
+        let succeeded = get_batch(&pool, &batches[0])
+            .await
+            .expect("get batch")
+            .expect("batch exists");
+        assert_eq!(
+            (succeeded.pending, succeeded.completed, succeeded.failed),
+            (0, 2, 0)
+        );
+        assert!(succeeded.finished_at.is_some());
+        let failed = get_batch(&pool, &batches[1])
+            .await
+            .expect("get batch")
+            .expect("batch exists");
+        assert_eq!((failed.pending, failed.completed, failed.failed), (0, 1, 1));
+
+        let callbacks = get_all_jobs(&pool)
+            .await
+            .into_iter()
+            .filter(|job| job.name != "ImportWorker")
+            .map(|job| (job.name, job.data))
+            .collect::<Vec<_>>();
+        assert_eq!(callbacks.len(), 3);
+        assert!(callbacks.contains(&("SummaryWorker".to_string(), serde_json::json!(batches[0]))));
+        assert!(callbacks.contains(&("AlertWorker".to_string(), serde_json::json!(batches[1]))));
+        assert!(callbacks.contains(&("SummaryWorker".to_string(), serde_json::json!(batches[2]))));
+    }
+
+    #[tokio::test]
+    async fn can_retry_failed_job() {
+        let (pool, _container) = setup_pg_test().await;
+
+        let job_id = enqueue(
+            &pool,
+            "FlakyJob",
+            serde_json::json!(null),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await
+        .expect("Failed to enqueue job");
+
+        struct FlakyWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<()> for FlakyWorker {
+            fn max_retries() -> u32 {
+                1
+            }
+            fn backoff(_attempt: u32) -> Duration {
+                Duration::ZERO
+            }
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                Err(crate::Error::string("temporary failure"))
+            }
+        }
+
+        let mut registry = JobRegistry::new();
+        assert!(registry
+            .register_worker("FlakyJob".to_string(), FlakyWorker)
+            .is_ok());
+
+        let opts = RunOpts {
+            num_workers: 1,
+            poll_interval_sec: 1,
+            shutdown_timeout: None,
+        };
+        let token = CancellationToken::new();
+        let handles = registry.run(&pool, &opts, &token, &[]);
+
+        // first attempt and its retry both run within the poll interval
+        sleep(Duration::from_secs(3)).await;
+
+        for handle in handles {
+            handle.abort();
+        }
+
+        let job = get_job(&pool, &job_id).await;
+        assert_eq!(job.status, JobStatus::Dead);
+        assert_eq!(job.attempts, 2);
+        assert_eq!(job.last_error.as_deref(), Some("temporary failure"));
+        assert_eq!(job.data, serde_json::json!(null));
+    }
+
+    #[tokio::test]
+    async fn can_retry_jobs() {
+        let (pool, _container) = setup_pg_test().await;
+
+        assert!(initialize_database(&pool).await.is_ok());
+        sqlx::query(
+            r#"INSERT INTO pg_loco_queue (id, name, task_data, status, run_at, attempts) VALUES
+            ('job1', 'JobA', '{"a": 1, "error": "boom"}', 'failed', NOW(), 1),
+            ('job2', 'JobA', '{"a": 2}', 'dead', NOW(), 3),
+            ('job3', 'JobB', '{}', 'dead', NOW(), 3),
+            ('job4', 'JobA', '{}', 'completed', NOW(), 0),
+            ('job5', 'JobC', '{}', 'failed', NOW(), 1)"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO pg_loco_queue (id, name, task_data, status, run_at, attempts, unique_key) VALUES
+            ('job6', 'JobD', '{}', 'failed', NOW(), 1, 'order-1'),
+            ('job7', 'JobD', '{}', 'queued', NOW(), 0, 'order-1'),
+            ('job8', 'JobD', '{}', 'dead', NOW(), 3, 'order-2')"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(retry_jobs(&pool, &RetryTarget::Id("job3".to_string()))
+            .await
+            .is_ok());
+        let job = get_job(&pool, "job3").await;
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.attempts, 0);
+        assert_eq!(get_job(&pool, "job2").await.status, JobStatus::Dead);
+
+        assert!(retry_jobs(&pool, &RetryTarget::Name("JobA".to_string()))
+            .await
+            .is_ok());
+        let job = get_job(&pool, "job1").await;
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.data, serde_json::json!({"a": 1, "error": "boom"}));
+        let job = get_job(&pool, "job2").await;
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.data, serde_json::json!({"a": 2}));
+        assert_eq!(get_job(&pool, "job4").await.status, JobStatus::Completed);
+        assert_eq!(get_job(&pool, "job5").await.status, JobStatus::Failed);
+
+        assert!(retry_jobs(&pool, &RetryTarget::AllFailed).await.is_ok());
+        assert_eq!(get_job(&pool, "job5").await.status, JobStatus::Queued);
+        // order-1 is held by job7
+        assert_eq!(get_job(&pool, "job6").await.status, JobStatus::Failed);
+        let job = get_job(&pool, "job8").await;
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.unique_key.as_deref(), Some("order-2"));
+    }
+
     #[tokio::test]
     async fn can_dequeue_with_tags() {
         let (pool, _container) = setup_pg_test().await;
@@ -1202,9 +2996,11 @@ mod tests {
             &pool,
             "EmailNotification",
             job_data.clone(),
-            run_at,
-            None,
             email_tags,
+            &EnqueueOpts {
+                run_at: Some(run_at),
+                ..Default::default()
+            },
         )
         .await
         .expect("Failed to enqueue email job");
@@ -1215,9 +3011,11 @@ mod tests {
             &pool,
             "SmsNotification",
             job_data.clone(),
-            run_at,
-            None,
             sms_tags,
+            &EnqueueOpts {
+                run_at: Some(run_at),
+                ..Default::default()
+            },
         )
         .await
         .expect("Failed to enqueue sms job");
@@ -1228,9 +3026,11 @@ mod tests {
             &pool,
             "PriorityEmail",
             job_data.clone(),
-            run_at,
-            None,
             multi_tags,
+            &EnqueueOpts {
+                run_at: Some(run_at),
+                ..Default::default()
+            },
         )
         .await
         .expect("Failed to enqueue multi-tag job");
@@ -1240,9 +3040,11 @@ mod tests {
             &pool,
             "GenericNotification",
             job_data.clone(),
-            run_at,
-            None,
             None,
+            &EnqueueOpts {
+                run_at: Some(run_at),
+                ..Default::default()
+            },
         )
         .await
         .expect("Failed to enqueue untagged job");
@@ -1252,19 +3054,21 @@ mod tests {
         assert_eq!(all_jobs.len(), 4);
 
         // 1. Worker with no tags should only get untagged jobs
-        let job = dequeue(&pool, &[]).await.expect("dequeue failed");
+        let job = dequeue(&pool, &[], &HashMap::new(), "worker")
+            .await
+            .expect("dequeue failed");
         assert!(job.is_some());
         let job = job.unwrap();
         assert_eq!(job.id, no_tag_id);
         assert!(job.tags.is_none());
 
         // Mark the job as completed to remove it from the queued items
-        complete_job(&pool, &job.id, None)
+        complete_job(&pool, &job.id, None, None)
             .await
             .expect("Failed to complete job");
 
         // 2. Worker with "email" tag should get one of the email-tagged jobs
-        let job = dequeue(&pool, &["email".to_string()])
+        let job = dequeue(&pool, &["email".to_string()], &HashMap::new(), "worker")
             .await
             .expect("dequeue failed");
         assert!(job.is_some());
@@ -1276,12 +3080,12 @@ mod tests {
         assert!(job.tags.is_some());
 
         // Mark the job as completed
-        complete_job(&pool, &job.id, None)
+        complete_job(&pool, &job.id, None, None)
             .await
             .expect("Failed to complete job");
 
         // 3. Worker with "email" tag should get the remaining email job
-        let job = dequeue(&pool, &["email".to_string()])
+        let job = dequeue(&pool, &["email".to_string()], &HashMap::new(), "worker")
             .await
             .expect("dequeue failed");
         assert!(job.is_some());
@@ -1293,12 +3097,12 @@ mod tests {
         assert!(job.tags.is_some());
 
         // Mark the job as completed
-        complete_job(&pool, &job.id, None)
+        complete_job(&pool, &job.id, None, None)
             .await
             .expect("Failed to complete job");
 
         // 4. Worker with "sms" tag should get the sms job
-        let job = dequeue(&pool, &["sms".to_string()])
+        let job = dequeue(&pool, &["sms".to_string()], &HashMap::new(), "worker")
             .await
             .expect("dequeue failed");
         assert!(job.is_some());
@@ -1307,18 +3111,20 @@ mod tests {
         assert!(job.tags.is_some());
 
         // Mark the job as completed
-        complete_job(&pool, &job.id, None)
+        complete_job(&pool, &job.id, None, None)
             .await
             .expect("Failed to complete job");
 
         // 5. No more jobs should be available
-        let job = dequeue(&pool, &["email".to_string()])
+        let job = dequeue(&pool, &["email".to_string()], &HashMap::new(), "worker")
             .await
             .expect("dequeue failed");
         assert!(job.is_none());
 
         // 6. No more jobs should be available for untagged worker
-        let job = dequeue(&pool, &[]).await.expect("dequeue failed");
+        let job = dequeue(&pool, &[], &HashMap::new(), "worker")
+            .await
+            .expect("dequeue failed");
         assert!(job.is_none());
     }
 }
@@ -4,14 +4,20 @@ use std::{
     time::Duration,
 };
 
-use super::{BackgroundWorker, JobStatus, Queue};
+#[cfg(feature = "bg_dashboard")]
+use super::dashboard::{JobCount, JobTally, RecentJobs};
+use super::{
+    error_details, keep_alive, perform_job, run_job, BackgroundWorker, Batch, BatchInfo, BatchJob,
+    EnqueueOpts, JobInfo, JobOutcome, JobStatus, Queue, RetryTarget, WorkerInfo, WorkerOpts,
+    HEARTBEAT_TTL,
+};
 use crate::{config::RedisQueueConfig, Error, Result};
 use chrono::{DateTime, Utc};
 use futures_util::FutureExt;
 use redis::{aio::MultiplexedConnection as Connection, AsyncCommands, Client, Script};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, trace};
 use ulid::Ulid;
@@ -23,12 +29,27 @@ type JobData = JsonValue;
 const QUEUE_KEY_PREFIX: &str = "queue:";
 const JOB_KEY_PREFIX: &str = "job:";
 const PROCESSING_KEY_PREFIX: &str = "processing:";
+const SCHEDULED_KEY_PREFIX: &str = "scheduled:";
+const DEAD_KEY_PREFIX: &str = "dead:";
+const PRIORITIES_KEY_PREFIX: &str = "priorities:";
+const UNIQUE_KEY_PREFIX: &str = "unique:";
+const BATCH_KEY_PREFIX: &str = "batch:";
+const LIMIT_KEY_PREFIX: &str = "limit:";
+const WORKER_KEY_PREFIX: &str = "worker:";
+const WORKER_JOBS_KEY_PREFIX: &str = "worker_jobs:";
+const WORKERS_KEY: &str = "workers";
+const LOCK_KEY_PREFIX: &str = "lock:";
+
+/// How long a job waits before trying again when its worker is already
+/// running [`WorkerOpts::concurrency_limit`] jobs.
+const CONCURRENCY_RETRY_DELAY: Duration = Duration::from_secs(1);
 
 type JobHandler = Box<
     dyn Fn(
             JobId,
             JobData,
-        ) -> Pin<Box<dyn std::future::Future<Output = Result<(), crate::Error>> + Send>>
+            CancellationToken,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<Option<JsonValue>>> + Send>>
         + Send
         + Sync,
 >;
@@ -45,6 +66,40 @@ pub struct Job {
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
     pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub attempts: i32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub backtrace: Option<String>,
+    /// Queue the job was enqueued to, so failed jobs can be sent back to it.
+    #[serde(default)]
+    pub queue: Option<String>,
+    #[serde(default)]
+    pub priority: i16,
+    #[serde(default)]
+    pub unique_key: Option<String>,
+    #[serde(default)]
+    pub result: Option<JsonValue>,
+    #[serde(default)]
+    pub batch_id: Option<String>,
+}
+
+impl From<Job> for JobInfo {
+    fn from(job: Job) -> Self {
+        Self {
+            id: job.id,
+            name: job.name,
+            status: job.status,
+            run_at: job.run_at,
+            attempts: job.attempts,
+            error: job.last_error,
+            result: job.result,
+            batch_id: job.batch_id,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+        }
+    }
 }
 
 // Implementation for job creation and serialization
@@ -61,6 +116,14 @@ impl Job {
             created_at: Some(now),
             updated_at: Some(now),
             tags: None,
+            attempts: 0,
+            last_error: None,
+            backtrace: None,
+            queue: None,
+            priority: 0,
+            unique_key: None,
+            result: None,
+            batch_id: None,
         }
     }
 
@@ -77,6 +140,7 @@ impl Job {
 
 pub struct JobRegistry {
     handlers: Arc<HashMap<String, JobHandler>>,
+    worker_opts: Arc<HashMap<String, WorkerOpts>>,
 }
 
 impl JobRegistry {
@@ -85,6 +149,7 @@ impl JobRegistry {
     pub fn new() -> Self {
         Self {
             handlers: Arc::new(HashMap::new()),
+            worker_opts: Arc::new(HashMap::new()),
         }
     }
 
@@ -100,14 +165,19 @@ impl JobRegistry {
         for<'de> Args: Deserialize<'de>,
     {
         let worker = Arc::new(worker);
-        let wrapped_handler = move |_job_id: String, job_data: JobData| {
+        let wrapped_handler = move |_job_id: String,
+                                    job_data: JobData,
+                                    token: CancellationToken| {
             let w = worker.clone();
             Box::pin(async move {
                 let args = serde_json::from_value::<Args>(job_data);
                 match args {
                     Ok(args) => {
                         // Wrap the perform call in catch_unwind to handle panics
-                        match AssertUnwindSafe(w.perform(args)).catch_unwind().await {
+                        match AssertUnwindSafe(perform_job(w.perform_with_token(args, token)))
+                            .catch_unwind()
+                            .await
+                        {
                             Ok(result) => result,
                             Err(panic) => {
                                 let panic_msg = panic
@@ -122,8 +192,11 @@ impl JobRegistry {
                     }
                     Err(err) => Err(err.into()),
                 }
-            }) as Pin<Box<dyn Future<Output = Result<(), crate::Error>> + Send>>
+            }) as Pin<Box<dyn Future<Output = Result<Option<JsonValue>>> + Send>>
         };
+        Arc::get_mut(&mut self.worker_opts)
+            .ok_or_else(|| Error::string("cannot register worker"))?
+            .insert(name.clone(), WorkerOpts::from_worker::<Args, W>());
         Arc::get_mut(&mut self.handlers)
             .ok_or_else(|| Error::string("cannot register worker"))?
             .insert(name, Box::new(wrapped_handler));
@@ -148,15 +221,30 @@ impl JobRegistry {
         let mut jobs = Vec::new();
         let queues = get_queues(&opts.queues);
         let interval = opts.poll_interval_sec;
-
+        let shutdown_timeout = opts.shutdown_timeout;
+
+        let worker = WorkerInfo::current(tags);
+        let (alive, alive_rx) = mpsc::channel(1);
+        if opts.num_workers > 0 {
+            jobs.push(tokio::spawn(heartbeat(
+                client.clone(),
+                worker.clone(),
+                alive_rx,
+            )));
+        }
         for idx in 0..opts.num_workers {
             let handlers = self.handlers.clone();
+            let worker_opts = self.worker_opts.clone();
             let worker_token = token.clone();
             let client = client.clone();
             let queues = queues.clone();
             let tags = tags.to_owned();
+            let worker_jobs_key = format!("{WORKER_JOBS_KEY_PREFIX}{}", worker.id);
+            let alive = alive.clone();
 
             let job = tokio::spawn(async move {
+                // keeps the heartbeat going until this worker is done
+                let _alive = alive;
                 let mut conn = match client.get_multiplexed_async_connection().await {
                     Ok(conn) => conn,
                     Err(err) => {
@@ -172,42 +260,109 @@ impl JobRegistry {
                         break;
                     }
 
-                    let job_opt = match dequeue_with_conn(&mut conn, &queues, &tags).await {
-                        Ok(t) => t,
-                        Err(err) => {
-                            error!(err = err.to_string(), "cannot fetch from queue");
-                            None
-                        }
-                    };
+                    let job_opt =
+                        match dequeue_with_conn(&mut conn, &queues, &tags, &worker_opts).await {
+                            Ok(t) => t,
+                            Err(err) => {
+                                error!(err = err.to_string(), "cannot fetch from queue");
+                                None
+                            }
+                        };
 
                     if let Some((job, queue_name)) = job_opt {
                         debug!(job_id = job.id, name = job.name, "working on job");
+                        if let Err(err) = conn.sadd::<_, _, ()>(&worker_jobs_key, &job.id).await {
+                            error!(err = err.to_string(), job = ?job, "cannot record job of worker");
+                        }
                         if let Some(handler) = handlers.get(&job.name) {
-                            match handler(job.id.clone(), job.data.clone()).await {
-                                Ok(()) => {
+                            let opts = worker_opts.get(&job.name).copied().unwrap_or_default();
+                            let token = CancellationToken::new();
+                            let outcome = run_job(
+                                handler(job.id.clone(), job.data.clone(), token.clone()),
+                                &token,
+                                opts.timeout,
+                                Duration::from_secs(interval.into()),
+                                || is_job_cancelled(conn.clone(), &job.id),
+                                &worker_token,
+                                shutdown_timeout,
+                            )
+                            .await;
+                            let finished = !matches!(outcome, JobOutcome::Interrupted);
+                            match outcome {
+                                JobOutcome::Completed(result) => {
                                     if let Err(err) = complete_job_with_conn(
                                         &mut conn,
                                         &job.id,
                                         &queue_name,
                                         job.interval,
+                                        result,
                                     )
                                     .await
                                     {
                                         error!(err = err.to_string(), job = ?job, "cannot complete job");
                                     }
                                 }
-                                Err(err) => {
-                                    if let Err(err) =
+                                JobOutcome::Failed(err) => {
+                                    if let Some(run_at) = opts.retry_at(job.attempts + 1) {
+                                        if let Err(err) = retry_job_with_conn(
+                                            &mut conn,
+                                            &job.id,
+                                            &queue_name,
+                                            &err,
+                                            run_at,
+                                        )
+                                        .await
+                                        {
+                                            error!(err = err.to_string(), job = ?job, "cannot reschedule job");
+                                        }
+                                    } else if opts.max_retries > 0 {
+                                        if let Err(err) = bury_job_with_conn(
+                                            &mut conn,
+                                            &job.id,
+                                            &queue_name,
+                                            &err,
+                                        )
+                                        .await
+                                        {
+                                            error!(err = err.to_string(), job = ?job, "cannot move job to the dead-letter queue");
+                                        }
+                                    } else if let Err(err) =
                                         fail_job_with_conn(&mut conn, &job.id, &queue_name, &err)
                                             .await
                                     {
                                         error!(err = err.to_string(), job = ?job, "cannot fail job");
                                     }
                                 }
+                                JobOutcome::Cancelled => {
+                                    let processing_key =
+                                        format!("{PROCESSING_KEY_PREFIX}{queue_name}");
+                                    if let Err(err) =
+                                        conn.srem::<_, _, ()>(&processing_key, &job.id).await
+                                    {
+                                        error!(err = err.to_string(), job = ?job, "cannot release cancelled job");
+                                    }
+                                }
+                                JobOutcome::Interrupted => {
+                                    if let Err(err) =
+                                        requeue_job_with_conn(&mut conn, &job, &queue_name).await
+                                    {
+                                        error!(err = err.to_string(), job = ?job, "cannot put interrupted job back in the queue");
+                                    }
+                                }
+                            }
+                            if let Some(batch_id) = job.batch_id.as_ref().filter(|_| finished) {
+                                if let Err(err) =
+                                    finish_batch_job(&mut conn, batch_id, &job.id).await
+                                {
+                                    error!(err = err.to_string(), job = ?job, "cannot update batch progress");
+                                }
                             }
                         } else {
                             error!(job = job.name, "no handler found for job");
                         }
+                        if let Err(err) = conn.srem::<_, _, ()>(&worker_jobs_key, &job.id).await {
+                            error!(err = err.to_string(), job = ?job, "cannot release job of worker");
+                        }
                     } else {
                         tokio::select! {
                             biased;
@@ -232,6 +387,142 @@ impl Default for JobRegistry {
     }
 }
 
+/// Records the heartbeat of `worker` until its worker tasks are done, then
+/// removes its keys.
+async fn heartbeat(client: RedisPool, worker: WorkerInfo, alive: mpsc::Receiver<()>) {
+    let mut conn = match get_connection(&client).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            error!(
+                err = err.to_string(),
+                "Failed to create heartbeat connection"
+            );
+            return;
+        }
+    };
+    keep_alive(
+        alive,
+        || record_heartbeat(conn.clone(), &worker),
+        || requeue_orphaned_jobs_with_conn(conn.clone()),
+    )
+    .await;
+    let removed: redis::RedisResult<()> = redis::pipe()
+        .srem(WORKERS_KEY, &worker.id)
+        .del(format!("{WORKER_KEY_PREFIX}{}", worker.id))
+        .del(format!("{WORKER_JOBS_KEY_PREFIX}{}", worker.id))
+        .query_async(&mut conn)
+        .await;
+    if let Err(err) = removed {
+        error!(
+            err = err.to_string(),
+            worker_id = worker.id,
+            "Failed to remove worker"
+        );
+    }
+}
+
+/// Stores `worker` under a key that expires after [`HEARTBEAT_TTL`] unless
+/// the next heartbeat renews it.
+async fn record_heartbeat(mut conn: Connection, worker: &WorkerInfo) -> Result<()> {
+    trace!(worker_id = worker.id, "Recording worker heartbeat");
+    let worker = WorkerInfo {
+        heartbeat_at: Utc::now(),
+        ..worker.clone()
+    };
+    // the key is set first, so a worker in the index without its key has
+    // always expired
+    let _: () = redis::pipe()
+        .atomic()
+        .pset_ex(
+            format!("{WORKER_KEY_PREFIX}{}", worker.id),
+            serde_json::to_string(&worker)?,
+            u64::try_from(HEARTBEAT_TTL.as_millis()).unwrap_or(u64::MAX),
+        )
+        .sadd(WORKERS_KEY, &worker.id)
+        .query_async(&mut conn)
+        .await?;
+    Ok(())
+}
+
+/// Puts the jobs that workers whose heartbeat key expired were running back
+/// in their queue, and forgets about those workers. Returns how many jobs
+/// were requeued.
+///
+/// Running workers do this on every heartbeat, so jobs of a worker process
+/// that was killed are picked up again without `cargo loco jobs requeue`.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn requeue_orphaned_jobs(client: &RedisPool) -> Result<u64> {
+    requeue_orphaned_jobs_with_conn(get_connection(client).await?).await
+}
+
+async fn requeue_orphaned_jobs_with_conn(mut conn: Connection) -> Result<u64> {
+    let worker_ids: Vec<String> = conn.smembers(WORKERS_KEY).await?;
+    let mut requeued = 0;
+    for worker_id in worker_ids {
+        let alive: bool = conn
+            .exists(format!("{WORKER_KEY_PREFIX}{worker_id}"))
+            .await?;
+        if alive {
+            continue;
+        }
+        // only the process that removes the worker from the index requeues
+        // its jobs
+        let claimed: i32 = conn.srem(WORKERS_KEY, &worker_id).await?;
+        if claimed == 0 {
+            continue;
+        }
+
+        debug!(worker_id, "Requeueing jobs of expired worker");
+        let jobs_key = format!("{WORKER_JOBS_KEY_PREFIX}{worker_id}");
+        let job_ids: Vec<String> = conn.smembers(&jobs_key).await?;
+        for id in job_ids {
+            let job_json: Option<String> = conn.get(format!("{JOB_KEY_PREFIX}{id}")).await?;
+            let Some(job) = job_json.map(|json| Job::from_json(&json)).transpose()? else {
+                continue;
+            };
+            let queue_name = job.queue.clone().unwrap_or_else(|| "default".to_string());
+            let released: i32 = conn
+                .srem(format!("{PROCESSING_KEY_PREFIX}{queue_name}"), &id)
+                .await?;
+            if released == 1 {
+                push_job(&mut conn, &queue_name, &id, job.priority).await?;
+                requeued += 1;
+            }
+        }
+        let _: () = conn.del(&jobs_key).await?;
+    }
+    Ok(requeued)
+}
+
+/// Lists the worker processes whose heartbeat hasn't expired, with the jobs
+/// they are running.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn get_workers(client: &RedisPool) -> Result<Vec<WorkerInfo>> {
+    let mut conn = get_connection(client).await?;
+    let worker_ids: Vec<String> = conn.smembers(WORKERS_KEY).await?;
+    let mut workers = Vec::with_capacity(worker_ids.len());
+    for worker_id in worker_ids {
+        let worker_json: Option<String> =
+            conn.get(format!("{WORKER_KEY_PREFIX}{worker_id}")).await?;
+        let Some(worker_json) = worker_json else {
+            continue;
+        };
+        let mut worker: WorkerInfo = serde_json::from_str(&worker_json)?;
+        worker.jobs = conn
+            .smembers(format!("{WORKER_JOBS_KEY_PREFIX}{worker_id}"))
+            .await?;
+        workers.push(worker);
+    }
+    workers.sort_by_key(|worker| worker.started_at);
+    Ok(workers)
+}
+
 fn connect(url: &str) -> Result<RedisPool> {
     let client = Client::open(url.to_string())?;
     Ok(client)
@@ -255,6 +546,11 @@ pub async fn clear(client: &RedisPool) -> Result<()> {
 
 /// Add a task
 ///
+/// When `opts.unique_key` is set, a lock is taken on the job name and key for
+/// as long as the job is queued or processing (or `opts.unique_for`, if
+/// shorter), and enqueuing another job with the same key returns the id of
+/// the job holding the lock instead.
+///
 /// # Errors
 ///
 /// This function will return an error if it fails
@@ -264,10 +560,9 @@ pub async fn enqueue(
     queue: Option<String>,
     args: impl serde::Serialize + Send,
     tags: Option<Vec<String>>,
-) -> Result<()> {
+    opts: &EnqueueOpts,
+) -> Result<JobId> {
     let mut conn = get_connection(client).await?;
-    let queue_name = queue.unwrap_or_else(|| "default".to_string());
-    let queue_key = format!("{QUEUE_KEY_PREFIX}{queue_name}");
 
     // Convert args to JSON
     let args_json = serde_json::to_value(args)?;
@@ -275,21 +570,414 @@ pub async fn enqueue(
     // Create a job ID using ULID
     let job_id = Ulid::new().to_string();
 
-    // Create job
-    let mut job = Job::new(job_id.clone(), class, args_json);
+    let job = Job::new(job_id, class, args_json);
+    enqueue_job(&mut conn, job, queue, tags, opts).await
+}
+
+// Stores a job and pushes it to its list, or to its scheduled set when ARGV[5]
+// holds the time it is due. With a unique lock key as KEYS[4], the job is only
+// added when the lock is free, and the id of the job holding it is returned
+// otherwise. Taking the lock and adding the job at once means a lock never
+// outlives a failed enqueue.
+const ENQUEUE_SCRIPT: &str = r"
+local job_key = KEYS[1]
+local priorities_key = KEYS[2]
+local target_key = KEYS[3]
+local lock_key = KEYS[4]
+if lock_key then
+    local existing_id = redis.call('GET', lock_key)
+    if existing_id then
+        return existing_id
+    end
+    if tonumber(ARGV[6]) > 0 then
+        redis.call('SET', lock_key, ARGV[1], 'PX', ARGV[6])
+    else
+        redis.call('SET', lock_key, ARGV[1])
+    end
+end
+redis.call('SET', job_key, ARGV[2])
+redis.call('ZADD', priorities_key, ARGV[4], ARGV[3])
+if ARGV[5] == '' then
+    redis.call('RPUSH', target_key, ARGV[1])
+else
+    redis.call('ZADD', target_key, ARGV[5], ARGV[1])
+end
+return ARGV[1]
+";
+
+/// Stores a new job and pushes it to its queue, or to the scheduled set
+/// when it is due later.
+async fn enqueue_job(
+    conn: &mut Connection,
+    job: Job,
+    queue: Option<String>,
+    tags: Option<Vec<String>>,
+    opts: &EnqueueOpts,
+) -> Result<JobId> {
+    let script = Script::new(ENQUEUE_SCRIPT);
+    let (job_id, job_name) = (job.id.clone(), job.name.clone());
+    let id: String = enqueue_invocation(&script, job, queue, tags, opts)?
+        .invoke_async(conn)
+        .await?;
+
+    if id != job_id {
+        debug!(job_id = %id, job_name = %job_name, unique_key = ?opts.unique_key, "Job with the same unique key is already enqueued");
+    }
+    Ok(id)
+}
+
+/// Prepares [`ENQUEUE_SCRIPT`] for `job`, filled in from `queue`, `tags`
+/// and `opts`.
+fn enqueue_invocation<'a>(
+    script: &'a Script,
+    mut job: Job,
+    queue: Option<String>,
+    tags: Option<Vec<String>>,
+    opts: &EnqueueOpts,
+) -> Result<redis::ScriptInvocation<'a>> {
+    let queue_name = queue.unwrap_or_else(|| "default".to_string());
     job.tags = tags;
+    job.queue = Some(queue_name.clone());
+    job.priority = opts.priority.unwrap_or_default();
+    let run_at = opts.run_at.unwrap_or(job.run_at);
+    job.run_at = run_at;
+    #[allow(clippy::cast_possible_truncation)]
+    let interval_ms = opts.interval.map(|i| i.as_millis() as i64);
+    job.interval = interval_ms;
+    job.unique_key.clone_from(&opts.unique_key);
+
+    // Jobs due later wait in the scheduled set until `dequeue` promotes them
+    let queue_key = queue_key(&queue_name, job.priority);
+    let (target_key, due_at) = if run_at > Utc::now() {
+        (
+            scheduled_key(&queue_name, job.priority),
+            run_at.timestamp_millis().to_string(),
+        )
+    } else {
+        (queue_key.clone(), String::new())
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let window_ms = opts
+        .unique_for
+        .map_or(0, |unique_for| unique_for.as_millis() as u64);
+
+    let mut invocation = script.prepare_invoke();
+    invocation
+        .key(format!("{JOB_KEY_PREFIX}{}", job.id))
+        .key(format!("{PRIORITIES_KEY_PREFIX}{queue_name}"))
+        .key(target_key);
+    if let Some(unique_key) = &opts.unique_key {
+        invocation.key(unique_lock_key(&job.name, unique_key));
+    }
+    invocation
+        .arg(&job.id)
+        .arg(job.to_json()?)
+        .arg(queue_key)
+        .arg(job.priority)
+        .arg(due_at)
+        .arg(window_ms);
+    Ok(invocation)
+}
+
+fn batch_key(batch_id: &str) -> String {
+    format!("{BATCH_KEY_PREFIX}{batch_id}")
+}
+
+fn batch_pending_key(batch_id: &str) -> String {
+    format!("{BATCH_KEY_PREFIX}{batch_id}:pending")
+}
+
+/// Adds the jobs of `batch`.
+///
+/// The batch is kept in a hash holding its callbacks and counters, next to a
+/// set of the ids of its jobs that have not finished yet. The batch and all
+/// of its jobs are added in one transaction, so either every job is pushed
+/// and counted as pending, or none is.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn enqueue_batch(client: &RedisPool, batch: &Batch) -> Result<()> {
+    let mut conn = get_connection(client).await?;
+    let jobs = batch
+        .jobs
+        .iter()
+        .map(|member| {
+            let mut job = Job::new(
+                Ulid::new().to_string(),
+                member.name.clone(),
+                member.args.clone(),
+            );
+            job.batch_id = Some(batch.id.clone());
+            job
+        })
+        .collect::<Vec<_>>();
+
+    // EVALSHA can't fall back to EVAL within a transaction, so the script
+    // has to be known to the server beforehand
+    let script = Script::new(ENQUEUE_SCRIPT);
+    script.prepare_invoke().load_async(&mut conn).await?;
+
+    let batch_key = batch_key(&batch.id);
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    pipe.hset_multiple(
+        &batch_key,
+        &[("total", jobs.len()), ("completed", 0), ("failed", 0)],
+    )
+    .ignore();
+    if let Some(on_complete) = &batch.on_complete {
+        pipe.hset(
+            &batch_key,
+            "on_complete",
+            serde_json::to_string(on_complete)?,
+        )
+        .ignore();
+    }
+    if let Some(on_failure) = &batch.on_failure {
+        pipe.hset(&batch_key, "on_failure", serde_json::to_string(on_failure)?)
+            .ignore();
+    }
+    if !jobs.is_empty() {
+        pipe.sadd(
+            batch_pending_key(&batch.id),
+            jobs.iter().map(|job| &job.id).collect::<Vec<_>>(),
+        )
+        .ignore();
+    }
+    for (job, member) in jobs.into_iter().zip(&batch.jobs) {
+        let invocation = enqueue_invocation(
+            &script,
+            job,
+            member.queue.clone(),
+            member.tags.clone(),
+            &member.opts(),
+        )?;
+        pipe.invoke_script(&invocation).ignore();
+    }
+
+    debug!(batch_id = %batch.id, jobs = batch.jobs.len(), "Enqueueing batch");
+    let _: () = pipe.query_async(&mut conn).await?;
+
+    if batch.jobs.is_empty() {
+        finish_batch_job(&mut conn, &batch.id, "").await?;
+    }
+    Ok(())
+}
+
+// Takes a job of the batch out of the pending set once its stored status is
+// final, counting it as completed or failed. The caller that empties the set
+// and sets `finished_at` gets back the callback to enqueue; everyone else gets
+// nil, so calling it twice for the same job is harmless.
+const FINISH_BATCH_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return false
+end
+local job = redis.call('GET', KEYS[3])
+if job then
+    local status = cjson.decode(job)['status']
+    if status == 'queued' or status == 'processing' then
+        return false
+    end
+    if redis.call('SREM', KEYS[2], ARGV[1]) == 1 then
+        local counter = 'failed'
+        if status == 'completed' then
+            counter = 'completed'
+        end
+        redis.call('HINCRBY', KEYS[1], counter, 1)
+    end
+end
+if redis.call('SCARD', KEYS[2]) > 0 or redis.call('HSETNX', KEYS[1], 'finished_at', ARGV[2]) == 0 then
+    return false
+end
+if tonumber(redis.call('HGET', KEYS[1], 'failed')) == 0 then
+    return redis.call('HGET', KEYS[1], 'on_complete')
+end
+return redis.call('HGET', KEYS[1], 'on_failure')
+";
+
+/// Called after a job of the batch `batch_id` changed status. Enqueues the
+/// matching callback once none of the jobs of the batch is left to run.
+async fn finish_batch_job(conn: &mut Connection, batch_id: &str, id: &str) -> Result<()> {
+    let callback: Option<String> = Script::new(FINISH_BATCH_SCRIPT)
+        .key(batch_key(batch_id))
+        .key(batch_pending_key(batch_id))
+        .key(format!("{JOB_KEY_PREFIX}{id}"))
+        .arg(id)
+        .arg(Utc::now().to_rfc3339())
+        .invoke_async(conn)
+        .await?;
+
+    if let Some(callback) = callback {
+        let callback: BatchJob = serde_json::from_str(&callback)?;
+        debug!(batch_id, callback = callback.name, "Batch finished");
+        let opts = callback.opts();
+        let job = Job::new(Ulid::new().to_string(), callback.name, callback.args);
+        enqueue_job(conn, job, callback.queue, callback.tags, &opts).await?;
+    }
+    Ok(())
+}
+
+/// Fetches the progress of a batch.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn get_batch(client: &RedisPool, id: &str) -> Result<Option<BatchInfo>> {
+    let mut conn = get_connection(client).await?;
+    let (fields, pending): (HashMap<String, String>, u64) = redis::pipe()
+        .hgetall(batch_key(id))
+        .scard(batch_pending_key(id))
+        .query_async(&mut conn)
+        .await?;
+    if fields.is_empty() {
+        return Ok(None);
+    }
+
+    let count = |field: &str| {
+        fields
+            .get(field)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    };
+    Ok(Some(BatchInfo {
+        id: id.to_string(),
+        total: count("total"),
+        pending,
+        completed: count("completed"),
+        failed: count("failed"),
+        finished_at: fields
+            .get("finished_at")
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|value| value.with_timezone(&Utc)),
+    }))
+}
+
+fn unique_lock_key(name: &str, unique_key: &str) -> String {
+    format!("{UNIQUE_KEY_PREFIX}{name}:{unique_key}")
+}
+
+/// Takes the lock of the unique key of `job` again, for a job that released
+/// it and is put back in the queue. The lock is held until the job finishes.
+/// Returns `false` when another job holds it.
+async fn acquire_unique_key(conn: &mut Connection, job: &Job) -> Result<bool> {
+    let Some(unique_key) = &job.unique_key else {
+        return Ok(true);
+    };
+    let lock_key = unique_lock_key(&job.name, unique_key);
+    let set_opts = redis::SetOptions::default().conditional_set(redis::ExistenceCheck::NX);
+    loop {
+        let locked: Option<String> = conn.set_options(&lock_key, &job.id, set_opts).await?;
+        if locked.is_some() {
+            return Ok(true);
+        }
+        // the lock may expire between both calls, in which case try again
+        let holder: Option<String> = conn.get(&lock_key).await?;
+        if let Some(holder) = holder {
+            return Ok(holder == job.id);
+        }
+    }
+}
+
+// Deletes a uniqueness lock only while it still belongs to the given job, so
+// a lock that expired and was taken by a newer job is left alone.
+const RELEASE_UNIQUE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/// Releases the uniqueness lock of a job that left the queued and processing
+/// states, allowing the same key to be enqueued again.
+async fn release_unique_key(conn: &mut Connection, job: &Job) -> Result<()> {
+    if let Some(unique_key) = &job.unique_key {
+        let _: i32 = Script::new(RELEASE_UNIQUE_SCRIPT)
+            .key(unique_lock_key(&job.name, unique_key))
+            .arg(&job.id)
+            .invoke_async(conn)
+            .await?;
+    }
+    Ok(())
+}
 
-    // Serialize job for Redis storage
-    let job_json = job.to_json()?;
+/// Jobs of a queue are kept in one list per priority. Priority `0` uses the
+/// plain queue key, so lists created by older versions are still picked up.
+fn queue_key(queue_name: &str, priority: i16) -> String {
+    if priority == 0 {
+        format!("{QUEUE_KEY_PREFIX}{queue_name}")
+    } else {
+        format!("{QUEUE_KEY_PREFIX}{queue_name}:{priority}")
+    }
+}
 
-    // Store job in Redis queue and in job key
-    let job_key = format!("{JOB_KEY_PREFIX}{}", job.id);
-    let _: () = conn.set(&job_key, &job_json).await?;
-    let _: () = conn.rpush(&queue_key, &job.id).await?;
+/// Jobs due later wait in one sorted set per priority, scored by their
+/// `run_at`, next to the list they are promoted to.
+fn scheduled_key(queue_name: &str, priority: i16) -> String {
+    if priority == 0 {
+        format!("{SCHEDULED_KEY_PREFIX}{queue_name}")
+    } else {
+        format!("{SCHEDULED_KEY_PREFIX}{queue_name}:{priority}")
+    }
+}
 
+/// Appends a job to the list matching its priority and records that list in
+/// the queue's priority index, which `dequeue` walks from the highest
+/// priority down.
+async fn push_job(conn: &mut Connection, queue_name: &str, id: &str, priority: i16) -> Result<()> {
+    let queue_key = queue_key(queue_name, priority);
+    let _: () = redis::pipe()
+        .zadd(
+            format!("{PRIORITIES_KEY_PREFIX}{queue_name}"),
+            &queue_key,
+            priority,
+        )
+        .rpush(&queue_key, id)
+        .query_async(conn)
+        .await?;
     Ok(())
 }
 
+/// Adds a job due at `run_at` to the scheduled set matching its priority,
+/// recording the priority in the queue's priority index so the set gets
+/// promoted.
+fn schedule_job(
+    pipe: &mut redis::Pipeline,
+    queue_name: &str,
+    id: &str,
+    priority: i16,
+    run_at: DateTime<Utc>,
+) {
+    pipe.zadd(
+        format!("{PRIORITIES_KEY_PREFIX}{queue_name}"),
+        queue_key(queue_name, priority),
+        priority,
+    )
+    .zadd(
+        scheduled_key(queue_name, priority),
+        id,
+        run_at.timestamp_millis(),
+    );
+}
+
+/// Priorities of the lists of a queue, highest first. The default priority
+/// is always included, for lists created before the index existed.
+async fn queue_priorities(conn: &mut Connection, queue_name: &str) -> Result<Vec<i16>> {
+    let lists: Vec<(String, f64)> = conn
+        .zrevrange_withscores(format!("{PRIORITIES_KEY_PREFIX}{queue_name}"), 0, -1)
+        .await?;
+    #[allow(clippy::cast_possible_truncation)]
+    let mut priorities: Vec<i16> = lists
+        .into_iter()
+        .map(|(_, priority)| priority as i16)
+        .collect();
+    if !priorities.contains(&0) {
+        priorities.push(0);
+        priorities.sort_unstable_by(|a, b| b.cmp(a));
+    }
+    Ok(priorities)
+}
+
 const DEQUEUE_SCRIPT: &str = r#"
 local queue_key = KEYS[1]
 local processing_key = KEYS[2]
@@ -307,10 +995,138 @@ else
 end
 "#;
 
+// Moves jobs whose `run_at` has passed from a scheduled sorted set to the tail
+// of the list of the same priority.
+const PROMOTE_SCHEDULED_SCRIPT: &str = r"
+local scheduled_key = KEYS[1]
+local queue_key = KEYS[2]
+local job_ids = redis.call('ZRANGEBYSCORE', scheduled_key, '-inf', ARGV[1])
+for _, job_id in ipairs(job_ids) do
+    redis.call('ZREM', scheduled_key, job_id)
+    redis.call('RPUSH', queue_key, job_id)
+end
+return #job_ids
+";
+
+async fn promote_scheduled_jobs(
+    conn: &mut Connection,
+    queue_name: &str,
+    priorities: &[i16],
+) -> Result<()> {
+    let script = Script::new(PROMOTE_SCHEDULED_SCRIPT);
+    let now = Utc::now().timestamp_millis();
+    let mut promoted = 0;
+    for priority in priorities {
+        let count: usize = script
+            .key(scheduled_key(queue_name, *priority))
+            .key(queue_key(queue_name, *priority))
+            .arg(now)
+            .invoke_async(conn)
+            .await?;
+        promoted += count;
+    }
+    if promoted > 0 {
+        trace!(
+            queue = queue_name,
+            count = promoted,
+            "Promoted scheduled jobs"
+        );
+    }
+    Ok(())
+}
+
+// Checks whether a job may start under the limits of its worker and records
+// it when it may, returning 0. Otherwise returns how many milliseconds to wait
+// before trying again. Ids in the running set that left the processing set
+// finished since, so they are dropped before counting.
+const ACQUIRE_LIMITS_SCRIPT: &str = r"
+local limit = tonumber(ARGV[2])
+if limit > 0 then
+    for _, id in ipairs(redis.call('SMEMBERS', KEYS[1])) do
+        if redis.call('SISMEMBER', KEYS[2], id) == 0 then
+            redis.call('SREM', KEYS[1], id)
+        end
+    end
+    if redis.call('SCARD', KEYS[1]) >= limit then
+        return tonumber(ARGV[5])
+    end
+end
+local max = tonumber(ARGV[3])
+if max > 0 then
+    local count = redis.call('INCR', KEYS[3])
+    if count == 1 then
+        redis.call('PEXPIRE', KEYS[3], ARGV[4])
+    end
+    if count > max then
+        return math.max(redis.call('PTTL', KEYS[3]), 1)
+    end
+end
+if limit > 0 then
+    redis.call('SADD', KEYS[1], ARGV[1])
+end
+return 0
+";
+
+/// Checks the [`WorkerOpts::concurrency_limit`] and
+/// [`WorkerOpts::rate_limit`] of the worker of `job`, which was just moved to
+/// the processing set of `queue_name`.
+///
+/// Returns `None` when the job may start, or how long it should wait
+/// otherwise: until the current rate limit window closes, or
+/// [`CONCURRENCY_RETRY_DELAY`] when all slots are taken.
+async fn acquire_limits(
+    conn: &mut Connection,
+    job: &Job,
+    queue_name: &str,
+    opts: &WorkerOpts,
+) -> Result<Option<chrono::Duration>> {
+    let wait_ms: i64 = Script::new(ACQUIRE_LIMITS_SCRIPT)
+        .key(format!("{LIMIT_KEY_PREFIX}{}:running", job.name))
+        .key(format!("{PROCESSING_KEY_PREFIX}{queue_name}"))
+        .key(format!("{LIMIT_KEY_PREFIX}{}:rate", job.name))
+        .arg(&job.id)
+        .arg(opts.concurrency_limit.unwrap_or_default())
+        .arg(opts.rate_limit.map_or(0, |rate_limit| rate_limit.max))
+        .arg(opts.rate_limit.map_or(0, |rate_limit| {
+            u64::try_from(rate_limit.per.as_millis())
+                .unwrap_or(u64::MAX)
+                .max(1)
+        }))
+        .arg(u64::try_from(CONCURRENCY_RETRY_DELAY.as_millis()).unwrap_or(u64::MAX))
+        .invoke_async(conn)
+        .await?;
+    Ok((wait_ms > 0).then(|| chrono::Duration::milliseconds(wait_ms)))
+}
+
+/// Moves a job held back by the limits of its worker from the processing set
+/// to the scheduled set, where it waits for `wait` without blocking the jobs
+/// queued behind it.
+async fn park_job(
+    conn: &mut Connection,
+    mut job: Job,
+    queue_name: &str,
+    wait: chrono::Duration,
+) -> Result<()> {
+    job.run_at = Utc::now() + wait;
+    trace!(
+        job_id = job.id,
+        job_name = job.name,
+        run_at = %job.run_at,
+        "Worker limit reached, job parked until its limits allow it"
+    );
+    let mut pipe = redis::pipe();
+    pipe.set(format!("{JOB_KEY_PREFIX}{}", job.id), job.to_json()?)
+        .srem(format!("{PROCESSING_KEY_PREFIX}{queue_name}"), &job.id);
+    schedule_job(&mut pipe, queue_name, &job.id, job.priority, job.run_at);
+    let _: () = pipe.query_async(conn).await?;
+    Ok(())
+}
+
 async fn dequeue_with_conn(
     conn: &mut Connection,
     queues: &[String],
     tags: &[String],
+    worker_opts: &HashMap<String, WorkerOpts>,
 ) -> Result<Option<(Job, String)>> {
     if queues.is_empty() {
         return Ok(None);
@@ -320,16 +1136,30 @@ async fn dequeue_with_conn(
 
     // Try to get a job from each queue in order (round-robin is more complex)
     for queue_name in queues {
-        let queue_key = format!("{QUEUE_KEY_PREFIX}{queue_name}");
         let processing_key = format!("{PROCESSING_KEY_PREFIX}{queue_name}");
 
-        let job_id: Option<String> = script
-            .key(&queue_key)
-            .key(&processing_key)
-            .invoke_async(conn)
-            .await?;
+        let priorities = queue_priorities(conn, queue_name).await?;
+        promote_scheduled_jobs(conn, queue_name, &priorities).await?;
+
+        // Jobs held back by the limits of their worker are parked, so the
+        // next job of the queue gets a chance right away
+        loop {
+            // Take the first job of the highest priority list that has one
+            let mut job_id: Option<String> = None;
+            for priority in &priorities {
+                job_id = script
+                    .key(queue_key(queue_name, *priority))
+                    .key(&processing_key)
+                    .invoke_async(conn)
+                    .await?;
+                if job_id.is_some() {
+                    break;
+                }
+            }
+            let Some(job_id) = job_id else {
+                break;
+            };
 
-        if let Some(job_id) = job_id {
             let job_key = format!("{JOB_KEY_PREFIX}{job_id}");
             let job_json: Option<String> = conn.get(&job_key).await?;
 
@@ -344,17 +1174,26 @@ async fn dequeue_with_conn(
                             })
                         };
 
-                        if should_process {
+                        if !should_process {
+                            let _: () = conn.srem(&processing_key, &job_id).await?;
+                            push_job(conn, queue_name, &job_id, job.priority).await?;
+                            trace!(
+                                job_id = job_id,
+                                job_tags = ?job.tags,
+                                worker_tags = ?tags,
+                                "Job doesn't match tag criteria, returned to queue"
+                            );
+                            break;
+                        } else if let Some(opts) =
+                            worker_opts.get(&job.name).filter(|opts| opts.is_limited())
+                        {
+                            match acquire_limits(conn, &job, queue_name, opts).await? {
+                                None => return Ok(Some((job, queue_name.clone()))),
+                                Some(wait) => park_job(conn, job, queue_name, wait).await?,
+                            }
+                        } else {
                             return Ok(Some((job, queue_name.clone())));
                         }
-                        let _: () = conn.srem(&processing_key, &job_id).await?;
-                        let _: () = conn.rpush(&queue_key, &job_id).await?;
-                        trace!(
-                            job_id = job_id,
-                            job_tags = ?job.tags,
-                            worker_tags = ?tags,
-                            "Job doesn't match tag criteria, returned to queue"
-                        );
                     }
                     Err(err) => {
                         error!(
@@ -379,6 +1218,7 @@ async fn complete_job_with_conn(
     id: &JobId,
     queue_name: &str,
     interval_ms: Option<i64>,
+    result: Option<JsonValue>,
 ) -> Result<()> {
     let job_key = format!("{JOB_KEY_PREFIX}{id}");
     let processing_key = format!("{PROCESSING_KEY_PREFIX}{queue_name}");
@@ -386,21 +1226,22 @@ async fn complete_job_with_conn(
     let job_json: Option<String> = conn.get(&job_key).await?;
     if let Some(json) = job_json {
         if let Ok(mut job) = Job::from_json(&json) {
+            job.result = result;
             if let Some(interval) = interval_ms {
                 job.run_at = Utc::now() + chrono::Duration::milliseconds(interval);
                 job.status = JobStatus::Queued;
+                job.updated_at = Some(Utc::now());
                 let new_json = job.to_json()?;
-                let queue_key = format!("{QUEUE_KEY_PREFIX}{queue_name}");
-                let _: () = redis::pipe()
-                    .set(&job_key, &new_json)
-                    .rpush(&queue_key, id)
-                    .query_async(conn)
-                    .await?;
+                let mut pipe = redis::pipe();
+                pipe.set(&job_key, &new_json);
+                schedule_job(&mut pipe, queue_name, id, job.priority, job.run_at);
+                let _: () = pipe.query_async(conn).await?;
             } else {
                 job.status = JobStatus::Completed;
                 job.updated_at = Some(Utc::now());
                 let updated_json = job.to_json()?;
                 let _: () = conn.set(&job_key, &updated_json).await?;
+                release_unique_key(conn, &job).await?;
             }
             let _: () = conn.srem(&processing_key, id).await?;
         }
@@ -408,6 +1249,14 @@ async fn complete_job_with_conn(
     Ok(())
 }
 
+/// Whether the job was cancelled since a worker picked it up.
+async fn is_job_cancelled(mut conn: Connection, id: &JobId) -> Result<bool> {
+    let job_json: Option<String> = conn.get(format!("{JOB_KEY_PREFIX}{id}")).await?;
+    Ok(job_json
+        .and_then(|json| Job::from_json(&json).ok())
+        .is_some_and(|job| job.status == JobStatus::Cancelled))
+}
+
 async fn fail_job_with_conn(
     conn: &mut Connection,
     id: &JobId,
@@ -420,36 +1269,327 @@ async fn fail_job_with_conn(
     let job_json: Option<String> = conn.get(&job_key).await?;
     if let Some(json) = job_json {
         if let Ok(mut job) = Job::from_json(&json) {
-            let error_json = serde_json::json!({ "error": error.to_string() });
-            job.data = error_json;
+            let (msg, backtrace) = error_details(error);
             job.status = JobStatus::Failed;
+            job.attempts += 1;
+            job.last_error = Some(msg);
+            job.backtrace = backtrace;
             job.updated_at = Some(Utc::now());
             let updated_json = job.to_json()?;
             let _: () = conn.set(&job_key, &updated_json).await?;
+            release_unique_key(conn, &job).await?;
         }
     }
     let _: () = conn.srem(&processing_key, id).await?;
     Ok(())
 }
 
-/// Ping system
-///
-/// # Errors
-///
-/// This function will return an error if it fails
-pub async fn ping(client: &RedisPool) -> Result<()> {
-    let mut conn = get_connection(client).await?;
-    let _: String = redis::cmd("PING").query_async(&mut conn).await?;
+async fn retry_job_with_conn(
+    conn: &mut Connection,
+    id: &JobId,
+    queue_name: &str,
+    error: &crate::Error,
+    run_at: DateTime<Utc>,
+) -> Result<()> {
+    let job_key = format!("{JOB_KEY_PREFIX}{id}");
+    let processing_key = format!("{PROCESSING_KEY_PREFIX}{queue_name}");
+
+    let job_json: Option<String> = conn.get(&job_key).await?;
+    if let Some(json) = job_json {
+        if let Ok(mut job) = Job::from_json(&json) {
+            let (msg, backtrace) = error_details(error);
+            job.status = JobStatus::Queued;
+            job.run_at = run_at;
+            job.attempts += 1;
+            job.last_error = Some(msg);
+            job.backtrace = backtrace;
+            job.updated_at = Some(Utc::now());
+            let updated_json = job.to_json()?;
+            let mut pipe = redis::pipe();
+            pipe.set(&job_key, &updated_json);
+            schedule_job(&mut pipe, queue_name, id, job.priority, run_at);
+            let _: () = pipe.query_async(conn).await?;
+        }
+    }
+    let _: () = conn.srem(&processing_key, id).await?;
     Ok(())
 }
 
-/// Retrieves a list of jobs from the Redis queues.
-///
-/// This function queries Redis for jobs, optionally filtering by their
-/// `status` and age. It will search through all processing sets and queue keys
-/// to find jobs matching the criteria.
-///
-/// # Errors
+/// Puts a job that was still running when its worker shut down back in its
+/// queue. It doesn't count as an attempt, since the job didn't fail.
+async fn requeue_job_with_conn(conn: &mut Connection, job: &Job, queue_name: &str) -> Result<()> {
+    debug!(job_id = %job.id, "Requeueing interrupted job");
+    let processing_key = format!("{PROCESSING_KEY_PREFIX}{queue_name}");
+    let _: () = conn.srem(&processing_key, &job.id).await?;
+    push_job(conn, queue_name, &job.id, job.priority).await
+}
+
+/// Moves a job that ran out of retries to [`JobStatus::Dead`], leaving its
+/// arguments untouched so it can be replayed later.
+async fn bury_job_with_conn(
+    conn: &mut Connection,
+    id: &JobId,
+    queue_name: &str,
+    error: &crate::Error,
+) -> Result<()> {
+    let job_key = format!("{JOB_KEY_PREFIX}{id}");
+    let processing_key = format!("{PROCESSING_KEY_PREFIX}{queue_name}");
+    let dead_key = format!("{DEAD_KEY_PREFIX}{queue_name}");
+
+    let job_json: Option<String> = conn.get(&job_key).await?;
+    if let Some(json) = job_json {
+        if let Ok(mut job) = Job::from_json(&json) {
+            let (msg, backtrace) = error_details(error);
+            job.status = JobStatus::Dead;
+            job.attempts += 1;
+            job.last_error = Some(msg);
+            job.backtrace = backtrace;
+            job.updated_at = Some(Utc::now());
+            let updated_json = job.to_json()?;
+            let _: () = redis::pipe()
+                .set(&job_key, &updated_json)
+                .sadd(&dead_key, id)
+                .query_async(conn)
+                .await?;
+            release_unique_key(conn, &job).await?;
+        }
+    }
+    let _: () = conn.srem(&processing_key, id).await?;
+    Ok(())
+}
+
+/// Moves [`JobStatus::Failed`] and [`JobStatus::Dead`] jobs matching `target`
+/// back to the queue they were enqueued to.
+///
+/// Jobs run right away with their attempt counter reset. Jobs with a unique
+/// key take its lock again, and are left as they are while another job holds
+/// it.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn retry_jobs(client: &RedisPool, target: &RetryTarget) -> Result<()> {
+    let mut conn = get_connection(client).await?;
+
+    let job_pattern = format!("{JOB_KEY_PREFIX}*");
+    let job_keys: Vec<String> = redis::cmd("KEYS")
+        .arg(&job_pattern)
+        .query_async(&mut conn)
+        .await?;
+
+    let mut retried = 0;
+    for job_key in job_keys {
+        let job_json: Option<String> = conn.get(&job_key).await?;
+        let Some(mut job) = job_json.and_then(|json| Job::from_json(&json).ok()) else {
+            continue;
+        };
+        if !matches!(job.status, JobStatus::Failed | JobStatus::Dead) {
+            continue;
+        }
+        let matched = match target {
+            RetryTarget::Id(id) => &job.id == id,
+            RetryTarget::Name(name) => &job.name == name,
+            RetryTarget::AllFailed => true,
+        };
+        if !matched {
+            continue;
+        }
+
+        if !acquire_unique_key(&mut conn, &job).await? {
+            debug!(job_id = %job.id, job_name = %job.name, "Job with the same unique key is already enqueued, not retrying");
+            continue;
+        }
+        let queue_name = job.queue.clone().unwrap_or_else(|| "default".to_string());
+        job.status = JobStatus::Queued;
+        job.run_at = Utc::now();
+        job.attempts = 0;
+        job.updated_at = Some(Utc::now());
+        let updated_json = job.to_json()?;
+        let _: () = redis::pipe()
+            .set(&job_key, &updated_json)
+            .srem(format!("{DEAD_KEY_PREFIX}{queue_name}"), &job.id)
+            .query_async(&mut conn)
+            .await?;
+        push_job(&mut conn, &queue_name, &job.id, job.priority).await?;
+        retried += 1;
+    }
+
+    debug!(target = ?target, count = retried, "retried failed jobs");
+    Ok(())
+}
+
+/// Fetches a single job by id. Jobs that a worker picked up are reported
+/// as [`JobStatus::Processing`].
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn get_job(client: &RedisPool, id: &str) -> Result<Option<Job>> {
+    let mut conn = get_connection(client).await?;
+    let job_json: Option<String> = conn.get(format!("{JOB_KEY_PREFIX}{id}")).await?;
+    let Some(mut job) = job_json.map(|json| Job::from_json(&json)).transpose()? else {
+        return Ok(None);
+    };
+
+    if job.status == JobStatus::Queued {
+        let queue_name = job.queue.as_deref().unwrap_or("default");
+        let processing: bool = conn
+            .sismember(format!("{PROCESSING_KEY_PREFIX}{queue_name}"), id)
+            .await?;
+        if processing {
+            job.status = JobStatus::Processing;
+        }
+    }
+    Ok(Some(job))
+}
+
+/// Number of job keys fetched at once when going through every job.
+#[cfg(feature = "bg_dashboard")]
+const SCAN_BATCH_SIZE: usize = 500;
+
+/// Calls `f` with every stored job, found with `SCAN` and fetched a batch at
+/// a time. Jobs a worker is running get the [`JobStatus::Processing`] status.
+#[cfg(feature = "bg_dashboard")]
+async fn for_each_job(client: &RedisPool, mut f: impl FnMut(Job) + Send) -> Result<()> {
+    let mut conn = get_connection(client).await?;
+    let mut processing_keys = Vec::new();
+    {
+        let mut iter: redis::AsyncIter<'_, String> =
+            conn.scan_match(format!("{PROCESSING_KEY_PREFIX}*")).await?;
+        while let Some(key) = iter.next_item().await {
+            processing_keys.push(key);
+        }
+    }
+    let mut processing = std::collections::HashSet::new();
+    for key in processing_keys {
+        let ids: Vec<String> = conn.smembers(&key).await?;
+        processing.extend(ids);
+    }
+
+    let mut lookup = conn.clone();
+    let mut iter: redis::AsyncIter<'_, String> =
+        conn.scan_match(format!("{JOB_KEY_PREFIX}*")).await?;
+    let mut keys = Vec::with_capacity(SCAN_BATCH_SIZE);
+    let mut done = false;
+    while !done {
+        match iter.next_item().await {
+            Some(key) => keys.push(key),
+            None => done = true,
+        }
+        if keys.is_empty() || (!done && keys.len() < SCAN_BATCH_SIZE) {
+            continue;
+        }
+        let values: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(&mut lookup)
+            .await?;
+        keys.clear();
+        for json in values.into_iter().flatten() {
+            match Job::from_json(&json) {
+                Ok(mut job) => {
+                    if job.status == JobStatus::Queued && processing.contains(&job.id) {
+                        job.status = JobStatus::Processing;
+                    }
+                    f(job);
+                }
+                Err(err) => error!(err = err.to_string(), "Failed to parse job JSON"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Counts the jobs by queue, worker and status, for the dashboard. Goes
+/// through the jobs with `SCAN` rather than loading them all at once.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+#[cfg(feature = "bg_dashboard")]
+pub(crate) async fn count_jobs(client: &RedisPool) -> Result<Vec<JobCount>> {
+    let mut tally = JobTally::new(Utc::now());
+    for_each_job(client, |job| {
+        tally.add(
+            Some(job.queue.as_deref().unwrap_or("default")),
+            &job.name,
+            &job.status,
+            job.updated_at,
+        );
+    })
+    .await?;
+    Ok(tally.into_counts())
+}
+
+/// Lists the `limit` most recently updated jobs, optionally only those with
+/// the given `status` and `name`, for the dashboard.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+#[cfg(feature = "bg_dashboard")]
+pub(crate) async fn recent_jobs(
+    client: &RedisPool,
+    status: Option<&JobStatus>,
+    name: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Job>> {
+    let mut recent = RecentJobs::new(limit);
+    for_each_job(client, |job| {
+        if status.map_or(true, |status| &job.status == status)
+            && name.map_or(true, |name| job.name == name)
+        {
+            recent.add(job.updated_at, job);
+        }
+    })
+    .await?;
+    Ok(recent.into_jobs())
+}
+
+const TRY_LOCK_SCRIPT: &str = r"
+local owner = redis.call('GET', KEYS[1])
+if owner and owner ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+return 1
+";
+
+/// Takes the lock `name` for `owner` until `ttl` from now, or extends it
+/// when `owner` already holds it. Returns `false` while another owner holds
+/// a lock that hasn't expired.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn try_lock(client: &RedisPool, name: &str, owner: &str, ttl: Duration) -> Result<bool> {
+    let mut conn = get_connection(client).await?;
+    let locked: i32 = Script::new(TRY_LOCK_SCRIPT)
+        .key(format!("{LOCK_KEY_PREFIX}{name}"))
+        .arg(owner)
+        .arg(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1))
+        .invoke_async(&mut conn)
+        .await?;
+    Ok(locked == 1)
+}
+
+/// Ping system
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn ping(client: &RedisPool) -> Result<()> {
+    let mut conn = get_connection(client).await?;
+    let _: String = redis::cmd("PING").query_async(&mut conn).await?;
+    Ok(())
+}
+
+/// Retrieves a list of jobs from the Redis queues.
+///
+/// This function queries Redis for jobs, optionally filtering by their
+/// `status` and age. It will search through all processing sets and queue keys
+/// to find jobs matching the criteria.
+///
+/// # Errors
 ///
 /// This function will return an error if it fails
 pub async fn get_jobs(
@@ -490,6 +1630,48 @@ pub async fn get_jobs(
         }
     }
 
+    // Collect jobs waiting for their scheduled time
+    let scheduled_pattern = format!("{SCHEDULED_KEY_PREFIX}*");
+    let scheduled_keys: Vec<String> = redis::cmd("KEYS")
+        .arg(&scheduled_pattern)
+        .query_async(&mut conn)
+        .await?;
+    for scheduled_key in scheduled_keys {
+        let job_ids: Vec<String> = conn.zrange(&scheduled_key, 0, -1).await?;
+        for job_id in job_ids {
+            let job_key = format!("{JOB_KEY_PREFIX}{job_id}");
+            let job_json: Option<String> = conn.get(&job_key).await?;
+            if let Some(json) = job_json {
+                if let Ok(job) = Job::from_json(&json) {
+                    if should_include_job(&job, status, age_days) {
+                        jobs.push(job);
+                    }
+                }
+            }
+        }
+    }
+
+    // Collect jobs that ran out of retries
+    let dead_pattern = format!("{DEAD_KEY_PREFIX}*");
+    let dead_keys: Vec<String> = redis::cmd("KEYS")
+        .arg(&dead_pattern)
+        .query_async(&mut conn)
+        .await?;
+    for dead_key in dead_keys {
+        let job_ids: Vec<String> = conn.smembers(&dead_key).await?;
+        for job_id in job_ids {
+            let job_key = format!("{JOB_KEY_PREFIX}{job_id}");
+            let job_json: Option<String> = conn.get(&job_key).await?;
+            if let Some(json) = job_json {
+                if let Ok(job) = Job::from_json(&json) {
+                    if should_include_job(&job, status, age_days) {
+                        jobs.push(job);
+                    }
+                }
+            }
+        }
+    }
+
     // Collect jobs from processing sets
     for processing_key in processing_keys {
         let job_ids: Vec<String> = conn.smembers(&processing_key).await?;
@@ -745,7 +1927,6 @@ pub async fn requeue(client: &RedisPool, age_minutes: &i64) -> Result<()> {
         let queue_name = processing_key
             .trim_start_matches(PROCESSING_KEY_PREFIX)
             .to_string();
-        let queue_key = format!("{QUEUE_KEY_PREFIX}{queue_name}");
 
         // Get all jobs in the processing set
         let job_ids: Vec<String> = conn.smembers(&processing_key).await?;
@@ -769,7 +1950,7 @@ pub async fn requeue(client: &RedisPool, age_minutes: &i64) -> Result<()> {
                         let updated_json = job.to_json()?;
                         let _: () = conn.srem(&processing_key, &job_id).await?;
                         let _: () = conn.set(&job_key, &updated_json).await?;
-                        let _: () = conn.rpush(&queue_key, &job_id).await?;
+                        push_job(&mut conn, &queue_name, &job_id, job.priority).await?;
                         *requeued_counts.entry(queue_name.clone()).or_insert(0) += 1;
                     }
                 }
@@ -785,7 +1966,6 @@ pub async fn requeue(client: &RedisPool, age_minutes: &i64) -> Result<()> {
 
     for failed_key in failed_keys {
         let queue_name = failed_key.trim_start_matches("failed:").to_string();
-        let queue_key = format!("{QUEUE_KEY_PREFIX}{queue_name}");
         let job_ids: Vec<String> = conn.smembers(&failed_key).await?;
 
         for job_id in job_ids {
@@ -804,7 +1984,7 @@ pub async fn requeue(client: &RedisPool, age_minutes: &i64) -> Result<()> {
                         let updated_json = job.to_json()?;
                         let _: () = conn.srem(&failed_key, &job_id).await?;
                         let _: () = conn.set(&job_key, &updated_json).await?;
-                        let _: () = conn.rpush(&queue_key, &job_id).await?;
+                        push_job(&mut conn, &queue_name, &job_id, job.priority).await?;
                         *requeued_counts.entry(queue_name.clone()).or_insert(0) += 1;
                     }
                 }
@@ -826,6 +2006,11 @@ pub async fn requeue(client: &RedisPool, age_minutes: &i64) -> Result<()> {
 /// from [`JobStatus::Queued`] to [`JobStatus::Cancelled`]. Jobs are searched for in all queue keys,
 /// and only those that are currently in the [`JobStatus::Queued`] state will be affected.
 ///
+/// Jobs a worker already picked up are marked as cancelled as well. The worker notices on its next
+/// poll, cancels the token the job got in [`super::BackgroundWorker::perform_with_token`] and drops
+/// it from the processing set.
+/// Batches whose last remaining jobs were cancelled are finished, enqueuing their `on_failure` job.
+///
 /// # Errors
 ///
 /// This function will return an error if it fails
@@ -859,6 +2044,77 @@ pub async fn cancel_jobs_by_name(client: &RedisPool, job_name: &str) -> Result<(
                             queue_key.trim_start_matches(QUEUE_KEY_PREFIX)
                         );
                         let _: () = conn.sadd(&cancelled_key, &job_id).await?;
+                        release_unique_key(&mut conn, &job).await?;
+                        if let Some(batch_id) = &job.batch_id {
+                            finish_batch_job(&mut conn, batch_id, &job.id).await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Jobs waiting for their scheduled time are queued as well
+    let scheduled_pattern = format!("{SCHEDULED_KEY_PREFIX}*");
+    let scheduled_keys: Vec<String> = redis::cmd("KEYS")
+        .arg(&scheduled_pattern)
+        .query_async(&mut conn)
+        .await?;
+    for scheduled_key in scheduled_keys {
+        let job_ids: Vec<String> = conn.zrange(&scheduled_key, 0, -1).await?;
+        for job_id in job_ids {
+            let job_key = format!("{JOB_KEY_PREFIX}{job_id}");
+            let job_json: Option<String> = conn.get(&job_key).await?;
+            if let Some(json) = job_json {
+                if let Ok(mut job) = Job::from_json(&json) {
+                    if job.name == job_name && job.status == JobStatus::Queued {
+                        job.status = JobStatus::Cancelled;
+                        job.updated_at = Some(Utc::now());
+                        let updated_json = job.to_json()?;
+                        let _: () = conn.zrem(&scheduled_key, &job_id).await?;
+                        let _: () = conn.set(&job_key, &updated_json).await?;
+                        let cancelled_key = format!(
+                            "cancelled:{}",
+                            scheduled_key.trim_start_matches(SCHEDULED_KEY_PREFIX)
+                        );
+                        let _: () = conn.sadd(&cancelled_key, &job_id).await?;
+                        release_unique_key(&mut conn, &job).await?;
+                        if let Some(batch_id) = &job.batch_id {
+                            finish_batch_job(&mut conn, batch_id, &job.id).await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Running jobs are stopped by their worker once it sees the new status
+    let processing_pattern = format!("{PROCESSING_KEY_PREFIX}*");
+    let processing_keys: Vec<String> = redis::cmd("KEYS")
+        .arg(&processing_pattern)
+        .query_async(&mut conn)
+        .await?;
+    for processing_key in processing_keys {
+        let job_ids: Vec<String> = conn.smembers(&processing_key).await?;
+        for job_id in job_ids {
+            let job_key = format!("{JOB_KEY_PREFIX}{job_id}");
+            let job_json: Option<String> = conn.get(&job_key).await?;
+            if let Some(json) = job_json {
+                if let Ok(mut job) = Job::from_json(&json) {
+                    if job.name == job_name && job.status == JobStatus::Queued {
+                        job.status = JobStatus::Cancelled;
+                        job.updated_at = Some(Utc::now());
+                        let updated_json = job.to_json()?;
+                        let _: () = conn.set(&job_key, &updated_json).await?;
+                        let cancelled_key = format!(
+                            "cancelled:{}",
+                            processing_key.trim_start_matches(PROCESSING_KEY_PREFIX)
+                        );
+                        let _: () = conn.sadd(&cancelled_key, &job_id).await?;
+                        release_unique_key(&mut conn, &job).await?;
+                        if let Some(batch_id) = &job.batch_id {
+                            finish_batch_job(&mut conn, batch_id, &job.id).await?;
+                        }
                     }
                 }
             }
@@ -867,6 +2123,43 @@ pub async fn cancel_jobs_by_name(client: &RedisPool, job_name: &str) -> Result<(
     Ok(())
 }
 
+/// Cancels a single job by id, the same way [`cancel_jobs_by_name`] does.
+///
+/// Returns `false` when no queued or running job has this id.
+///
+/// # Errors
+///
+/// This function will return an error if it fails
+pub async fn cancel_job(client: &RedisPool, id: &str) -> Result<bool> {
+    let mut conn = get_connection(client).await?;
+    let job_key = format!("{JOB_KEY_PREFIX}{id}");
+    let job_json: Option<String> = conn.get(&job_key).await?;
+    let Some(mut job) = job_json.map(|json| Job::from_json(&json)).transpose()? else {
+        return Ok(false);
+    };
+    if job.status != JobStatus::Queued {
+        return Ok(false);
+    }
+
+    debug!(job_id = %id, "Cancelling job");
+    let queue_name = job.queue.clone().unwrap_or_else(|| "default".to_string());
+    let _: () = conn
+        .lrem(queue_key(&queue_name, job.priority), 1, id)
+        .await?;
+    let _: () = conn
+        .zrem(scheduled_key(&queue_name, job.priority), id)
+        .await?;
+    job.status = JobStatus::Cancelled;
+    job.updated_at = Some(Utc::now());
+    let _: () = conn.set(&job_key, job.to_json()?).await?;
+    let _: () = conn.sadd(format!("cancelled:{queue_name}"), id).await?;
+    release_unique_key(&mut conn, &job).await?;
+    if let Some(batch_id) = &job.batch_id {
+        finish_batch_job(&mut conn, batch_id, &job.id).await?;
+    }
+    Ok(true)
+}
+
 pub const DEFAULT_QUEUES: &[&str] = &["default", "mailer"];
 
 pub fn get_queues(config_queues: &Option<Vec<String>>) -> Vec<String> {
@@ -887,6 +2180,9 @@ pub fn get_queues(config_queues: &Option<Vec<String>>) -> Vec<String> {
 pub struct RunOpts {
     pub num_workers: u32,
     pub poll_interval_sec: u32,
+    /// How long running jobs may take to finish once the queue shuts down,
+    /// see `workers.shutdown_timeout`.
+    pub shutdown_timeout: Option<Duration>,
     pub queues: Option<Vec<String>>,
 }
 
@@ -903,6 +2199,7 @@ pub async fn create_provider(qcfg: &RedisQueueConfig) -> Result<Queue> {
     let run_opts = RunOpts {
         num_workers: qcfg.num_workers,
         poll_interval_sec: 1,
+        shutdown_timeout: None,
         queues: qcfg.queues.clone(),
     };
     debug!(
@@ -953,6 +2250,14 @@ mod tests {
                 created_at: Some(now - chrono::Duration::days(15)),
                 updated_at: Some(now - chrono::Duration::days(15)),
                 tags: None,
+                attempts: 0,
+                last_error: None,
+                backtrace: None,
+                queue: None,
+                priority: 0,
+                unique_key: None,
+                result: None,
+                batch_id: None,
             };
 
             let mut conn = get_connection(client).await?;
@@ -964,7 +2269,15 @@ mod tests {
 
         // Create queued jobs
         let args = serde_json::json!({"hello": "world"});
-        enqueue(client, "TestJob".to_string(), None, args, None).await?;
+        enqueue(
+            client,
+            "TestJob".to_string(),
+            None,
+            args,
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await?;
 
         // Create job with tags
         let args = serde_json::json!({"hello": "tagged"});
@@ -974,6 +2287,7 @@ mod tests {
             None,
             args,
             Some(vec!["important".to_string(), "urgent".to_string()]),
+            &EnqueueOpts::default(),
         )
         .await?;
 
@@ -992,7 +2306,7 @@ mod tests {
         // Dequeue job
         let queues = vec!["default".to_string()];
         let mut conn = get_test_connection(&client).await;
-        let job_opt = dequeue_with_conn(&mut conn, &queues, &[])
+        let job_opt = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
             .await
             .expect("dequeue");
 
@@ -1000,6 +2314,467 @@ mod tests {
         assert!(job_opt.is_some());
     }
 
+    #[tokio::test]
+    async fn test_can_enqueue_delayed_job_redis() {
+        let (client, _container) = setup_redis().await;
+
+        let run_at = Utc::now() + chrono::Duration::hours(1);
+        assert!(enqueue(
+            &client,
+            "DelayedJob".to_string(),
+            None,
+            serde_json::json!({}),
+            None,
+            &EnqueueOpts {
+                run_at: Some(run_at),
+                ..Default::default()
+            }
+        )
+        .await
+        .is_ok());
+
+        let mut conn = get_test_connection(&client).await;
+        let scheduled: Vec<String> = conn
+            .zrange(format!("{SCHEDULED_KEY_PREFIX}default"), 0, -1)
+            .await
+            .expect("read scheduled set");
+        assert_eq!(scheduled.len(), 1);
+
+        // not due yet, so nothing is promoted to the queue
+        let job_opt = dequeue_with_conn(&mut conn, &["default".to_string()], &[], &HashMap::new())
+            .await
+            .expect("dequeue");
+        assert!(job_opt.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_can_dequeue_by_priority_redis() {
+        let (client, _container) = setup_redis().await;
+
+        for (name, priority) in [("Low", -1), ("Normal", 0), ("High", 10)] {
+            assert!(enqueue(
+                &client,
+                name.to_string(),
+                None,
+                serde_json::json!({}),
+                None,
+                &EnqueueOpts {
+                    priority: Some(priority),
+                    ..Default::default()
+                }
+            )
+            .await
+            .is_ok());
+        }
+
+        let queues = vec!["default".to_string()];
+        let mut conn = get_test_connection(&client).await;
+        let mut names = Vec::new();
+        while let Some((job, _)) = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+        {
+            names.push(job.name);
+        }
+        assert_eq!(names, vec!["High", "Normal", "Low"]);
+    }
+
+    #[tokio::test]
+    async fn test_can_promote_scheduled_jobs_by_priority_redis() {
+        let (client, _container) = setup_redis().await;
+
+        let run_at = Utc::now() + chrono::Duration::milliseconds(100);
+        for (name, priority) in [("Low", -1), ("High", 10)] {
+            assert!(enqueue(
+                &client,
+                name.to_string(),
+                None,
+                serde_json::json!({}),
+                None,
+                &EnqueueOpts {
+                    run_at: Some(run_at),
+                    priority: Some(priority),
+                    ..Default::default()
+                }
+            )
+            .await
+            .is_ok());
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let queues = vec!["default".to_string()];
+        let mut conn = get_test_connection(&client).await;
+        let mut names = Vec::new();
+        while let Some((job, _)) = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+        {
+            names.push(job.name);
+        }
+        assert_eq!(names, vec!["High", "Low"]);
+    }
+
+    #[tokio::test]
+    async fn test_can_dequeue_with_limits_redis() {
+        let (client, _container) = setup_redis().await;
+        let queues = vec!["default".to_string()];
+        let mut conn = get_test_connection(&client).await;
+        let worker_opts = HashMap::from([
+            (
+                "Limited".to_string(),
+                WorkerOpts {
+                    concurrency_limit: Some(1),
+                    ..Default::default()
+                },
+            ),
+            (
+                "Throttled".to_string(),
+                WorkerOpts {
+                    rate_limit: Some(crate::bgworker::RateLimit::per_minute(2)),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        for (name, count, priority) in [("Limited", 2, 10), ("Throttled", 3, 5), ("Other", 1, 0)] {
+            for _ in 0..count {
+                assert!(enqueue(
+                    &client,
+                    name.to_string(),
+                    None,
+                    serde_json::json!({}),
+                    None,
+                    &EnqueueOpts {
+                        priority: Some(priority),
+                        ..Default::default()
+                    }
+                )
+                .await
+                .is_ok());
+            }
+        }
+
+        let mut jobs = Vec::new();
+        while let Some((job, _)) = dequeue_with_conn(&mut conn, &queues, &[], &worker_opts)
+            .await
+            .expect("dequeue")
+        {
+            jobs.push(job);
+        }
+        assert_eq!(
+            jobs.iter().map(|job| job.name.as_str()).collect::<Vec<_>>(),
+            vec!["Limited", "Throttled", "Throttled", "Other"]
+        );
+
+        // held back jobs wait in the scheduled sets instead of blocking the queue
+        let mut parked = Vec::new();
+        for priority in [10, 5] {
+            let ids: Vec<String> = conn
+                .zrange(scheduled_key("default", priority), 0, -1)
+                .await
+                .expect("read scheduled set");
+            assert_eq!(ids.len(), 1);
+            parked.push((priority, ids[0].clone()));
+        }
+
+        // a finished job frees a slot and a new window lets more jobs start
+        assert!(
+            complete_job_with_conn(&mut conn, &jobs[0].id, "default", None, None)
+                .await
+                .is_ok()
+        );
+        let _: () = conn
+            .del(format!("{LIMIT_KEY_PREFIX}Throttled:rate"))
+            .await
+            .expect("reset rate limit window");
+        for (priority, id) in parked {
+            let _: () = conn
+                .zadd(scheduled_key("default", priority), id, 0)
+                .await
+                .expect("make parked job due");
+        }
+        let mut names = Vec::new();
+        while let Some((job, _)) = dequeue_with_conn(&mut conn, &queues, &[], &worker_opts)
+            .await
+            .expect("dequeue")
+        {
+            names.push(job.name);
+        }
+        assert_eq!(names, vec!["Limited", "Throttled"]);
+    }
+
+    #[tokio::test]
+    async fn test_can_enqueue_unique_jobs_redis() {
+        let (client, _container) = setup_redis().await;
+        let unique = EnqueueOpts {
+            unique_key: Some("a".to_string()),
+            ..Default::default()
+        };
+
+        for _ in 0..2 {
+            assert!(enqueue(
+                &client,
+                "Report".to_string(),
+                None,
+                serde_json::json!({}),
+                None,
+                &unique
+            )
+            .await
+            .is_ok());
+        }
+        assert_eq!(get_all_jobs(&client).await.len(), 1);
+
+        // the key is free again once the job is done
+        let queues = vec!["default".to_string()];
+        let mut conn = get_test_connection(&client).await;
+        let (job, _) = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+            .expect("job");
+        assert!(
+            complete_job_with_conn(&mut conn, &job.id, "default", None, None)
+                .await
+                .is_ok()
+        );
+        assert!(enqueue(
+            &client,
+            "Report".to_string(),
+            None,
+            serde_json::json!({}),
+            None,
+            &unique
+        )
+        .await
+        .is_ok());
+        let jobs = get_all_jobs(&client).await;
+        assert_eq!(jobs.len(), 1);
+        assert_ne!(jobs[0].id, job.id);
+    }
+
+    #[tokio::test]
+    async fn test_can_retry_unique_jobs_redis() {
+        let (client, _container) = setup_redis().await;
+        let unique = EnqueueOpts {
+            unique_key: Some("a".to_string()),
+            ..Default::default()
+        };
+        let queues = vec!["default".to_string()];
+        let mut conn = get_test_connection(&client).await;
+
+        let failed_id = enqueue(
+            &client,
+            "Report".to_string(),
+            None,
+            serde_json::json!({}),
+            None,
+            &unique,
+        )
+        .await
+        .expect("enqueue");
+        let (job, _) = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+            .expect("job");
+        assert!(
+            fail_job_with_conn(&mut conn, &job.id, "default", &Error::string("failure"))
+                .await
+                .is_ok()
+        );
+        let queued_id = enqueue(
+            &client,
+            "Report".to_string(),
+            None,
+            serde_json::json!({}),
+            None,
+            &unique,
+        )
+        .await
+        .expect("enqueue");
+        assert_ne!(queued_id, failed_id);
+
+        // the key is taken by the queued job, so the failed one stays failed
+        let target = RetryTarget::Id(failed_id.clone());
+        assert!(retry_jobs(&client, &target).await.is_ok());
+        let failed = get_job(&client, &failed_id)
+            .await
+            .expect("get job")
+            .expect("job");
+        assert_eq!(failed.status, JobStatus::Failed);
+
+        let (job, _) = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+            .expect("job");
+        assert!(
+            complete_job_with_conn(&mut conn, &job.id, "default", None, None)
+                .await
+                .is_ok()
+        );
+        assert!(retry_jobs(&client, &target).await.is_ok());
+        let retried = get_job(&client, &failed_id)
+            .await
+            .expect("get job")
+            .expect("job");
+        assert_eq!(retried.status, JobStatus::Queued);
+        assert_eq!(
+            enqueue(
+                &client,
+                "Report".to_string(),
+                None,
+                serde_json::json!({}),
+                None,
+                &unique,
+            )
+            .await
+            .expect("enqueue"),
+            failed_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_can_get_job_redis() {
+        let (client, _container) = setup_redis().await;
+
+        let job_id = enqueue(
+            &client,
+            "Report".to_string(),
+            None,
+            serde_json::json!({}),
+            None,
+            &EnqueueOpts::default(),
+        )
+        .await
+        .expect("enqueue");
+
+        let job = get_job(&client, &job_id)
+            .await
+            .expect("get job")
+            .expect("job exists");
+        assert_eq!(job.status, JobStatus::Queued);
+
+        let queues = vec!["default".to_string()];
+        let mut conn = get_test_connection(&client).await;
+        assert!(dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+            .is_some());
+        let job = get_job(&client, &job_id)
+            .await
+            .expect("get job")
+            .expect("job exists");
+        assert_eq!(job.status, JobStatus::Processing);
+
+        assert!(complete_job_with_conn(
+            &mut conn,
+            &job_id,
+            "default",
+            None,
+            Some(serde_json::json!({ "rows": 42 }))
+        )
+        .await
+        .is_ok());
+        let job: JobInfo = get_job(&client, &job_id)
+            .await
+            .expect("get job")
+            .expect("job exists")
+            .into();
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.result, Some(serde_json::json!({ "rows": 42 })));
+
+        assert!(get_job(&client, "missing")
+            .await
+            .expect("get job")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_can_finish_batch_redis() {
+        let (client, _container) = setup_redis().await;
+        struct ImportWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<bool> for ImportWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _succeed: bool) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+        struct AlertWorker;
+        #[async_trait::async_trait]
+        impl BackgroundWorker<()> for AlertWorker {
+            fn build(_ctx: &crate::app::AppContext) -> Self {
+                Self
+            }
+            async fn perform(&self, _args: ()) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut batch = Batch::new();
+        batch
+            .add::<ImportWorker, _>(true)
+            .expect("add job")
+            .add::<ImportWorker, _>(false)
+            .expect("add job")
+            .on_failure::<AlertWorker, _>(())
+            .expect("set on_failure");
+        assert!(enqueue_batch(&client, &batch).await.is_ok());
+
+        let queues = vec!["default".to_string()];
+        let mut conn = get_test_connection(&client).await;
+        let (completed, _) = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+            .expect("job");
+        assert!(
+            complete_job_with_conn(&mut conn, &completed.id, "default", None, None)
+                .await
+                .is_ok()
+        );
+        assert!(finish_batch_job(&mut conn, batch.id(), &completed.id)
+            .await
+            .is_ok());
+        let info = get_batch(&client, batch.id())
+            .await
+            .expect("get batch")
+            .expect("batch exists");
+        assert_eq!((info.total, info.pending, info.completed), (2, 1, 1));
+        assert!(info.finished_at.is_none());
+
+        let (failed, _) = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
+            .await
+            .expect("dequeue")
+            .expect("job");
+        assert!(fail_job_with_conn(
+            &mut conn,
+            &failed.id,
+            "default",
+            &Error::string("import failed")
+        )
+        .await
+        .is_ok());
+        // the worker and `cancel_jobs_by_name` may both report the same job
+        for _ in 0..2 {
+            assert!(finish_batch_job(&mut conn, batch.id(), &failed.id)
+                .await
+                .is_ok());
+        }
+        let info = get_batch(&client, batch.id())
+            .await
+            .expect("get batch")
+            .expect("batch exists");
+        assert_eq!((info.pending, info.completed, info.failed), (0, 1, 1));
+        assert!(info.finished_at.is_some());
+
+        let alerts = get_all_jobs(&client)
+            .await
+            .into_iter()
+            .filter(|job| job.name == "AlertWorker")
+            .count();
+        assert_eq!(alerts, 1);
+    }
+
     #[tokio::test]
     async fn test_can_clear_redis() {
         // Setup Redis directly with testcontainer
@@ -1038,11 +2813,16 @@ mod tests {
 
         // Test enqueue
         let args = serde_json::json!({"user_id": 42});
-        assert!(
-            enqueue(&client, "PasswordReset".to_string(), None, args, None)
-                .await
-                .is_ok()
-        );
+        assert!(enqueue(
+            &client,
+            "PasswordReset".to_string(),
+            None,
+            args,
+            None,
+            &EnqueueOpts::default()
+        )
+        .await
+        .is_ok());
 
         // Verify job was created
         let jobs = get_all_jobs(&client).await;
@@ -1065,7 +2845,8 @@ mod tests {
             "EmailNotification".to_string(),
             Some("mailer".to_string()),
             args,
-            None
+            None,
+            &EnqueueOpts::default()
         )
         .await
         .is_ok());
@@ -1078,7 +2859,7 @@ mod tests {
 
         // Test dequeue from mailer queue
         let queues = vec!["mailer".to_string()];
-        let _job_opt = dequeue_with_conn(&mut conn, &queues, &[])
+        let _job_opt = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
             .await
             .expect("dequeue");
 
@@ -1093,22 +2874,31 @@ mod tests {
 
         // Add job
         let args = serde_json::json!({"task": "test"});
-        assert!(enqueue(&client, "TestJob".to_string(), None, args, None)
-            .await
-            .is_ok());
+        assert!(enqueue(
+            &client,
+            "TestJob".to_string(),
+            None,
+            args,
+            None,
+            &EnqueueOpts::default()
+        )
+        .await
+        .is_ok());
 
         // Dequeue job
         let queues = vec!["default".to_string()];
         let mut conn = get_test_connection(&client).await;
-        let job_opt = dequeue_with_conn(&mut conn, &queues, &[])
+        let job_opt = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
             .await
             .expect("dequeue");
         let (job, queue) = job_opt.unwrap();
 
         // Complete job
-        assert!(complete_job_with_conn(&mut conn, &job.id, &queue, None)
-            .await
-            .is_ok());
+        assert!(
+            complete_job_with_conn(&mut conn, &job.id, &queue, None, None)
+                .await
+                .is_ok()
+        );
 
         // Verify job is not in processing set
         let processing_key = format!("{PROCESSING_KEY_PREFIX}{queue}");
@@ -1135,23 +2925,28 @@ mod tests {
 
         // Add job
         let args = serde_json::json!({"task": "recurring"});
-        assert!(
-            enqueue(&client, "RecurringJob".to_string(), None, args, None)
-                .await
-                .is_ok()
-        );
+        assert!(enqueue(
+            &client,
+            "RecurringJob".to_string(),
+            None,
+            args,
+            None,
+            &EnqueueOpts::default()
+        )
+        .await
+        .is_ok());
 
         // Dequeue job
         let queues = vec!["default".to_string()];
         let mut conn = get_test_connection(&client).await;
-        let job_opt = dequeue_with_conn(&mut conn, &queues, &[])
+        let job_opt = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
             .await
             .expect("dequeue");
         let (job, queue) = job_opt.unwrap();
 
         // Complete job with interval to reschedule
         assert!(
-            complete_job_with_conn(&mut conn, &job.id, &queue, Some(1000))
+            complete_job_with_conn(&mut conn, &job.id, &queue, Some(1000), None)
                 .await
                 .is_ok()
         );
@@ -1179,14 +2974,21 @@ mod tests {
 
         // Add job
         let args = serde_json::json!({"task": "test"});
-        assert!(enqueue(&client, "TestJob".to_string(), None, args, None)
-            .await
-            .is_ok());
+        assert!(enqueue(
+            &client,
+            "TestJob".to_string(),
+            None,
+            args,
+            None,
+            &EnqueueOpts::default()
+        )
+        .await
+        .is_ok());
 
         // Dequeue job
         let queues = vec!["default".to_string()];
         let mut conn = get_test_connection(&client).await;
-        let job_opt = dequeue_with_conn(&mut conn, &queues, &[])
+        let job_opt = dequeue_with_conn(&mut conn, &queues, &[], &HashMap::new())
             .await
             .expect("dequeue");
         let (job, queue) = job_opt.unwrap();
@@ -1210,7 +3012,8 @@ mod tests {
         let job_json: String = conn.get(&job_key).await.expect("get job");
         let failed_job = Job::from_json(&job_json).expect("parse job");
         assert_eq!(failed_job.status, JobStatus::Failed);
-        assert!(failed_job.data.get("error").is_some());
+        assert_eq!(failed_job.data, job.data);
+        assert!(failed_job.last_error.is_some());
     }
 
     #[tokio::test]
@@ -1282,14 +3085,22 @@ mod tests {
 
         // Add job
         let args = serde_json::json!("test args");
-        assert!(enqueue(&client, "TestJob".to_string(), None, args, None)
-            .await
-            .is_ok());
+        assert!(enqueue(
+            &client,
+            "TestJob".to_string(),
+            None,
+            args,
+            None,
+            &EnqueueOpts::default()
+        )
+        .await
+        .is_ok());
 
         // Run registry with worker for a short time
         let opts = RunOpts {
             num_workers: 1,
             poll_interval_sec: 1,
+            shutdown_timeout: None,
             queues: None,
         };
 
@@ -1320,7 +3131,8 @@ mod tests {
             "TaggedJob".to_string(),
             Some("default".to_string()),
             args1,
-            Some(vec!["tag1".to_string(), "common".to_string()])
+            Some(vec!["tag1".to_string(), "common".to_string()]),
+            &EnqueueOpts::default()
         )
         .await
         .is_ok());
@@ -1331,7 +3143,8 @@ mod tests {
             "TaggedJob".to_string(),
             Some("default".to_string()),
             args2,
-            Some(vec!["tag2".to_string(), "common".to_string()])
+            Some(vec!["tag2".to_string(), "common".to_string()]),
+            &EnqueueOpts::default()
         )
         .await
         .is_ok());
@@ -1342,7 +3155,8 @@ mod tests {
             "TaggedJob".to_string(),
             Some("default".to_string()),
             args3,
-            Some(vec!["tag3".to_string()])
+            Some(vec!["tag3".to_string()]),
+            &EnqueueOpts::default()
         )
         .await
         .is_ok());
@@ -1350,7 +3164,7 @@ mod tests {
         // Test dequeue with tag1 filter
         let queues = vec!["default".to_string()];
         let mut conn = get_test_connection(&client).await;
-        let job_opt = dequeue_with_conn(&mut conn, &queues, &["tag1".to_string()])
+        let job_opt = dequeue_with_conn(&mut conn, &queues, &["tag1".to_string()], &HashMap::new())
             .await
             .expect("dequeue with tag1");
 
@@ -1372,6 +3186,26 @@ mod tests {
         ping(&client).await.expect("ping redis");
     }
 
+    #[tokio::test]
+    async fn test_try_lock_redis() {
+        let (client, _container) = setup_redis().await;
+
+        let ttl = Duration::from_secs(30);
+        assert!(try_lock(&client, "report", "a", ttl).await.expect("lock"));
+        assert!(!try_lock(&client, "report", "b", ttl).await.expect("lock"));
+        // the owner extends its lock
+        assert!(try_lock(&client, "report", "a", ttl).await.expect("lock"));
+        assert!(try_lock(&client, "other", "b", ttl).await.expect("lock"));
+
+        let mut conn = get_connection(&client).await.expect("connection");
+        let _: () = conn
+            .del(format!("{LOCK_KEY_PREFIX}report"))
+            .await
+            .expect("expire lock");
+        assert!(try_lock(&client, "report", "b", ttl).await.expect("lock"));
+        assert!(!try_lock(&client, "report", "a", ttl).await.expect("lock"));
+    }
+
     #[tokio::test]
     async fn test_can_clear_by_status_redis() {
         // Setup Redis directly with testcontainer using the reliable method
@@ -1443,6 +3277,14 @@ mod tests {
             created_at: Some(Utc::now() - chrono::Duration::days(15)),
             updated_at: Some(Utc::now() - chrono::Duration::days(15)),
             tags: None,
+            attempts: 0,
+            last_error: None,
+            backtrace: None,
+            queue: None,
+            priority: 0,
+            unique_key: None,
+            result: None,
+            batch_id: None,
         };
 
         // Create an old completed job (older than 10 days)
@@ -1456,6 +3298,14 @@ mod tests {
             created_at: Some(Utc::now() - chrono::Duration::days(15)),
             updated_at: Some(Utc::now() - chrono::Duration::days(15)),
             tags: None,
+            attempts: 0,
+            last_error: None,
+            backtrace: None,
+            queue: None,
+            priority: 0,
+            unique_key: None,
+            result: None,
+            batch_id: None,
         };
 
         // Store both jobs directly
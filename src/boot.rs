@@ -223,6 +223,11 @@ fn scheduler<H: Hooks>(
         }
     };
 
+    let scheduler = match &app_context.queue_provider {
+        Some(queue_provider) => scheduler.with_queue_provider(queue_provider.clone()),
+        None => scheduler,
+    };
+
     Ok(scheduler.by_spec(&scheduler::Spec { name, tag }))
 }
 
@@ -0,0 +1,247 @@
+//! # Cache Codecs
+//!
+//! This module converts the values stored through [`super::Cache`] to and
+//! from the bytes handed to the cache drivers.
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CacheError, CacheResult};
+use crate::config::{CacheCodecConfig, CacheCompressionConfig, CacheFormat};
+
+/// Header of the values stored as is when compression is enabled.
+#[cfg(feature = "cache_zstd")]
+const UNCOMPRESSED: u8 = 0;
+/// Header of the values compressed with zstd.
+#[cfg(feature = "cache_zstd")]
+const ZSTD: u8 = 1;
+
+/// Serializes cached values in a [`CacheFormat`], optionally compressing the
+/// large ones.
+///
+/// Without compression, values are stored exactly as the format encodes
+/// them, so JSON values stay readable in Redis. With compression, every
+/// value starts with a header byte telling whether it is compressed.
+#[derive(Debug, Clone, Default)]
+pub struct Codec {
+    format: CacheFormat,
+    compression: Option<CacheCompressionConfig>,
+}
+
+impl Codec {
+    /// Creates a codec from its configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CacheError`] if the configuration needs a feature that is
+    /// not enabled.
+    pub fn new(config: &CacheCodecConfig) -> CacheResult<Self> {
+        let feature = match config.format {
+            CacheFormat::Json => None,
+            CacheFormat::MessagePack => {
+                (!cfg!(feature = "cache_msgpack")).then_some("cache_msgpack")
+            }
+            CacheFormat::Bincode => (!cfg!(feature = "cache_bincode")).then_some("cache_bincode"),
+        }
+        .or_else(|| {
+            (config.compression.is_some() && !cfg!(feature = "cache_zstd")).then_some("cache_zstd")
+        });
+        if let Some(feature) = feature {
+            return Err(CacheError::Any(
+                format!("the cache codec requires the `{feature}` feature").into(),
+            ));
+        }
+
+        Ok(Self {
+            format: config.format,
+            compression: config.compression.clone(),
+        })
+    }
+
+    /// Serializes `value`, and compresses it if it is large enough.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CacheError::Serialization`] if `value` can not be
+    /// serialized.
+    pub fn encode<T: Serialize + ?Sized>(&self, value: &T) -> CacheResult<Vec<u8>> {
+        let bytes = self.serialize(value)?;
+        match &self.compression {
+            #[cfg(feature = "cache_zstd")]
+            Some(compression) => compress(bytes, compression),
+            _ => Ok(bytes),
+        }
+    }
+
+    /// Decompresses `bytes` if needed, and deserializes them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CacheError::Deserialization`] if `bytes` were not encoded
+    /// by a codec of the same configuration.
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> CacheResult<T> {
+        match &self.compression {
+            #[cfg(feature = "cache_zstd")]
+            Some(_) => self.deserialize(&decompress(bytes)?),
+            _ => self.deserialize(bytes),
+        }
+    }
+
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> CacheResult<Vec<u8>> {
+        let serialization = |e: &dyn std::fmt::Display| CacheError::Serialization(e.to_string());
+        match self.format {
+            CacheFormat::Json => serde_json::to_vec(value).map_err(|e| serialization(&e)),
+            #[cfg(feature = "cache_msgpack")]
+            CacheFormat::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|e| serialization(&e))
+            }
+            #[cfg(feature = "cache_bincode")]
+            CacheFormat::Bincode => bincode::serialize(value).map_err(|e| serialization(&e)),
+            #[cfg(not(all(feature = "cache_msgpack", feature = "cache_bincode")))]
+            format => Err(disabled(format)),
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> CacheResult<T> {
+        let deserialization =
+            |e: &dyn std::fmt::Display| CacheError::Deserialization(e.to_string());
+        match self.format {
+            CacheFormat::Json => serde_json::from_slice(bytes).map_err(|e| deserialization(&e)),
+            #[cfg(feature = "cache_msgpack")]
+            CacheFormat::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|e| deserialization(&e))
+            }
+            #[cfg(feature = "cache_bincode")]
+            CacheFormat::Bincode => bincode::deserialize(bytes).map_err(|e| deserialization(&e)),
+            #[cfg(not(all(feature = "cache_msgpack", feature = "cache_bincode")))]
+            format => Err(disabled(format)),
+        }
+    }
+}
+
+#[cfg(not(all(feature = "cache_msgpack", feature = "cache_bincode")))]
+fn disabled(format: CacheFormat) -> CacheError {
+    CacheError::Any(format!("cache format {format:?} is not enabled").into())
+}
+
+#[cfg(feature = "cache_zstd")]
+fn compress(bytes: Vec<u8>, compression: &CacheCompressionConfig) -> CacheResult<Vec<u8>> {
+    if bytes.len() < compression.threshold {
+        let mut framed = Vec::with_capacity(bytes.len() + 1);
+        framed.push(UNCOMPRESSED);
+        framed.extend_from_slice(&bytes);
+        return Ok(framed);
+    }
+    let mut framed = vec![ZSTD];
+    zstd::stream::copy_encode(bytes.as_slice(), &mut framed, compression.level)
+        .map_err(|e| CacheError::Serialization(e.to_string()))?;
+    Ok(framed)
+}
+
+#[cfg(feature = "cache_zstd")]
+fn decompress(bytes: &[u8]) -> CacheResult<std::borrow::Cow<'_, [u8]>> {
+    match bytes.split_first() {
+        Some((&UNCOMPRESSED, rest)) => Ok(std::borrow::Cow::Borrowed(rest)),
+        Some((&ZSTD, rest)) => zstd::stream::decode_all(rest)
+            .map(std::borrow::Cow::Owned)
+            .map_err(|e| CacheError::Deserialization(e.to_string())),
+        _ => Err(CacheError::Deserialization(
+            "cached value has no compression header".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        bio: String,
+    }
+
+    fn user(bio_len: usize) -> User {
+        User {
+            name: "Alice".to_string(),
+            bio: "a".repeat(bio_len),
+        }
+    }
+
+    #[cfg(any(
+        feature = "cache_msgpack",
+        feature = "cache_bincode",
+        feature = "cache_zstd"
+    ))]
+    fn codec(format: CacheFormat, compression: Option<CacheCompressionConfig>) -> Codec {
+        Codec::new(&CacheCodecConfig {
+            format,
+            compression,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn can_encode_json() {
+        let codec = Codec::default();
+        let bytes = codec.encode(&user(3)).unwrap();
+        assert_eq!(bytes, br#"{"name":"Alice","bio":"aaa"}"#);
+        assert_eq!(codec.decode::<User>(&bytes).unwrap(), user(3));
+        assert!(codec.decode::<User>(b"not json").is_err());
+    }
+
+    #[cfg(feature = "cache_msgpack")]
+    #[test]
+    fn can_encode_msgpack() {
+        let codec = codec(CacheFormat::MessagePack, None);
+        let bytes = codec.encode(&user(3)).unwrap();
+        assert!(bytes.len() < Codec::default().encode(&user(3)).unwrap().len());
+        assert_eq!(codec.decode::<User>(&bytes).unwrap(), user(3));
+    }
+
+    #[cfg(feature = "cache_bincode")]
+    #[test]
+    fn can_encode_bincode() {
+        let codec = codec(CacheFormat::Bincode, None);
+        let bytes = codec.encode(&user(3)).unwrap();
+        assert_eq!(codec.decode::<User>(&bytes).unwrap(), user(3));
+    }
+
+    #[cfg(feature = "cache_zstd")]
+    #[test]
+    fn can_compress_large_values() {
+        let codec = codec(
+            CacheFormat::Json,
+            Some(CacheCompressionConfig {
+                threshold: 100,
+                level: 3,
+            }),
+        );
+
+        let small = codec.encode(&user(3)).unwrap();
+        assert_eq!(small[0], UNCOMPRESSED);
+        assert_eq!(codec.decode::<User>(&small).unwrap(), user(3));
+
+        let large = codec.encode(&user(10_000)).unwrap();
+        assert_eq!(large[0], ZSTD);
+        assert!(large.len() < 1000);
+        assert_eq!(codec.decode::<User>(&large).unwrap(), user(10_000));
+    }
+
+    #[test]
+    fn can_not_use_disabled_features() {
+        let config = CacheCodecConfig {
+            format: CacheFormat::Json,
+            compression: Some(CacheCompressionConfig {
+                threshold: 100,
+                level: 3,
+            }),
+        };
+        assert_eq!(Codec::new(&config).is_ok(), cfg!(feature = "cache_zstd"));
+        let config = CacheCodecConfig {
+            format: CacheFormat::MessagePack,
+            compression: None,
+        };
+        assert_eq!(Codec::new(&config).is_ok(), cfg!(feature = "cache_msgpack"));
+    }
+}
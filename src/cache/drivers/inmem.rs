@@ -2,15 +2,21 @@
 //!
 //! This module implements a cache driver using an in-memory cache.
 use std::{
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, PoisonError},
     time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
-use moka::{sync::Cache, Expiry};
+use moka::{
+    notification::RemovalCause,
+    ops::compute::{CompResult, Op},
+    sync::Cache,
+    Expiry,
+};
 
 use super::CacheDriver;
-use crate::cache::CacheResult;
+use crate::cache::{stats, CacheError, CacheResult};
 use crate::config::InMemCacheConfig;
 
 /// Creates a new instance of the in-memory cache driver, with a default Loco
@@ -21,28 +27,152 @@ use crate::config::InMemCacheConfig;
 /// A [`Cache`] instance.
 #[must_use]
 pub fn new(config: &InMemCacheConfig) -> crate::cache::Cache {
-    let cache: Cache<String, (Expiration, String)> = Cache::builder()
-        .max_capacity(config.max_capacity)
-        .expire_after(InMemExpiry)
-        .build();
-    crate::cache::Cache::new(Inmem::from(cache))
+    crate::cache::Cache::new(Box::new(Inmem::build(config)))
+}
+
+/// Evicted keys, by prefix.
+type Evictions = Arc<Mutex<HashMap<String, u64>>>;
+
+fn count_eviction(evictions: &Evictions, key: &str) {
+    let prefix = stats::prefix(key);
+    tracing::trace!(key, prefix, "cache_eviction");
+    *evictions
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(prefix.to_string())
+        .or_default() += 1;
 }
 
+/// Tag indexes smaller than this are never pruned.
+const MIN_TAG_INDEX_PRUNE: usize = 1024;
+
 /// Represents the in-memory cache driver.
 #[derive(Debug)]
 pub struct Inmem {
-    cache: Cache<String, (Expiration, String)>,
+    cache: Cache<String, (Expiration, Vec<u8>)>,
+    tags: Mutex<TagIndex>,
+    evictions: Evictions,
+}
+
+/// Keys inserted with each tag.
+///
+/// Keys the cache evicted are only dropped from it when their tag is
+/// invalidated, or once the index grows past twice the number of cached
+/// entries.
+#[derive(Debug, Default)]
+struct TagIndex {
+    keys: HashMap<String, HashSet<String>>,
+    len: usize,
+}
+
+impl TagIndex {
+    fn add(&mut self, tag: &str, key: &str) {
+        if self
+            .keys
+            .entry(tag.to_string())
+            .or_default()
+            .insert(key.to_string())
+        {
+            self.len += 1;
+        }
+    }
+
+    fn take(&mut self, tag: &str) -> HashSet<String> {
+        let keys = self.keys.remove(tag).unwrap_or_default();
+        self.len -= keys.len();
+        keys
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(&str) -> bool) {
+        self.keys.retain(|_, keys| {
+            keys.retain(|key| f(key));
+            !keys.is_empty()
+        });
+        self.len = self.keys.values().map(HashSet::len).sum();
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.len = 0;
+    }
 }
 
 impl Inmem {
     /// Constructs a new [`Inmem`] instance from a given cache.
     ///
+    /// Evictions are only counted by the cache built with [`new`], which
+    /// listens to them.
+    ///
     /// # Returns
     ///
     /// A boxed [`CacheDriver`] instance.
     #[must_use]
-    pub fn from(cache: Cache<String, (Expiration, String)>) -> Box<dyn CacheDriver> {
-        Box::new(Self { cache })
+    pub fn from(cache: Cache<String, (Expiration, Vec<u8>)>) -> Box<dyn CacheDriver> {
+        Box::new(Self {
+            cache,
+            tags: Mutex::default(),
+            evictions: Evictions::default(),
+        })
+    }
+
+    /// Builds a driver counting the keys its cache evicts.
+    fn build(config: &InMemCacheConfig) -> Self {
+        let evictions = Evictions::default();
+        let cache = Cache::builder()
+            .max_capacity(config.max_capacity)
+            .expire_after(InMemExpiry)
+            .eviction_listener({
+                let evictions = evictions.clone();
+                move |key: Arc<String>, _, cause: RemovalCause| {
+                    if cause.was_evicted() {
+                        count_eviction(&evictions, &key);
+                    }
+                }
+            })
+            .build();
+        Self {
+            cache,
+            tags: Mutex::default(),
+            evictions,
+        }
+    }
+
+    fn tags(&self) -> std::sync::MutexGuard<'_, TagIndex> {
+        self.tags.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds `delta` to the counter at `key`. The entry is updated under the
+    /// key lock of the cache, so concurrent updates are not lost.
+    fn add(&self, key: &str, delta: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        let mut result = Ok(0);
+        self.cache.entry(key.to_string()).and_compute_with(|entry| {
+            let (expiration, current) = match entry {
+                Some(entry) => {
+                    let (_, value) = entry.into_value();
+                    let Some(current) = std::str::from_utf8(&value)
+                        .ok()
+                        .and_then(|value| value.parse::<i64>().ok())
+                    else {
+                        result = Err(CacheError::Any(
+                            format!("value of `{key}` is not an integer").into(),
+                        ));
+                        return Op::Nop;
+                    };
+                    (Expiration::Unchanged, current)
+                }
+                None => (ttl.map_or(Expiration::Never, Expiration::AfterDuration), 0),
+            };
+            if let Some(next) = current.checked_add(delta) {
+                result = Ok(next);
+                Op::Put((expiration, next.to_string().into_bytes()))
+            } else {
+                result = Err(CacheError::Any(
+                    format!("counter `{key}` overflowed").into(),
+                ));
+                Op::Nop
+            }
+        });
+        result
     }
 }
 
@@ -71,7 +201,7 @@ impl CacheDriver for Inmem {
     /// # Errors
     ///
     /// Returns a `CacheError` if there is an error during the operation.
-    async fn get(&self, key: &str) -> CacheResult<Option<String>> {
+    async fn get(&self, key: &str) -> CacheResult<Option<Vec<u8>>> {
         let result = self.cache.get(key);
         match result {
             None => Ok(None),
@@ -79,16 +209,26 @@ impl CacheDriver for Inmem {
         }
     }
 
+    /// Retrieves the values of `keys` one by one.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn get_many(&self, keys: &[&str]) -> CacheResult<Vec<Option<Vec<u8>>>> {
+        Ok(keys
+            .iter()
+            .map(|key| self.cache.get(*key).map(|(_, value)| value))
+            .collect())
+    }
+
     /// Inserts a key-value pair into the cache.
     ///
     /// # Errors
     ///
     /// Returns a `CacheError` if there is an error during the operation.
-    async fn insert(&self, key: &str, value: &str) -> CacheResult<()> {
-        self.cache.insert(
-            key.to_string(),
-            (Expiration::Never, Arc::new(value).to_string()),
-        );
+    async fn insert(&self, key: &str, value: &[u8]) -> CacheResult<()> {
+        self.cache
+            .insert(key.to_string(), (Expiration::Never, value.to_vec()));
         Ok(())
     }
 
@@ -102,19 +242,143 @@ impl CacheDriver for Inmem {
     async fn insert_with_expiry(
         &self,
         key: &str,
-        value: &str,
+        value: &[u8],
         duration: Duration,
+    ) -> CacheResult<()> {
+        self.cache.insert(
+            key.to_string(),
+            (Expiration::AfterDuration(duration), value.to_vec()),
+        );
+        Ok(())
+    }
+
+    /// Inserts a key-value pair into the cache and records the key under
+    /// each of `tags`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_with_tags(
+        &self,
+        key: &str,
+        value: &[u8],
+        tags: &[&str],
+        ttl: Option<Duration>,
     ) -> CacheResult<()> {
         self.cache.insert(
             key.to_string(),
             (
-                Expiration::AfterDuration(duration),
-                Arc::new(value).to_string(),
+                ttl.map_or(Expiration::Never, Expiration::AfterDuration),
+                value.to_vec(),
             ),
         );
+
+        let mut index = self.tags();
+        for tag in tags {
+            index.add(tag, key);
+        }
+        let limit = usize::try_from(self.cache.entry_count())
+            .unwrap_or(usize::MAX)
+            .saturating_mul(2)
+            .max(MIN_TAG_INDEX_PRUNE);
+        if index.len > limit {
+            index.retain(|key| self.cache.contains_key(key));
+        }
+        Ok(())
+    }
+
+    /// Inserts every key-value pair of `entries`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_many(
+        &self,
+        entries: &[(&str, &[u8])],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        let expiration = ttl.map_or(Expiration::Never, Expiration::AfterDuration);
+        for (key, value) in entries {
+            self.cache
+                .insert((*key).to_string(), (expiration, (*value).to_vec()));
+        }
         Ok(())
     }
 
+    /// Inserts a key-value pair into the cache unless the key already exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_if_absent(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> CacheResult<bool> {
+        let entry = self.cache.entry(key.to_string()).or_insert((
+            ttl.map_or(Expiration::Never, Expiration::AfterDuration),
+            value.to_vec(),
+        ));
+        Ok(entry.is_fresh())
+    }
+
+    /// Replaces the value of `key` with `new` if it is `current`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn compare_and_swap(&self, key: &str, current: &[u8], new: &[u8]) -> CacheResult<bool> {
+        let result = self
+            .cache
+            .entry(key.to_string())
+            .and_compute_with(|entry| match entry {
+                Some(entry) if entry.value().1 == current => {
+                    Op::Put((Expiration::Unchanged, new.to_vec()))
+                }
+                _ => Op::Nop,
+            });
+        Ok(matches!(result, CompResult::ReplacedWith(_)))
+    }
+
+    /// Removes `key` if its value is `current`, holding the key lock of the
+    /// cache in between.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn compare_and_remove(&self, key: &str, current: &[u8]) -> CacheResult<bool> {
+        let result = self
+            .cache
+            .entry(key.to_string())
+            .and_compute_with(|entry| match entry {
+                Some(entry) if entry.value().1 == current => Op::Remove,
+                _ => Op::Nop,
+            });
+        Ok(matches!(result, CompResult::Removed(_)))
+    }
+
+    /// Atomically adds `by` to the integer counter at `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if the value of `key` is not an integer.
+    async fn increment(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        self.add(key, by, ttl)
+    }
+
+    /// Atomically subtracts `by` from the integer counter at `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if the value of `key` is not an integer.
+    async fn decrement(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        let delta = by
+            .checked_neg()
+            .ok_or_else(|| CacheError::Any(format!("counter `{key}` overflowed").into()))?;
+        self.add(key, delta, ttl)
+    }
+
     /// Removes a key-value pair from the cache.
     ///
     /// # Errors
@@ -125,6 +389,64 @@ impl CacheDriver for Inmem {
         Ok(())
     }
 
+    /// Removes every key of `keys` from the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn remove_many(&self, keys: &[&str]) -> CacheResult<()> {
+        for key in keys {
+            self.cache.remove(*key);
+        }
+        Ok(())
+    }
+
+    /// Removes every key inserted with `tag`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn invalidate_tag(&self, tag: &str) -> CacheResult<()> {
+        let keys = self.tags().take(tag);
+        for key in keys {
+            self.cache.invalidate(&key);
+        }
+        Ok(())
+    }
+
+    /// Removes every key starting with `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn clear_prefix(&self, prefix: &str) -> CacheResult<()> {
+        let keys: Vec<Arc<String>> = self
+            .cache
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+        for key in keys {
+            self.cache.invalidate(key.as_str());
+        }
+        Ok(())
+    }
+
+    /// Lists the keys starting with `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn keys(&self, prefix: &str) -> CacheResult<Vec<String>> {
+        Ok(self
+            .cache
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .map(|key| key.to_string())
+            .collect())
+    }
+
     /// Clears all key-value pairs from the cache.
     ///
     /// # Errors
@@ -132,21 +454,34 @@ impl CacheDriver for Inmem {
     /// Returns a `CacheError` if there is an error during the operation.
     async fn clear(&self) -> CacheResult<()> {
         self.cache.invalidate_all();
+        self.tags().clear();
         Ok(())
     }
+
+    /// Returns the number of keys that expired or were evicted to stay
+    /// within `max_capacity`.
+    fn evictions(&self) -> HashMap<String, u64> {
+        self.evictions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Expiration {
     Never,
     AfterDuration(Duration),
+    /// Keeps the expiry of the entry it replaces, for updates in place such
+    /// as counters.
+    Unchanged,
 }
 
 impl Expiration {
     #[must_use]
     pub fn as_duration(&self) -> Option<Duration> {
         match self {
-            Self::Never => None,
+            Self::Never | Self::Unchanged => None,
             Self::AfterDuration(d) => Some(*d),
         }
     }
@@ -154,15 +489,28 @@ impl Expiration {
 
 pub struct InMemExpiry;
 
-impl Expiry<String, (Expiration, String)> for InMemExpiry {
+impl Expiry<String, (Expiration, Vec<u8>)> for InMemExpiry {
     fn expire_after_create(
         &self,
         _key: &String,
-        value: &(Expiration, String),
+        value: &(Expiration, Vec<u8>),
         _current_time: Instant,
     ) -> Option<Duration> {
         value.0.as_duration()
     }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        value: &(Expiration, Vec<u8>),
+        _updated_at: Instant,
+        duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        match value.0 {
+            Expiration::Unchanged => duration_until_expiry,
+            expiration => expiration.as_duration(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -214,6 +562,225 @@ mod tests {
         assert!(!mem.contains_key("key").await.unwrap());
     }
 
+    #[tokio::test]
+    async fn can_invalidate_tag() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        mem.insert_with_tags("user:1", "alice", &["user:1"], None)
+            .await
+            .unwrap();
+        mem.insert_with_tags("posts:1", "[]", &["user:1", "posts"], None)
+            .await
+            .unwrap();
+        mem.insert_with_tags("posts:2", "[]", &["posts"], None)
+            .await
+            .unwrap();
+        assert!(mem.insert("user:2", "bob").await.is_ok());
+
+        mem.invalidate_tag("user:1").await.unwrap();
+        assert!(!mem.contains_key("user:1").await.unwrap());
+        assert!(!mem.contains_key("posts:1").await.unwrap());
+        assert!(mem.contains_key("posts:2").await.unwrap());
+        assert!(mem.contains_key("user:2").await.unwrap());
+
+        mem.invalidate_tag("posts").await.unwrap();
+        assert!(!mem.contains_key("posts:2").await.unwrap());
+        assert!(mem.contains_key("user:2").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn can_clear_prefix() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        for key in ["users:1", "users:2", "posts:1"] {
+            assert!(mem.insert(key, "loco").await.is_ok());
+        }
+        mem.driver.clear_prefix("users:").await.unwrap();
+        assert!(!mem.contains_key("users:1").await.unwrap());
+        assert!(!mem.contains_key("users:2").await.unwrap());
+        assert!(mem.contains_key("posts:1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn can_list_keys() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        for key in ["users:1", "users:2", "posts:1"] {
+            assert!(mem.insert(key, "loco").await.is_ok());
+        }
+        let mut keys = mem.driver.keys("users:").await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["users:1", "users:2"]);
+        assert_eq!(mem.driver.keys("").await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn can_count_evictions() {
+        let mem = Inmem::build(&InMemCacheConfig { max_capacity: 1 });
+
+        mem.insert("users:1", b"1").await.unwrap();
+        mem.insert("users:2", b"2").await.unwrap();
+        mem.cache.run_pending_tasks();
+        assert_eq!(mem.evictions(), HashMap::from([("users".to_string(), 1)]));
+
+        // removed keys are not evicted
+        mem.clear().await.unwrap();
+        mem.cache.run_pending_tasks();
+        assert_eq!(mem.evictions(), HashMap::from([("users".to_string(), 1)]));
+    }
+
+    #[tokio::test]
+    async fn can_get_insert_and_remove_many() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        mem.driver
+            .insert_many(&[("a", b"1".as_slice()), ("b", b"2".as_slice())], None)
+            .await
+            .unwrap();
+        assert_eq!(
+            mem.driver.get_many(&["a", "missing", "b"]).await.unwrap(),
+            vec![Some(b"1".to_vec()), None, Some(b"2".to_vec())]
+        );
+
+        mem.driver.remove_many(&["a", "missing"]).await.unwrap();
+        assert!(!mem.contains_key("a").await.unwrap());
+        assert!(mem.contains_key("b").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn can_insert_many_with_expiry() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        mem.driver
+            .insert_many(
+                &[("a", b"1".as_slice()), ("b", b"2".as_slice())],
+                Some(Duration::from_millis(100)),
+            )
+            .await
+            .unwrap();
+        assert!(mem.contains_key("a").await.unwrap());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            mem.driver.get_many(&["a", "b"]).await.unwrap(),
+            vec![None, None]
+        );
+    }
+
+    #[tokio::test]
+    async fn can_replace_expiry() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        mem.insert_with_expiry("session", "old", Duration::from_secs(60))
+            .await
+            .unwrap();
+        mem.insert_with_expiry("session", "new", Duration::from_millis(100))
+            .await
+            .unwrap();
+        mem.insert_with_expiry("token", "old", Duration::from_millis(100))
+            .await
+            .unwrap();
+        mem.insert("token", "new").await.unwrap();
+        mem.insert_with_expiry("state", "new", Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert!(mem.compare_and_swap("state", "new", "done").await.unwrap());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!mem.contains_key("session").await.unwrap());
+        assert_eq!(
+            mem.get::<String>("token").await.unwrap(),
+            Some("new".to_string())
+        );
+        // swapping a value keeps its expiry
+        assert!(!mem.contains_key("state").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn can_increment_and_decrement() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        assert_eq!(mem.increment("hits", 1, None).await.unwrap(), 1);
+        assert_eq!(mem.increment("hits", 5, None).await.unwrap(), 6);
+        assert_eq!(mem.decrement("hits", 2, None).await.unwrap(), 4);
+        assert_eq!(mem.get::<i64>("hits").await.unwrap(), Some(4));
+        assert_eq!(mem.decrement("balance", 3, None).await.unwrap(), -3);
+
+        assert!(mem.insert("name", "loco").await.is_ok());
+        assert!(mem.increment("name", 1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn can_expire_counter() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        let ttl = Some(Duration::from_millis(300));
+        assert_eq!(mem.increment("requests", 1, ttl).await.unwrap(), 1);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        // the counter keeps the expiry it was created with
+        assert_eq!(mem.increment("requests", 1, ttl).await.unwrap(), 2);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(mem.increment("requests", 1, ttl).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn can_count_concurrently() {
+        let config = create_test_config();
+        let mem = Arc::new(new(&config));
+
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let mem = mem.clone();
+                tokio::spawn(async move { mem.increment("hits", 1, None).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(mem.get::<i64>("hits").await.unwrap(), Some(50));
+    }
+
+    #[tokio::test]
+    async fn can_insert_if_absent() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        assert!(mem.insert_if_absent("key", "first", None).await.unwrap());
+        assert!(!mem.insert_if_absent("key", "second", None).await.unwrap());
+        assert_eq!(
+            mem.get::<String>("key").await.unwrap(),
+            Some("first".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn can_compare_and_swap() {
+        let config = create_test_config();
+        let mem = new(&config);
+
+        assert!(!mem
+            .compare_and_swap("state", "new", "running")
+            .await
+            .unwrap());
+        assert!(mem.insert("state", "new").await.is_ok());
+        assert!(mem
+            .compare_and_swap("state", "new", "running")
+            .await
+            .unwrap());
+        assert!(!mem.compare_and_swap("state", "new", "done").await.unwrap());
+        assert_eq!(
+            mem.get::<String>("state").await.unwrap(),
+            Some("running".to_string())
+        );
+    }
+
     #[tokio::test]
     async fn can_clear() {
         let config = create_test_config();
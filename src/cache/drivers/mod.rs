@@ -1,17 +1,22 @@
 //! # Cache Drivers Module
 //!
 //! This module defines traits and implementations for cache drivers.
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 use async_trait::async_trait;
 
-use super::CacheResult;
+use super::{
+    stats::{CacheStats, PrefixStats},
+    CacheResult,
+};
 
 #[cfg(feature = "cache_inmem")]
 pub mod inmem;
 pub mod null;
 #[cfg(feature = "cache_redis")]
 pub mod redis;
+#[cfg(all(feature = "cache_inmem", feature = "cache_redis"))]
+pub mod tiered;
 
 /// Trait representing a cache driver.
 #[async_trait]
@@ -38,7 +43,16 @@ pub trait CacheDriver: Sync + Send {
     ///
     /// Returns a [`super::CacheError`] if there is an error during the
     /// operation.
-    async fn get(&self, key: &str) -> CacheResult<Option<String>>;
+    async fn get(&self, key: &str) -> CacheResult<Option<Vec<u8>>>;
+
+    /// Retrieves the values of `keys`, in the same order, with `None` for
+    /// the keys that are not in the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn get_many(&self, keys: &[&str]) -> CacheResult<Vec<Option<Vec<u8>>>>;
 
     /// Inserts a key-value pair into the cache.
     ///
@@ -46,7 +60,7 @@ pub trait CacheDriver: Sync + Send {
     ///
     /// Returns a [`super::CacheError`] if there is an error during the
     /// operation.
-    async fn insert(&self, key: &str, value: &str) -> CacheResult<()>;
+    async fn insert(&self, key: &str, value: &[u8]) -> CacheResult<()>;
 
     /// Inserts a key-value pair into the cache that expires after the
     /// specified duration.
@@ -58,10 +72,93 @@ pub trait CacheDriver: Sync + Send {
     async fn insert_with_expiry(
         &self,
         key: &str,
-        value: &str,
+        value: &[u8],
         duration: Duration,
     ) -> CacheResult<()>;
 
+    /// Inserts a key-value pair into the cache and records the key under
+    /// each of `tags`, to be removed by [`CacheDriver::invalidate_tag`]. The
+    /// key expires after `ttl` when set.
+    ///
+    /// Tags are only added to a key: inserting it again with other tags, or
+    /// without tags, keeps it under the tags it had.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn insert_with_tags(
+        &self,
+        key: &str,
+        value: &[u8],
+        tags: &[&str],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()>;
+
+    /// Inserts every key-value pair of `entries`, expiring after `ttl` when
+    /// set.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn insert_many(
+        &self,
+        entries: &[(&str, &[u8])],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()>;
+
+    /// Inserts a key-value pair into the cache unless the key already exists,
+    /// expiring after `ttl` when set. Returns whether the value was inserted.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn insert_if_absent(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> CacheResult<bool>;
+
+    /// Replaces the value of `key` with `new` if it is `current`, keeping the
+    /// expiry of the key. Returns whether the value was replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn compare_and_swap(&self, key: &str, current: &[u8], new: &[u8]) -> CacheResult<bool>;
+
+    /// Removes `key` if its value is `current`. Returns whether the key was
+    /// removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn compare_and_remove(&self, key: &str, current: &[u8]) -> CacheResult<bool>;
+
+    /// Atomically adds `by` to the integer counter at `key`, and returns the
+    /// new value. A missing counter starts from `0` and, when `ttl` is set,
+    /// expires `ttl` after this call.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation, or if the value of `key` is not an integer.
+    async fn increment(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64>;
+
+    /// Atomically subtracts `by` from the integer counter at `key`, like
+    /// [`CacheDriver::increment`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation, or if the value of `key` is not an integer.
+    async fn decrement(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64>;
+
     /// Removes a key-value pair from the cache.
     ///
     /// # Errors
@@ -70,6 +167,57 @@ pub trait CacheDriver: Sync + Send {
     /// operation.
     async fn remove(&self, key: &str) -> CacheResult<()>;
 
+    /// Removes every key of `keys` from the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn remove_many(&self, keys: &[&str]) -> CacheResult<()>;
+
+    /// Removes every key inserted with `tag` since it was last invalidated.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn invalidate_tag(&self, tag: &str) -> CacheResult<()>;
+
+    /// Removes every key starting with `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn clear_prefix(&self, prefix: &str) -> CacheResult<()>;
+
+    /// Lists the keys starting with `prefix`, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn keys(&self, prefix: &str) -> CacheResult<Vec<String>>;
+
+    /// Calls `f` with each key starting with `prefix`, in no particular
+    /// order. Drivers that can walk their keys without listing them all at
+    /// once do so.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn for_each_key(
+        &self,
+        prefix: &str,
+        f: &mut (dyn FnMut(String) + Send),
+    ) -> CacheResult<()> {
+        for key in self.keys(prefix).await? {
+            f(key);
+        }
+        Ok(())
+    }
+
     /// Clears all key-value pairs from the cache.
     ///
     /// # Errors
@@ -77,4 +225,40 @@ pub trait CacheDriver: Sync + Send {
     /// Returns a [`super::CacheError`] if there is an error during the
     /// operation.
     async fn clear(&self) -> CacheResult<()>;
+
+    /// Returns the number of keys the driver removed by itself, because
+    /// they expired or to make room for others, by
+    /// [prefix](super::stats::prefix).
+    ///
+    /// Drivers that are not told about evictions return an empty map.
+    fn evictions(&self) -> HashMap<String, u64> {
+        HashMap::new()
+    }
+
+    /// Adds the counters of an instance of the application, by
+    /// [prefix](super::stats::prefix), to the ones the driver keeps for all
+    /// instances.
+    ///
+    /// Drivers living in the application process keep no such counters and
+    /// ignore them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn save_stats(&self, stats: &HashMap<String, PrefixStats>) -> CacheResult<()> {
+        let _ = stats;
+        Ok(())
+    }
+
+    /// Returns the counters added by [`CacheDriver::save_stats`], or `None`
+    /// when the driver keeps none.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`super::CacheError`] if there is an error during the
+    /// operation.
+    async fn saved_stats(&self) -> CacheResult<Option<CacheStats>> {
+        Ok(None)
+    }
 }
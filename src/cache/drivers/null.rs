@@ -54,16 +54,25 @@ impl CacheDriver for Null {
     /// # Errors
     ///
     /// Returns always error
-    async fn get(&self, _key: &str) -> CacheResult<Option<String>> {
+    async fn get(&self, _key: &str) -> CacheResult<Option<Vec<u8>>> {
         Ok(None)
     }
 
+    /// Returns `None` for every key, since the null cache never holds any.
+    ///
+    /// # Errors
+    ///
+    /// Never errors.
+    async fn get_many(&self, keys: &[&str]) -> CacheResult<Vec<Option<Vec<u8>>>> {
+        Ok(vec![None; keys.len()])
+    }
+
     /// Inserts a key-value pair into the cache.
     ///
     /// # Errors
     ///
     /// Returns always error
-    async fn insert(&self, _key: &str, _value: &str) -> CacheResult<()> {
+    async fn insert(&self, _key: &str, _value: &[u8]) -> CacheResult<()> {
         Err(CacheError::Any(
             "Operation not supported by null cache".into(),
         ))
@@ -78,7 +87,7 @@ impl CacheDriver for Null {
     async fn insert_with_expiry(
         &self,
         _key: &str,
-        _value: &str,
+        _value: &[u8],
         _duration: Duration,
     ) -> CacheResult<()> {
         Err(CacheError::Any(
@@ -86,6 +95,94 @@ impl CacheDriver for Null {
         ))
     }
 
+    /// Inserts a key-value pair into the cache under the provided tags.
+    ///
+    /// # Errors
+    ///
+    /// Returns always error
+    async fn insert_with_tags(
+        &self,
+        _key: &str,
+        _value: &[u8],
+        _tags: &[&str],
+        _ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        Err(CacheError::Any(
+            "Operation not supported by null cache".into(),
+        ))
+    }
+
+    /// Inserts every key-value pair of `entries`.
+    ///
+    /// # Errors
+    ///
+    /// Returns always error
+    async fn insert_many(
+        &self,
+        _entries: &[(&str, &[u8])],
+        _ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        Err(CacheError::Any(
+            "Operation not supported by null cache".into(),
+        ))
+    }
+
+    /// Reports the key as inserted, since the null cache never holds it.
+    ///
+    /// # Errors
+    ///
+    /// Never errors.
+    async fn insert_if_absent(
+        &self,
+        _key: &str,
+        _value: &[u8],
+        _ttl: Option<Duration>,
+    ) -> CacheResult<bool> {
+        Ok(true)
+    }
+
+    /// Never swaps, since the null cache never holds `current`.
+    ///
+    /// # Errors
+    ///
+    /// Never errors.
+    async fn compare_and_swap(
+        &self,
+        _key: &str,
+        _current: &[u8],
+        _new: &[u8],
+    ) -> CacheResult<bool> {
+        Ok(false)
+    }
+
+    /// Never removes, since the null cache never holds `current`.
+    ///
+    /// # Errors
+    ///
+    /// Never errors.
+    async fn compare_and_remove(&self, _key: &str, _current: &[u8]) -> CacheResult<bool> {
+        Ok(false)
+    }
+
+    /// Returns `by`, as if every counter was created by this call.
+    ///
+    /// # Errors
+    ///
+    /// Never errors.
+    async fn increment(&self, _key: &str, by: i64, _ttl: Option<Duration>) -> CacheResult<i64> {
+        Ok(by)
+    }
+
+    /// Returns `-by`, as if every counter was created by this call.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `by` is `i64::MIN`.
+    async fn decrement(&self, key: &str, by: i64, _ttl: Option<Duration>) -> CacheResult<i64> {
+        by.checked_neg()
+            .ok_or_else(|| CacheError::Any(format!("counter `{key}` overflowed").into()))
+    }
+
     /// Removes a key-value pair from the cache.
     ///
     /// # Errors
@@ -97,6 +194,50 @@ impl CacheDriver for Null {
         ))
     }
 
+    /// Removes every key of `keys` from the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns always error
+    async fn remove_many(&self, _keys: &[&str]) -> CacheResult<()> {
+        Err(CacheError::Any(
+            "Operation not supported by null cache".into(),
+        ))
+    }
+
+    /// Removes every key inserted with the provided tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns always error
+    async fn invalidate_tag(&self, _tag: &str) -> CacheResult<()> {
+        Err(CacheError::Any(
+            "Operation not supported by null cache".into(),
+        ))
+    }
+
+    /// Removes every key starting with the provided prefix.
+    ///
+    /// # Errors
+    ///
+    /// Returns always error
+    async fn clear_prefix(&self, _prefix: &str) -> CacheResult<()> {
+        Err(CacheError::Any(
+            "Operation not supported by null cache".into(),
+        ))
+    }
+
+    /// Lists the keys starting with the provided prefix.
+    ///
+    /// # Errors
+    ///
+    /// Returns always error
+    async fn keys(&self, _prefix: &str) -> CacheResult<Vec<String>> {
+        Err(CacheError::Any(
+            "Operation not supported by null cache".into(),
+        ))
+    }
+
     /// Clears all key-value pairs from the cache.
     ///
     /// # Errors
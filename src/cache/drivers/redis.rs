@@ -1,20 +1,103 @@
 //! # Redis Cache Driver
 //!
 //! This module implements a cache driver using Redis.
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 use async_trait::async_trait;
 use bb8::Pool;
 use bb8_redis::{
     bb8,
-    redis::{cmd, AsyncCommands},
+    redis::{cmd, pipe, AsyncCommands, AsyncIter, ExistenceCheck, Script, SetExpiry, SetOptions},
     RedisConnectionManager,
 };
 
 use super::CacheDriver;
-use crate::cache::{CacheError, CacheResult};
+use crate::cache::{
+    stats::{CacheStats, PrefixStats},
+    CacheError, CacheResult, Codec,
+};
 use crate::config::RedisCacheConfig;
 
+/// Prefix of the sets holding the keys inserted with each tag.
+const TAG_KEY_PREFIX: &str = "loco:cache:tag:";
+
+/// Prefix of the sets holding the tags of each tagged key, used to take the
+/// key out of the sets of its tags when it is removed.
+const KEY_TAGS_PREFIX: &str = "loco:cache:key_tags:";
+
+/// Number of random keys of a tag's set checked on each insert with the
+/// tag, to drop those that expired.
+const TAG_PRUNE_SAMPLE_SIZE: usize = 20;
+
+/// Hash holding the counters saved by every instance of the application, in
+/// `{prefix}:{counter}` fields.
+const STATS_KEY: &str = "loco:cache:stats";
+
+/// Number of keys removed by each `DEL` when removing many keys.
+const DEL_BATCH_SIZE: usize = 1000;
+
+const INCREMENT_SCRIPT: &str = r"
+local created = redis.call('EXISTS', KEYS[1]) == 0
+local value = redis.call('INCRBY', KEYS[1], ARGV[1])
+if created and tonumber(ARGV[2]) > 0 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return value
+";
+
+const COMPARE_AND_SWAP_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'KEEPTTL')
+    return 1
+end
+return 0
+";
+
+const COMPARE_AND_REMOVE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/// Removes the keys that no longer exist from a random sample of the set of
+/// a tag. The keys expire one by one, but the set holding them can't.
+const PRUNE_TAG_SCRIPT: &str = r"
+local removed = 0
+for _, key in ipairs(redis.call('SRANDMEMBER', KEYS[1], ARGV[1])) do
+    if redis.call('EXISTS', key) == 0 then
+        removed = removed + redis.call('SREM', KEYS[1], key)
+    end
+end
+return removed
+";
+
+/// Converts `ttl` to the milliseconds expected by Redis, `0` meaning no
+/// expiry.
+fn ttl_millis(ttl: Option<Duration>) -> u64 {
+    ttl.map_or(0, |ttl| {
+        u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1)
+    })
+}
+
+fn key_tags_key(key: &str) -> String {
+    format!("{KEY_TAGS_PREFIX}{key}")
+}
+
+/// Escapes the glob characters of `prefix` for a `SCAN` pattern matching the
+/// keys that start with it.
+fn prefix_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
+}
+
 /// Creates a new instance of the Redis cache driver with a default configuration.
 ///
 /// # Returns
@@ -25,13 +108,9 @@ use crate::config::RedisCacheConfig;
 ///
 /// Returns a `CacheError` if there is an error connecting to Redis.
 pub async fn new(config: &RedisCacheConfig) -> CacheResult<crate::cache::Cache> {
-    let manager = RedisConnectionManager::new(config.uri.clone())?;
-    let pool = Pool::builder()
-        .max_size(config.max_size)
-        .build(manager)
-        .await?;
-
-    Ok(crate::cache::Cache::new(Redis::from(pool)))
+    let codec = Codec::new(&config.codec)?;
+    let redis = Redis::connect(&config.uri, config.max_size).await?;
+    Ok(crate::cache::Cache::new(Box::new(redis)).with_codec(codec))
 }
 
 /// Represents the Redis cache driver.
@@ -50,6 +129,80 @@ impl Redis {
     pub fn from(pool: Pool<RedisConnectionManager>) -> Box<dyn CacheDriver> {
         Box::new(Self { pool })
     }
+
+    pub(super) async fn connect(uri: &str, max_size: u32) -> CacheResult<Self> {
+        let manager = RedisConnectionManager::new(uri)?;
+        let pool = Pool::builder().max_size(max_size).build(manager).await?;
+        Ok(Self { pool })
+    }
+
+    /// Removes every key in the set of `tag`, and returns them. The set is
+    /// emptied with `SPOP` in batches, so keys tagged meanwhile are removed
+    /// as well.
+    pub(super) async fn take_tag(&self, tag: &str) -> CacheResult<Vec<String>> {
+        let tag_key = format!("{TAG_KEY_PREFIX}{tag}");
+        let mut taken = Vec::new();
+        loop {
+            let keys: Vec<String> = {
+                let mut conn = self.pool.get().await?;
+                cmd("SPOP")
+                    .arg(&tag_key)
+                    .arg(DEL_BATCH_SIZE)
+                    .query_async(&mut *conn)
+                    .await?
+            };
+            if keys.is_empty() {
+                return Ok(taken);
+            }
+            self.remove_keys(&keys.iter().map(String::as_str).collect::<Vec<_>>())
+                .await?;
+            taken.extend(keys);
+        }
+    }
+
+    /// Removes `keys` in batches, taking each of them out of the sets of its
+    /// tags so those don't keep growing with keys that are gone.
+    async fn remove_keys(&self, keys: &[&str]) -> CacheResult<()> {
+        let mut conn = self.pool.get().await?;
+        for batch in keys.chunks(DEL_BATCH_SIZE) {
+            let mut lookup = pipe();
+            for key in batch {
+                lookup.smembers(key_tags_key(key));
+            }
+            let tags: Vec<Vec<String>> = lookup.query_async(&mut *conn).await?;
+
+            let mut pipe = pipe();
+            pipe.atomic().del(batch).ignore();
+            for (key, tags) in batch.iter().zip(tags) {
+                if tags.is_empty() {
+                    continue;
+                }
+                pipe.del(key_tags_key(key)).ignore();
+                for tag in tags {
+                    pipe.srem(format!("{TAG_KEY_PREFIX}{tag}"), key).ignore();
+                }
+            }
+            pipe.query_async::<()>(&mut *conn).await?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "cache_inmem")]
+    pub(super) async fn publish(&self, channel: &str, message: &str) -> CacheResult<()> {
+        let mut conn = self.pool.get().await?;
+        conn.publish::<_, _, ()>(channel, message).await?;
+        Ok(())
+    }
+
+    async fn add(&self, key: &str, delta: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        let mut conn = self.pool.get().await?;
+        Ok(Script::new(INCREMENT_SCRIPT)
+            .key(key)
+            .arg(delta)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut *conn)
+            .await?)
+    }
 }
 
 #[async_trait]
@@ -82,18 +235,31 @@ impl CacheDriver for Redis {
     /// # Errors
     ///
     /// Returns a `CacheError` if there is an error during the operation.
-    async fn get(&self, key: &str) -> CacheResult<Option<String>> {
+    async fn get(&self, key: &str) -> CacheResult<Option<Vec<u8>>> {
         let mut conn = self.pool.get().await?;
-        let result: Option<String> = conn.get(key).await?;
+        let result: Option<Vec<u8>> = conn.get(key).await?;
         Ok(result)
     }
 
+    /// Retrieves the values of `keys` with a single `MGET`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn get_many(&self, keys: &[&str]) -> CacheResult<Vec<Option<Vec<u8>>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.pool.get().await?;
+        Ok(cmd("MGET").arg(keys).query_async(&mut *conn).await?)
+    }
+
     /// Inserts a key-value pair into the cache.
     ///
     /// # Errors
     ///
     /// Returns a `CacheError` if there is an error during the operation.
-    async fn insert(&self, key: &str, value: &str) -> CacheResult<()> {
+    async fn insert(&self, key: &str, value: &[u8]) -> CacheResult<()> {
         let mut conn = self.pool.get().await?;
         conn.set::<_, _, ()>(key, value).await?;
         Ok(())
@@ -109,7 +275,7 @@ impl CacheDriver for Redis {
     async fn insert_with_expiry(
         &self,
         key: &str,
-        value: &str,
+        value: &[u8],
         duration: Duration,
     ) -> CacheResult<()> {
         let mut conn = self.pool.get().await?;
@@ -119,17 +285,282 @@ impl CacheDriver for Redis {
         Ok(())
     }
 
-    /// Removes a key-value pair from the cache.
+    /// Inserts a key-value pair into the cache and adds the key to the set
+    /// of each of `tags`, and the tags to the set of the key, which expires
+    /// along with the key. Keys that expired are then dropped from a sample
+    /// of the set of each tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_with_tags(
+        &self,
+        key: &str,
+        value: &[u8],
+        tags: &[&str],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        let mut conn = self.pool.get().await?;
+        let key_tags = key_tags_key(key);
+        let tag_keys = tags
+            .iter()
+            .map(|tag| format!("{TAG_KEY_PREFIX}{tag}"))
+            .collect::<Vec<_>>();
+        let mut pipe = pipe();
+        pipe.atomic();
+        if ttl.is_some() {
+            pipe.pset_ex(key, value, ttl_millis(ttl)).ignore();
+        } else {
+            pipe.set(key, value).ignore();
+        }
+        for (tag, tag_key) in tags.iter().zip(&tag_keys) {
+            pipe.sadd(tag_key, key)
+                .ignore()
+                .sadd(&key_tags, tag)
+                .ignore();
+        }
+        if ttl.is_some() {
+            pipe.cmd("PEXPIRE")
+                .arg(&key_tags)
+                .arg(ttl_millis(ttl))
+                .ignore();
+        } else {
+            pipe.persist(&key_tags).ignore();
+        }
+        pipe.query_async::<()>(&mut *conn).await?;
+
+        for tag_key in &tag_keys {
+            Script::new(PRUNE_TAG_SCRIPT)
+                .key(tag_key)
+                .arg(TAG_PRUNE_SAMPLE_SIZE)
+                .invoke_async::<i64>(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Inserts every key-value pair of `entries` with `SET` commands sent in
+    /// a single pipeline.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_many(
+        &self,
+        entries: &[(&str, &[u8])],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.pool.get().await?;
+        let mut pipe = pipe();
+        for (key, value) in entries {
+            if ttl.is_some() {
+                pipe.pset_ex(key, value, ttl_millis(ttl)).ignore();
+            } else {
+                pipe.set(key, value).ignore();
+            }
+        }
+        pipe.query_async::<()>(&mut *conn).await?;
+        Ok(())
+    }
+
+    /// Inserts a key-value pair into the cache with `SET NX`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_if_absent(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> CacheResult<bool> {
+        let mut conn = self.pool.get().await?;
+        let mut opts = SetOptions::default().conditional_set(ExistenceCheck::NX);
+        if ttl.is_some() {
+            opts = opts.with_expiration(SetExpiry::PX(ttl_millis(ttl)));
+        }
+        let inserted: Option<String> = conn.set_options(key, value, opts).await?;
+        Ok(inserted.is_some())
+    }
+
+    /// Replaces the value of `key` with `new` if it is `current`, within a
+    /// script so no other client writes the key in between.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn compare_and_swap(&self, key: &str, current: &[u8], new: &[u8]) -> CacheResult<bool> {
+        let mut conn = self.pool.get().await?;
+        let swapped: i32 = Script::new(COMPARE_AND_SWAP_SCRIPT)
+            .key(key)
+            .arg(current)
+            .arg(new)
+            .invoke_async(&mut *conn)
+            .await?;
+        Ok(swapped == 1)
+    }
+
+    /// Removes `key` if its value is `current`, within a script so no other
+    /// client writes the key in between.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn compare_and_remove(&self, key: &str, current: &[u8]) -> CacheResult<bool> {
+        let mut conn = self.pool.get().await?;
+        let removed: i32 = Script::new(COMPARE_AND_REMOVE_SCRIPT)
+            .key(key)
+            .arg(current)
+            .invoke_async(&mut *conn)
+            .await?;
+        Ok(removed == 1)
+    }
+
+    /// Atomically adds `by` to the integer counter at `key` with `INCRBY`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if the value of `key` is not an integer.
+    async fn increment(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        self.add(key, by, ttl).await
+    }
+
+    /// Atomically subtracts `by` from the integer counter at `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if the value of `key` is not an integer.
+    async fn decrement(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        let delta = by
+            .checked_neg()
+            .ok_or_else(|| CacheError::Any(format!("counter `{key}` overflowed").into()))?;
+        self.add(key, delta, ttl).await
+    }
+
+    /// Removes a key-value pair from the cache, and the key from the sets of
+    /// its tags.
     ///
     /// # Errors
     ///
     /// Returns a `CacheError` if there is an error during the operation.
     async fn remove(&self, key: &str) -> CacheResult<()> {
+        self.remove_keys(&[key]).await
+    }
+
+    /// Removes every key of `keys` with `DEL`, and the keys from the sets of
+    /// their tags.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn remove_many(&self, keys: &[&str]) -> CacheResult<()> {
+        self.remove_keys(keys).await
+    }
+
+    /// Removes every key in the set of `tag`, and the set itself.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn invalidate_tag(&self, tag: &str) -> CacheResult<()> {
+        self.take_tag(tag).await?;
+        Ok(())
+    }
+
+    /// Removes every key starting with `prefix`, found with `SCAN`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn clear_prefix(&self, prefix: &str) -> CacheResult<()> {
+        let keys = self.keys(prefix).await?;
+        self.remove_keys(&keys.iter().map(String::as_str).collect::<Vec<_>>())
+            .await
+    }
+
+    /// Lists the keys starting with `prefix`, found with `SCAN`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn keys(&self, prefix: &str) -> CacheResult<Vec<String>> {
+        let mut keys = Vec::new();
+        self.for_each_key(prefix, &mut |key| keys.push(key)).await?;
+        Ok(keys)
+    }
+
+    /// Calls `f` with each key starting with `prefix` as `SCAN` returns
+    /// them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn for_each_key(
+        &self,
+        prefix: &str,
+        f: &mut (dyn FnMut(String) + Send),
+    ) -> CacheResult<()> {
+        let mut conn = self.pool.get().await?;
+        let mut iter: AsyncIter<'_, String> = conn.scan_match(prefix_pattern(prefix)).await?;
+        while let Some(key) = iter.next_item().await {
+            f(key);
+        }
+        Ok(())
+    }
+
+    /// Adds the counters to the [`STATS_KEY`] hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn save_stats(&self, stats: &HashMap<String, PrefixStats>) -> CacheResult<()> {
+        let mut pipe = pipe();
+        for (prefix, stats) in stats {
+            for (counter, count) in [
+                ("hits", stats.hits),
+                ("misses", stats.misses),
+                ("inserts", stats.inserts),
+                ("evictions", stats.evictions),
+            ] {
+                if count > 0 {
+                    pipe.hincr(STATS_KEY, format!("{prefix}:{counter}"), count)
+                        .ignore();
+                }
+            }
+        }
         let mut conn = self.pool.get().await?;
-        conn.del::<_, ()>(key).await?;
+        pipe.query_async::<()>(&mut *conn).await?;
         Ok(())
     }
 
+    /// Returns the counters of the [`STATS_KEY`] hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn saved_stats(&self) -> CacheResult<Option<CacheStats>> {
+        let mut conn = self.pool.get().await?;
+        let fields: HashMap<String, u64> = conn.hgetall(STATS_KEY).await?;
+        let mut stats = CacheStats::default();
+        for (field, count) in fields {
+            let Some((prefix, counter)) = field.rsplit_once(':') else {
+                continue;
+            };
+            let prefix_stats = stats.prefixes.entry(prefix.to_string()).or_default();
+            match counter {
+                "hits" => prefix_stats.hits = count,
+                "misses" => prefix_stats.misses = count,
+                "inserts" => prefix_stats.inserts = count,
+                "evictions" => prefix_stats.evictions = count,
+                _ => {}
+            }
+        }
+        Ok(Some(stats))
+    }
+
     /// Clears all key-value pairs from the cache.
     ///
     /// # Errors
@@ -156,6 +587,7 @@ mod tests {
         let redis_config = crate::config::RedisCacheConfig {
             uri: redis_url,
             max_size: 10,
+            codec: crate::config::CacheCodecConfig::default(),
         };
 
         let cache = new(&redis_config)
@@ -185,7 +617,7 @@ mod tests {
             .expect("Failed to check if key exists"));
 
         redis
-            .insert("test_key", "test_value")
+            .insert("test_key", b"test_value")
             .await
             .expect("Failed to insert key");
 
@@ -200,7 +632,7 @@ mod tests {
         let (redis, _container) = setup_redis_driver().await;
 
         redis
-            .insert("test_key", "test_value")
+            .insert("test_key", b"test_value")
             .await
             .expect("Failed to insert key");
 
@@ -209,7 +641,7 @@ mod tests {
                 .get("test_key")
                 .await
                 .expect("Failed to get value for key"),
-            Some("test_value".to_string())
+            Some(b"test_value".to_vec())
         );
 
         assert_eq!(
@@ -226,7 +658,7 @@ mod tests {
         let (redis, _container) = setup_redis_driver().await;
 
         redis
-            .insert("test_key", "test_value")
+            .insert("test_key", b"test_value")
             .await
             .expect("Failed to insert key");
 
@@ -253,7 +685,7 @@ mod tests {
         let keys = vec!["key1", "key2", "key3"];
         for key in &keys {
             redis
-                .insert(key, "test_value")
+                .insert(key, b"test_value")
                 .await
                 .expect("Failed to insert key");
         }
@@ -275,12 +707,374 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_invalidate_tag() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        redis
+            .insert_with_tags("user:1", b"alice", &["user:1"], None)
+            .await
+            .expect("Failed to insert key with tags");
+        redis
+            .insert_with_tags("posts:1", b"[]", &["user:1", "posts"], None)
+            .await
+            .expect("Failed to insert key with tags");
+        redis
+            .insert("user:2", b"bob")
+            .await
+            .expect("Failed to insert key");
+
+        redis
+            .invalidate_tag("user:1")
+            .await
+            .expect("Failed to invalidate tag");
+
+        assert!(!redis
+            .contains_key("user:1")
+            .await
+            .expect("Failed to check if key exists"));
+        assert!(!redis
+            .contains_key("posts:1")
+            .await
+            .expect("Failed to check if key exists"));
+        assert!(redis
+            .contains_key("user:2")
+            .await
+            .expect("Failed to check if key exists"));
+
+        // removed keys leave the sets of their tags
+        redis
+            .insert_with_tags("posts:2", b"[]", &["posts"], None)
+            .await
+            .expect("Failed to insert key with tags");
+        redis.remove("posts:2").await.expect("Failed to remove key");
+        assert!(redis
+            .keys("loco:cache:")
+            .await
+            .expect("Failed to list keys")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expire_tagged_keys() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        redis
+            .insert_with_tags(
+                "session:1",
+                b"alice",
+                &["user:1"],
+                Some(Duration::from_millis(100)),
+            )
+            .await
+            .expect("Failed to insert key with tags");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!redis
+            .contains_key("session:1")
+            .await
+            .expect("Failed to check if key exists"));
+        // the tags of the key expire along with it
+        assert!(redis
+            .keys(KEY_TAGS_PREFIX)
+            .await
+            .expect("Failed to list keys")
+            .is_empty());
+
+        // inserting under the tag drops the expired key from its set
+        redis
+            .insert_with_tags("profile:1", b"alice", &["user:1"], None)
+            .await
+            .expect("Failed to insert key with tags");
+        redis
+            .remove("profile:1")
+            .await
+            .expect("Failed to remove key");
+        assert!(redis
+            .keys("loco:cache:")
+            .await
+            .expect("Failed to list keys")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keys() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        for key in ["users:1", "users:2", "users*", "posts:1"] {
+            redis
+                .insert(key, b"test_value")
+                .await
+                .expect("Failed to insert key");
+        }
+
+        let mut keys = redis.keys("users:").await.expect("Failed to list keys");
+        keys.sort();
+        assert_eq!(keys, vec!["users:1", "users:2"]);
+        assert_eq!(redis.keys("").await.expect("Failed to list keys").len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_save_stats() {
+        let (redis, _container) = setup_redis_driver().await;
+        assert_eq!(
+            redis.saved_stats().await.expect("Failed to read stats"),
+            Some(CacheStats::default())
+        );
+
+        // two instances saving their counters
+        for hits in [1, 2] {
+            let stats = HashMap::from([
+                (
+                    "users".to_string(),
+                    PrefixStats {
+                        hits,
+                        misses: 1,
+                        ..PrefixStats::default()
+                    },
+                ),
+                (
+                    String::new(),
+                    PrefixStats {
+                        inserts: 1,
+                        ..PrefixStats::default()
+                    },
+                ),
+            ]);
+            redis
+                .save_stats(&stats)
+                .await
+                .expect("Failed to save stats");
+        }
+
+        let stats = redis
+            .saved_stats()
+            .await
+            .expect("Failed to read stats")
+            .expect("Redis keeps stats");
+        assert_eq!(
+            stats.prefixes["users"],
+            PrefixStats {
+                hits: 3,
+                misses: 2,
+                ..PrefixStats::default()
+            }
+        );
+        assert_eq!(stats.prefixes[""].inserts, 2);
+        assert_eq!(stats.prefixes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_clear_prefix() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        for key in ["users:1", "users:2", "users*", "posts:1"] {
+            redis
+                .insert(key, b"test_value")
+                .await
+                .expect("Failed to insert key");
+        }
+
+        redis
+            .clear_prefix("users:")
+            .await
+            .expect("Failed to clear prefix");
+
+        for (key, exists) in [
+            ("users:1", false),
+            ("users:2", false),
+            ("users*", true),
+            ("posts:1", true),
+        ] {
+            assert_eq!(
+                redis
+                    .contains_key(key)
+                    .await
+                    .expect("Failed to check if key exists"),
+                exists
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_increment_and_decrement() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        assert_eq!(
+            redis
+                .increment("hits", 1, None)
+                .await
+                .expect("Failed to increment"),
+            1
+        );
+        assert_eq!(
+            redis
+                .increment("hits", 5, Some(Duration::from_secs(60)))
+                .await
+                .expect("Failed to increment"),
+            6
+        );
+        assert_eq!(
+            redis
+                .decrement("hits", 2, None)
+                .await
+                .expect("Failed to decrement"),
+            4
+        );
+
+        redis
+            .insert("name", b"loco")
+            .await
+            .expect("Failed to insert key");
+        assert!(redis.increment("name", 1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expire_counter() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        let ttl = Some(Duration::from_secs(1));
+        assert_eq!(
+            redis
+                .increment("requests", 1, ttl)
+                .await
+                .expect("Failed to increment"),
+            1
+        );
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(
+            redis
+                .increment("requests", 1, ttl)
+                .await
+                .expect("Failed to increment"),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_insert_if_absent() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        assert!(redis
+            .insert_if_absent("key", b"first", Some(Duration::from_secs(60)))
+            .await
+            .expect("Failed to insert key"));
+        assert!(!redis
+            .insert_if_absent("key", b"second", None)
+            .await
+            .expect("Failed to insert key"));
+        assert_eq!(
+            redis.get("key").await.expect("Failed to get value for key"),
+            Some(b"first".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compare_and_swap() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        assert!(!redis
+            .compare_and_swap("state", b"new", b"running")
+            .await
+            .expect("Failed to compare and swap"));
+        redis
+            .insert("state", b"new")
+            .await
+            .expect("Failed to insert key");
+        assert!(redis
+            .compare_and_swap("state", b"new", b"running")
+            .await
+            .expect("Failed to compare and swap"));
+        assert!(!redis
+            .compare_and_swap("state", b"new", b"done")
+            .await
+            .expect("Failed to compare and swap"));
+        assert_eq!(
+            redis
+                .get("state")
+                .await
+                .expect("Failed to get value for key"),
+            Some(b"running".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compare_and_remove() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        redis
+            .insert("lock", b"mine")
+            .await
+            .expect("Failed to insert key");
+        assert!(!redis
+            .compare_and_remove("lock", b"theirs")
+            .await
+            .expect("Failed to compare and remove"));
+        assert!(redis
+            .contains_key("lock")
+            .await
+            .expect("Failed to check key"));
+        assert!(redis
+            .compare_and_remove("lock", b"mine")
+            .await
+            .expect("Failed to compare and remove"));
+        assert!(!redis
+            .contains_key("lock")
+            .await
+            .expect("Failed to check key"));
+    }
+
+    #[tokio::test]
+    async fn test_get_insert_and_remove_many() {
+        let (redis, _container) = setup_redis_driver().await;
+
+        assert_eq!(
+            redis.get_many(&[]).await.expect("Failed to get values"),
+            Vec::<Option<Vec<u8>>>::new()
+        );
+        redis
+            .insert_many(
+                &[("a", b"1".as_slice()), ("b", b"2".as_slice())],
+                Some(Duration::from_secs(60)),
+            )
+            .await
+            .expect("Failed to insert keys");
+        assert_eq!(
+            redis
+                .get_many(&["a", "missing", "b"])
+                .await
+                .expect("Failed to get values"),
+            vec![Some(b"1".to_vec()), None, Some(b"2".to_vec())]
+        );
+        assert_eq!(
+            redis.get_many(&["a"]).await.expect("Failed to get values"),
+            vec![Some(b"1".to_vec())]
+        );
+
+        redis
+            .remove_many(&["a", "missing"])
+            .await
+            .expect("Failed to remove keys");
+        assert_eq!(
+            redis
+                .get_many(&["a", "b"])
+                .await
+                .expect("Failed to get values"),
+            vec![None, Some(b"2".to_vec())]
+        );
+    }
+
+    #[test]
+    fn can_escape_prefix_pattern() {
+        assert_eq!(prefix_pattern("users:"), "users:*");
+        assert_eq!(prefix_pattern("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\*");
+    }
+
     #[tokio::test]
     async fn test_expiry() {
         let (redis, _container) = setup_redis_driver().await;
 
         redis
-            .insert_with_expiry("expiring_key", "test_value", Duration::from_secs(1))
+            .insert_with_expiry("expiring_key", b"test_value", Duration::from_secs(1))
             .await
             .expect("Failed to insert key with expiry");
 
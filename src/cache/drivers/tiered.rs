@@ -0,0 +1,620 @@
+//! # Tiered Cache Driver
+//!
+//! This module implements a two-tier cache driver: values read from Redis
+//! (L2) are kept for a short time in an in-memory cache (L1) of the running
+//! instance, so hot keys are served without a round trip to Redis.
+//!
+//! Every write or removal goes to Redis and is published on a pub/sub
+//! channel, which all instances listen to in order to drop the affected keys
+//! from their own L1.
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use bb8_redis::redis::{aio::PubSubStream, Client};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::task::AbortHandle;
+
+use super::{inmem, redis::Redis, CacheDriver};
+use crate::cache::{
+    stats::{CacheStats, PrefixStats},
+    CacheError, CacheResult, Codec,
+};
+use crate::config::{InMemCacheConfig, TieredCacheConfig};
+
+/// Delay between two attempts to subscribe again to the invalidation
+/// channel after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Creates a new instance of the tiered cache driver, and subscribes to the
+/// invalidation channel.
+///
+/// # Returns
+///
+/// A [`Cache`](crate::cache::Cache) instance.
+///
+/// # Errors
+///
+/// Returns a `CacheError` if there is an error connecting to Redis.
+pub async fn new(config: &TieredCacheConfig) -> CacheResult<crate::cache::Cache> {
+    let codec = Codec::new(&config.codec)?;
+    let l2 = Redis::connect(&config.uri, config.max_size).await?;
+    let l1: Arc<dyn CacheDriver> = Arc::from(
+        inmem::new(&InMemCacheConfig {
+            max_capacity: config.l1_max_capacity,
+        })
+        .driver,
+    );
+
+    let client = Client::open(config.uri.as_str())?;
+    let messages = subscribe(&client, &config.channel).await?;
+    let listener =
+        tokio::spawn(listen(client, config.channel.clone(), l1.clone(), messages)).abort_handle();
+
+    Ok(crate::cache::Cache::new(Box::new(Tiered {
+        l1,
+        l2,
+        l1_ttl: Duration::from_millis(config.l1_ttl),
+        channel: config.channel.clone(),
+        listener,
+    }))
+    .with_codec(codec))
+}
+
+async fn subscribe(client: &Client, channel: &str) -> CacheResult<PubSubStream> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+    Ok(pubsub.into_on_message())
+}
+
+/// Applies the invalidations published on `channel` to `l1`, subscribing
+/// again whenever the connection is lost.
+async fn listen(
+    client: Client,
+    channel: String,
+    l1: Arc<dyn CacheDriver>,
+    mut messages: PubSubStream,
+) {
+    loop {
+        while let Some(message) = messages.next().await {
+            let invalidation = message
+                .get_payload::<String>()
+                .map_err(CacheError::from)
+                .and_then(|payload| {
+                    serde_json::from_str::<Invalidation>(&payload)
+                        .map_err(|e| CacheError::Deserialization(e.to_string()))
+                });
+            let result = match invalidation {
+                Ok(invalidation) => invalidation.apply(l1.as_ref()).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                tracing::error!(err.msg = %err, err.detail = ?err, "cache_invalidation_error");
+            }
+        }
+
+        tracing::warn!(
+            channel,
+            "cache invalidation subscription lost, subscribing again"
+        );
+        messages = loop {
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            match subscribe(&client, &channel).await {
+                Ok(messages) => break messages,
+                Err(err) => {
+                    tracing::error!(err.msg = %err, err.detail = ?err, "cache_invalidation_subscribe_error");
+                }
+            }
+        };
+        // invalidations published while disconnected were missed
+        if let Err(err) = l1.clear().await {
+            tracing::error!(err.msg = %err, err.detail = ?err, "cache_invalidation_error");
+        }
+    }
+}
+
+/// Keys to drop from the L1 of every instance.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Invalidation {
+    Keys { keys: Vec<String> },
+    Prefix { prefix: String },
+    All,
+}
+
+impl Invalidation {
+    fn keys(keys: &[&str]) -> Self {
+        Self::Keys {
+            keys: keys.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    async fn apply(&self, l1: &dyn CacheDriver) -> CacheResult<()> {
+        match self {
+            Self::Keys { keys } => {
+                let keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+                l1.remove_many(&keys).await
+            }
+            Self::Prefix { prefix } => l1.clear_prefix(prefix).await,
+            Self::All => l1.clear().await,
+        }
+    }
+}
+
+/// Represents the tiered cache driver.
+pub struct Tiered {
+    l1: Arc<dyn CacheDriver>,
+    l2: Redis,
+    l1_ttl: Duration,
+    channel: String,
+    listener: AbortHandle,
+}
+
+impl Drop for Tiered {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+impl Tiered {
+    /// Drops the keys of `invalidation` from the L1 of this instance, and
+    /// publishes it to the other instances.
+    async fn invalidate(&self, invalidation: Invalidation) -> CacheResult<()> {
+        invalidation.apply(self.l1.as_ref()).await?;
+        let message = serde_json::to_string(&invalidation)
+            .map_err(|e| CacheError::Serialization(e.to_string()))?;
+        self.l2.publish(&self.channel, &message).await
+    }
+}
+
+#[async_trait]
+impl CacheDriver for Tiered {
+    /// Pings Redis to check if it is reachable.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn ping(&self) -> CacheResult<()> {
+        self.l2.ping().await
+    }
+
+    /// Checks if a key exists in L1, or else in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn contains_key(&self, key: &str) -> CacheResult<bool> {
+        Ok(self.l1.contains_key(key).await? || self.l2.contains_key(key).await?)
+    }
+
+    /// Retrieves a value from L1, or else from Redis, keeping it in L1.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn get(&self, key: &str) -> CacheResult<Option<Vec<u8>>> {
+        if let Some(value) = self.l1.get(key).await? {
+            return Ok(Some(value));
+        }
+        let value = self.l2.get(key).await?;
+        if let Some(value) = &value {
+            self.l1.insert_with_expiry(key, value, self.l1_ttl).await?;
+        }
+        Ok(value)
+    }
+
+    /// Retrieves the values of `keys` from L1, and the ones it misses from
+    /// Redis with a single `MGET`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn get_many(&self, keys: &[&str]) -> CacheResult<Vec<Option<Vec<u8>>>> {
+        let mut values = self.l1.get_many(keys).await?;
+        let missing = keys
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return Ok(values);
+        }
+
+        let mut found = self.l2.get_many(&missing).await?.into_iter();
+        let mut entries = Vec::new();
+        for (key, value) in keys.iter().zip(values.iter_mut()) {
+            if value.is_none() {
+                *value = found.next().flatten();
+                if let Some(value) = value {
+                    entries.push((*key, value.as_slice()));
+                }
+            }
+        }
+        self.l1.insert_many(&entries, Some(self.l1_ttl)).await?;
+        Ok(values)
+    }
+
+    /// Inserts a key-value pair into Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert(&self, key: &str, value: &[u8]) -> CacheResult<()> {
+        self.l2.insert(key, value).await?;
+        self.invalidate(Invalidation::keys(&[key])).await
+    }
+
+    /// Inserts a key-value pair into Redis that expires after the specified
+    /// duration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_with_expiry(
+        &self,
+        key: &str,
+        value: &[u8],
+        duration: Duration,
+    ) -> CacheResult<()> {
+        self.l2.insert_with_expiry(key, value, duration).await?;
+        self.invalidate(Invalidation::keys(&[key])).await
+    }
+
+    /// Inserts a key-value pair into Redis under the provided tags.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_with_tags(
+        &self,
+        key: &str,
+        value: &[u8],
+        tags: &[&str],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        self.l2.insert_with_tags(key, value, tags, ttl).await?;
+        self.invalidate(Invalidation::keys(&[key])).await
+    }
+
+    /// Inserts every key-value pair of `entries` into Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_many(
+        &self,
+        entries: &[(&str, &[u8])],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.l2.insert_many(entries, ttl).await?;
+        let keys = entries.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        self.invalidate(Invalidation::keys(&keys)).await
+    }
+
+    /// Inserts a key-value pair into Redis unless the key already exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn insert_if_absent(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> CacheResult<bool> {
+        let inserted = self.l2.insert_if_absent(key, value, ttl).await?;
+        if inserted {
+            self.invalidate(Invalidation::keys(&[key])).await?;
+        }
+        Ok(inserted)
+    }
+
+    /// Replaces the value of `key` in Redis with `new` if it is `current`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn compare_and_swap(&self, key: &str, current: &[u8], new: &[u8]) -> CacheResult<bool> {
+        let swapped = self.l2.compare_and_swap(key, current, new).await?;
+        if swapped {
+            self.invalidate(Invalidation::keys(&[key])).await?;
+        }
+        Ok(swapped)
+    }
+
+    /// Removes `key` from Redis if its value is `current`, and from the L1 of
+    /// every instance when it was.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn compare_and_remove(&self, key: &str, current: &[u8]) -> CacheResult<bool> {
+        let removed = self.l2.compare_and_remove(key, current).await?;
+        if removed {
+            self.invalidate(Invalidation::keys(&[key])).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Atomically adds `by` to the integer counter at `key` in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if the value of `key` is not an integer.
+    async fn increment(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        let value = self.l2.increment(key, by, ttl).await?;
+        self.invalidate(Invalidation::keys(&[key])).await?;
+        Ok(value)
+    }
+
+    /// Atomically subtracts `by` from the integer counter at `key` in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if the value of `key` is not an integer.
+    async fn decrement(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        let value = self.l2.decrement(key, by, ttl).await?;
+        self.invalidate(Invalidation::keys(&[key])).await?;
+        Ok(value)
+    }
+
+    /// Removes a key-value pair from Redis and from the L1 of every instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn remove(&self, key: &str) -> CacheResult<()> {
+        self.l2.remove(key).await?;
+        self.invalidate(Invalidation::keys(&[key])).await
+    }
+
+    /// Removes every key of `keys` from Redis and from the L1 of every
+    /// instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn remove_many(&self, keys: &[&str]) -> CacheResult<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        self.l2.remove_many(keys).await?;
+        self.invalidate(Invalidation::keys(keys)).await
+    }
+
+    /// Removes every key inserted with `tag` from Redis and from the L1 of
+    /// every instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn invalidate_tag(&self, tag: &str) -> CacheResult<()> {
+        let keys = self.l2.take_tag(tag).await?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+        self.invalidate(Invalidation::Keys { keys }).await
+    }
+
+    /// Removes every key starting with `prefix` from Redis and from the L1
+    /// of every instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn clear_prefix(&self, prefix: &str) -> CacheResult<()> {
+        self.l2.clear_prefix(prefix).await?;
+        self.invalidate(Invalidation::Prefix {
+            prefix: prefix.to_string(),
+        })
+        .await
+    }
+
+    /// Lists the keys starting with `prefix` in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn keys(&self, prefix: &str) -> CacheResult<Vec<String>> {
+        self.l2.keys(prefix).await
+    }
+
+    /// Walks the keys starting with `prefix` in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn for_each_key(
+        &self,
+        prefix: &str,
+        f: &mut (dyn FnMut(String) + Send),
+    ) -> CacheResult<()> {
+        self.l2.for_each_key(prefix, f).await
+    }
+
+    /// Adds the counters to the ones kept in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn save_stats(&self, stats: &HashMap<String, PrefixStats>) -> CacheResult<()> {
+        self.l2.save_stats(stats).await
+    }
+
+    /// Returns the counters kept in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn saved_stats(&self) -> CacheResult<Option<CacheStats>> {
+        self.l2.saved_stats().await
+    }
+
+    /// Clears Redis and the L1 of every instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CacheError` if there is an error during the operation.
+    async fn clear(&self) -> CacheResult<()> {
+        self.l2.clear().await?;
+        self.invalidate(Invalidation::All).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests_cfg::redis::setup_redis_container;
+    use testcontainers::{ContainerAsync, GenericImage};
+
+    use super::*;
+
+    async fn setup_tiered_cache(uri: &str) -> crate::cache::Cache {
+        new(&TieredCacheConfig {
+            uri: uri.to_string(),
+            max_size: 10,
+            l1_max_capacity: 100,
+            l1_ttl: 60_000,
+            channel: "loco:cache:invalidate".to_string(),
+            codec: crate::config::CacheCodecConfig::default(),
+        })
+        .await
+        .expect("Failed to create tiered driver")
+    }
+
+    async fn setup_instances() -> (
+        crate::cache::Cache,
+        crate::cache::Cache,
+        ContainerAsync<GenericImage>,
+    ) {
+        let (redis_url, container) = setup_redis_container().await;
+        let first = setup_tiered_cache(&redis_url).await;
+        let second = setup_tiered_cache(&redis_url).await;
+        (first, second, container)
+    }
+
+    /// Waits for the invalidations to reach the other instances.
+    async fn propagate() {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    #[test]
+    fn can_serialize_invalidation() {
+        let invalidation = Invalidation::keys(&["user:1"]);
+        let message = serde_json::to_string(&invalidation).unwrap();
+        assert_eq!(message, r#"{"op":"keys","keys":["user:1"]}"#);
+        assert_eq!(
+            serde_json::from_str::<Invalidation>(&message).unwrap(),
+            invalidation
+        );
+        assert_eq!(
+            serde_json::to_string(&Invalidation::All).unwrap(),
+            r#"{"op":"all"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn can_apply_invalidation() {
+        let l1 = inmem::new(&InMemCacheConfig { max_capacity: 100 }).driver;
+        for key in ["users:1", "users:2", "posts:1"] {
+            l1.insert(key, b"loco").await.unwrap();
+        }
+
+        Invalidation::keys(&["users:1"])
+            .apply(l1.as_ref())
+            .await
+            .unwrap();
+        assert!(!l1.contains_key("users:1").await.unwrap());
+        assert!(l1.contains_key("users:2").await.unwrap());
+
+        Invalidation::Prefix {
+            prefix: "users:".to_string(),
+        }
+        .apply(l1.as_ref())
+        .await
+        .unwrap();
+        assert!(!l1.contains_key("users:2").await.unwrap());
+        assert!(l1.contains_key("posts:1").await.unwrap());
+
+        Invalidation::All.apply(l1.as_ref()).await.unwrap();
+        assert!(!l1.contains_key("posts:1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_serve_from_l1() {
+        let (redis_url, _container) = setup_redis_container().await;
+        let cache = setup_tiered_cache(&redis_url).await;
+        let redis = crate::cache::drivers::redis::new(&crate::config::RedisCacheConfig {
+            uri: redis_url,
+            max_size: 10,
+            codec: crate::config::CacheCodecConfig::default(),
+        })
+        .await
+        .expect("Failed to create Redis driver");
+
+        cache.insert("flag", "on").await.unwrap();
+        assert_eq!(
+            cache.get::<String>("flag").await.unwrap(),
+            Some("on".to_string())
+        );
+        // written to Redis directly, so no invalidation is published
+        redis.insert("flag", "off").await.unwrap();
+        assert_eq!(
+            cache.get::<String>("flag").await.unwrap(),
+            Some("on".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_other_instances() {
+        let (first, second, _container) = setup_instances().await;
+
+        first.insert("flag", "on").await.unwrap();
+        assert_eq!(
+            second.get::<String>("flag").await.unwrap(),
+            Some("on".to_string())
+        );
+
+        first.insert("flag", "off").await.unwrap();
+        propagate().await;
+        assert_eq!(
+            second.get::<String>("flag").await.unwrap(),
+            Some("off".to_string())
+        );
+
+        first.remove("flag").await.unwrap();
+        propagate().await;
+        assert_eq!(second.get::<String>("flag").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_tags_and_prefixes_of_other_instances() {
+        let (first, second, _container) = setup_instances().await;
+
+        first
+            .insert_with_tags("user:1:posts", "hello", &["user:1"], None)
+            .await
+            .unwrap();
+        first.insert("settings:theme", "dark").await.unwrap();
+        assert_eq!(
+            second
+                .get_many::<String>(&["user:1:posts", "settings:theme"])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        first.invalidate_tag("user:1").await.unwrap();
+        first.scoped("settings").clear().await.unwrap();
+        propagate().await;
+        assert!(second
+            .get_many::<String>(&["user:1:posts", "settings:theme"])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
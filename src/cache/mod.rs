@@ -1,13 +1,24 @@
 //! # Cache Module
 //!
 //! This module provides a generic cache interface for various cache drivers.
+pub mod codec;
 pub mod drivers;
+mod stampede;
+pub mod stats;
 
-use std::{future::Future, time::Duration};
+use std::{
+    collections::HashMap,
+    future::Future,
+    time::{Duration, Instant},
+};
 
 use serde::{de::DeserializeOwned, Serialize};
 
+pub use self::codec::Codec;
 pub use self::drivers::CacheDriver;
+use self::stampede::{KeyLocks, Lock, Stamped};
+pub use self::stats::{CacheStats, PrefixStats};
+use self::stats::{Event, Recorder};
 use crate::config;
 use crate::Result as LocoResult;
 use std::sync::Arc;
@@ -54,6 +65,11 @@ pub async fn create_cache_provider(config: &config::Config) -> crate::Result<Arc
             let cache = crate::cache::drivers::inmem::new(config);
             Ok(Arc::new(cache))
         }
+        #[cfg(all(feature = "cache_inmem", feature = "cache_redis"))]
+        config::CacheConfig::Tiered(config) => {
+            let cache = crate::cache::drivers::tiered::new(config).await?;
+            Ok(Arc::new(cache))
+        }
         config::CacheConfig::Null => {
             let driver = crate::cache::drivers::null::new();
             Ok(Arc::new(Cache::new(driver)))
@@ -65,13 +81,31 @@ pub async fn create_cache_provider(config: &config::Config) -> crate::Result<Arc
 pub struct Cache {
     /// The cache driver used for underlying operations
     pub driver: Box<dyn CacheDriver>,
+    codec: Codec,
+    inflight: KeyLocks,
+    stats: Recorder,
 }
 
 impl Cache {
     /// Creates a new cache instance with the specified cache driver.
     #[must_use]
     pub fn new(driver: Box<dyn CacheDriver>) -> Self {
-        Self { driver }
+        Self {
+            driver,
+            codec: Codec::default(),
+            inflight: KeyLocks::default(),
+            stats: Recorder::default(),
+        }
+    }
+
+    /// Sets the codec serializing the cached values, JSON by default.
+    ///
+    /// Values encoded by another codec can not be read anymore, so the
+    /// cache should be cleared when changing it.
+    #[must_use]
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
     }
 
     /// Pings the cache to check if it is reachable.
@@ -151,16 +185,74 @@ impl Cache {
     /// A [`CacheResult`] containing an `Option` representing the retrieved
     /// and deserialized value.
     pub async fn get<T: DeserializeOwned>(&self, key: &str) -> CacheResult<Option<T>> {
+        let value = self.lookup(key).await?;
+        self.stats.record(
+            key,
+            if value.is_some() {
+                Event::Hit
+            } else {
+                Event::Miss
+            },
+        );
+        self.save_due_stats().await;
+        Ok(value)
+    }
+
+    /// Retrieves and deserializes a value without counting the read, for
+    /// the reads the cache makes on its own.
+    async fn lookup<T: DeserializeOwned>(&self, key: &str) -> CacheResult<Option<T>> {
         let result = self.driver.get(key).await?;
         if let Some(value) = result {
-            let deserialized = serde_json::from_str::<T>(&value)
-                .map_err(|e| CacheError::Deserialization(e.to_string()))?;
-            Ok(Some(deserialized))
+            Ok(Some(self.codec.decode(&value)?))
         } else {
             Ok(None)
         }
     }
 
+    /// Retrieves and deserializes the values of `keys` in a single round
+    /// trip to the cache. Only the keys found in the cache are in the
+    /// returned map.
+    ///
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn get_many() -> CacheResult<HashMap<String, String>> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache.get_many::<String>(&["post:1", "post:2", "post:3"]).await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// A [`CacheResult`] containing the deserialized values of the keys found.
+    pub async fn get_many<T: DeserializeOwned>(
+        &self,
+        keys: &[&str],
+    ) -> CacheResult<HashMap<String, T>> {
+        let values = self.driver.get_many(keys).await?;
+        let found = keys
+            .iter()
+            .zip(values)
+            .filter_map(|(key, value)| {
+                self.stats.record(
+                    key,
+                    if value.is_some() {
+                        Event::Hit
+                    } else {
+                        Event::Miss
+                    },
+                );
+                value.map(|value| (key, value))
+            })
+            .map(|(key, value)| Ok(((*key).to_string(), self.codec.decode(&value)?)))
+            .collect();
+        self.save_due_stats().await;
+        found
+    }
+
     /// Inserts a serializable value into the cache with the provided key.
     ///
     /// # Example
@@ -203,9 +295,11 @@ impl Cache {
         key: &str,
         value: &T,
     ) -> CacheResult<()> {
-        let serialized =
-            serde_json::to_string(value).map_err(|e| CacheError::Serialization(e.to_string()))?;
-        self.driver.insert(key, &serialized).await
+        let serialized = self.codec.encode(value)?;
+        self.driver.insert(key, &serialized).await?;
+        self.stats.record(key, Event::Insert);
+        self.save_due_stats().await;
+        Ok(())
     }
 
     /// Inserts a serializable value into the cache with the provided key and expiry duration.
@@ -253,17 +347,103 @@ impl Cache {
         value: &T,
         duration: Duration,
     ) -> CacheResult<()> {
-        let serialized =
-            serde_json::to_string(value).map_err(|e| CacheError::Serialization(e.to_string()))?;
+        let serialized = self.codec.encode(value)?;
         self.driver
             .insert_with_expiry(key, &serialized, duration)
-            .await
+            .await?;
+        self.stats.record(key, Event::Insert);
+        self.save_due_stats().await;
+        Ok(())
+    }
+
+    /// Inserts a serializable value into the cache with the provided key, and
+    /// records the key under each of `tags`. Invalidating any of the tags with
+    /// [`Cache::invalidate_tag`] removes the key, which also expires after
+    /// `ttl` when set.
+    ///
+    /// # Example
+    /// ```
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn insert_with_tags() -> CacheResult<()> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache.insert_with_tags("user:1:posts", &vec!["hello"], &["user:1"], None).await?;
+    ///     // after user 1 changes
+    ///     cache.invalidate_tag("user:1").await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert_with_tags<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        tags: &[&str],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        let serialized = self.codec.encode(value)?;
+        self.driver
+            .insert_with_tags(key, &serialized, tags, ttl)
+            .await?;
+        self.stats.record(key, Event::Insert);
+        self.save_due_stats().await;
+        Ok(())
+    }
+
+    /// Inserts every key-value pair of `entries` in a single round trip to
+    /// the cache, expiring after `ttl` when set.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn insert_many() -> CacheResult<()> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache
+    ///         .insert_many(&[("post:1", "hello"), ("post:2", "world")], Some(Duration::from_secs(300)))
+    ///         .await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert_many<T: Serialize + Sync + ?Sized>(
+        &self,
+        entries: &[(&str, &T)],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        let serialized = entries
+            .iter()
+            .map(|(key, value)| Ok((*key, self.codec.encode(value)?)))
+            .collect::<CacheResult<Vec<_>>>()?;
+        let entries: Vec<(&str, &[u8])> = serialized
+            .iter()
+            .map(|(key, value)| (*key, value.as_slice()))
+            .collect();
+        self.driver.insert_many(&entries, ttl).await?;
+        for (key, _) in entries {
+            self.stats.record(key, Event::Insert);
+        }
+        self.save_due_stats().await;
+        Ok(())
     }
 
     /// Retrieves and deserializes the value associated with the given key from the cache,
     /// or inserts it if it does not exist, using the provided closure to
     /// generate the value.
     ///
+    /// Concurrent callers missing the same key wait for a single one of them
+    /// to generate the value, both within this instance and, through a short
+    /// lock held in the cache, across instances.
+    ///
     /// # Example
     /// ```
     /// use loco_rs::{app::AppContext};
@@ -307,19 +487,16 @@ impl Cache {
         T: Serialize + DeserializeOwned + Send + Sync,
         F: Future<Output = LocoResult<T>> + Send,
     {
-        if let Some(value) = self.get::<T>(key).await? {
-            Ok(value)
-        } else {
-            let value = f.await?;
-            self.insert(key, &value).await?;
-            Ok(value)
-        }
+        self.get_or_compute(key, None, f).await
     }
 
     /// Retrieves and deserializes the value associated with the given key from the cache,
     /// or inserts it (with expiry after provided duration) if it does not
     /// exist, using the provided closure to generate the value.
     ///
+    /// Like [`Cache::get_or_insert`], concurrent callers missing the same key
+    /// wait for a single one of them to generate the value.
+    ///
     /// # Example
     /// ```
     /// use std::time::Duration;
@@ -366,17 +543,314 @@ impl Cache {
         duration: Duration,
         f: F,
     ) -> LocoResult<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+        F: Future<Output = LocoResult<T>> + Send,
+    {
+        self.get_or_compute(key, Some(duration), f).await
+    }
+
+    /// Retrieves and deserializes the value associated with the given key
+    /// from the cache, or inserts the one generated by the provided closure,
+    /// like [`Cache::get_or_insert_with_expiry`].
+    ///
+    /// The value is fresh for `fresh`, then kept for `stale` more. A stale
+    /// value is returned right away, while a single caller across instances
+    /// generates the new value in the background. Callers only wait when the
+    /// key is missing.
+    ///
+    /// The key is stored with its freshness, so it must only be read with
+    /// this method.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use loco_rs::tests_cfg::app::*;
+    ///
+    /// pub async fn get_or_insert_with_refresh(){
+    ///    let app_ctx = get_app_context().await;
+    ///    let settings = app_ctx.cache.get_or_insert_with_refresh::<Vec<String>, _>(
+    ///         "settings",
+    ///         Duration::from_secs(60),
+    ///         Duration::from_secs(3600),
+    ///         async { Ok(vec!["dark_mode".to_string()]) },
+    ///     ).await.unwrap();
+    ///    assert_eq!(settings, vec!["dark_mode".to_string()]);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// A [`LocoResult`] indicating the success of the operation. Errors of a
+    /// background refresh are logged, and the stale value is kept.
+    pub async fn get_or_insert_with_refresh<T, F>(
+        self: &Arc<Self>,
+        key: &str,
+        fresh: Duration,
+        stale: Duration,
+        f: F,
+    ) -> LocoResult<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+        F: Future<Output = LocoResult<T>> + Send + 'static,
+    {
+        let expiry = fresh.saturating_add(stale);
+        if let Some(stamped) = self.get::<Stamped<T>>(key).await? {
+            if stamped.is_stale() {
+                self.refresh(key, fresh, expiry, f).await?;
+            }
+            return Ok(stamped.value);
+        }
+        let stamped = self
+            .get_or_compute(key, Some(expiry), async move {
+                Ok(Stamped::new(f.await?, fresh))
+            })
+            .await?;
+        Ok(stamped.value)
+    }
+
+    /// Generates the value of `key` and inserts it, unless another caller is
+    /// already generating it. Waits for that caller otherwise.
+    async fn get_or_compute<T, F>(
+        &self,
+        key: &str,
+        duration: Option<Duration>,
+        f: F,
+    ) -> LocoResult<T>
     where
         T: Serialize + DeserializeOwned + Send + Sync,
         F: Future<Output = LocoResult<T>> + Send,
     {
         if let Some(value) = self.get::<T>(key).await? {
-            Ok(value)
-        } else {
+            return Ok(value);
+        }
+        let _guard = self.inflight.lock(key).await;
+        if let Some(value) = self.lookup::<T>(key).await? {
+            return Ok(value);
+        }
+
+        let token = match self.lock(key).await? {
+            Lock::Acquired(token) => Some(token),
+            Lock::Ready(value) => return Ok(value),
+            Lock::TimedOut => None,
+        };
+        let result = async {
             let value = f.await?;
-            self.insert_with_expiry(key, &value, duration).await?;
+            match duration {
+                Some(duration) => self.insert_with_expiry(key, &value, duration).await?,
+                None => self.insert(key, &value).await?,
+            }
             Ok(value)
         }
+        .await;
+        if let Some(token) = token {
+            self.unlock(key, &token).await;
+        }
+        result
+    }
+
+    /// Takes the lock generating the value of `key` across instances, or
+    /// waits for its holder to insert the value.
+    async fn lock<T: DeserializeOwned>(&self, key: &str) -> CacheResult<Lock<T>> {
+        let lock_key = stampede::lock_key(key);
+        let token = stampede::lock_token();
+        let deadline = Instant::now() + stampede::LOCK_TTL;
+        loop {
+            if self
+                .driver
+                .insert_if_absent(&lock_key, token.as_bytes(), Some(stampede::LOCK_TTL))
+                .await?
+            {
+                return Ok(Lock::Acquired(token));
+            }
+            if Instant::now() >= deadline {
+                return Ok(Lock::TimedOut);
+            }
+            tokio::time::sleep(stampede::LOCK_POLL_INTERVAL).await;
+            if let Some(value) = self.lookup::<T>(key).await? {
+                return Ok(Lock::Ready(value));
+            }
+        }
+    }
+
+    /// Releases the lock generating the value of `key`, unless it expired and
+    /// another caller holding a different `token` took it over. The lock
+    /// expires by itself if this fails, so the error is only logged.
+    async fn unlock(&self, key: &str, token: &str) {
+        if let Err(err) = self
+            .driver
+            .compare_and_remove(&stampede::lock_key(key), token.as_bytes())
+            .await
+        {
+            tracing::debug!(err.msg = %err, err.detail = ?err, key, "cache_unlock_error");
+        }
+    }
+
+    /// Generates the new value of the stale `key` in the background, unless
+    /// another caller already does.
+    async fn refresh<T, F>(
+        self: &Arc<Self>,
+        key: &str,
+        fresh: Duration,
+        expiry: Duration,
+        f: F,
+    ) -> CacheResult<()>
+    where
+        T: Serialize + Send + Sync + 'static,
+        F: Future<Output = LocoResult<T>> + Send + 'static,
+    {
+        let lock_key = stampede::lock_key(key);
+        let token = stampede::lock_token();
+        if !self
+            .driver
+            .insert_if_absent(&lock_key, token.as_bytes(), Some(stampede::LOCK_TTL))
+            .await?
+        {
+            return Ok(());
+        }
+
+        let cache = Arc::clone(self);
+        let key = key.to_string();
+        tokio::spawn(async move {
+            let result = match f.await {
+                Ok(value) => cache
+                    .insert_with_expiry(&key, &Stamped::new(value, fresh), expiry)
+                    .await
+                    .map_err(crate::Error::from),
+                Err(err) => Err(err),
+            };
+            cache.unlock(&key, &token).await;
+            if let Err(err) = result {
+                tracing::error!(err.msg = %err, err.detail = ?err, key, "cache_refresh_error");
+            }
+        });
+        Ok(())
+    }
+
+    /// Inserts a serializable value only if the key does not exist yet,
+    /// returning whether it was inserted. Check and insert happen atomically
+    /// in the driver, so of several concurrent callers exactly one wins.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn insert_if_absent() -> CacheResult<bool> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     // only the first request with this idempotency key goes through
+    ///     cache
+    ///         .insert_if_absent("idempotency:abc", &"processing", Some(Duration::from_secs(60)))
+    ///         .await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert_if_absent<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> CacheResult<bool> {
+        let serialized = self.codec.encode(value)?;
+        let inserted = self.driver.insert_if_absent(key, &serialized, ttl).await?;
+        if inserted {
+            self.stats.record(key, Event::Insert);
+            self.save_due_stats().await;
+        }
+        Ok(inserted)
+    }
+
+    /// Replaces the value of the key with `new` only if it currently holds
+    /// `current`, returning whether it was replaced. Values are compared in
+    /// their serialized form, and the key keeps its expiry.
+    ///
+    /// # Example
+    /// ```
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn compare_and_swap() -> CacheResult<bool> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache.insert("import:1", &"pending").await?;
+    ///     cache.compare_and_swap("import:1", &"pending", &"running").await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn compare_and_swap<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        current: &T,
+        new: &T,
+    ) -> CacheResult<bool> {
+        let current = self.codec.encode(current)?;
+        let new = self.codec.encode(new)?;
+        let swapped = self.driver.compare_and_swap(key, &current, &new).await?;
+        if swapped {
+            self.stats.record(key, Event::Insert);
+            self.save_due_stats().await;
+        }
+        Ok(swapped)
+    }
+
+    /// Atomically adds `by` to the counter stored at the key and returns the
+    /// new value. A missing counter starts at `0` and, when `ttl` is given,
+    /// expires after it; incrementing an existing counter keeps its expiry,
+    /// which makes fixed-window rate limits straightforward.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn increment() -> CacheResult<bool> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     let hits = cache
+    ///         .increment("rate:127.0.0.1", 1, Some(Duration::from_secs(60)))
+    ///         .await?;
+    ///     Ok(hits <= 100)
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CacheError`] if the key holds a value that is not an
+    /// integer, or if the counter overflows.
+    pub async fn increment(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        self.driver.increment(key, by, ttl).await
+    }
+
+    /// Atomically subtracts `by` from the counter stored at the key and
+    /// returns the new value, see [`Cache::increment`].
+    ///
+    /// # Example
+    /// ```
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn decrement() -> CacheResult<i64> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache.decrement("seats:42", 1, None).await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CacheError`] if the key holds a value that is not an
+    /// integer, or if the counter overflows.
+    pub async fn decrement(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        self.driver.decrement(key, by, ttl).await
     }
 
     /// Removes a key-value pair from the cache.
@@ -400,6 +874,49 @@ impl Cache {
         self.driver.remove(key).await
     }
 
+    /// Removes every key of `keys` from the cache in a single round trip.
+    ///
+    /// # Example
+    /// ```
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn remove_many() -> CacheResult<()> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache.remove_many(&["post:1", "post:2"]).await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn remove_many(&self, keys: &[&str]) -> CacheResult<()> {
+        self.driver.remove_many(keys).await
+    }
+
+    /// Removes every key inserted with `tag` through
+    /// [`Cache::insert_with_tags`].
+    ///
+    /// # Example
+    /// ```
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn invalidate_tag() -> CacheResult<()> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache.invalidate_tag("user:1").await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn invalidate_tag(&self, tag: &str) -> CacheResult<()> {
+        self.driver.invalidate_tag(tag).await
+    }
+
     /// Clears all key-value pairs from the cache.
     ///
     /// # Example
@@ -420,17 +937,367 @@ impl Cache {
     pub async fn clear(&self) -> CacheResult<()> {
         self.driver.clear().await
     }
-}
-
-#[cfg(test)]
-mod tests {
 
-    use crate::tests_cfg;
-    use serde::{Deserialize, Serialize};
+    /// Returns the hits, misses, inserts and evictions of this cache since
+    /// it was created, grouped by [key prefix](stats::prefix).
+    ///
+    /// Counters are kept by each instance of the application, see
+    /// [`Cache::saved_stats`] for those of all instances. Evictions are only
+    /// counted by drivers that are told about them, such as the in-memory
+    /// driver.
+    ///
+    /// # Example
+    /// ```
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn stats() -> CacheResult<()> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     cache.get::<String>("user:1").await?;
+    ///     assert_eq!(cache.stats().prefixes["user"].misses, 1);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[must_use]
+    pub fn stats(&self) -> CacheStats {
+        self.stats.snapshot(self.driver.evictions())
+    }
 
-    #[tokio::test]
-    async fn can_get_or_insert() {
-        let app_ctx = tests_cfg::app::get_app_context().await;
+    /// Returns the counters of every instance of the application added
+    /// together, as kept by drivers shared between instances such as Redis,
+    /// or `None` for drivers living in the application process.
+    ///
+    /// Each instance saves its counters every few seconds, along with a read
+    /// or write of the cache. [`Cache::save_stats`] saves them right away.
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn saved_stats(&self) -> CacheResult<Option<CacheStats>> {
+        self.driver.saved_stats().await
+    }
+
+    /// Saves the counters of this instance that were not saved yet to the
+    /// driver, for example before the application exits.
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn save_stats(&self) -> CacheResult<()> {
+        self.save_unsaved_stats(true).await
+    }
+
+    /// Saves the counters of this instance once they are due, logging
+    /// failures instead of failing the operation that recorded them.
+    async fn save_due_stats(&self) {
+        if let Err(err) = self.save_unsaved_stats(false).await {
+            tracing::warn!(error = %err, "could not save cache stats");
+        }
+    }
+
+    async fn save_unsaved_stats(&self, now: bool) -> CacheResult<()> {
+        let Some(unsaved) = self.stats.take_unsaved(now) else {
+            return Ok(());
+        };
+        if let Err(err) = self.driver.save_stats(&unsaved).await {
+            self.stats.restore_unsaved(unsaved);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Returns a view of the cache that stores its keys under `namespace`,
+    /// as `{namespace}:{key}`. Clearing the view only removes its own keys.
+    ///
+    /// # Example
+    /// ```
+    /// use loco_rs::cache::{self, CacheResult};
+    /// use loco_rs::config::InMemCacheConfig;
+    ///
+    /// pub async fn scoped() -> CacheResult<()> {
+    ///     let config = InMemCacheConfig { max_capacity: 100 };
+    ///     let cache = cache::Cache::new(cache::drivers::inmem::new(&config).driver);
+    ///     let users = cache.scoped("users");
+    ///     users.insert("1", &"Alice".to_string()).await?;
+    ///     users.clear().await
+    /// }
+    /// ```
+    #[must_use]
+    pub fn scoped(&self, namespace: &str) -> ScopedCache<'_> {
+        ScopedCache {
+            cache: self,
+            prefix: format!("{namespace}:"),
+        }
+    }
+}
+
+/// A view of a [`Cache`] whose keys are prefixed with a namespace, created
+/// with [`Cache::scoped`].
+///
+/// Tags are shared with the whole cache, so invalidating a tag with
+/// [`Cache::invalidate_tag`] also removes the keys of every scope.
+pub struct ScopedCache<'a> {
+    cache: &'a Cache,
+    prefix: String,
+}
+
+impl ScopedCache<'_> {
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    /// Returns a view nested in this one, whose keys are stored as
+    /// `{namespace}:{nested}:{key}`.
+    #[must_use]
+    pub fn scoped(&self, namespace: &str) -> ScopedCache<'_> {
+        ScopedCache {
+            cache: self.cache,
+            prefix: format!("{}{namespace}:", self.prefix),
+        }
+    }
+
+    /// Checks if a key exists in this scope, see [`Cache::contains_key`].
+    ///
+    /// # Errors
+    /// A [`CacheResult`] indicating whether the key exists in the cache.
+    pub async fn contains_key(&self, key: &str) -> CacheResult<bool> {
+        self.cache.contains_key(&self.key(key)).await
+    }
+
+    /// Retrieves a value of this scope, see [`Cache::get`].
+    ///
+    /// # Errors
+    /// A [`CacheResult`] containing an `Option` representing the retrieved
+    /// and deserialized value.
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> CacheResult<Option<T>> {
+        self.cache.get(&self.key(key)).await
+    }
+
+    /// Retrieves values of this scope, see [`Cache::get_many`]. The returned
+    /// map is keyed without the prefix of the scope.
+    ///
+    /// # Errors
+    /// A [`CacheResult`] containing the deserialized values of the keys found.
+    pub async fn get_many<T: DeserializeOwned>(
+        &self,
+        keys: &[&str],
+    ) -> CacheResult<HashMap<String, T>> {
+        let scoped = keys.iter().map(|key| self.key(key)).collect::<Vec<_>>();
+        let scoped = scoped.iter().map(String::as_str).collect::<Vec<_>>();
+        let values = self.cache.get_many(&scoped).await?;
+        Ok(values
+            .into_iter()
+            .map(|(key, value)| (key[self.prefix.len()..].to_string(), value))
+            .collect())
+    }
+
+    /// Inserts a value in this scope, see [`Cache::insert`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+    ) -> CacheResult<()> {
+        self.cache.insert(&self.key(key), value).await
+    }
+
+    /// Inserts a value in this scope that expires after `duration`, see
+    /// [`Cache::insert_with_expiry`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert_with_expiry<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        duration: Duration,
+    ) -> CacheResult<()> {
+        self.cache
+            .insert_with_expiry(&self.key(key), value, duration)
+            .await
+    }
+
+    /// Inserts a value in this scope under `tags`, see
+    /// [`Cache::insert_with_tags`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert_with_tags<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        tags: &[&str],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        self.cache
+            .insert_with_tags(&self.key(key), value, tags, ttl)
+            .await
+    }
+
+    /// Inserts values in this scope, see [`Cache::insert_many`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert_many<T: Serialize + Sync + ?Sized>(
+        &self,
+        entries: &[(&str, &T)],
+        ttl: Option<Duration>,
+    ) -> CacheResult<()> {
+        let keys = entries
+            .iter()
+            .map(|(key, _)| self.key(key))
+            .collect::<Vec<_>>();
+        let entries = keys
+            .iter()
+            .zip(entries)
+            .map(|(key, (_, value))| (key.as_str(), *value))
+            .collect::<Vec<_>>();
+        self.cache.insert_many(&entries, ttl).await
+    }
+
+    /// Retrieves a value of this scope, or inserts the one produced by `f`,
+    /// see [`Cache::get_or_insert`].
+    ///
+    /// # Errors
+    ///
+    /// A [`LocoResult`] indicating the success of the operation.
+    pub async fn get_or_insert<T, F>(&self, key: &str, f: F) -> LocoResult<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+        F: Future<Output = LocoResult<T>> + Send,
+    {
+        self.cache.get_or_insert(&self.key(key), f).await
+    }
+
+    /// Retrieves a value of this scope, or inserts the one produced by `f`
+    /// with an expiry, see [`Cache::get_or_insert_with_expiry`].
+    ///
+    /// # Errors
+    ///
+    /// A [`LocoResult`] indicating the success of the operation.
+    pub async fn get_or_insert_with_expiry<T, F>(
+        &self,
+        key: &str,
+        duration: Duration,
+        f: F,
+    ) -> LocoResult<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+        F: Future<Output = LocoResult<T>> + Send,
+    {
+        self.cache
+            .get_or_insert_with_expiry(&self.key(key), duration, f)
+            .await
+    }
+
+    /// Inserts a value in this scope if the key does not exist yet, see
+    /// [`Cache::insert_if_absent`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn insert_if_absent<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> CacheResult<bool> {
+        self.cache
+            .insert_if_absent(&self.key(key), value, ttl)
+            .await
+    }
+
+    /// Replaces a value of this scope if it holds `current`, see
+    /// [`Cache::compare_and_swap`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn compare_and_swap<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        current: &T,
+        new: &T,
+    ) -> CacheResult<bool> {
+        self.cache
+            .compare_and_swap(&self.key(key), current, new)
+            .await
+    }
+
+    /// Adds `by` to a counter of this scope, see [`Cache::increment`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] containing the new value of the counter.
+    pub async fn increment(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        self.cache.increment(&self.key(key), by, ttl).await
+    }
+
+    /// Subtracts `by` from a counter of this scope, see
+    /// [`Cache::decrement`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] containing the new value of the counter.
+    pub async fn decrement(&self, key: &str, by: i64, ttl: Option<Duration>) -> CacheResult<i64> {
+        self.cache.decrement(&self.key(key), by, ttl).await
+    }
+
+    /// Removes a key of this scope, see [`Cache::remove`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn remove(&self, key: &str) -> CacheResult<()> {
+        self.cache.remove(&self.key(key)).await
+    }
+
+    /// Removes keys of this scope, see [`Cache::remove_many`].
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn remove_many(&self, keys: &[&str]) -> CacheResult<()> {
+        let keys = keys.iter().map(|key| self.key(key)).collect::<Vec<_>>();
+        let keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+        self.cache.remove_many(&keys).await
+    }
+
+    /// Removes every key of this scope, including the keys of nested scopes,
+    /// leaving the rest of the cache untouched.
+    ///
+    /// # Errors
+    ///
+    /// A [`CacheResult`] indicating the success of the operation.
+    pub async fn clear(&self) -> CacheResult<()> {
+        self.cache.driver.clear_prefix(&self.prefix).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::tests_cfg;
+    use serde::{Deserialize, Serialize};
+
+    #[tokio::test]
+    async fn can_get_or_insert() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
         let get_key = "loco";
 
         assert_eq!(app_ctx.cache.get::<String>(get_key).await.unwrap(), None);
@@ -474,6 +1341,303 @@ mod tests {
         assert_eq!(retrieved.unwrap(), user);
     }
 
+    #[tokio::test]
+    async fn can_clear_scoped_keys() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+        let users = app_ctx.cache.scoped("users");
+        let admins = users.scoped("admins");
+
+        users.insert("1", "Alice").await.unwrap();
+        admins.insert("2", "Bob").await.unwrap();
+        app_ctx.cache.insert("posts:1", "hello").await.unwrap();
+
+        assert_eq!(
+            app_ctx.cache.get::<String>("users:1").await.unwrap(),
+            Some("Alice".to_string())
+        );
+        assert_eq!(
+            users.get::<String>("admins:2").await.unwrap(),
+            Some("Bob".to_string())
+        );
+
+        admins.clear().await.unwrap();
+        assert!(!admins.contains_key("2").await.unwrap());
+        assert!(users.contains_key("1").await.unwrap());
+
+        users.clear().await.unwrap();
+        assert!(!users.contains_key("1").await.unwrap());
+        assert!(app_ctx.cache.contains_key("posts:1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn can_get_insert_and_remove_many() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        struct User {
+            name: String,
+        }
+        let alice = User {
+            name: "Alice".to_string(),
+        };
+        let bob = User {
+            name: "Bob".to_string(),
+        };
+
+        app_ctx
+            .cache
+            .insert_many(&[("user:1", &alice), ("user:2", &bob)], None)
+            .await
+            .unwrap();
+        app_ctx.cache.insert("user:3", "not a user").await.unwrap();
+
+        let users = app_ctx
+            .cache
+            .get_many::<User>(&["user:1", "user:2", "user:4"])
+            .await
+            .unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users.get("user:1"), Some(&alice));
+        assert_eq!(users.get("user:2"), Some(&bob));
+        assert!(app_ctx
+            .cache
+            .get_many::<User>(&["user:1", "user:3"])
+            .await
+            .is_err());
+
+        app_ctx
+            .cache
+            .remove_many(&["user:1", "user:2"])
+            .await
+            .unwrap();
+        assert!(app_ctx
+            .cache
+            .get_many::<User>(&["user:1", "user:2"])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn can_get_many_in_scope() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+        let posts = app_ctx.cache.scoped("posts");
+
+        posts
+            .insert_many(&[("1", "hello"), ("2", "world")], None)
+            .await
+            .unwrap();
+        assert_eq!(
+            app_ctx.cache.get::<String>("posts:1").await.unwrap(),
+            Some("hello".to_string())
+        );
+
+        let found = posts.get_many::<String>(&["1", "2", "3"]).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found.get("1"), Some(&"hello".to_string()));
+        assert_eq!(found.get("2"), Some(&"world".to_string()));
+
+        posts.remove_many(&["1"]).await.unwrap();
+        assert!(!posts.contains_key("1").await.unwrap());
+        assert!(posts.contains_key("2").await.unwrap());
+    }
+
+    #[cfg(all(feature = "cache_inmem", feature = "cache_msgpack"))]
+    #[tokio::test]
+    async fn can_cache_with_codec() {
+        let codec = super::Codec::new(&crate::config::CacheCodecConfig {
+            format: crate::config::CacheFormat::MessagePack,
+            compression: None,
+        })
+        .unwrap();
+        let cache =
+            super::drivers::inmem::new(&crate::config::InMemCacheConfig { max_capacity: 100 })
+                .with_codec(codec.clone());
+
+        cache.insert("key", &vec![1, 2, 3]).await.unwrap();
+        assert_eq!(
+            cache.get::<Vec<u32>>("key").await.unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        let stored = cache.driver.get("key").await.unwrap().unwrap();
+        assert_eq!(codec.decode::<Vec<u32>>(&stored).unwrap(), vec![1, 2, 3]);
+        assert!(serde_json::from_slice::<Vec<u32>>(&stored).is_err());
+    }
+
+    #[tokio::test]
+    async fn can_coalesce_concurrent_get_or_insert() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let cache = app_ctx.cache.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_insert("report", async move {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok("computed".to_string())
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), "computed");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn can_count_stats_by_prefix() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+
+        for _ in 0..2 {
+            app_ctx
+                .cache
+                .get_or_insert("report:1", async { Ok("computed".to_string()) })
+                .await
+                .unwrap();
+        }
+        app_ctx
+            .cache
+            .get_many::<String>(&["report:1", "report:2"])
+            .await
+            .unwrap();
+        app_ctx.cache.insert("settings", "dark").await.unwrap();
+
+        let stats = app_ctx.cache.stats();
+        assert_eq!(
+            stats.prefixes["report"],
+            super::PrefixStats {
+                hits: 2,
+                misses: 2,
+                inserts: 1,
+                evictions: 0,
+            }
+        );
+        assert_eq!(stats.prefixes[""].inserts, 1);
+        // the lock taken to compute the value is not counted
+        assert_eq!(stats.prefixes.len(), 2);
+
+        // the in-memory cache is not shared with other instances
+        assert!(app_ctx.cache.save_stats().await.is_ok());
+        assert_eq!(app_ctx.cache.saved_stats().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn can_wait_for_value_computed_elsewhere() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+
+        // another instance holds the lock and inserts the value shortly
+        assert!(app_ctx
+            .cache
+            .driver
+            .insert_if_absent(&super::stampede::lock_key("report"), b"", None)
+            .await
+            .unwrap());
+        let cache = app_ctx.cache.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cache.insert("report", "from elsewhere").await.unwrap();
+        });
+
+        let value = app_ctx
+            .cache
+            .get_or_insert("report", async { Ok("computed".to_string()) })
+            .await
+            .unwrap();
+        assert_eq!(value, "from elsewhere");
+    }
+
+    #[tokio::test]
+    async fn cannot_release_lock_taken_over_by_another_caller() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+        let lock_key = super::stampede::lock_key("report");
+
+        let Ok(super::Lock::Acquired(token)) = app_ctx.cache.lock::<String>("report").await else {
+            panic!("lock not acquired");
+        };
+        // the lock expired and another caller took it over
+        app_ctx
+            .cache
+            .driver
+            .insert(&lock_key, b"another token")
+            .await
+            .unwrap();
+        app_ctx.cache.unlock("report", &token).await;
+        assert!(app_ctx.cache.driver.contains_key(&lock_key).await.unwrap());
+
+        app_ctx.cache.unlock("report", "another token").await;
+        assert!(!app_ctx.cache.driver.contains_key(&lock_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn can_serve_stale_value_while_refreshing() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+        let fresh = Duration::from_millis(100);
+        let stale = Duration::from_secs(60);
+
+        let value = app_ctx
+            .cache
+            .get_or_insert_with_refresh("settings", fresh, stale, async { Ok(1) })
+            .await
+            .unwrap();
+        assert_eq!(value, 1);
+        let value = app_ctx
+            .cache
+            .get_or_insert_with_refresh("settings", fresh, stale, async { Ok(2) })
+            .await
+            .unwrap();
+        assert_eq!(value, 1);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let value = app_ctx
+            .cache
+            .get_or_insert_with_refresh("settings", fresh, stale, async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(3)
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 1);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let value = app_ctx
+            .cache
+            .get_or_insert_with_refresh("settings", fresh, stale, async { Ok(4) })
+            .await
+            .unwrap();
+        assert_eq!(value, 3);
+    }
+
+    #[tokio::test]
+    async fn can_invalidate_tags_across_scopes() {
+        let app_ctx = tests_cfg::app::get_app_context().await;
+        let users = app_ctx.cache.scoped("users");
+
+        users
+            .insert_with_tags("1", "Alice", &["user:1"], None)
+            .await
+            .unwrap();
+        app_ctx
+            .cache
+            .insert_with_tags(
+                "posts:user:1",
+                &vec!["hello"],
+                &["user:1"],
+                Some(Duration::from_secs(60)),
+            )
+            .await
+            .unwrap();
+
+        app_ctx.cache.invalidate_tag("user:1").await.unwrap();
+        assert!(!users.contains_key("1").await.unwrap());
+        assert!(!app_ctx.cache.contains_key("posts:user:1").await.unwrap());
+    }
+
     #[tokio::test]
     async fn can_get_or_insert_generic() {
         let app_ctx = tests_cfg::app::get_app_context().await;
@@ -0,0 +1,137 @@
+//! Building blocks used by [`super::Cache`] to compute a missing value once,
+//! however many callers ask for it at the same time.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OwnedMutexGuard;
+
+/// How long the lock computing a value is held across instances, at most.
+/// Callers waiting for a slower computation stop waiting and compute the
+/// value themselves.
+pub(super) const LOCK_TTL: Duration = Duration::from_secs(5);
+
+/// Delay between two checks of a caller waiting for another instance to
+/// compute a value.
+pub(super) const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Key of the lock held across instances while computing the value of `key`.
+pub(super) fn lock_key(key: &str) -> String {
+    format!("loco:cache:lock:{key}")
+}
+
+/// A token telling the holder of a lock apart from the caller that takes it
+/// over once it expired.
+pub(super) fn lock_token() -> String {
+    crate::hash::random_string(16)
+}
+
+/// Outcome of waiting for the lock computing a value.
+pub(super) enum Lock<T> {
+    /// The caller holds the lock, identified by the token, and computes the
+    /// value.
+    Acquired(String),
+    /// The lock holder inserted the value in the meantime.
+    Ready(T),
+    /// The lock holder took longer than [`LOCK_TTL`].
+    TimedOut,
+}
+
+/// One async lock per key being computed by this instance.
+#[derive(Debug, Default)]
+pub(super) struct KeyLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl KeyLocks {
+    /// Waits until no other caller of this instance holds the lock of `key`.
+    pub(super) async fn lock(&self, key: &str) -> KeyGuard<'_> {
+        let lock = self.locks().entry(key.to_string()).or_default().clone();
+        KeyGuard {
+            locks: self,
+            key: key.to_string(),
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+
+    fn locks(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<tokio::sync::Mutex<()>>>> {
+        self.locks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Releases the lock of a key when dropped, and forgets the key once nobody
+/// waits for it anymore.
+pub(super) struct KeyGuard<'a> {
+    locks: &'a KeyLocks,
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for KeyGuard<'_> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        let mut locks = self.locks.locks();
+        if locks
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+/// A cached value along with the time until which it is fresh.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Stamped<T> {
+    pub(super) value: T,
+    fresh_until: i64,
+}
+
+impl<T> Stamped<T> {
+    pub(super) fn new(value: T, fresh: Duration) -> Self {
+        let fresh = i64::try_from(fresh.as_millis()).unwrap_or(i64::MAX);
+        Self {
+            value,
+            fresh_until: Utc::now().timestamp_millis().saturating_add(fresh),
+        }
+    }
+
+    pub(super) fn is_stale(&self) -> bool {
+        Utc::now().timestamp_millis() >= self.fresh_until
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn can_forget_released_keys() {
+        let locks = Arc::new(KeyLocks::default());
+
+        let guard = locks.lock("key").await;
+        let waiter = tokio::spawn({
+            let locks = locks.clone();
+            async move {
+                drop(locks.lock("key").await);
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        drop(guard);
+        assert_eq!(locks.locks().len(), 1);
+        waiter.await.unwrap();
+        assert!(locks.locks().is_empty());
+    }
+
+    #[test]
+    fn can_tell_stale_values() {
+        assert!(!Stamped::new("value", Duration::from_secs(60)).is_stale());
+        assert!(Stamped::new("value", Duration::ZERO).is_stale());
+    }
+}
@@ -0,0 +1,229 @@
+//! # Cache Statistics
+//!
+//! This module counts the hits, misses, inserts and evictions of a
+//! [`super::Cache`], grouped by key prefix. Drivers shared by the instances
+//! of the application, such as Redis, also keep the counters of all
+//! instances added together.
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Returns the prefix `key` is counted under: the part before its first
+/// `:`, or an empty prefix for keys without one.
+///
+/// # Example
+/// ```
+/// use loco_rs::cache::stats;
+///
+/// assert_eq!(stats::prefix("user:1:profile"), "user");
+/// assert_eq!(stats::prefix("settings"), "");
+/// ```
+#[must_use]
+pub fn prefix(key: &str) -> &str {
+    key.split_once(':').map_or("", |(prefix, _)| prefix)
+}
+
+/// Counters of the keys sharing a prefix.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PrefixStats {
+    /// Reads that found a value.
+    pub hits: u64,
+    /// Reads that found no value.
+    pub misses: u64,
+    /// Values written.
+    pub inserts: u64,
+    /// Values the driver removed by itself, because they expired or to make
+    /// room for others.
+    pub evictions: u64,
+}
+
+impl PrefixStats {
+    /// Returns the share of reads that found a value, or `None` before the
+    /// first read.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let reads = self.hits + self.misses;
+        (reads > 0).then(|| self.hits as f64 / reads as f64)
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.inserts += other.inserts;
+        self.evictions += other.evictions;
+    }
+}
+
+/// Counters of a cache since it was created, by key prefix.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// Counters of each prefix, see [`prefix`].
+    pub prefixes: BTreeMap<String, PrefixStats>,
+}
+
+impl CacheStats {
+    /// Returns the counters of all prefixes added together.
+    #[must_use]
+    pub fn total(&self) -> PrefixStats {
+        self.prefixes
+            .values()
+            .fold(PrefixStats::default(), |mut total, stats| {
+                total.add(stats);
+                total
+            })
+    }
+}
+
+/// Something that happened to a key.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Event {
+    Hit,
+    Miss,
+    Insert,
+}
+
+/// How long the counters of an instance are kept before they are added to
+/// the ones the driver keeps for all instances.
+pub(super) const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Counts the events of a cache, and traces them.
+#[derive(Debug, Default)]
+pub(super) struct Recorder {
+    counters: Mutex<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// Everything recorded since the cache was created.
+    total: HashMap<String, PrefixStats>,
+    /// What was recorded since the last save, and when that started.
+    unsaved: HashMap<String, PrefixStats>,
+    unsaved_since: Option<Instant>,
+}
+
+impl Recorder {
+    pub(super) fn record(&self, key: &str, event: Event) {
+        let prefix = prefix(key);
+        match event {
+            Event::Hit => tracing::trace!(key, prefix, "cache_hit"),
+            Event::Miss => tracing::trace!(key, prefix, "cache_miss"),
+            Event::Insert => tracing::trace!(key, prefix, "cache_insert"),
+        }
+
+        let mut guard = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        let counters = &mut *guard;
+        counters.unsaved_since.get_or_insert_with(Instant::now);
+        for prefixes in [&mut counters.total, &mut counters.unsaved] {
+            let stats = if let Some(stats) = prefixes.get_mut(prefix) {
+                stats
+            } else {
+                prefixes.entry(prefix.to_string()).or_default()
+            };
+            match event {
+                Event::Hit => stats.hits += 1,
+                Event::Miss => stats.misses += 1,
+                Event::Insert => stats.inserts += 1,
+            }
+        }
+    }
+
+    /// Takes the counters recorded since the last save, once they are older
+    /// than [`SAVE_INTERVAL`] or right away when `now` is set.
+    pub(super) fn take_unsaved(&self, now: bool) -> Option<HashMap<String, PrefixStats>> {
+        let mut counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        let since = counters.unsaved_since?;
+        if !now && since.elapsed() < SAVE_INTERVAL {
+            return None;
+        }
+        counters.unsaved_since = None;
+        Some(std::mem::take(&mut counters.unsaved))
+    }
+
+    /// Puts back counters taken by [`Recorder::take_unsaved`] that could not
+    /// be saved, so the next save includes them.
+    pub(super) fn restore_unsaved(&self, unsaved: HashMap<String, PrefixStats>) {
+        let mut counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        counters.unsaved_since.get_or_insert_with(Instant::now);
+        for (prefix, stats) in unsaved {
+            counters.unsaved.entry(prefix).or_default().add(&stats);
+        }
+    }
+
+    /// Returns the counters recorded so far, along with the `evictions`
+    /// reported by the driver.
+    pub(super) fn snapshot(&self, evictions: HashMap<String, u64>) -> CacheStats {
+        let mut prefixes: BTreeMap<String, PrefixStats> = self
+            .counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .total
+            .iter()
+            .map(|(prefix, stats)| (prefix.clone(), *stats))
+            .collect();
+        for (prefix, count) in evictions {
+            prefixes.entry(prefix).or_default().evictions += count;
+        }
+        CacheStats { prefixes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_count_events_by_prefix() {
+        let recorder = Recorder::default();
+        recorder.record("user:1", Event::Miss);
+        recorder.record("user:1", Event::Insert);
+        recorder.record("user:1", Event::Hit);
+        recorder.record("user:2", Event::Hit);
+        recorder.record("settings", Event::Hit);
+
+        let stats = recorder.snapshot(HashMap::from([("user".to_string(), 2)]));
+        assert_eq!(
+            stats.prefixes["user"],
+            PrefixStats {
+                hits: 2,
+                misses: 1,
+                inserts: 1,
+                evictions: 2,
+            }
+        );
+        assert_eq!(stats.prefixes[""].hits, 1);
+        assert_eq!(stats.total().hits, 3);
+        assert_eq!(stats.prefixes["user"].hit_ratio(), Some(2.0 / 3.0));
+        assert_eq!(PrefixStats::default().hit_ratio(), None);
+    }
+
+    #[test]
+    fn can_take_unsaved_counters() {
+        let recorder = Recorder::default();
+        assert_eq!(recorder.take_unsaved(true), None);
+
+        recorder.record("user:1", Event::Hit);
+        assert_eq!(recorder.take_unsaved(false), None);
+        let unsaved = recorder.take_unsaved(true).expect("unsaved counters");
+        assert_eq!(unsaved["user"].hits, 1);
+        assert_eq!(recorder.take_unsaved(true), None);
+
+        // a failed save is retried with the next one
+        recorder.restore_unsaved(unsaved);
+        recorder.record("user:2", Event::Miss);
+        let unsaved = recorder.take_unsaved(true).expect("unsaved counters");
+        assert_eq!(
+            unsaved["user"],
+            PrefixStats {
+                hits: 1,
+                misses: 1,
+                ..PrefixStats::default()
+            }
+        );
+        assert_eq!(recorder.snapshot(HashMap::new()).prefixes["user"].hits, 1);
+    }
+}
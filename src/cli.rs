@@ -25,7 +25,7 @@ use std::process::exit;
 use std::{collections::BTreeMap, path::PathBuf};
 
 #[cfg(any(feature = "bg_redis", feature = "bg_pg", feature = "bg_sqlt"))]
-use crate::bgworker::JobStatus;
+use crate::bgworker::{JobStatus, RetryTarget};
 #[cfg(debug_assertions)]
 use crate::controller;
 use crate::{
@@ -115,6 +115,12 @@ enum Commands {
         #[command(subcommand)]
         command: JobsCommands,
     },
+    /// Inspect and manage the configured cache. Not available for the
+    /// in-memory cache, which only lives in the application process.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
     /// Run the scheduler
     Scheduler {
         /// Run a specific job by its name.
@@ -593,7 +599,7 @@ impl DeploymentKind {
 #[derive(Subcommand)]
 enum JobsCommands {
     /// Cancels jobs with the specified names, setting their status to
-    /// `cancelled`.
+    /// `cancelled`. Jobs that are already running are asked to stop.
     Cancel {
         /// Names of jobs to cancel.
         #[arg(long)]
@@ -638,6 +644,45 @@ enum JobsCommands {
         #[arg(long, default_value_t = 0)]
         from_age: i64,
     },
+    /// Lists the worker processes that are running, and the jobs they hold.
+    Workers {},
+    /// Puts `failed` and `dead` jobs back in the queue.
+    #[command(group(ArgGroup::new("target").required(true).args(&["id", "name", "all_failed"])))]
+    Retry {
+        /// Retry the job with this id.
+        #[arg(long)]
+        id: Option<String>,
+        /// Retry all jobs with this name.
+        #[arg(long)]
+        name: Option<String>,
+        /// Retry every failed and dead job.
+        #[arg(long)]
+        all_failed: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Prints the hits, misses and inserts of every instance of the
+    /// application, and the number of keys, by prefix (the part before the
+    /// first `:`).
+    Stats {},
+    /// Prints the value of a key.
+    Get {
+        /// Key to print.
+        key: String,
+    },
+    /// Removes a key.
+    Del {
+        /// Key to remove.
+        key: String,
+    },
+    /// Removes every key of the cache.
+    Clear {
+        /// Only removes the keys starting with this prefix.
+        #[arg(long)]
+        prefix: Option<String>,
+    },
 }
 
 /// Parse a single key-value pair
@@ -758,6 +803,9 @@ pub async fn main<H: Hooks, M: MigratorTrait>() -> crate::Result<()> {
         Commands::Jobs { command } => {
             handle_job_command::<H>(command, &environment, app_context.config).await?;
         }
+        Commands::Cache { command } => {
+            handle_cache_command(command, &app_context).await?;
+        }
         Commands::Routes {} => {
             let app_context = create_context::<H>(&environment, app_context.config).await?;
             show_list_endpoints::<H>(&app_context);
@@ -924,6 +972,9 @@ pub async fn main<H: Hooks>() -> crate::Result<()> {
         Commands::Jobs { command } => {
             handle_job_command::<H>(command, &environment, app_context.config).await?
         }
+        Commands::Cache { command } => {
+            handle_cache_command(command, &app_context).await?;
+        }
         Commands::Scheduler {
             name,
             config_path,
@@ -1210,6 +1261,99 @@ fn create_root_span(environment: &Environment) -> tracing::Span {
     tracing::span!(tracing::Level::DEBUG, "app", environment = %environment)
 }
 
+async fn handle_cache_command(
+    command: CacheCommands,
+    app_context: &AppContext,
+) -> crate::Result<()> {
+    #[cfg(feature = "cache_inmem")]
+    if matches!(
+        app_context.config.cache,
+        crate::config::CacheConfig::InMem(_)
+    ) {
+        return Err(crate::Error::string(
+            "the in-memory cache only lives in the application process, `cargo loco cache` needs \
+             a Redis cache",
+        ));
+    }
+    let cache = &app_context.cache;
+    match command {
+        CacheCommands::Stats {} => {
+            let mut keys: BTreeMap<String, u64> = BTreeMap::new();
+            cache
+                .driver
+                .for_each_key("", &mut |key| {
+                    // locks, tag sets and counters of the cache itself
+                    if !key.starts_with("loco:cache:") {
+                        *keys
+                            .entry(crate::cache::stats::prefix(&key).to_string())
+                            .or_default() += 1;
+                    }
+                })
+                .await?;
+            let stats = cache.saved_stats().await?.unwrap_or_default();
+            let prefixes = keys
+                .keys()
+                .chain(stats.prefixes.keys())
+                .collect::<std::collections::BTreeSet<_>>();
+
+            let print_row = |prefix: &str, keys: u64, stats: &crate::cache::PrefixStats| {
+                let hit_ratio = stats
+                    .hit_ratio()
+                    .map_or_else(|| "-".to_string(), |ratio| format!("{:.1}%", ratio * 100.0));
+                println!(
+                    "{prefix:<30} {keys:>10} {:>10} {:>10} {:>10} {hit_ratio:>10}",
+                    stats.hits, stats.misses, stats.inserts
+                );
+            };
+            println!(
+                "{:<30} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                "prefix".bold(),
+                "keys".bold(),
+                "hits".bold(),
+                "misses".bold(),
+                "inserts".bold(),
+                "hit ratio".bold()
+            );
+            for prefix in prefixes {
+                print_row(
+                    if prefix.is_empty() { "(none)" } else { prefix },
+                    keys.get(prefix).copied().unwrap_or_default(),
+                    &stats.prefixes.get(prefix).copied().unwrap_or_default(),
+                );
+            }
+            print_row("total", keys.values().sum(), &stats.total());
+        }
+        CacheCommands::Get { key } => match cache.get::<serde_json::Value>(&key).await {
+            Ok(Some(value)) => println!("{}", serde_json::to_string_pretty(&value)?),
+            Ok(None) => println!("`{key}` not found"),
+            // values of a codec that can not be read without their type
+            Err(crate::cache::CacheError::Deserialization(_)) => {
+                if let Some(value) = cache.driver.get(&key).await? {
+                    match std::str::from_utf8(&value) {
+                        Ok(value) => println!("{value}"),
+                        Err(_) => println!("<{} bytes>", value.len()),
+                    }
+                }
+            }
+            Err(err) => return Err(err.into()),
+        },
+        CacheCommands::Del { key } => {
+            cache.remove(&key).await?;
+            println!("`{key}` removed");
+        }
+        CacheCommands::Clear { prefix } => {
+            if let Some(prefix) = prefix {
+                cache.driver.clear_prefix(&prefix).await?;
+                println!("keys starting with `{prefix}` removed");
+            } else {
+                cache.clear().await?;
+                println!("cache cleared");
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(any(feature = "bg_redis", feature = "bg_pg", feature = "bg_sqlt"))]
 async fn handle_job_command<H: Hooks>(
     command: JobsCommands,
@@ -1238,6 +1382,7 @@ async fn handle_job_command<H: Hooks>(
                 || {
                     vec![
                         JobStatus::Failed,
+                        JobStatus::Dead,
                         JobStatus::Cancelled,
                         JobStatus::Queued,
                         JobStatus::Completed,
@@ -1263,6 +1408,35 @@ async fn handle_job_command<H: Hooks>(
         }
         JobsCommands::Import { file } => queue.import(file.as_path()).await,
         JobsCommands::Requeue { from_age } => queue.requeue(from_age).await,
+        JobsCommands::Retry { id, name, .. } => {
+            let target = match (id, name) {
+                (Some(id), _) => RetryTarget::Id(id.clone()),
+                (None, Some(name)) => RetryTarget::Name(name.clone()),
+                (None, None) => RetryTarget::AllFailed,
+            };
+            queue.retry_jobs(&target).await
+        }
+        JobsCommands::Workers {} => {
+            let workers = queue.get_workers().await?;
+            if workers.is_empty() {
+                println!("no workers running");
+            }
+            for worker in workers {
+                println!(
+                    "{} {}:{} tags: [{}] started: {} last heartbeat: {}",
+                    worker.id,
+                    worker.hostname,
+                    worker.pid,
+                    worker.tags.join(", "),
+                    worker.started_at,
+                    worker.heartbeat_at,
+                );
+                for job in &worker.jobs {
+                    println!("  {job}");
+                }
+            }
+            Ok(())
+        }
     }
 }
 
@@ -251,6 +251,9 @@ pub enum CacheConfig {
     #[cfg(feature = "cache_redis")]
     /// Redis cache
     Redis(RedisCacheConfig),
+    #[cfg(all(feature = "cache_inmem", feature = "cache_redis"))]
+    /// In-memory cache in front of a Redis cache
+    Tiered(TieredCacheConfig),
     /// Null cache
     #[default]
     Null,
@@ -271,6 +274,109 @@ pub struct RedisCacheConfig {
     pub uri: String,
     /// Sets the maximum number of connections managed by the pool.
     pub max_size: u32,
+    /// How values are encoded before being sent to Redis.
+    #[serde(default)]
+    pub codec: CacheCodecConfig,
+}
+
+/// Two-tier cache: every instance keeps the values it reads in a small
+/// in-memory cache (L1), in front of the Redis cache shared by all instances
+/// (L2).
+///
+/// Example (development):
+/// ```yaml
+/// # config/development.yaml
+/// cache:
+///   kind: Tiered
+///   uri: redis://127.0.0.1
+///   max_size: 10
+///   l1_max_capacity: 10000
+///   l1_ttl: 5000
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TieredCacheConfig {
+    pub uri: String,
+    /// Sets the maximum number of connections managed by the pool.
+    pub max_size: u32,
+    /// Maximum number of entries kept in memory by each instance.
+    #[serde(default = "cache_tiered_l1_max_capacity")]
+    pub l1_max_capacity: u64,
+    /// Time, in milliseconds, a value read from Redis is served from memory.
+    /// This bounds how stale a value can get if an invalidation is missed.
+    #[serde(default = "cache_tiered_l1_ttl")]
+    pub l1_ttl: u64,
+    /// Redis pub/sub channel used to invalidate the in-memory entries of the
+    /// other instances when keys are written or removed.
+    #[serde(default = "cache_tiered_channel")]
+    pub channel: String,
+    /// How values are encoded before being sent to Redis.
+    #[serde(default)]
+    pub codec: CacheCodecConfig,
+}
+
+/// Encoding of the cached values.
+///
+/// Example (development):
+/// ```yaml
+/// # config/development.yaml
+/// cache:
+///   kind: Redis
+///   uri: redis://127.0.0.1
+///   max_size: 10
+///   codec:
+///     format: MessagePack
+///     compression:
+///       threshold: 1024
+///       level: 3
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CacheCodecConfig {
+    #[serde(default)]
+    pub format: CacheFormat,
+    /// Compresses the values larger than a threshold, requires the
+    /// `cache_zstd` feature.
+    pub compression: Option<CacheCompressionConfig>,
+}
+
+/// Serialization format of the cached values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CacheFormat {
+    #[default]
+    Json,
+    /// Requires the `cache_msgpack` feature
+    MessagePack,
+    /// Requires the `cache_bincode` feature
+    Bincode,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheCompressionConfig {
+    /// Values of at least this size, in bytes, are compressed with zstd.
+    #[serde(default = "cache_compression_threshold")]
+    pub threshold: usize,
+    /// zstd compression level, from 1 to 22.
+    #[serde(default = "cache_compression_level")]
+    pub level: i32,
+}
+
+fn cache_compression_threshold() -> usize {
+    1024
+}
+
+fn cache_compression_level() -> i32 {
+    3
+}
+
+fn cache_tiered_l1_max_capacity() -> u64 {
+    10_000
+}
+
+fn cache_tiered_l1_ttl() -> u64 {
+    5_000
+}
+
+fn cache_tiered_channel() -> String {
+    "loco:cache:invalidate".to_string()
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -282,6 +388,8 @@ pub enum QueueConfig {
     Postgres(PostgresQueueConfig),
     /// Sqlite queue
     Sqlite(SqliteQueueConfig),
+    /// In-memory queue
+    InMem(InMemQueueConfig),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -320,6 +428,9 @@ pub struct PostgresQueueConfig {
     #[serde(default = "db_idle_timeout")]
     pub idle_timeout: u64,
 
+    /// How often workers look for jobs whose `run_at` has come, and for
+    /// running jobs that were cancelled. Workers also `LISTEN` for new jobs
+    /// and wake up right away, holding one connection of the pool.
     #[serde(default = "pgq_poll_interval")]
     pub poll_interval_sec: u32,
 
@@ -356,6 +467,24 @@ pub struct SqliteQueueConfig {
     pub num_workers: u32,
 }
 
+/// Queue kept in the memory of the running process. Jobs don't survive a
+/// restart and are only seen by workers of the same process.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InMemQueueConfig {
+    /// How often workers look for jobs whose `run_at` has come, and for
+    /// running jobs that were cancelled. New jobs wake workers right away.
+    #[serde(default = "inmem_poll_interval")]
+    pub poll_interval_sec: u32,
+
+    #[serde(default = "num_workers")]
+    pub num_workers: u32,
+
+    /// How many completed and cancelled jobs are kept around for
+    /// `get_job` and `jobs` commands. Past that, the oldest ones are dropped.
+    #[serde(default = "inmem_max_finished_jobs")]
+    pub max_finished_jobs: usize,
+}
+
 fn db_min_conn() -> u32 {
     1
 }
@@ -380,6 +509,14 @@ fn sqlt_poll_interval() -> u32 {
     1
 }
 
+fn inmem_poll_interval() -> u32 {
+    1
+}
+
+fn inmem_max_finished_jobs() -> usize {
+    1000
+}
+
 fn num_workers() -> u32 {
     2
 }
@@ -495,11 +632,17 @@ impl Server {
 /// # config/development.yaml
 /// workers:
 ///   mode: BackgroundQueue
+///   shutdown_timeout: 30000
 /// ```
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct Workers {
     /// Toggle between different worker modes
     pub mode: WorkerMode,
+    /// How long, in milliseconds, running jobs may take to finish once the
+    /// queue shuts down. Jobs still running after that are stopped and put
+    /// back in the queue for another worker. When not set, shutdown waits for
+    /// running jobs to finish.
+    pub shutdown_timeout: Option<u64>,
 }
 
 /// Worker mode configuration
@@ -85,6 +85,8 @@ pub mod format;
 pub mod middleware;
 pub mod monitoring;
 mod routes;
+#[cfg(feature = "storage_signed_urls")]
+pub mod storage;
 pub mod views;
 
 /// Create an unauthorized error with a specified message.
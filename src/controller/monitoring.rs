@@ -70,6 +70,13 @@ pub async fn readiness(State(ctx): State<AppContext>) -> Result<Response> {
                     return format::json(Health { ok: false });
                 }
             }
+            #[cfg(all(feature = "cache_inmem", feature = "cache_redis"))]
+            config::CacheConfig::Tiered(_) => {
+                if let Err(error) = &ctx.cache.driver.ping().await {
+                    tracing::error!(err.msg = %error, err.detail = ?error, "readiness_cache_ping_error");
+                    return format::json(Health { ok: false });
+                }
+            }
             config::CacheConfig::Null => (),
         }
     }
@@ -284,6 +291,7 @@ mod tests {
         let redis_cache = cache::drivers::redis::new(&config::RedisCacheConfig {
             uri: redis_url,
             max_size: 10,
+            codec: config::CacheCodecConfig::default(),
         })
         .await
         .expect("Failed to create Redis cache");
@@ -322,11 +330,13 @@ mod tests {
         ctx.config.cache = config::CacheConfig::Redis(loco_rs::config::RedisCacheConfig {
             uri: failour_redis_url.to_string(),
             max_size: 10,
+            codec: config::CacheCodecConfig::default(),
         });
         // Create Redis cache driver and assign to ctx.cache
         ctx.cache = cache::drivers::redis::new(&config::RedisCacheConfig {
             uri: failour_redis_url.to_string(),
             max_size: 10,
+            codec: config::CacheCodecConfig::default(),
         })
         .await
         .expect("Failed to create Redis cache")
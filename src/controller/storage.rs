@@ -0,0 +1,221 @@
+//! This module serves the presigned requests of the stores that sign their
+//! own URLs with a [`UrlSigner`](crate::storage::presign::UrlSigner), such as
+//! the local and in-memory stores. Cloud stores hand out URLs of the cloud
+//! service instead.
+//!
+//! The routes are not part of the default routes, and are added with
+//! `AppRoutes::add_route(loco_rs::controller::storage::routes())`.
+
+use std::path::Path as StdPath;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::Response,
+    routing::get,
+};
+use futures_util::TryStreamExt;
+
+use super::{format, routes::Routes, ErrorDetail};
+use crate::{
+    app::AppContext,
+    storage::{drivers::StoreDriver, presign::SignedQuery, stream::BytesStream},
+    Error, Result,
+};
+
+/// Streams the content of a presigned download.
+///
+/// # Errors
+///
+/// Returns a not found error if the store does not sign URLs, and a
+/// forbidden error if the URL is not signed or has expired.
+pub async fn download(
+    State(ctx): State<AppContext>,
+    Path((store, path)): Path<(String, String)>,
+    Query(query): Query<SignedQuery>,
+) -> Result<Response> {
+    let store = signed_store(&ctx, &store, &Method::GET, &path, &query)?;
+    let stream = store.get_stream(StdPath::new(&path)).await?;
+    Ok(Response::new(stream.into_body()))
+}
+
+/// Stores the body of a presigned upload.
+///
+/// # Errors
+///
+/// Returns a not found error if the store does not sign URLs, and a
+/// forbidden error if the URL is not signed, has expired or was signed for
+/// another content type.
+pub async fn upload(
+    State(ctx): State<AppContext>,
+    Path((store, path)): Path<(String, String)>,
+    Query(query): Query<SignedQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response> {
+    let store = signed_store(&ctx, &store, &Method::PUT, &path, &query)?;
+    if let Some(content_type) = &query.content_type {
+        if headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            != Some(content_type.as_str())
+        {
+            return Err(forbidden("content type does not match the signed one"));
+        }
+    }
+
+    let stream = BytesStream::from_body_stream(
+        body.into_data_stream()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)),
+    );
+    store.upload_stream(StdPath::new(&path), stream).await?;
+    format::empty()
+}
+
+/// Returns the store named `store` if it signed this request.
+fn signed_store<'a>(
+    ctx: &'a AppContext,
+    store: &str,
+    method: &Method,
+    path: &str,
+    query: &SignedQuery,
+) -> Result<&'a dyn StoreDriver> {
+    let store = ctx.storage.as_store(store).ok_or(Error::NotFound)?;
+    let signer = store.url_signer().ok_or(Error::NotFound)?;
+    if !signer.verify(method, path, query) {
+        return Err(forbidden("invalid or expired signature"));
+    }
+    Ok(store)
+}
+
+fn forbidden(description: &str) -> Error {
+    Error::CustomError(
+        StatusCode::FORBIDDEN,
+        ErrorDetail::new("forbidden", description),
+    )
+}
+
+/// Defines and returns the routes serving presigned requests.
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("/_storage")
+        .add("/{store}/{*path}", get(download).put(upload))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{http::Request, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        storage::{drivers::mem, presign::UrlSigner, Storage},
+        tests_cfg,
+    };
+
+    async fn router() -> (axum::Router, AppContext) {
+        let mut ctx = tests_cfg::app::get_app_context().await;
+        let signer = UrlSigner::new("http://localhost/_storage", "store", "secret");
+        ctx.storage = Storage::single(mem::with_signed_urls(signer)).into();
+        let router = axum::Router::new()
+            .route("/_storage/{store}/{*path}", get(download).put(upload))
+            .with_state(ctx.clone());
+        (router, ctx)
+    }
+
+    fn request(method: Method, url: &str, content_type: Option<&str>, body: Body) -> Request<Body> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(url.trim_start_matches("http://localhost"));
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        request.body(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn can_upload_and_download_with_presigned_urls() {
+        let (router, ctx) = router().await;
+        let store = ctx.storage.as_store("store").unwrap();
+        let path = StdPath::new("users/1 notes.txt");
+
+        let put = store
+            .presign_put(path, Duration::from_secs(60), Some("text/plain"))
+            .await
+            .unwrap();
+        assert_eq!(put.method, Method::PUT);
+        let response = router
+            .clone()
+            .oneshot(request(
+                Method::PUT,
+                &put.url,
+                Some("text/plain"),
+                Body::from("hello"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let get = store
+            .presign_get(path, Duration::from_secs(60))
+            .await
+            .unwrap();
+        let response = router
+            .oneshot(request(Method::GET, &get.url, None, Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    async fn can_not_use_invalid_presigned_urls() {
+        let (router, ctx) = router().await;
+        let store = ctx.storage.as_store("store").unwrap();
+        let path = StdPath::new("1.txt");
+        let put = store
+            .presign_put(path, Duration::from_secs(60), Some("text/plain"))
+            .await
+            .unwrap();
+
+        for (method, url, content_type, status) in [
+            // another content type
+            (
+                Method::PUT,
+                put.url.clone(),
+                Some("text/html"),
+                StatusCode::FORBIDDEN,
+            ),
+            // another method
+            (Method::GET, put.url.clone(), None, StatusCode::FORBIDDEN),
+            // another path
+            (
+                Method::PUT,
+                put.url.replace("1.txt", "2.txt"),
+                Some("text/plain"),
+                StatusCode::FORBIDDEN,
+            ),
+            // another store
+            (
+                Method::PUT,
+                put.url.replace("/store/", "/other/"),
+                Some("text/plain"),
+                StatusCode::NOT_FOUND,
+            ),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(request(method, &url, content_type, Body::from("hello")))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{url}");
+        }
+        assert!(!store.exists(path).await.unwrap());
+    }
+}
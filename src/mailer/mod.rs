@@ -129,6 +129,12 @@ impl BackgroundWorker<Email> for MailerWorker {
         Some("mailer".to_string())
     }
 
+    /// SMTP errors are frequently transient, so give delivery a few more
+    /// chances before giving up.
+    fn max_retries() -> u32 {
+        3
+    }
+
     fn build(ctx: &AppContext) -> Self {
         Self { ctx: ctx.clone() }
     }
@@ -5,16 +5,21 @@ use std::{
     collections::HashMap,
     fmt, io,
     path::{Path, PathBuf},
-    sync::OnceLock,
+    sync::{Arc, OnceLock},
     time::{Duration, Instant},
 };
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tokio_cron_scheduler::{JobScheduler, JobSchedulerError};
+use tracing::Instrument;
 use uuid::Uuid;
 
-use crate::{app::Hooks, environment::Environment, task::Tasks};
+use crate::{app::Hooks, bgworker::Queue, environment::Environment, task::Tasks};
+
+/// How long a scheduler instance keeps a job to itself after enqueuing it.
+/// Other instances sharing the queue skip the ticks of that job meanwhile.
+const LOCK_TTL: Duration = Duration::from_secs(30);
 
 static RE_IS_CRON_SYNTAX: OnceLock<Regex> = OnceLock::new();
 
@@ -31,6 +36,12 @@ pub enum Error {
     #[error("task `{0}` not found")]
     TaskNotFound(String),
 
+    #[error("job `{0}` can't be both a shell command and a worker")]
+    ShellWorker(String),
+
+    #[error("job `{0}` enqueues a worker, but no queue provider is configured")]
+    QueueNotConfigured(String),
+
     #[error("Scheduler config file not found in path: '{}'", path.display())]
     ConfigNotFound { path: PathBuf, error: io::Error },
 
@@ -67,9 +78,17 @@ pub struct Config {
 pub struct Job {
     /// The command to run.
     /// In case of task: it should be a task name and also task arguments
+    /// In case of worker: it should be the class name of the worker
     pub run: String,
     #[serde(default)]
     pub shell: bool,
+    /// Enqueue the worker named by `run` instead of running a command.
+    #[serde(default)]
+    pub worker: bool,
+    /// Arguments of the enqueued worker, `null` when not set.
+    pub args: Option<serde_json::Value>,
+    /// Queue to enqueue the worker to, the default queue when not set.
+    pub queue: Option<String>,
     #[serde(default)]
     pub run_on_start: bool,
     #[serde(rename = "schedule")]
@@ -123,6 +142,9 @@ pub struct Scheduler {
     binary_path: PathBuf,
     default_output: Output,
     environment: Environment,
+    queue_provider: Option<Arc<Queue>>,
+    /// Identifies this instance when locking jobs in the queue.
+    id: String,
 }
 
 /// Specification used to filter all scheduler job with the given Spec.
@@ -238,7 +260,9 @@ impl Scheduler {
 
         let mut jobs = HashMap::new();
         for (job_name, job) in &data.jobs {
-            if job.shell {
+            if job.shell && job.worker {
+                return Err(Error::ShellWorker(job_name.clone()));
+            } else if job.shell || job.worker {
                 jobs.insert(job_name.clone(), job.clone());
             } else {
                 let task_name = job.run.split_whitespace().next().unwrap_or("");
@@ -259,9 +283,20 @@ impl Scheduler {
             binary_path: std::env::current_exe()?,
             default_output: data.output.clone(),
             environment: environment.clone(),
+            queue_provider: None,
+            id: Uuid::new_v4().to_string(),
         })
     }
 
+    /// Sets the queue that jobs with `worker: true` are enqueued to.
+    #[must_use]
+    pub fn with_queue_provider(self, queue_provider: Arc<Queue>) -> Self {
+        Self {
+            queue_provider: Some(queue_provider),
+            ..self
+        }
+    }
+
     /// Filters the scheduler's jobs based on the provided specification.
     #[must_use]
     pub fn by_spec(self, include_jobs: &Spec) -> Self {
@@ -288,6 +323,10 @@ impl Scheduler {
 
     /// Runs the scheduled jobs according to their cron expressions.
     ///
+    /// Jobs with `worker: true` are enqueued to the queue provider. When
+    /// several scheduler instances share the queue, only one of them enqueues
+    /// each tick of a job.
+    ///
     /// # Errors
     ///
     /// When could not add job to the scheduler
@@ -295,8 +334,23 @@ impl Scheduler {
         let mut sched = JobScheduler::new().await?;
 
         for (job_name, job) in &self.jobs {
-            let job_description =
-                job.prepare_command(&self.binary_path, &self.default_output, &self.environment);
+            let action = if job.worker {
+                let queue = self
+                    .queue_provider
+                    .clone()
+                    .ok_or_else(|| Error::QueueNotConfigured(job_name.clone()))?;
+                Action::Enqueue {
+                    queue,
+                    owner: self.id.clone(),
+                    job: job.clone(),
+                }
+            } else {
+                Action::Command(job.prepare_command(
+                    &self.binary_path,
+                    &self.default_output,
+                    &self.environment,
+                ))
+            };
 
             let cron_syntax = if get_re_is_cron_syntax().is_match(&job.cron) {
                 job.cron.clone()
@@ -310,16 +364,16 @@ impl Scheduler {
             };
 
             if job.run_on_start {
-                let job_description = job_description.clone();
+                let action = action.clone();
                 let job_name = job_name.clone();
                 sched
                     .add(tokio_cron_scheduler::Job::new_one_shot_async(
                         Duration::from_secs(0),
                         move |uuid, _l| {
-                            let job_description = job_description.clone();
+                            let action = action.clone();
                             let job_name = job_name.clone();
                             Box::pin(async move {
-                                execute_job(job_name.as_str(), uuid, &job_description);
+                                action.execute(job_name.as_str(), uuid).await;
                             })
                         },
                     )?)
@@ -331,10 +385,10 @@ impl Scheduler {
                 .add(tokio_cron_scheduler::Job::new_async(
                     cron_syntax.as_str(),
                     move |uuid, mut _l| {
-                        let job_description = job_description.clone();
+                        let action = action.clone();
                         let job_name = job_name.clone();
                         Box::pin(async move {
-                            execute_job(job_name.as_str(), uuid, &job_description);
+                            action.execute(job_name.as_str(), uuid).await;
                         })
                     },
                 )?)
@@ -350,6 +404,76 @@ impl Scheduler {
     }
 }
 
+/// What a scheduler job does on each tick.
+#[derive(Clone)]
+enum Action {
+    /// Runs a task or shell command in a child process.
+    Command(JobDescription),
+    /// Enqueues a worker, unless another scheduler instance owns the job.
+    Enqueue {
+        queue: Arc<Queue>,
+        owner: String,
+        job: Job,
+    },
+}
+
+impl Action {
+    async fn execute(&self, job_name: &str, uuid: Uuid) {
+        match self {
+            Self::Command(job_description) => execute_job(job_name, uuid, job_description),
+            Self::Enqueue { queue, owner, job } => {
+                let task_span = tracing::span!(
+                    tracing::Level::DEBUG,
+                    "enqueue_job",
+                    job_name,
+                    job_id = ?uuid,
+                );
+                match enqueue_job(queue, owner, job_name, job)
+                    .instrument(task_span)
+                    .await
+                {
+                    Ok(Some(id)) => {
+                        tracing::debug!(queue_job_id = id, "enqueued scheduler job");
+                    }
+                    Ok(None) => {
+                        tracing::debug!(
+                            job_name,
+                            "scheduler job is owned by another scheduler instance, skipping"
+                        );
+                    }
+                    Err(err) => {
+                        tracing::error!(job_name, error = %err, "failed to enqueue scheduler job");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Enqueues the worker of `job` when `owner` holds the lock of the job,
+/// returning the id of the enqueued job.
+async fn enqueue_job(
+    queue: &Queue,
+    owner: &str,
+    job_name: &str,
+    job: &Job,
+) -> crate::Result<Option<String>> {
+    if !queue
+        .try_lock(&format!("scheduler:{job_name}"), owner, LOCK_TTL)
+        .await?
+    {
+        return Ok(None);
+    }
+    queue
+        .enqueue(
+            job.run.clone(),
+            job.queue.clone(),
+            job.args.clone().unwrap_or_default(),
+            None,
+        )
+        .await
+}
+
 fn execute_job(job_name: &str, uuid: Uuid, job_description: &JobDescription) {
     let task_span = tracing::span!(
         tracing::Level::DEBUG,
@@ -496,6 +620,9 @@ jobs:
         let job = Job {
             run: run.to_string(),
             shell,
+            worker: false,
+            args: None,
+            queue: None,
             run_on_start: false,
             cron: "*/5 * * * * *".to_string(),
             tags: None,
@@ -513,6 +640,94 @@ jobs:
         );
     }
 
+    #[test]
+    pub fn can_not_load_shell_worker_job() {
+        let config: Config = serde_yaml::from_str(
+            r"
+jobs:
+  report:
+    run: ReportWorker
+    shell: true
+    worker: true
+    schedule: every 1 hour
+",
+        )
+        .expect("parse scheduler config");
+
+        assert!(matches!(
+            Scheduler::new::<AppHook>(&config, &Environment::Test),
+            Err(Error::ShellWorker(name)) if name == "report"
+        ));
+    }
+
+    #[tokio::test]
+    pub async fn can_not_run_worker_job_without_queue() {
+        let config: Config = serde_yaml::from_str(
+            r"
+jobs:
+  report:
+    run: ReportWorker
+    worker: true
+    schedule: every 1 hour
+",
+        )
+        .expect("parse scheduler config");
+        let scheduler =
+            Scheduler::new::<AppHook>(&config, &Environment::Test).expect("create scheduler");
+
+        assert!(matches!(
+            scheduler.run().await,
+            Err(Error::QueueNotConfigured(name)) if name == "report"
+        ));
+    }
+
+    #[cfg(feature = "bg_inmem")]
+    #[tokio::test]
+    pub async fn can_enqueue_worker_once_per_tick() {
+        use crate::{bgworker::inmem, config::InMemQueueConfig};
+
+        let queue = inmem::create_provider(&InMemQueueConfig {
+            poll_interval_sec: 1,
+            num_workers: 1,
+            max_finished_jobs: 100,
+        });
+        let job = Job {
+            run: "ReportWorker".to_string(),
+            shell: false,
+            worker: true,
+            args: Some(serde_json::json!({"user_id": 1})),
+            queue: None,
+            run_on_start: false,
+            cron: "*/5 * * * * *".to_string(),
+            tags: None,
+            output: None,
+        };
+
+        let first = enqueue_job(&queue, "scheduler-1", "report", &job)
+            .await
+            .expect("enqueue")
+            .expect("job id");
+        // another scheduler instance sharing the queue skips the tick
+        assert_eq!(
+            enqueue_job(&queue, "scheduler-2", "report", &job)
+                .await
+                .expect("enqueue"),
+            None
+        );
+        let second = enqueue_job(&queue, "scheduler-1", "report", &job)
+            .await
+            .expect("enqueue")
+            .expect("job id");
+        assert_ne!(first, second);
+
+        let Queue::InMem(store, _, _, _) = &queue else {
+            panic!("expected an in-memory queue");
+        };
+        let enqueued = inmem::get_job(store, &first).expect("job exists");
+        assert_eq!(enqueued.name, "ReportWorker");
+        assert_eq!(enqueued.data, serde_json::json!({"user_id": 1}));
+    }
+
     #[tokio::test]
     pub async fn can_run() {
         let (mut scheduler, _config_tree) = setup_scheduler_config();
@@ -556,6 +771,9 @@ jobs:
                         tree_fs.root.join("scheduler.txt").display()
                     ),
                     shell: true,
+                    worker: false,
+                    args: None,
+                    queue: None,
                     run_on_start: false,
                     cron: "run every 1 second".to_string(),
                     tags: None,
@@ -570,6 +788,9 @@ jobs:
                         tree_fs.root.join("scheduler2.txt").display()
                     ),
                     shell: true,
+                    worker: false,
+                    args: None,
+                    queue: None,
                     run_on_start: false,
                     cron: "* * * * * ? *".to_string(),
                     tags: None,
@@ -584,6 +805,9 @@ jobs:
                         tree_fs.root.join("scheduler3.txt").display()
                     ),
                     shell: true,
+                    worker: false,
+                    args: None,
+                    queue: None,
                     run_on_start: true,
                     cron: "0 0 * * * * *".to_string(),
                     tags: None,
@@ -118,3 +118,50 @@ pub fn with_failure() -> Box<dyn StoreDriver> {
 
     Box::new(OpendalAdapter::new(Operator::new(s3).unwrap().finish()))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use axum::http::Method;
+
+    use super::*;
+
+    fn store() -> Box<dyn StoreDriver> {
+        with_credentials_and_endpoint(
+            "loco-test",
+            "us-east-1",
+            "http://127.0.0.1:9000",
+            Credential {
+                key_id: "key-id".to_string(),
+                secret_key: "secret-key".to_string(),
+                token: None,
+            },
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn can_presign_requests() {
+        let store = store();
+        let path = Path::new("users/1.png");
+
+        let download = store
+            .presign_get(path, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(download.method, Method::GET);
+        assert!(download
+            .url
+            .starts_with("http://127.0.0.1:9000/loco-test/users/1.png?"));
+        assert!(download.url.contains("X-Amz-Expires=60"));
+        assert!(download.url.contains("X-Amz-Signature="));
+
+        let upload = store
+            .presign_put(path, Duration::from_secs(60), Some("image/png"))
+            .await
+            .unwrap();
+        assert_eq!(upload.method, Method::PUT);
+        assert!(upload.url.contains("X-Amz-Signature="));
+    }
+}
@@ -30,3 +30,41 @@ pub fn new(
         Operator::new(azure)?.finish(),
     )))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use axum::http::Method;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn can_presign_requests() {
+        let store = new(
+            "loco-test",
+            "account",
+            "c2VjcmV0LWtleQ==",
+            "http://127.0.0.1:10000/account",
+        )
+        .unwrap();
+        let path = Path::new("users/1.png");
+
+        let download = store
+            .presign_get(path, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(download.method, Method::GET);
+        assert!(download
+            .url
+            .starts_with("http://127.0.0.1:10000/account/loco-test/users/1.png?"));
+        assert!(download.url.contains("sig="));
+
+        let upload = store
+            .presign_put(path, Duration::from_secs(60), None)
+            .await
+            .unwrap();
+        assert_eq!(upload.method, Method::PUT);
+        assert!(upload.url.contains("sig="));
+    }
+}
@@ -21,3 +21,35 @@ pub fn new(bucket_name: &str, credential_path: &str) -> StorageResult<Box<dyn St
 
     Ok(Box::new(OpendalAdapter::new(Operator::new(gcs)?.finish())))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use axum::http::Method;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn can_presign_requests() {
+        let store = new("loco-test", "tests/fixtures/storage/gcp_credential.json").unwrap();
+        let path = Path::new("users/1.png");
+
+        let download = store
+            .presign_get(path, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(download.method, Method::GET);
+        assert!(download
+            .url
+            .starts_with("https://storage.googleapis.com/loco-test/users/1.png?"));
+        assert!(download.url.contains("X-Goog-Signature="));
+
+        let upload = store
+            .presign_put(path, Duration::from_secs(60), None)
+            .await
+            .unwrap();
+        assert_eq!(upload.method, Method::PUT);
+        assert!(upload.url.contains("X-Goog-Signature="));
+    }
+}
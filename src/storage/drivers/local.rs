@@ -1,6 +1,8 @@
 use opendal::{services::Fs, Operator};
 
 use super::StoreDriver;
+#[cfg(feature = "storage_signed_urls")]
+use crate::storage::presign::UrlSigner;
 use crate::storage::{drivers::opendal_adapter::OpendalAdapter, StorageResult};
 
 /// Create new filesystem storage with no prefix
@@ -39,3 +41,28 @@ pub fn new_with_prefix(prefix: impl AsRef<std::path::Path>) -> StorageResult<Box
     let fs = Fs::default().root(&prefix.as_ref().display().to_string());
     Ok(Box::new(OpendalAdapter::new(Operator::new(fs)?.finish())))
 }
+
+/// Create new filesystem storage with `prefix` applied to all paths, whose
+/// presigned requests are signed by `signer` and served by
+/// [`crate::controller::storage`].
+///
+/// # Examples
+///```
+/// use loco_rs::storage::{drivers::local, presign::UrlSigner};
+/// let signer = UrlSigner::new("http://localhost:5150/_storage", "store", "secret");
+/// let file_system_driver = local::with_signed_urls("users", signer);
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the path does not exist
+#[cfg(feature = "storage_signed_urls")]
+pub fn with_signed_urls(
+    prefix: impl AsRef<std::path::Path>,
+    signer: UrlSigner,
+) -> StorageResult<Box<dyn StoreDriver>> {
+    let fs = Fs::default().root(&prefix.as_ref().display().to_string());
+    Ok(Box::new(
+        OpendalAdapter::new(Operator::new(fs)?.finish()).with_url_signer(signer),
+    ))
+}
@@ -2,6 +2,8 @@ use opendal::{services::Memory, Operator};
 
 use super::StoreDriver;
 use crate::storage::drivers::opendal_adapter::OpendalAdapter;
+#[cfg(feature = "storage_signed_urls")]
+use crate::storage::presign::UrlSigner;
 
 /// Create new in-memory storage.
 ///
@@ -16,9 +18,32 @@ use crate::storage::drivers::opendal_adapter::OpendalAdapter;
 /// Panics if the memory service built failed.
 #[must_use]
 pub fn new() -> Box<dyn StoreDriver> {
-    Box::new(OpendalAdapter::new(
+    Box::new(operator())
+}
+
+/// Create new in-memory storage, whose presigned requests are signed by
+/// `signer` and served by [`crate::controller::storage`].
+///
+/// # Examples
+///```
+/// use loco_rs::storage::{drivers::mem, presign::UrlSigner};
+/// let signer = UrlSigner::new("http://localhost:5150/_storage", "store", "secret");
+/// let mem_storage = mem::with_signed_urls(signer);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the memory service built failed.
+#[cfg(feature = "storage_signed_urls")]
+#[must_use]
+pub fn with_signed_urls(signer: UrlSigner) -> Box<dyn StoreDriver> {
+    Box::new(operator().with_url_signer(signer))
+}
+
+fn operator() -> OpendalAdapter {
+    OpendalAdapter::new(
         Operator::new(Memory::default())
             .expect("memory service must build with success")
             .finish(),
-    ))
+    )
 }
@@ -1,7 +1,13 @@
-use std::path::Path;
+use std::{
+    ops::{Bound, Range},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use async_trait::async_trait;
+use axum::http::{HeaderMap, Method};
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use opendal::Reader;
 
 #[cfg(feature = "storage_aws_s3")]
@@ -15,7 +21,19 @@ pub mod mem;
 pub mod null;
 pub mod opendal_adapter;
 
-use super::{stream::BytesStream, StorageResult};
+#[cfg(feature = "storage_signed_urls")]
+use super::presign::UrlSigner;
+use super::{stream::BytesStream, StorageError, StorageResult};
+
+/// A request a client can send to a store until it expires, without going
+/// through the application.
+#[derive(Debug, Clone)]
+pub struct PresignedRequest {
+    pub method: Method,
+    pub url: String,
+    /// Headers the client must send along with the request.
+    pub headers: HeaderMap,
+}
 
 #[derive(Debug)]
 pub struct UploadResponse {
@@ -23,17 +41,35 @@ pub struct UploadResponse {
     pub version: Option<String>,
 }
 
-/// TODO: Add more methods to `GetResponse` to read the content in different
-/// ways
-///
-/// For example, we can read a specific range of bytes from the stream.
+/// Metadata of an object in the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMetadata {
+    /// Size of the content, in bytes.
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub e_tag: Option<String>,
+}
+
+/// Content retrieved from the object store, either whole or a range of it.
 pub struct GetResponse {
     stream: Reader,
+    range: (Bound<u64>, Bound<u64>),
 }
 
 impl GetResponse {
     pub(crate) fn new(stream: Reader) -> Self {
-        Self { stream }
+        Self {
+            stream,
+            range: (Bound::Unbounded, Bound::Unbounded),
+        }
+    }
+
+    pub(crate) fn with_range(stream: Reader, range: Range<u64>) -> Self {
+        Self {
+            stream,
+            range: (Bound::Included(range.start), Bound::Excluded(range.end)),
+        }
     }
 
     /// Read all content from the stream and return as `Bytes`.
@@ -42,7 +78,7 @@ impl GetResponse {
     ///
     /// Returns a `StorageError` with the reason for the failure.
     pub async fn bytes(&self) -> StorageResult<Bytes> {
-        Ok(self.stream.read(..).await?.to_bytes())
+        Ok(self.stream.read(self.range).await?.to_bytes())
     }
 
     /// Convert the response into a streaming bytes reader.
@@ -54,7 +90,7 @@ impl GetResponse {
     ///
     /// Returns a `StorageError` if the stream cannot be created.
     pub async fn into_stream(self) -> StorageResult<BytesStream> {
-        BytesStream::from_reader(self.stream).await
+        BytesStream::from_reader(self.stream, self.range).await
     }
 }
 
@@ -107,6 +143,88 @@ pub trait StoreDriver: Sync + Send {
     /// content.
     async fn exists(&self, path: &Path) -> StorageResult<bool>;
 
+    /// Lists the paths of the content stored under the `prefix` directory,
+    /// including its subdirectories, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the paths found, or an error if the
+    /// store can not list its content.
+    async fn list(&self, _prefix: &Path) -> StorageResult<Vec<PathBuf>> {
+        Err(StorageError::Any(
+            "Listing content is not supported by this store".into(),
+        ))
+    }
+
+    /// Retrieves the size, content type, last modification time and entity
+    /// tag of the content at the specified path. The store may not know all
+    /// of them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the metadata, or an error if the
+    /// content does not exist or the store can not retrieve metadata.
+    async fn metadata(&self, _path: &Path) -> StorageResult<ObjectMetadata> {
+        Err(StorageError::Any(
+            "Metadata is not supported by this store".into(),
+        ))
+    }
+
+    /// Retrieves the bytes of `range` from the content at the specified path,
+    /// e.g. to resume a download.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the result of the retrieval operation,
+    /// or an error if `range` starts after its end or the store can not
+    /// retrieve ranges.
+    async fn get_range(&self, _path: &Path, _range: Range<u64>) -> StorageResult<GetResponse> {
+        Err(StorageError::Any(
+            "Range requests are not supported by this store".into(),
+        ))
+    }
+
+    /// Creates a request a client can send until `ttl` elapses to download
+    /// the content at the specified path, without going through the
+    /// application.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the presigned request, or an error if
+    /// the store can not presign requests.
+    async fn presign_get(&self, _path: &Path, _ttl: Duration) -> StorageResult<PresignedRequest> {
+        Err(StorageError::Any(
+            "Presigned requests are not supported by this store".into(),
+        ))
+    }
+
+    /// Creates a request a client can send until `ttl` elapses to upload
+    /// content to the specified path, without going through the application.
+    /// When `content_type` is given, the client must send it as the
+    /// `Content-Type` of the upload.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the presigned request, or an error if
+    /// the store can not presign requests.
+    async fn presign_put(
+        &self,
+        _path: &Path,
+        _ttl: Duration,
+        _content_type: Option<&str>,
+    ) -> StorageResult<PresignedRequest> {
+        Err(StorageError::Any(
+            "Presigned requests are not supported by this store".into(),
+        ))
+    }
+
+    /// Returns the signer of the URLs served by
+    /// [`crate::controller::storage`] for this store, if any.
+    #[cfg(feature = "storage_signed_urls")]
+    fn url_signer(&self) -> Option<&UrlSigner> {
+        None
+    }
+
     /// Retrieves content from the specified path and returns it as a stream.
     /// This method is more memory-efficient than `get()` for large files as it
     /// doesn't load the entire content into memory.
@@ -146,7 +264,7 @@ pub trait StoreDriver: Sync + Send {
         let bytes = stream
             .collect()
             .await
-            .map_err(|e| super::StorageError::Any(Box::new(e)))?;
+            .map_err(|e| StorageError::Any(Box::new(e)))?;
         self.upload(path, &bytes).await
     }
 }
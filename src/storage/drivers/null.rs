@@ -4,12 +4,15 @@
 //! Loco framework is initialized. The primary purpose of this driver is to
 //! simplify the user workflow by avoiding the need for feature flags or
 //! optional storage driver configurations.
-use std::path::Path;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use async_trait::async_trait;
 use bytes::Bytes;
 
-use super::{GetResponse, StorageResult, StoreDriver, UploadResponse};
+use super::{GetResponse, ObjectMetadata, StorageResult, StoreDriver, UploadResponse};
 use crate::storage::StorageError;
 
 pub struct NullStorage {}
@@ -92,4 +95,37 @@ impl StoreDriver for NullStorage {
             "Operation not supported by null storage".into(),
         ))
     }
+
+    /// Lists the paths of the content stored under the `prefix` directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the paths found.
+    async fn list(&self, _prefix: &Path) -> StorageResult<Vec<PathBuf>> {
+        Err(StorageError::Any(
+            "Operation not supported by null storage".into(),
+        ))
+    }
+
+    /// Retrieves the metadata of the content at the specified path.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the metadata.
+    async fn metadata(&self, _path: &Path) -> StorageResult<ObjectMetadata> {
+        Err(StorageError::Any(
+            "Operation not supported by null storage".into(),
+        ))
+    }
+
+    /// Retrieves a range of bytes from the content at the specified path.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the result of the retrieval operation.
+    async fn get_range(&self, _path: &Path, _range: Range<u64>) -> StorageResult<GetResponse> {
+        Err(StorageError::Any(
+            "Operation not supported by null storage".into(),
+        ))
+    }
 }
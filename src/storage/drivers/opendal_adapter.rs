@@ -1,15 +1,25 @@
-use std::path::Path;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use async_trait::async_trait;
+#[cfg(feature = "storage_signed_urls")]
+use axum::http::Method;
 use bytes::Bytes;
 use futures_util::{SinkExt, StreamExt};
 use opendal::{layers::RetryLayer, Operator};
 
-use super::{GetResponse, StoreDriver, UploadResponse};
+use super::{GetResponse, ObjectMetadata, PresignedRequest, StoreDriver, UploadResponse};
+#[cfg(feature = "storage_signed_urls")]
+use crate::storage::presign::UrlSigner;
 use crate::storage::{stream::BytesStream, StorageError, StorageResult};
 
 pub struct OpendalAdapter {
     opendal_impl: Operator,
+    #[cfg(feature = "storage_signed_urls")]
+    url_signer: Option<UrlSigner>,
 }
 
 impl OpendalAdapter {
@@ -19,7 +29,28 @@ impl OpendalAdapter {
         let opendal_impl = opendal_impl
             // Add retry layer with default settings
             .layer(RetryLayer::default().with_jitter());
-        Self { opendal_impl }
+        Self {
+            opendal_impl,
+            #[cfg(feature = "storage_signed_urls")]
+            url_signer: None,
+        }
+    }
+
+    /// Presigns requests with `signer`, for them to be served by
+    /// [`crate::controller::storage`], instead of by the underlying service.
+    #[cfg(feature = "storage_signed_urls")]
+    #[must_use]
+    pub fn with_url_signer(mut self, signer: UrlSigner) -> Self {
+        self.url_signer = Some(signer);
+        self
+    }
+
+    fn presigned(request: &opendal::raw::PresignedRequest) -> PresignedRequest {
+        PresignedRequest {
+            method: request.method().clone(),
+            url: request.uri().to_string(),
+            headers: request.header().clone(),
+        }
     }
 }
 
@@ -142,6 +173,111 @@ impl StoreDriver for OpendalAdapter {
         Ok(self.opendal_impl.exists(&path).await.unwrap_or(false))
     }
 
+    /// Lists the paths of the files under the `prefix` directory, including
+    /// its subdirectories.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the paths found.
+    async fn list(&self, prefix: &Path) -> StorageResult<Vec<PathBuf>> {
+        let mut prefix = prefix.display().to_string();
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        let entries = self.opendal_impl.list_with(&prefix).recursive(true).await?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.metadata().is_file())
+            .map(|entry| PathBuf::from(entry.path()))
+            .collect())
+    }
+
+    /// Retrieves the metadata of the content at the specified path.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the metadata, or an error if the
+    /// content does not exist.
+    async fn metadata(&self, path: &Path) -> StorageResult<ObjectMetadata> {
+        let meta = self.opendal_impl.stat(&path.display().to_string()).await?;
+        Ok(ObjectMetadata {
+            size: meta.content_length(),
+            content_type: meta.content_type().map(ToString::to_string),
+            last_modified: meta.last_modified(),
+            e_tag: meta.etag().map(ToString::to_string),
+        })
+    }
+
+    /// Retrieves `range` from the content at the specified path. Only the
+    /// requested bytes are read from the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the result of the retrieval operation,
+    /// or an error if `range` starts after its end.
+    async fn get_range(&self, path: &Path, range: Range<u64>) -> StorageResult<GetResponse> {
+        if range.start > range.end {
+            return Err(StorageError::Any(
+                format!("Invalid range {}..{}", range.start, range.end).into(),
+            ));
+        }
+        let r = self
+            .opendal_impl
+            .reader(&path.display().to_string())
+            .await?;
+        Ok(GetResponse::with_range(r, range))
+    }
+
+    /// Presigns a download with the URL signer of the store if it has one,
+    /// or with the underlying service otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the presigned request, or an error if
+    /// the service can not presign requests.
+    async fn presign_get(&self, path: &Path, ttl: Duration) -> StorageResult<PresignedRequest> {
+        #[cfg(feature = "storage_signed_urls")]
+        if let Some(signer) = &self.url_signer {
+            return Ok(signer.sign(Method::GET, path, ttl, None));
+        }
+        let request = self
+            .opendal_impl
+            .presign_read(&path.display().to_string(), ttl)
+            .await?;
+        Ok(Self::presigned(&request))
+    }
+
+    /// Presigns an upload with the URL signer of the store if it has one,
+    /// or with the underlying service otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageResult` with the presigned request, or an error if
+    /// the service can not presign requests.
+    async fn presign_put(
+        &self,
+        path: &Path,
+        ttl: Duration,
+        content_type: Option<&str>,
+    ) -> StorageResult<PresignedRequest> {
+        #[cfg(feature = "storage_signed_urls")]
+        if let Some(signer) = &self.url_signer {
+            return Ok(signer.sign(Method::PUT, path, ttl, content_type));
+        }
+        let mut request = self
+            .opendal_impl
+            .presign_write_with(&path.display().to_string(), ttl);
+        if let Some(content_type) = content_type {
+            request = request.content_type(content_type);
+        }
+        Ok(Self::presigned(&request.await?))
+    }
+
+    #[cfg(feature = "storage_signed_urls")]
+    fn url_signer(&self) -> Option<&UrlSigner> {
+        self.url_signer.as_ref()
+    }
+
     /// Native streaming implementation for `OpenDAL`.
     /// This directly uses `OpenDAL`'s reader for efficient streaming.
     async fn get_stream(&self, path: &Path) -> StorageResult<BytesStream> {
@@ -149,7 +285,7 @@ impl StoreDriver for OpendalAdapter {
             .opendal_impl
             .reader(&path.display().to_string())
             .await?;
-        BytesStream::from_reader(reader).await
+        BytesStream::from_reader(reader, ..).await
     }
 
     /// Native streaming upload for `OpenDAL`.
@@ -180,3 +316,78 @@ impl StoreDriver for OpendalAdapter {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::drivers::{local, mem};
+
+    async fn upload_files(store: &dyn StoreDriver) {
+        for path in ["users/1.txt", "users/avatars/1.png", "posts/1.txt"] {
+            store
+                .upload(Path::new(path), &Bytes::from("file content"))
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn assert_can_list(store: &dyn StoreDriver) {
+        upload_files(store).await;
+
+        let mut paths = store.list(Path::new("users")).await.unwrap();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("users/1.txt"),
+                PathBuf::from("users/avatars/1.png")
+            ]
+        );
+        assert_eq!(store.list(Path::new("")).await.unwrap().len(), 3);
+        assert!(store.list(Path::new("comments")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn can_list() {
+        assert_can_list(&*mem::new()).await;
+
+        let tree = tree_fs::TreeBuilder::default().create().unwrap();
+        assert_can_list(&*local::new_with_prefix(&tree.root).unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn can_get_metadata() {
+        let tree = tree_fs::TreeBuilder::default().create().unwrap();
+        let store = local::new_with_prefix(&tree.root).unwrap();
+        upload_files(&*store).await;
+
+        let meta = store.metadata(Path::new("users/1.txt")).await.unwrap();
+        assert_eq!(meta.size, 12);
+        assert!(meta.last_modified.is_some());
+        assert!(store.metadata(Path::new("users/2.txt")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn can_get_range() {
+        let store = mem::new();
+        upload_files(&*store).await;
+        let path = Path::new("users/1.txt");
+
+        let response = store.get_range(path, 5..12).await.unwrap();
+        assert_eq!(response.bytes().await.unwrap(), "content");
+
+        let stream = store
+            .get_range(path, 0..4)
+            .await
+            .unwrap()
+            .into_stream()
+            .await
+            .unwrap();
+        assert_eq!(stream.collect().await.unwrap(), "file");
+
+        assert!(store
+            .get_range(path, Range { start: 12, end: 5 })
+            .await
+            .is_err());
+    }
+}
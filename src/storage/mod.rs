@@ -12,6 +12,8 @@
 //! The selected strategy can be dynamically changed at runtime.
 mod contents;
 pub mod drivers;
+#[cfg(feature = "storage_signed_urls")]
+pub mod presign;
 pub mod strategies;
 pub mod stream;
 use std::{
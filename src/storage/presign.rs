@@ -0,0 +1,208 @@
+//! # Signed URLs
+//!
+//! This module signs the requests a client can send to a store directly, for
+//! the stores that can not presign them themselves. It is available with the
+//! `storage_signed_urls` feature.
+use std::{path::Path, time::Duration};
+
+use axum::http::{header, HeaderMap, HeaderValue, Method};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use super::drivers::PresignedRequest;
+
+/// Characters left as is in the path and query of a signed URL.
+const URL_SAFE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Signs the URLs served by [`crate::controller::storage`] for a store that
+/// can not presign requests itself, such as the local and in-memory stores.
+///
+/// # Example
+/// ```
+/// use loco_rs::storage::{drivers::mem, presign::UrlSigner};
+///
+/// let signer = UrlSigner::new("https://example.com/_storage", "store", "a long random secret");
+/// let store = mem::with_signed_urls(signer);
+/// ```
+#[derive(Clone)]
+pub struct UrlSigner {
+    base_url: String,
+    store: String,
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for UrlSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UrlSigner")
+            .field("base_url", &self.base_url)
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Query parameters of a signed URL.
+#[derive(Debug, Deserialize)]
+pub struct SignedQuery {
+    /// Unix timestamp after which the URL is rejected.
+    pub expires: i64,
+    pub content_type: Option<String>,
+    pub signature: String,
+}
+
+impl UrlSigner {
+    /// Creates a signer for the store registered as `store` in
+    /// [`super::Storage`], whose URLs start with `base_url`, the URL
+    /// [`crate::controller::storage::routes`] are served at.
+    #[must_use]
+    pub fn new(base_url: &str, store: &str, secret: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            store: store.to_string(),
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    /// Signs a `method` request to `path`, valid for `ttl`. Uploads are only
+    /// accepted with the signed `content_type`.
+    #[must_use]
+    pub fn sign(
+        &self,
+        method: Method,
+        path: &Path,
+        ttl: Duration,
+        content_type: Option<&str>,
+    ) -> PresignedRequest {
+        let path = path.display().to_string();
+        let ttl = i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX);
+        let expires = Utc::now().timestamp().saturating_add(ttl);
+        let signature = hex::encode(
+            self.mac(&method, &path, expires, content_type)
+                .finalize()
+                .into_bytes(),
+        );
+
+        let mut url = format!("{}/{}", self.base_url, encode(&self.store));
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            url.push('/');
+            url.push_str(&encode(segment));
+        }
+        url.push_str(&format!("?expires={expires}"));
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = content_type {
+            url.push_str(&format!("&content_type={}", encode(content_type)));
+            if let Ok(value) = HeaderValue::from_str(content_type) {
+                headers.insert(header::CONTENT_TYPE, value);
+            }
+        }
+        url.push_str(&format!("&signature={signature}"));
+
+        PresignedRequest {
+            method,
+            url,
+            headers,
+        }
+    }
+
+    /// Checks that `query` signs a `method` request to `path` of this store,
+    /// and has not expired.
+    #[must_use]
+    pub fn verify(&self, method: &Method, path: &str, query: &SignedQuery) -> bool {
+        if query.expires < Utc::now().timestamp() {
+            return false;
+        }
+        let Ok(signature) = hex::decode(&query.signature) else {
+            return false;
+        };
+        self.mac(method, path, query.expires, query.content_type.as_deref())
+            .verify_slice(&signature)
+            .is_ok()
+    }
+
+    fn mac(
+        &self,
+        method: &Method,
+        path: &str,
+        expires: i64,
+        content_type: Option<&str>,
+    ) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        let path = path.trim_start_matches('/');
+        let content_type = content_type.unwrap_or_default();
+        let store = &self.store;
+        mac.update(format!("{method}\n{store}\n{path}\n{expires}\n{content_type}").as_bytes());
+        mac
+    }
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, URL_SAFE).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(request: &PresignedRequest) -> SignedQuery {
+        let uri = request.url.parse().unwrap();
+        axum::extract::Query::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn can_sign_and_verify() {
+        let signer = UrlSigner::new("http://localhost:5150/_storage/", "store", "secret");
+        let request = signer.sign(
+            Method::PUT,
+            Path::new("users/1 avatar.png"),
+            Duration::from_secs(60),
+            Some("image/png"),
+        );
+        assert!(request
+            .url
+            .starts_with("http://localhost:5150/_storage/store/users/1%20avatar.png?expires="));
+        assert_eq!(request.headers[header::CONTENT_TYPE], "image/png");
+
+        let query = query(&request);
+        assert!(signer.verify(&Method::PUT, "users/1 avatar.png", &query));
+        assert!(!signer.verify(&Method::GET, "users/1 avatar.png", &query));
+        assert!(!signer.verify(&Method::PUT, "users/2.png", &query));
+        let other = UrlSigner::new("http://localhost:5150/_storage", "other", "secret");
+        assert!(!other.verify(&Method::PUT, "users/1 avatar.png", &query));
+        let other = UrlSigner::new("http://localhost:5150/_storage", "store", "other secret");
+        assert!(!other.verify(&Method::PUT, "users/1 avatar.png", &query));
+
+        let tampered = SignedQuery {
+            content_type: Some("text/html".to_string()),
+            ..query
+        };
+        assert!(!signer.verify(&Method::PUT, "users/1 avatar.png", &tampered));
+    }
+
+    #[test]
+    fn can_not_verify_expired_urls() {
+        let signer = UrlSigner::new("http://localhost:5150/_storage", "store", "secret");
+        let request = signer.sign(
+            Method::GET,
+            Path::new("1.txt"),
+            Duration::from_secs(60),
+            None,
+        );
+        assert!(signer.verify(&Method::GET, "1.txt", &query(&request)));
+
+        let expires = Utc::now().timestamp() - 1;
+        let signature = signer.mac(&Method::GET, "1.txt", expires, None);
+        let query = SignedQuery {
+            expires,
+            content_type: None,
+            signature: hex::encode(signature.finalize().into_bytes()),
+        };
+        assert!(!signer.verify(&Method::GET, "1.txt", &query));
+    }
+}
@@ -1,3 +1,4 @@
+use std::ops::RangeBounds;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
@@ -12,13 +13,15 @@ pub struct BytesStream {
 }
 
 impl BytesStream {
-    /// Create a `BytesStream` from an `OpenDAL` `Reader`.
+    /// Create a `BytesStream` reading `range` from an `OpenDAL` `Reader`.
     /// This is an internal method used by storage drivers.
-    pub(crate) async fn from_reader(reader: Reader) -> Result<Self, crate::storage::StorageError> {
+    pub(crate) async fn from_reader(
+        reader: Reader,
+        range: impl RangeBounds<u64>,
+    ) -> Result<Self, crate::storage::StorageError> {
         // Convert the Reader into a stream of bytes
-        // The range parameter (..) means we want to read the entire content
         let stream = reader
-            .into_bytes_stream(..)
+            .into_bytes_stream(range)
             .await
             .map_err(crate::storage::StorageError::from)?;
 
@@ -32,6 +32,7 @@ pub fn test_config() -> Config {
         auth: None,
         workers: config::Workers {
             mode: config::WorkerMode::ForegroundBlocking,
+            shutdown_timeout: None,
         },
         mailer: None,
         initializers: None,
@@ -42,6 +43,9 @@ pub fn test_config() -> Config {
                 scheduler::Job {
                     run: "echo loco".to_string(),
                     shell: true,
+                    worker: false,
+                    args: None,
+                    queue: None,
                     run_on_start: false,
                     cron: "*/5 * * * * *".to_string(),
                     tags: Some(vec!["base".to_string()]),